@@ -2,15 +2,35 @@
 /// It's preferred way to handle the data asynchronously using data and
 /// system message channels in order not to block the module routines
 
+use std::{ffi::c_void, sync::atomic::Ordering, thread, time::Duration};
+
 use log::{debug, error};
 
-use torustiq_common::ffi::types::module::{ModuleHandle, Record};
+use torustiq_common::ffi::types::module::{ModuleHandle as FfiModuleHandle, Record};
+
+use crate::{
+    modules::extensions::StepStats,
+    pipeline::{
+        channels::get_channels,
+        drops::{on_record_dropped, DropReason},
+        handle::ModuleHandle,
+        pipeline::is_step_paused,
+        step_context::{get_step_context, StepContext},
+    },
+    records::{append_provenance, stamp_deadline, stamp_origin_timestamp},
+    xthread::{CANCELLED_STEPS, IS_DEADLINE_TRACKING_ENABLED, LATENCY_SOURCE_HANDLE, MODULE_HANDLES, PROVENANCE_STEP_IDS, RAMP_UP, RETRY_QUEUE, SAMPLING, SHADOW, SOURCE_DRAIN, SOURCE_LEASE, STEP_STATS, SYSTEM_MESSAGES, SystemMessage},
+};
 
-use crate::xthread::{SENDERS, SYSTEM_MESSAGES, SystemMessage};
+/// How often a paused step checks if it's resumed
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Called from modules on step thread termination
-pub extern "C"  fn on_step_terminate_cb(module_handle: ModuleHandle) {
+pub extern "C"  fn on_step_terminate_cb(module_handle: FfiModuleHandle) {
     debug!("A step termination signal is triggered from step with index {}", module_handle);
+    let module_handle = match ModuleHandle::from_ffi(module_handle, "Termination callback") {
+        Some(h) => h,
+        None => return,
+    };
     let msg_chan = match SYSTEM_MESSAGES.get() {
         Some(c) => c,
         None => {
@@ -18,21 +38,179 @@ pub extern "C"  fn on_step_terminate_cb(module_handle: ModuleHandle) {
             return
         }
     };
+    if let Some(drain) = SOURCE_DRAIN.get().filter(|d| d.source_handle == module_handle) {
+        drain.acknowledge();
+    }
     if let Err(e) = msg_chan.send(SystemMessage::TerminateStep(module_handle)) {
         error!("Termination callback failure: cannot send a termination message ({})", e);
     }
 }
 
 /// Steps use this function to pass the produced record to dependent step
-pub extern "C" fn on_rcv_cb(module_handle: ModuleHandle, record: Record) {
-    let sender = match SENDERS.lock().unwrap().get(&module_handle) {
-        Some(s) => s.clone(),
+pub extern "C" fn on_rcv_cb(module_handle: FfiModuleHandle, record: Record) {
+    let mut record = record;
+    let module_handle = match ModuleHandle::from_ffi(module_handle, "Data receive callback") {
+        Some(h) => h,
+        None => {
+            // The host owns the record once the callback is called
+            record.free_contents();
+            return
+        },
+    };
+    // The self-test handle doesn't belong to a step, so it has no context
+    send_record(module_handle, get_step_context(module_handle).map(|c| c.as_ref()), record);
+}
+
+/// Steps which received the context pointer use this function instead of `on_rcv_cb`, see `modules::extensions`
+///
+/// # Safety
+/// `context` must be null or a pointer which is passed to module with `torustiq_module_pipeline_set_context`.
+/// Null pointers are rejected
+pub unsafe extern "C" fn on_rcv_ctx_cb(context: *const c_void, record: Record) {
+    let context = match (context as *const StepContext).as_ref() {
+        Some(c) => c,
+        None => {
+            error!("Data receive callback: the step context is null");
+            return
+        },
+    };
+    send_record(context.handle, Some(context), record);
+}
+
+/// Passes the record produced by step to dependent step.
+/// Steps are located by context. Components without context are located through the global channel registry
+fn send_record(module_handle: ModuleHandle, context: Option<&StepContext>, record: Record) {
+    // Steps which received the context pointer might produce records while priming, when outputs are not started yet
+    if MODULE_HANDLES.get().is_none() {
+        error!("Data receive callback: step '{}' produced a record before the steps are started, e.g. while priming. The record is dropped", module_handle);
+        let mut record = record;
+        record.free_contents();
+        return
+    }
+    // Paused steps must not emit records, e.g. from their own threads
+    while context.map(|c| c.is_paused()).unwrap_or(false) {
+        thread::sleep(PAUSE_CHECK_INTERVAL);
+    }
+    // Another instance holds the lease, so the records of stopped source are not delivered
+    if let Some(lease) = SOURCE_LEASE.get().filter(|l| l.get_source_handle() == module_handle) {
+        if !lease.accepts_records() {
+            let mut record = record;
+            on_record_dropped(module_handle, &record, DropReason::SourceDrained);
+            record.free_contents();
+            return
+        }
+    }
+    if let Some(drain) = SOURCE_DRAIN.get().filter(|d| d.source_handle == module_handle) {
+        if !drain.accept() {
+            let mut record = record;
+            on_record_dropped(module_handle, &record, DropReason::SourceDrained);
+            record.free_contents();
+            return
+        }
+    }
+    let outputs = match context {
+        Some(c) => c.get_outputs().cloned(),
+        None => get_channels().and_then(|c| c.get_outputs(module_handle)),
+    };
+    let outputs = match outputs {
+        Some(o) => o,
         None => return, // no sender exists: no action
     };
+    if let Some(ramp_up) = RAMP_UP.get() {
+        if module_handle == ramp_up.source_handle {
+            ramp_up.wait();
+        }
+    }
+    let record = match LATENCY_SOURCE_HANDLE.get() {
+        Some(h) if *h == module_handle => stamp_origin_timestamp(record),
+        _ => record,
+    };
+    let record = match IS_DEADLINE_TRACKING_ENABLED.load(Ordering::Relaxed) {
+        true => stamp_deadline(record),
+        false => record,
+    };
+    let record = match PROVENANCE_STEP_IDS.get().and_then(|ids| ids.get(&module_handle)) {
+        Some(step_id) => append_provenance(record, step_id, "ok"),
+        None => record,
+    };
 
+    let record = match (outputs.mirrors.is_empty(), SHADOW.get()) {
+        (false, Some(shadow)) => shadow.mirror(record, &outputs.mirrors),
+        _ => record,
+    };
+    if let (Some(sample), Some(sampling)) = (&outputs.sample, SAMPLING.get()) {
+        sampling.sample(&record, sample);
+    }
+    let sender = match outputs.select(&record) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Failed to route a record from step '{}': {}", module_handle, e);
+            let mut record = record;
+            on_record_dropped(module_handle, &record, DropReason::Unroutable);
+            record.free_contents();
+            return;
+        },
+    };
     // Sends a cloned record to further processing and deallocates the original record
-    if let Err(e) = sender.send(record) {
-        error!("Failed to send a record from step '{}' to the next steep: {}", module_handle, e);
-        return;
+    if let Err(mut record) = sender.send(record) {
+        error!("Failed to send a record from step '{}' to the next step: the input queue is closed", module_handle);
+        on_record_dropped(module_handle, &record, DropReason::QueueClosed);
+        record.free_contents();
+    }
+}
+
+/// Steps use this function to check if they should stop the current work, e.g. long I/O inside record processing
+pub extern "C" fn is_cancelled_cb(module_handle: FfiModuleHandle) -> bool {
+    let module_handle = match ModuleHandle::from_ffi(module_handle, "Cancellation check callback") {
+        Some(h) => h,
+        None => return false,
+    };
+    is_step_paused(module_handle) || CANCELLED_STEPS.lock().unwrap().contains(&module_handle)
+}
+
+/// Steps use this function to park a record for delayed retry. See `pipeline::retry`
+pub extern "C" fn retry_record_cb(module_handle: FfiModuleHandle, record: Record, delay_ms: u64) -> bool {
+    let mut record = record;
+    let module_handle = match ModuleHandle::from_ffi(module_handle, "Retry callback") {
+        Some(h) => h,
+        None => {
+            record.free_contents();
+            return false
+        },
+    };
+    let retry_queue = match RETRY_QUEUE.get() {
+        Some(q) => q,
+        None => {
+            error!("Step '{}' requested a retry, but retries are not configured in pipeline", module_handle);
+            on_record_dropped(module_handle, &record, DropReason::RetryNotConfigured);
+            record.free_contents();
+            return false
+        },
+    };
+    let delay = match delay_ms {
+        0 => None,
+        d => Some(Duration::from_millis(d)),
+    };
+    retry_queue.park(module_handle, record, delay)
+}
+
+/// Listeners use this function to read the current statistics of step
+///
+/// # Safety
+/// `stats` must be null or point to a valid writable `StepStats` structure. Null pointers are rejected
+pub unsafe extern "C" fn get_step_stats_cb(module_handle: FfiModuleHandle, stats: *mut StepStats) -> bool {
+    if stats.is_null() {
+        return false
     }
-}
\ No newline at end of file
+    let module_handle = match ModuleHandle::from_ffi(module_handle, "Step statistics callback") {
+        Some(h) => h,
+        None => return false,
+    };
+    let step_stats = match STEP_STATS.lock().unwrap().get(&module_handle) {
+        Some(s) => s.snapshot(),
+        None => return false,
+    };
+    // The pointer is checked for null above, the caller guarantees that it's valid otherwise
+    unsafe { *stats = step_stats };
+    true
+}
@@ -1,20 +1,204 @@
-use clap::{arg, command, Parser};
+use std::{io, path::Path};
+
+use clap::{arg, command, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 
 /// Starts a data processing pipeline from provided config
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct CliArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// A YAML file to read the pipeline structure from
-    #[arg(short, long, default_value="pipeline.yaml")]
+    #[arg(short, long, default_value="pipeline.yaml", global = true)]
     pub pipeline_file: String,
 
+    /// A name of pipeline to run, if pipeline file contains multiple pipelines
+    #[arg(long, global = true)]
+    pub pipeline: Option<String>,
+
+    /// A value of pipeline parameter in `key=value` format. Can be repeated.
+    /// Parameters are declared in `params` section of pipeline file
+    #[arg(long, global = true)]
+    pub param: Vec<String>,
+
+    /// An environment profile of pipeline, e.g. `prod`. Profiles are declared in `profiles` section of pipeline file
+    /// and override the arguments of steps and listeners
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
     /// A YAML file to read the pipeline structure from
-    #[arg(short, long, default_value="modules")]
+    #[arg(short, long, default_value="modules", global = true)]
     pub module_dir: String,
+
+    /// A YAML file with module policy. If set, only the modules listed in policy are allowed
+    #[arg(long, global = true)]
+    pub policy_file: Option<String>,
+
+    /// Watch the pipeline file and restart the pipeline once the file is changed.
+    /// The new pipeline definition is validated first; an invalid definition is ignored
+    #[arg(long, global = true)]
+    pub watch_config: bool,
+
+    /// Read control commands from stdin: status, stats, pause, resume, set-param, shutdown
+    #[arg(long, global = true)]
+    pub interactive: bool,
+
+    /// Holds each record at the input of step until it's advanced with `next` command in interactive console.
+    /// Used to debug the pipeline logic on small inputs. Implies `--interactive`
+    #[arg(long, global = true)]
+    pub step_mode: bool,
+
+    /// A directory of CPU and allocation profiles of host threads. Profiles are captured with `profile` command
+    /// in interactive console. Implies `--interactive`
+    #[arg(long, global = true)]
+    pub profile_dir: Option<String>,
+
+    /// A file to write the metrics to in Prometheus text format, e.g. for textfile collector of node exporter.
+    /// The file is updated periodically while the pipeline is running
+    #[arg(long, global = true)]
+    pub metrics_file: Option<String>,
+
+    /// An address to serve the stream of pipeline events on, e.g. `127.0.0.1:9300`, `[::1]:9300`
+    /// or `unix:///run/torustiq.sock`.
+    /// Clients read the events from `GET /events` as Server-Sent Events. There is no authentication
+    #[arg(long, global = true)]
+    pub events_addr: Option<String>,
+
+    /// A file to write the report of fatal error to in JSON format: error code, exit code, step and module
+    #[arg(long, global = true)]
+    pub error_report: Option<String>,
+
+    /// A directory of instance locks. If set, the pipeline locks a file named after the pipeline in this directory,
+    /// so another copy of the same pipeline cannot start until this one is stopped
+    #[arg(long, global = true)]
+    pub instance_lock: Option<String>,
+
+    /// A file with a key to decrypt the `!encrypted` values in pipeline file. See `encrypt-value` command
+    #[arg(long, global = true)]
+    pub key_file: Option<String>,
+
+    /// Detaches the application from terminal and runs it in background. Unix only
+    #[arg(long)]
+    pub daemonize: bool,
+
+    /// A PID file of daemon. Prevents starting of second instance
+    #[arg(long, default_value = "/run/torustiq.pid")]
+    pub pid_file: String,
+
+    /// A file to write the output of daemon to. If not set, the output is discarded
+    #[arg(long)]
+    pub log_file: Option<String>,
+}
+
+/// Additional commands. If no command is provided, the pipeline is started
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Runs the pipeline with fixture input instead of source and compares the output with expected records
+    Test(TestArgs),
+    /// Downloads the module libraries listed in pipeline file into the module directory
+    FetchModules,
+    /// Checks if the pipeline can be created from pipeline file: validates the definition and policy,
+    /// loads the modules and constructs the pipeline without starting it. Exits with non-zero code if invalid
+    Validate,
+    /// Replays the captured records through the pipeline with destination replaced by a timing model
+    /// and reports the predicted throughput and queue behavior
+    Simulate(SimulateArgs),
+    /// Runs a payload through a single transformation module and prints the output records,
+    /// e.g. `torustiq-cli eval --step json_to_csv --input payload.json --arg delimiter=;`
+    Eval(EvalArgs),
+    /// Prints an example configuration of module, e.g. `torustiq-cli example kafka_source`
+    Example(ExampleArgs),
+    /// Encrypts a secret value for pipeline file. The output is used as step argument, e.g. `password: !encrypted ...`
+    EncryptValue(EncryptValueArgs),
+    /// Upgrades a legacy pipeline file: fills in the missing names of steps and pipeline.
+    /// Prints the upgraded definition unless the output file is set
+    MigrateConfig(MigrateConfigArgs),
+    /// Prints a shell completion script, e.g. `torustiq-cli completions bash > /etc/bash_completion.d/torustiq-cli`
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TestArgs {
+    /// A YAML file with test case definition
+    pub test_file: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SimulateArgs {
+    /// A fixture file with records to replay, e.g. written by `builtin.capture` module
+    pub input: String,
+    /// A latency distribution of destination, milliseconds:
+    /// `fixed:<ms>`, `uniform:<min_ms>:<max_ms>`, `normal:<mean_ms>:<stddev_ms>`, `exponential:<mean_ms>`
+    #[arg(long, default_value="fixed:0")]
+    pub latency: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct EvalArgs {
+    /// A module ID of transformation step
+    #[arg(long)]
+    pub step: String,
+    /// A file with payload. Use `-` to read the payload from stdin
+    #[arg(long)]
+    pub input: String,
+    /// An argument of step in `key=value` format. Can be repeated
+    #[arg(long)]
+    pub arg: Vec<String>,
+    /// A metadata entry of input record in `key=value` format. Can be repeated
+    #[arg(long)]
+    pub metadata: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExampleArgs {
+    /// A module ID with optional version requirement, e.g. `kafka_source` or `kafka_source@^1.2`
+    pub module: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct EncryptValueArgs {
+    /// A value to encrypt. If not set, the value is read from stdin, so it doesn't appear in shell history
+    pub value: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MigrateConfigArgs {
+    /// A file to write the upgraded definition to. Might be the same as pipeline file
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CompletionsArgs {
+    /// A shell to generate the completion script for
+    pub shell: Shell,
 }
 
 impl CliArgs {
     pub fn do_parse() -> CliArgs {
         CliArgs::parse()
     }
-}
\ No newline at end of file
+
+    /// Writes the completion script for provided shell to stdout
+    pub fn print_completions(shell: Shell) {
+        let mut cmd = CliArgs::command();
+        let bin_name = cmd.get_name().to_string();
+        clap_complete::generate(shell, &mut cmd, bin_name, &mut io::stdout());
+    }
+
+    /// Checks if the required pipeline file and module directory exist.
+    /// This is done before any heavy startup work in order to report the wrong paths early
+    pub fn validate_paths(&self, is_pipeline_file_required: bool, is_module_dir_required: bool) -> Result<(), String> {
+        if is_pipeline_file_required && !Path::new(&self.pipeline_file).is_file() {
+            return Err(format!("Pipeline file '{}' does not exist. Please set the path with '--pipeline-file' option",
+                self.pipeline_file))
+        }
+        if is_module_dir_required && !Path::new(&self.module_dir).is_dir() {
+            return Err(format!("Module directory '{}' does not exist. Please set the path with '--module-dir' option",
+                self.module_dir))
+        }
+        Ok(())
+    }
+}
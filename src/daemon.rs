@@ -0,0 +1,95 @@
+/// Daemon mode for environments without service manager.
+/// The process detaches from terminal, writes its PID to file and redirects the output to log file.
+/// The working directory is kept, so relative paths in arguments and pipeline files stay valid
+
+use std::fs;
+
+use log::warn;
+use once_cell::sync::OnceCell;
+
+/// A PID file of the current process. Set once the process is daemonized
+static PID_FILE: OnceCell<String> = OnceCell::new();
+
+/// Returns an error if PID file refers to a running process other than the current one.
+/// Stale PID files are removed
+#[cfg(unix)]
+fn check_pid_file(path: &str) -> Result<(), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(_) => return Ok(()), // no PID file
+    };
+    let pid: libc::pid_t = match contents.trim().parse() {
+        Ok(p) => p,
+        Err(_) => return Err(format!("PID file '{}' has invalid contents. Please check and remove it manually", path)),
+    };
+    // The process restarted itself, e.g. on configuration change
+    if pid == std::process::id() as libc::pid_t {
+        return Ok(())
+    }
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return Err(format!("Another instance is already running with PID {} (PID file: '{}')", pid, path))
+    }
+    warn!("Removing the stale PID file '{}' of process {}", path, pid);
+    match fs::remove_file(path) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Cannot remove the stale PID file '{}': {}", path, e)),
+    }
+}
+
+/// Detaches the process from terminal.
+/// Must be called before any thread is started, as only the calling thread survives the fork
+#[cfg(unix)]
+pub fn daemonize(pid_file: &str, log_file: Option<&String>) -> Result<(), String> {
+    use std::{fs::OpenOptions, os::fd::AsRawFd};
+
+    check_pid_file(pid_file)?;
+    let output = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path),
+        None => OpenOptions::new().write(true).open("/dev/null"),
+    };
+    let output = match output {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Cannot open the log file: {}", e)),
+    };
+    let input = match OpenOptions::new().read(true).open("/dev/null") {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Cannot open /dev/null: {}", e)),
+    };
+
+    // The first fork returns control to shell. The second one ensures that the daemon never acquires a terminal
+    for i in 0..2 {
+        match unsafe { libc::fork() } {
+            -1 => return Err(String::from("Cannot fork the process")),
+            0 => {},
+            _ => std::process::exit(0),
+        }
+        if i == 0 && unsafe { libc::setsid() } == -1 {
+            return Err(String::from("Cannot create a new session"))
+        }
+    }
+    unsafe {
+        libc::dup2(input.as_raw_fd(), libc::STDIN_FILENO);
+        libc::dup2(output.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(output.as_raw_fd(), libc::STDERR_FILENO);
+    }
+
+    if let Err(e) = fs::write(pid_file, format!("{}\n", std::process::id())) {
+        return Err(format!("Cannot write the PID file '{}': {}", pid_file, e))
+    }
+    let _ = PID_FILE.set(pid_file.to_string());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_pid_file: &str, _log_file: Option<&String>) -> Result<(), String> {
+    Err(String::from("Daemon mode is supported on unix only"))
+}
+
+/// Removes the PID file if the process is daemonized
+pub fn remove_pid_file() {
+    if let Some(path) = PID_FILE.get() {
+        if let Err(e) = fs::remove_file(path) {
+            warn!("Cannot remove the PID file '{}': {}", path, e);
+        }
+    }
+}
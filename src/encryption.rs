@@ -0,0 +1,84 @@
+/// Encryption of step arguments at rest.
+/// Secret arguments are stored in pipeline files as `!encrypted <value>` where value is produced by `encrypt-value` command.
+/// Values are encrypted with AES-256-GCM and decrypted in memory only, once the pipeline is created.
+/// The value is base64 of nonce followed by ciphertext.
+/// A key file contains a 256-bit key encoded in base64, e.g. generated by `openssl rand -base64 32 > torustiq.key`
+
+use std::fs;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::{masking::register_secret_value, xthread::ENCRYPTION_KEY};
+
+/// A YAML tag of encrypted values
+pub const ENCRYPTED_TAG: &str = "encrypted";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Reads the key from file
+fn read_key_file(path: &str) -> Result<Vec<u8>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Cannot read the key file '{}': {}", path, e)),
+    };
+    match BASE64.decode(contents.trim()) {
+        Ok(k) if k.len() == KEY_LEN => Ok(k),
+        Ok(k) => Err(format!("Invalid key in file '{}': expected {} bytes, got {}", path, KEY_LEN, k.len())),
+        Err(e) => Err(format!("Invalid key in file '{}': {}", path, e)),
+    }
+}
+
+/// Reads the key which is used to decrypt the encrypted values in pipeline files
+pub fn init_encryption_key(path: &str) -> Result<(), String> {
+    let key = read_key_file(path)?;
+    if ENCRYPTION_KEY.set(key).is_err() {
+        return Err(String::from("The encryption key is already initialized"))
+    }
+    Ok(())
+}
+
+/// Encrypts the value with key from file. Returns a value to put after `!encrypted` tag
+pub fn encrypt_value(key_path: &str, value: &str) -> Result<String, String> {
+    let key = read_key_file(key_path)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = match cipher.encrypt(&nonce, value.as_bytes()) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Failed to encrypt the value: {}", e)),
+    };
+    let mut result = nonce.to_vec();
+    result.extend(ciphertext);
+    Ok(BASE64.encode(result))
+}
+
+/// Decrypts the value from pipeline file. The decrypted value is masked in diagnostics
+pub fn decrypt_value(encrypted: &str) -> Result<String, String> {
+    let key = match ENCRYPTION_KEY.get() {
+        Some(k) => k,
+        None => return Err(String::from("the pipeline contains encrypted values, but no key file is provided. \
+            Please set the path with '--key-file' option")),
+    };
+    let bytes = match BASE64.decode(encrypted.trim()) {
+        Ok(b) if b.len() > NONCE_LEN => b,
+        _ => return Err(String::from("the encrypted value is malformed")),
+    };
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let value = match cipher.decrypt(Nonce::from_slice(nonce), ciphertext) {
+        Ok(v) => v,
+        // Intentionally vague: the details of failure don't help, but might reveal something about the key
+        Err(_) => return Err(String::from("cannot decrypt the value. Probably the key is wrong")),
+    };
+    match String::from_utf8(value) {
+        Ok(v) => {
+            register_secret_value(&v);
+            Ok(v)
+        },
+        Err(_) => Err(String::from("the decrypted value is not a valid UTF-8 string")),
+    }
+}
@@ -0,0 +1,164 @@
+/// Errors of application.
+/// Each error has a stable code which is written to log, to error report and control command output,
+/// and is mapped to the exit code, so automation can react to failures without parsing the messages
+
+use std::fs;
+
+use serde_json::json;
+use thiserror::Error;
+
+/// Exit code of errors which have no specific exit code
+pub const EXIT_CODE_GENERIC_ERROR: i32 = -1;
+
+#[derive(Debug, Error)]
+pub enum TorustiqError {
+    /// Pipeline file cannot be read or parsed, or the definition is invalid
+    #[error("{0}")]
+    Config(String),
+    /// Module directory or library cannot be read, or the required modules are not found
+    #[error("{message}")]
+    ModuleLoad { module_id: Option<String>, message: String },
+    /// Module is not allowed by module policy
+    #[error("{0}")]
+    Policy(String),
+    #[error("Failed to configure pipeline step '{step}' (module '{module_id}'): {message}")]
+    StepConfigure { step: String, module_id: String, message: String },
+    #[error("Failed to start pipeline step '{step}' (module '{module_id}'): {message}")]
+    StepStart { step: String, module_id: String, message: String },
+    /// Failures of multiple steps. The code of error is the code of the first failure
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("; "))]
+    Multiple(Vec<TorustiqError>),
+    /// Callbacks or channels don't work as expected before the steps are started
+    #[error("Self-test of callbacks failed: {0}")]
+    SelfTest(String),
+    #[error("Pipeline '{pipeline}' is stopped because step '{step}' terminated before the upstream steps")]
+    DownstreamTerminated { pipeline: String, step: String },
+    /// Other failures of running pipeline
+    #[error("{0}")]
+    Pipeline(String),
+    /// Control commands: the command or its arguments are not valid
+    #[error("{0}")]
+    InvalidCommand(String),
+    #[error("Step not found: '{0}'")]
+    StepNotFound(String),
+    #[error("Pipeline is not running yet")]
+    NotRunning,
+    /// Control commands: the operation is not supported by step
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    Timeout(String),
+    /// Another instance of pipeline holds the instance lock, or the lock cannot be acquired
+    #[error("{0}")]
+    InstanceLocked(String),
+}
+
+impl TorustiqError {
+    /// Combines the errors of multiple steps
+    pub fn from_many(mut errors: Vec<TorustiqError>) -> TorustiqError {
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => TorustiqError::Multiple(errors),
+        }
+    }
+
+    /// Returns a stable machine-readable code of error
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            TorustiqError::Config(_) => "config_invalid",
+            TorustiqError::ModuleLoad { .. } => "module_load_failed",
+            TorustiqError::Policy(_) => "policy_violation",
+            TorustiqError::StepConfigure { .. } => "step_configure_failed",
+            TorustiqError::StepStart { .. } => "step_start_failed",
+            TorustiqError::Multiple(errors) => errors.first().map(|e| e.get_code()).unwrap_or("pipeline_failed"),
+            TorustiqError::SelfTest(_) => "self_test_failed",
+            TorustiqError::DownstreamTerminated { .. } => "downstream_terminated",
+            TorustiqError::Pipeline(_) => "pipeline_failed",
+            TorustiqError::InvalidCommand(_) => "invalid_command",
+            TorustiqError::StepNotFound(_) => "step_not_found",
+            TorustiqError::NotRunning => "pipeline_not_running",
+            TorustiqError::Unsupported(_) => "unsupported",
+            TorustiqError::Timeout(_) => "timeout",
+            TorustiqError::InstanceLocked(_) => "instance_locked",
+        }
+    }
+
+    /// Returns the exit code of application which is terminated by this error.
+    /// Codes 3 and 4 are reserved for the exceeded runtime, see `pipeline::watchdog`
+    pub fn get_exit_code(&self) -> i32 {
+        match self {
+            TorustiqError::Config(_) => 10,
+            TorustiqError::ModuleLoad { .. } => 11,
+            TorustiqError::Policy(_) => 12,
+            TorustiqError::StepConfigure { .. } => 13,
+            TorustiqError::StepStart { .. } => 14,
+            TorustiqError::Multiple(errors) => errors.first().map(|e| e.get_exit_code()).unwrap_or(EXIT_CODE_GENERIC_ERROR),
+            TorustiqError::SelfTest(_) => 15,
+            TorustiqError::DownstreamTerminated { .. } => 16,
+            TorustiqError::Pipeline(_) => 17,
+            TorustiqError::InstanceLocked(_) => 18,
+            _ => EXIT_CODE_GENERIC_ERROR,
+        }
+    }
+
+    /// Returns the name of step the error is related to
+    pub fn get_step(&self) -> Option<&String> {
+        match self {
+            TorustiqError::StepConfigure { step, .. }
+            | TorustiqError::StepStart { step, .. }
+            | TorustiqError::DownstreamTerminated { step, .. }
+            | TorustiqError::StepNotFound(step) => Some(step),
+            TorustiqError::Multiple(errors) => errors.first().and_then(|e| e.get_step()),
+            _ => None,
+        }
+    }
+
+    /// Returns the module ID the error is related to
+    pub fn get_module_id(&self) -> Option<&String> {
+        match self {
+            TorustiqError::StepConfigure { module_id, .. } | TorustiqError::StepStart { module_id, .. } => Some(module_id),
+            TorustiqError::ModuleLoad { module_id, .. } => module_id.as_ref(),
+            TorustiqError::Multiple(errors) => errors.first().and_then(|e| e.get_module_id()),
+            _ => None,
+        }
+    }
+
+    /// Returns a JSON report of error. The message is masked, see `masking::mask_text`
+    pub fn to_report(&self, message: &str) -> serde_json::Value {
+        let errors: Vec<serde_json::Value> = match self {
+            TorustiqError::Multiple(errors) => errors.iter().map(|e| json!({
+                "code": e.get_code(),
+                "step": e.get_step(),
+                "module_id": e.get_module_id(),
+            })).collect(),
+            _ => Vec::new(),
+        };
+        json!({
+            "code": self.get_code(),
+            "exit_code": self.get_exit_code(),
+            "message": message,
+            "step": self.get_step(),
+            "module_id": self.get_module_id(),
+            "errors": errors,
+        })
+    }
+}
+
+/// Functions which are not migrated to typed errors yet receive the error message
+impl From<TorustiqError> for String {
+    fn from(e: TorustiqError) -> String {
+        e.to_string()
+    }
+}
+
+/// Writes a JSON report of error to file
+pub fn write_error_report(path: &String, error: &TorustiqError, message: &str) -> Result<(), String> {
+    let report = match serde_json::to_string_pretty(&error.to_report(message)) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("Cannot serialize the error report: {}", e)),
+    };
+    match fs::write(path, report) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Cannot write the error report to '{}': {}", path, e)),
+    }
+}
@@ -0,0 +1,101 @@
+/// Evaluation of a single transformation module.
+/// The payload is passed through the module once and the output records are printed with metadata,
+/// so module and configuration authors don't need to build a pipeline to check the result
+
+use std::{collections::HashMap, env, fs::{self, File}, io::{self, Read}, process};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    cli::{CliArgs, EvalArgs},
+    config::PipelineDefinition,
+    modules::builtin::{capture, fixture::{read_fixture_file, write_fixture_record, FixtureRecord}, fixture_source},
+    runner::{create_pipeline, run_pipeline},
+};
+
+/// Runs the payload through module. Returns the output records as JSON lines
+pub fn run_eval(args: &CliArgs, eval_args: &EvalArgs) -> Result<String, String> {
+    let payload = read_payload(&eval_args.input)?;
+    let input_record = FixtureRecord {
+        payload,
+        metadata: parse_key_values(&eval_args.metadata, "metadata")?,
+    };
+    let input_path = get_temp_path("input");
+    let capture_path = get_temp_path("output");
+    match File::create(&input_path) {
+        Ok(mut f) => write_fixture_record(&mut f, &input_record)?,
+        Err(e) => return Err(format!("Cannot create the input file '{}': {}", input_path, e)),
+    };
+
+    let result = evaluate(args, eval_args, &input_path, &capture_path);
+    let _ = fs::remove_file(&input_path);
+    let _ = fs::remove_file(&capture_path);
+    let records = result?;
+    if records.is_empty() {
+        return Ok(String::from("The module produced no records"))
+    }
+    let lines: Result<Vec<String>, String> = records.iter()
+        .map(|r| serde_json::to_string(r).map_err(|e| format!("Cannot serialize the output record: {}", e)))
+        .collect();
+    Ok(lines?.join("\n"))
+}
+
+/// Runs a pipeline of three steps: fixture source, evaluated module and capture destination
+fn evaluate(args: &CliArgs, eval_args: &EvalArgs, input_path: &str, capture_path: &str) -> Result<Vec<FixtureRecord>, String> {
+    let step_args: Mapping = parse_key_values(&eval_args.arg, "argument")?
+        .into_iter()
+        .map(|(k, v)| (Value::String(k), Value::String(v)))
+        .collect();
+    let step = |name: &str, handler: &str, args: Mapping| -> Value {
+        Value::Mapping(Mapping::from_iter([
+            (Value::from("name"), Value::from(name)),
+            (Value::from("handler"), Value::from(handler)),
+            (Value::from("args"), Value::Mapping(args)),
+        ]))
+    };
+    let definition = Value::Mapping(Mapping::from_iter([
+        (Value::from("name"), Value::from("eval")),
+        (Value::from("steps"), Value::Sequence(vec![
+            step("source", fixture_source::MODULE_ID, Mapping::from_iter([(Value::from("path"), Value::from(input_path))])),
+            step("eval", &eval_args.step, step_args),
+            step("destination", capture::MODULE_ID, Mapping::from_iter([(Value::from("path"), Value::from(capture_path))])),
+        ])),
+    ]));
+    let pipeline_def: PipelineDefinition = match serde_yaml::from_value(definition) {
+        Ok(d) => d,
+        Err(e) => return Err(format!("Cannot create a pipeline definition: {}", e)),
+    };
+
+    let (pipeline, _loaded_libs) = create_pipeline(args, &pipeline_def)?;
+    run_pipeline(pipeline)?;
+    read_fixture_file(capture_path)
+}
+
+/// Reads the payload from file or from stdin if path is `-`
+fn read_payload(path: &str) -> Result<String, String> {
+    let mut payload = String::new();
+    let result = match path {
+        "-" => io::stdin().read_to_string(&mut payload).map(|_| ()),
+        _ => fs::read_to_string(path).map(|p| payload = p),
+    };
+    match result {
+        Ok(_) => Ok(payload),
+        Err(e) => Err(format!("Cannot read the payload from '{}': {}", path, e)),
+    }
+}
+
+/// Parses the `key=value` pairs
+fn parse_key_values(pairs: &[String], kind: &str) -> Result<HashMap<String, String>, String> {
+    pairs.iter()
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => Ok((k.to_string(), v.to_string())),
+            None => Err(format!("Invalid {}: '{}'. Expected format: key=value", kind, p)),
+        })
+        .collect()
+}
+
+fn get_temp_path(suffix: &str) -> String {
+    env::temp_dir()
+        .join(format!("torustiq_eval_{}_{}.jsonl", process::id(), suffix))
+        .to_string_lossy().to_string()
+}
@@ -0,0 +1,246 @@
+/// A real-time stream of pipeline events for external orchestrators.
+/// If `--events-addr` is set, the host serves `GET /events` as Server-Sent Events. Each event is a JSON object
+/// with `event`, `pipeline` and `time` fields plus event-specific fields, e.g. `step` and `message`.
+/// Events: `pipeline_started`, `pipeline_terminated`, `pipeline_failure`, `step_terminated`, `step_stalled`,
+/// `runtime_exceeded`, `record_error`, `profile_completed`, `lease_acquired` and `lease_lost`. Record errors follow the error log sampling of step, see `pipeline::error_log`.
+/// Clients which don't read the events fast enough are disconnected and should reconnect.
+/// The endpoint has no authentication, so it should listen on a local or otherwise protected address.
+/// The address is either a TCP address, e.g. `127.0.0.1:9300` or `[::1]:9300` for IPv6, or a path to unix domain socket
+/// prefixed with `unix://`, e.g. `unix:///run/torustiq.sock`, so the access is controlled by file permissions.
+/// A socket file left by a crashed instance is replaced, but a socket in use by another process is not
+
+#[cfg(unix)]
+use std::{fs, os::unix::{fs::FileTypeExt, net::{UnixListener, UnixStream}}};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{atomic::{AtomicU64, Ordering}, mpsc::{sync_channel, RecvTimeoutError, SyncSender, TrySendError}, Mutex, RwLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use crate::masking::mask_text;
+
+/// A path of event stream
+const EVENTS_PATH: &str = "/events";
+
+/// A prefix of unix domain socket addresses
+const UNIX_SOCKET_PREFIX: &str = "unix://";
+
+/// Maximum number of events waiting to be sent to a client
+const CLIENT_BUFFER_SIZE: usize = 1000;
+
+/// A comment is sent to idle clients, so proxies don't close the connection
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Clients which don't send the request within this time are disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A delay after failure to accept a client, e.g. if the process is out of file descriptors
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(500);
+
+/// Senders of events to connected clients
+static SUBSCRIBERS: Lazy<Mutex<Vec<SyncSender<String>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// A name of the running pipeline, which is added to each event
+static PIPELINE_NAME: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(String::new()));
+
+static NEXT_EVENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Starts a thread which serves the event stream on the address
+pub fn init_event_stream(addr: &str) -> Result<(), String> {
+    match addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+        Some(path) => listen_unix_socket(path),
+        None => listen_tcp(addr),
+    }
+}
+
+fn listen_tcp(addr: &str) -> Result<(), String> {
+    if addr.matches(':').count() > 1 && !addr.starts_with('[') {
+        return Err(format!("Invalid event stream address '{}': IPv6 address must be enclosed in square brackets, e.g. '[::1]:9300'", addr))
+    }
+    let listener = match TcpListener::bind(addr) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Cannot listen for event stream clients on '{}': {}", addr, e)),
+    };
+    // The local address has the actual port if port 0 is requested, and IPv6 address in brackets
+    let local_addr = listener.local_addr().map(|a| a.to_string()).unwrap_or(addr.to_string());
+    info!("Event stream is available at http://{}{}", local_addr, EVENTS_PATH);
+    start_accept_thread(move || listener.accept().map(|(s, a)| (s, a.to_string())))
+}
+
+#[cfg(unix)]
+fn listen_unix_socket(path: &str) -> Result<(), String> {
+    let is_socket = fs::metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false);
+    if is_socket {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("Cannot listen for event stream clients on socket '{}': the socket is in use by another process", path))
+        }
+        if let Err(e) = fs::remove_file(path) {
+            return Err(format!("Cannot remove the stale socket '{}': {}", path, e))
+        }
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Cannot listen for event stream clients on socket '{}': {}", path, e)),
+    };
+    info!("Event stream is available at socket '{}', path {}", path, EVENTS_PATH);
+    let peer = format!("{}{}", UNIX_SOCKET_PREFIX, path);
+    start_accept_thread(move || listener.accept().map(|(s, _)| (s, peer.clone())))
+}
+
+#[cfg(not(unix))]
+fn listen_unix_socket(path: &str) -> Result<(), String> {
+    Err(format!("Cannot listen for event stream clients on socket '{}': unix domain sockets are not supported on this platform", path))
+}
+
+/// Starts a thread which accepts the clients and serves each client in a dedicated thread.
+/// Accept function returns a stream and a peer address for logs
+fn start_accept_thread<S, F>(mut accept: F) -> Result<(), String>
+where
+    S: ClientStream + Send + 'static,
+    for<'a> &'a S: Read,
+    F: FnMut() -> io::Result<(S, String)> + Send + 'static,
+{
+    let result = thread::Builder::new().name(String::from("events")).spawn(move || loop {
+        let (stream, peer) = match accept() {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to accept an event stream client: {}", e);
+                thread::sleep(ACCEPT_ERROR_DELAY);
+                continue
+            },
+        };
+        if let Err(e) = thread::Builder::new().name(String::from("events-client")).spawn(move || handle_client(stream, peer)) {
+            warn!("Failed to start a thread of event stream client: {}", e);
+        }
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to start the event stream thread: {}", e)),
+    }
+}
+
+/// Sets the name of pipeline which is added to events
+pub fn set_pipeline_name(name: &str) {
+    *PIPELINE_NAME.write().unwrap() = name.to_string();
+}
+
+/// Sends the event to all connected clients. Fields are added to the event. Secret values are masked
+pub fn publish(event: &str, fields: Value) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return
+    }
+    let mut body = json!({
+        "event": event,
+        "pipeline": *PIPELINE_NAME.read().unwrap(),
+        "time": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+    });
+    if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), fields) {
+        body.extend(fields);
+    }
+    let message = format!("id: {}\nevent: {}\ndata: {}\n\n",
+        NEXT_EVENT_ID.fetch_add(1, Ordering::Relaxed), event, mask_text(&body.to_string()));
+    subscribers.retain(|s| match s.try_send(message.clone()) {
+        Ok(_) => true,
+        Err(TrySendError::Full(_)) => {
+            warn!("An event stream client is disconnected, as it doesn't read the events");
+            false
+        },
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
+
+/// Reads the request of client and streams the events until the client disconnects
+fn handle_client<S>(mut stream: S, peer: String)
+where
+    S: ClientStream,
+    for<'a> &'a S: Read,
+{
+    // Idle connections which never send the request must not hold the thread
+    if let Err(e) = stream.set_read_timeout(Some(REQUEST_TIMEOUT)) {
+        debug!("Cannot set the read timeout of event stream client {}: {}", peer, e);
+        return
+    }
+    let request_line = match read_request_head(&stream) {
+        Ok(l) => l,
+        Err(e) => {
+            debug!("Cannot read the request of event stream client {}: {}", peer, e);
+            return
+        },
+    };
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default();
+    let error_status = match (method, path) {
+        ("GET", EVENTS_PATH) => None,
+        (_, EVENTS_PATH) => Some("405 Method Not Allowed"),
+        _ => Some("404 Not Found"),
+    };
+    if let Some(status) = error_status {
+        let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+        return
+    }
+    let header = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if stream.write_all(header.as_bytes()).and_then(|_| stream.flush()).is_err() {
+        return
+    }
+    let (tx, rx) = sync_channel::<String>(CLIENT_BUFFER_SIZE);
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    debug!("Event stream client {} is connected", peer);
+    loop {
+        let message = match rx.recv_timeout(KEEP_ALIVE_INTERVAL) {
+            Ok(m) => m,
+            Err(RecvTimeoutError::Timeout) => String::from(": keep-alive\n\n"),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if stream.write_all(message.as_bytes()).and_then(|_| stream.flush()).is_err() {
+            break
+        }
+    }
+    // The sender is removed from subscribers on the next event, as the receiver is dropped
+    debug!("Event stream client {} is disconnected", peer);
+}
+
+/// A connection of event stream client
+trait ClientStream: Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ClientStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ClientStream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Reads the request line and skips the headers of HTTP request
+fn read_request_head<S>(stream: &S) -> Result<String, String>
+where
+    for<'a> &'a S: Read,
+{
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(|e| e.to_string())?;
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) if line.trim().is_empty() => break,
+            Ok(_) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(request_line.trim().to_string())
+}
@@ -0,0 +1,29 @@
+/// Example configurations of modules.
+/// Modules provide ready-to-paste step or listener definitions with documented arguments,
+/// so users don't need to look for documentation elsewhere
+
+use crate::{
+    cli::{CliArgs, ExampleArgs},
+    modules::{module_loader::load_libraries, native::{create_native_module, is_native_module}},
+};
+
+/// Returns an example configuration of module
+pub fn get_example(args: &CliArgs, example_args: &ExampleArgs) -> Result<String, String> {
+    let handler = &example_args.module;
+    let example = match is_native_module(handler) {
+        true => create_native_module(handler)?.get_example(),
+        false => {
+            // Libraries are loaded, but not initialized: the example is static information
+            let loaded_libs = load_libraries(&args.module_dir, vec![handler.clone()])?;
+            match (loaded_libs.pipeline.get(handler), loaded_libs.listeners.get(handler)) {
+                (Some(m), _) => m.base.get_example(),
+                (_, Some(m)) => m.base.get_example(),
+                (None, None) => return Err(format!("Module not found: {}", handler)),
+            }
+        },
+    };
+    match example {
+        Some(e) => Ok(e.trim_end().to_string()),
+        None => Err(format!("Module '{}' doesn't provide an example configuration", handler)),
+    }
+}
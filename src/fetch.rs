@@ -0,0 +1,344 @@
+/// Download of module libraries.
+/// Libraries are listed in `modules` section of pipeline definition. Supported sources:
+/// - `https://` (or `http://`) URL. A checksum is mandatory for these sources
+/// - `oci://registry/repository:tag` or `oci://registry/repository@sha256:<digest>`. The artifact must contain
+///   the library as a single layer, e.g. pushed with `oras push`. The layer digest is always verified
+///
+/// Libraries which already exist in module directory and match the checksum are not downloaded again.
+
+use std::{fs, io::Read, path::Path};
+
+use log::{debug, info};
+
+use crate::{
+    cli::CliArgs,
+    config::{ModuleSourceDefinition, PipelineDefinition},
+    modules::builtin::hash::HashAlgorithm,
+    network::{get_agent, init_network},
+};
+
+const OCI_PREFIX: &str = "oci://";
+const OCI_MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json";
+/// An annotation which contains the original file name in artifacts pushed with ORAS
+const OCI_TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// Downloads all libraries listed in pipeline definition into the module directory
+pub fn fetch_modules(args: &CliArgs) -> Result<(), String> {
+    let pipeline_def = PipelineDefinition::from_file(&args.pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)?;
+    init_network(pipeline_def.network.as_ref())?;
+    let sources = pipeline_def.modules.unwrap_or_default();
+    if sources.is_empty() {
+        info!("No module sources are defined in pipeline file");
+        return Ok(())
+    }
+    if let Err(e) = fs::create_dir_all(&args.module_dir) {
+        return Err(format!("Cannot create the module directory '{}': {}", args.module_dir, e))
+    }
+    for source in &sources {
+        if let Err(e) = fetch_module(&args.module_dir, source) {
+            return Err(format!("Failed to fetch module from '{}': {}", source.source, e))
+        }
+    }
+    info!("Fetched {} module(s)", sources.len());
+    Ok(())
+}
+
+/// Downloads a single library
+fn fetch_module(module_dir: &String, source: &ModuleSourceDefinition) -> Result<(), String> {
+    let checksum = match &source.checksum {
+        Some(c) => Some(parse_checksum(c)?),
+        None => None,
+    };
+    if let Some(reference) = source.source.strip_prefix(OCI_PREFIX) {
+        return fetch_oci_module(module_dir, reference, source.file.clone(), checksum)
+    }
+    if !source.source.starts_with("https://") && !source.source.starts_with("http://") {
+        return Err(String::from("Unsupported source. Expected an HTTP(S) URL or OCI reference"))
+    }
+    let checksum = match checksum {
+        Some(c) => c,
+        None => return Err(String::from("Checksum is mandatory for HTTP sources")),
+    };
+    let file_name = match &source.file {
+        Some(f) => f.clone(),
+        None => file_name_from_url(&source.source)?,
+    };
+    let path = Path::new(module_dir).join(&file_name);
+    if is_up_to_date(&path, &checksum) {
+        info!("Module '{}' is up to date", file_name);
+        return Ok(())
+    }
+    info!("Downloading '{}'...", source.source);
+    let contents = download(http_get(&source.source, None, None)?)?;
+    verify_checksum(&contents, &checksum)?;
+    write_library(&path, &contents)
+}
+
+/// Downloads a library from OCI registry
+fn fetch_oci_module(module_dir: &String, reference: &str, file_name: Option<String>, checksum: Option<String>) -> Result<(), String> {
+    let reference = OciReference::parse(reference)?;
+    let mut client = OciClient::new(&reference);
+    let manifest = client.get_manifest(&reference.reference)?;
+    // Multi-platform artifacts: pick the manifest for current platform
+    let manifest = match manifest.get("manifests") {
+        Some(manifests) => {
+            let digest = select_platform_manifest(manifests)?;
+            client.get_manifest(&digest)?
+        },
+        None => manifest,
+    };
+    let layer = match manifest.get("layers").and_then(|l| l.as_array()) {
+        Some(layers) if layers.len() == 1 => &layers[0],
+        Some(layers) => return Err(format!("Expected an artifact with a single layer, found {} layers", layers.len())),
+        None => return Err(String::from("The manifest has no layers")),
+    };
+    let digest = match layer.get("digest").and_then(|d| d.as_str()) {
+        Some(d) => parse_checksum(d)?,
+        None => return Err(String::from("The layer has no digest")),
+    };
+    if let Some(checksum) = &checksum {
+        if checksum != &digest {
+            return Err(format!("The layer digest 'sha256:{}' doesn't match the expected checksum 'sha256:{}'", digest, checksum))
+        }
+    }
+    let file_name = match file_name {
+        Some(f) => f,
+        None => match layer.pointer(&format!("/annotations/{}", OCI_TITLE_ANNOTATION.replace('/', "~1"))).and_then(|t| t.as_str()) {
+            Some(t) => t.to_string(),
+            None => format!("{}.{}", reference.get_name(), std::env::consts::DLL_EXTENSION),
+        },
+    };
+    let path = Path::new(module_dir).join(&file_name);
+    if is_up_to_date(&path, &digest) {
+        info!("Module '{}' is up to date", file_name);
+        return Ok(())
+    }
+    info!("Downloading '{}{}'...", OCI_PREFIX, reference);
+    let contents = client.get_blob(&format!("sha256:{}", digest))?;
+    verify_checksum(&contents, &digest)?;
+    write_library(&path, &contents)
+}
+
+/// Returns a digest of manifest which matches the current OS and architecture
+fn select_platform_manifest(manifests: &serde_json::Value) -> Result<String, String> {
+    let os = std::env::consts::OS;
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        a => a,
+    };
+    let manifests = match manifests.as_array() {
+        Some(m) => m,
+        None => return Err(String::from("Invalid list of manifests")),
+    };
+    for m in manifests {
+        let platform = m.get("platform");
+        let m_os = platform.and_then(|p| p.get("os")).and_then(|o| o.as_str());
+        let m_arch = platform.and_then(|p| p.get("architecture")).and_then(|a| a.as_str());
+        if m_os == Some(os) && m_arch == Some(arch) {
+            if let Some(digest) = m.get("digest").and_then(|d| d.as_str()) {
+                return Ok(digest.to_string())
+            }
+        }
+    }
+    Err(format!("The artifact has no manifest for platform {}/{}", os, arch))
+}
+
+/// A reference to OCI artifact: registry/repository:tag or registry/repository@digest
+struct OciReference {
+    registry: String,
+    repository: String,
+    /// Tag or digest
+    reference: String,
+}
+
+impl OciReference {
+    fn parse(s: &str) -> Result<OciReference, String> {
+        let (registry, rest) = match s.split_once('/') {
+            Some(r) => r,
+            None => return Err(format!("Invalid OCI reference '{}': expected registry/repository:tag", s)),
+        };
+        let (repository, reference) = if let Some((repo, digest)) = rest.split_once('@') {
+            (repo, digest)
+        } else {
+            match rest.rsplit_once(':') {
+                Some((repo, tag)) => (repo, tag),
+                None => (rest, "latest"),
+            }
+        };
+        Ok(OciReference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+
+    /// Returns the last segment of repository
+    fn get_name(&self) -> &str {
+        self.repository.rsplit('/').next().unwrap_or(&self.repository)
+    }
+}
+
+impl std::fmt::Display for OciReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reference.starts_with("sha256:") {
+            true => write!(f, "{}/{}@{}", self.registry, self.repository, self.reference),
+            false => write!(f, "{}/{}:{}", self.registry, self.repository, self.reference),
+        }
+    }
+}
+
+/// A client of OCI distribution API. Supports anonymous bearer tokens
+struct OciClient {
+    base_url: String,
+    token: Option<String>,
+}
+
+impl OciClient {
+    fn new(reference: &OciReference) -> OciClient {
+        OciClient {
+            base_url: format!("https://{}/v2/{}", reference.registry, reference.repository),
+            token: None,
+        }
+    }
+
+    fn get_manifest(&mut self, reference: &str) -> Result<serde_json::Value, String> {
+        let response = self.get(&format!("{}/manifests/{}", self.base_url, reference), Some(OCI_MANIFEST_MEDIA_TYPES))?;
+        match serde_json::from_reader(response.into_reader()) {
+            Ok(m) => Ok(m),
+            Err(e) => Err(format!("Cannot parse the manifest: {}", e)),
+        }
+    }
+
+    fn get_blob(&mut self, digest: &str) -> Result<Vec<u8>, String> {
+        let response = self.get(&format!("{}/blobs/{}", self.base_url, digest), None)?;
+        download(response)
+    }
+
+    /// Sends a GET request. Requests an anonymous token if registry requires it
+    fn get(&mut self, url: &str, accept: Option<&str>) -> Result<ureq::Response, String> {
+        match ureq_get(url, accept, self.token.as_deref()).map_err(|e| *e) {
+            Err(ureq::Error::Status(401, response)) if self.token.is_none() => {
+                let challenge = match response.header("WWW-Authenticate") {
+                    Some(c) => c.to_string(),
+                    None => return Err(String::from("Registry requires authentication")),
+                };
+                self.token = Some(request_token(&challenge)?);
+                http_get(url, accept, self.token.as_deref())
+            },
+            r => r.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Requests an anonymous token according to `WWW-Authenticate: Bearer realm=...,service=...,scope=...` challenge
+fn request_token(challenge: &str) -> Result<String, String> {
+    let params = match challenge.strip_prefix("Bearer ") {
+        Some(p) => p,
+        None => return Err(format!("Unsupported authentication challenge: {}", challenge)),
+    };
+    let mut realm: Option<String> = None;
+    let mut query: Vec<(String, String)> = Vec::new();
+    for param in params.split(',') {
+        if let Some((k, v)) = param.trim().split_once('=') {
+            let v = v.trim_matches('"').to_string();
+            match k {
+                "realm" => realm = Some(v),
+                _ => query.push((k.to_string(), v)),
+            }
+        }
+    }
+    let realm = match realm {
+        Some(r) => r,
+        None => return Err(format!("No realm in authentication challenge: {}", challenge)),
+    };
+    let mut request = get_agent(&realm).get(&realm);
+    for (k, v) in &query {
+        request = request.query(k, v);
+    }
+    let response: serde_json::Value = match request.call() {
+        Ok(r) => match serde_json::from_reader(r.into_reader()) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Cannot parse the token response: {}", e)),
+        },
+        Err(e) => return Err(format!("Cannot request a registry token: {}", e)),
+    };
+    match response.get("token").or(response.get("access_token")).and_then(|t| t.as_str()) {
+        Some(t) => Ok(t.to_string()),
+        None => Err(String::from("The token response contains no token")),
+    }
+}
+
+/// The error is boxed, as it contains the whole response
+fn ureq_get(url: &str, accept: Option<&str>, token: Option<&str>) -> Result<ureq::Response, Box<ureq::Error>> {
+    debug!("GET {}", url);
+    let mut request = get_agent(url).get(url);
+    if let Some(accept) = accept {
+        request = request.set("Accept", accept);
+    }
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    request.call().map_err(Box::new)
+}
+
+fn http_get(url: &str, accept: Option<&str>, token: Option<&str>) -> Result<ureq::Response, String> {
+    ureq_get(url, accept, token).map_err(|e| e.to_string())
+}
+
+/// Reads the response body
+fn download(response: ureq::Response) -> Result<Vec<u8>, String> {
+    let mut contents: Vec<u8> = Vec::new();
+    match response.into_reader().read_to_end(&mut contents) {
+        Ok(_) => Ok(contents),
+        Err(e) => Err(format!("Failed to download: {}", e)),
+    }
+}
+
+/// Parses a checksum in `sha256:<hex>` format. Returns the hex part
+fn parse_checksum(checksum: &str) -> Result<String, String> {
+    match checksum.strip_prefix("sha256:") {
+        Some(h) if h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()) => Ok(h.to_lowercase()),
+        _ => Err(format!("Invalid checksum '{}'. Expected format: sha256:<64 hex digits>", checksum)),
+    }
+}
+
+fn verify_checksum(contents: &[u8], expected: &str) -> Result<(), String> {
+    let actual = HashAlgorithm::Sha256.hash(contents);
+    match actual == expected {
+        true => Ok(()),
+        false => Err(format!("Checksum mismatch: expected sha256:{}, got sha256:{}", expected, actual)),
+    }
+}
+
+/// Returns true if the library exists and matches the checksum
+fn is_up_to_date(path: &Path, checksum: &str) -> bool {
+    match fs::read(path) {
+        Ok(contents) => verify_checksum(&contents, checksum).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn file_name_from_url(url: &str) -> Result<String, String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => Ok(name.to_string()),
+        _ => Err(format!("Cannot derive a file name from URL '{}'. Please set the file name explicitly", url)),
+    }
+}
+
+/// Writes the library into a temporary file first, so the module directory never contains a partial library
+fn write_library(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let tmp_path = path.with_file_name(format!("{}.part", path.file_name().unwrap_or_default().to_string_lossy()));
+    if let Err(e) = fs::write(&tmp_path, contents) {
+        return Err(format!("Cannot write the file '{}': {}", tmp_path.display(), e))
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        return Err(format!("Cannot move the file '{}' to '{}': {}", tmp_path.display(), path.display(), e))
+    }
+    info!("Saved module library '{}'", path.display());
+    Ok(())
+}
@@ -0,0 +1,104 @@
+/// Protection against duplicate starts of pipeline.
+/// Each pipeline locks a file named after the pipeline in the lock directory, so two copies of the same pipeline
+/// cannot run concurrently against the same checkpoints or dead letter queue. Different pipelines share the directory.
+/// The lock is advisory and is released by the operating system once the process exits, so no stale locks are left.
+/// The lock file contains the details of holder which are reported to the second instance
+
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::debug;
+use serde_json::json;
+
+use crate::errors::TorustiqError;
+
+const LOCK_FILE_EXTENSION: &str = "lock";
+
+/// A lock of pipeline instance. The lock is held until the value is dropped
+pub struct InstanceLock {
+    path: PathBuf,
+    // Keeps the lock
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Acquires the lock of pipeline in the lock directory. Fails if another process holds the lock
+    pub fn acquire(lock_dir: &str, pipeline_name: &str) -> Result<InstanceLock, TorustiqError> {
+        if let Err(e) = fs::create_dir_all(lock_dir) {
+            return Err(TorustiqError::InstanceLocked(format!("Cannot create the instance lock directory '{}': {}", lock_dir, e)))
+        }
+        let path = get_lock_file_path(lock_dir, pipeline_name);
+        // The file is not truncated before the lock is acquired, as it contains the details of the current holder
+        let mut file = match OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path) {
+            Ok(f) => f,
+            Err(e) => return Err(TorustiqError::InstanceLocked(format!("Cannot open the instance lock file '{}': {}", path.display(), e))),
+        };
+        match file.try_lock() {
+            Ok(_) => {},
+            Err(TryLockError::WouldBlock) => return Err(TorustiqError::InstanceLocked(format!(
+                "Pipeline '{}' is already running: the instance lock '{}' is held by {}. \
+                Please stop the other instance or use another lock directory",
+                pipeline_name, path.display(), get_holder(&path)))),
+            Err(TryLockError::Error(e)) => return Err(TorustiqError::InstanceLocked(format!(
+                "Cannot acquire the instance lock '{}': {}", path.display(), e))),
+        }
+        let holder = json!({
+            "pid": std::process::id(),
+            "host": get_host_name(),
+            "started_at": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        });
+        let write_result = file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| writeln!(file, "{}", holder));
+        if let Err(e) = write_result {
+            return Err(TorustiqError::InstanceLocked(format!("Cannot write the instance lock file '{}': {}", path.display(), e)))
+        }
+        debug!("Acquired the instance lock '{}'", path.display());
+        Ok(InstanceLock { path, _file: file })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        debug!("Released the instance lock '{}'", self.path.display());
+    }
+}
+
+/// Returns the path to lock file. Characters which are not safe in file names are replaced
+fn get_lock_file_path(lock_dir: &str, pipeline_name: &str) -> PathBuf {
+    let file_name: String = pipeline_name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    Path::new(lock_dir).join(format!("{}.{}", file_name, LOCK_FILE_EXTENSION))
+}
+
+/// Returns a description of lock holder from lock file
+fn get_holder(path: &Path) -> String {
+    let holder: Option<serde_json::Value> = fs::read_to_string(path).ok()
+        .and_then(|c| serde_json::from_str(&c).ok());
+    match holder {
+        Some(h) => format!("process {} on host '{}' (started at {})",
+            h["pid"], h["host"].as_str().unwrap_or("unknown"), h["started_at"].as_str().unwrap_or("unknown")),
+        None => String::from("another process"),
+    }
+}
+
+/// Returns the name of host which runs the application
+#[cfg(unix)]
+pub fn get_host_name() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return String::from("unknown")
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).to_string()
+}
+
+#[cfg(not(unix))]
+pub fn get_host_name() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("unknown"))
+}
@@ -0,0 +1,123 @@
+/// Emission of OpenLineage run events. Each run of pipeline is reported as a run of job named after the pipeline:
+/// `START` once the pipeline is created, then `COMPLETE` or `FAIL`.
+/// Datasets are identified by module ID (namespace) and a value of step argument (name), e.g. `kafka_source` and topic.
+/// Failures to send events are logged, as lineage must not affect the pipeline
+
+use std::time::{Duration, SystemTime};
+
+use log::{debug, error};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    config::LineageDefinition,
+    masking::mask_text,
+    network::get_agent,
+    pipeline::{pipeline::Pipeline, pipeline_step::PipelineStep},
+};
+
+const DEFAULT_NAMESPACE: &str = "torustiq";
+const DEFAULT_DATASET_ARGS: &[&str] = &["topic", "table", "path", "file", "bucket", "queue", "stream", "collection", "index", "url"];
+const PRODUCER: &str = "https://github.com/ignytis/torustiq-cli";
+const RUN_EVENT_SCHEMA_URL: &str = "https://openlineage.io/spec/2-0-2/OpenLineage.json#/definitions/RunEvent";
+const ERROR_MESSAGE_FACET_SCHEMA_URL: &str = "https://openlineage.io/spec/facets/1-0-1/ErrorMessageRunFacet.json";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A type of run event
+#[derive(Clone, Copy, PartialEq)]
+pub enum RunState {
+    Start,
+    Complete,
+    Fail,
+}
+
+impl RunState {
+    fn get_name(&self) -> &'static str {
+        match self {
+            RunState::Start => "START",
+            RunState::Complete => "COMPLETE",
+            RunState::Fail => "FAIL",
+        }
+    }
+}
+
+/// A run of pipeline which is reported to lineage endpoint
+pub struct LineageRun {
+    definition: LineageDefinition,
+    run_id: Uuid,
+    job_name: String,
+    inputs: Vec<Value>,
+    outputs: Vec<Value>,
+}
+
+impl LineageRun {
+    pub fn new(definition: &LineageDefinition, pipeline_name: &str) -> LineageRun {
+        LineageRun {
+            definition: definition.clone(),
+            run_id: Uuid::new_v4(),
+            job_name: pipeline_name.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Reports the run under the run ID of pipeline, so lineage events and listeners refer to the same run
+    pub fn set_run_id(&mut self, run_id: Uuid) {
+        self.run_id = run_id;
+    }
+
+    /// Derives the datasets from source and destinations of primary chain
+    pub fn set_datasets(&mut self, pipeline: &Pipeline) {
+        let dataset_args: Vec<String> = match &self.definition.dataset_args {
+            Some(a) => a.clone(),
+            None => DEFAULT_DATASET_ARGS.iter().map(|a| a.to_string()).collect(),
+        };
+        let get_dataset = |step: &PipelineStep| -> Option<Value> {
+            let name = dataset_args.iter().find_map(|a| step.component.args.get(a))?;
+            Some(json!({"namespace": step.module.get_id(), "name": mask_text(name)}))
+        };
+        self.inputs = pipeline.steps.first().and_then(|s| get_dataset(&s.lock().unwrap())).into_iter().collect();
+        self.outputs = (1..pipeline.steps.len())
+            .filter(|i| pipeline.topology.is_destination(*i))
+            .map(|i| pipeline.steps[i].lock().unwrap())
+            .filter(|s| !s.is_shadow)
+            .filter_map(|s| get_dataset(&s))
+            .collect();
+    }
+
+    /// Sends a run event. The error message is reported for failed runs only
+    pub fn emit(&self, state: RunState, error_message: Option<&str>) {
+        let mut run = json!({"runId": self.run_id.to_string()});
+        if let (RunState::Fail, Some(message)) = (state, error_message) {
+            run["facets"] = json!({"errorMessage": {
+                "_producer": PRODUCER,
+                "_schemaURL": ERROR_MESSAGE_FACET_SCHEMA_URL,
+                "message": mask_text(message),
+                "programmingLanguage": "Rust",
+            }});
+        }
+        let event = json!({
+            "eventType": state.get_name(),
+            "eventTime": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            "run": run,
+            "job": {
+                "namespace": self.definition.namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE),
+                "name": self.job_name,
+            },
+            "inputs": self.inputs,
+            "outputs": self.outputs,
+            "producer": PRODUCER,
+            "schemaURL": RUN_EVENT_SCHEMA_URL,
+        });
+        let mut request = get_agent(&self.definition.url).post(&self.definition.url)
+            .timeout(REQUEST_TIMEOUT)
+            .set("Content-Type", "application/json");
+        if let Some(key) = &self.definition.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+        match request.send_string(&event.to_string()) {
+            Ok(_) => debug!("Lineage event '{}' of run {} is sent", state.get_name(), self.run_id),
+            Err(e) => error!("Failed to send lineage event '{}' to '{}': {}", state.get_name(), self.definition.url, e),
+        }
+    }
+}
@@ -1,106 +1,254 @@
 pub mod callbacks;
 pub mod cli;
 pub mod config;
+pub mod daemon;
+pub mod encryption;
+pub mod errors;
+pub mod eval;
+pub mod events;
+pub mod example;
+pub mod fetch;
+pub mod instance_lock;
+pub mod lineage;
+pub mod masking;
+pub mod metrics;
+pub mod migrate;
 pub mod modules;
+pub mod network;
+pub mod notifications;
+pub mod params;
 pub mod pipeline;
+pub mod policy;
+pub mod profiles;
+pub mod profiling;
+pub mod records;
+pub mod reload;
+pub mod repl;
+pub mod runner;
 pub mod shutdown;
+pub mod signals;
+pub mod simulation;
+pub mod testing;
 pub mod xthread;
 
-use std::{
-    fs, process::exit, sync::{Arc, Mutex}, thread, time
-};
+use std::{io, process::exit};
 
-use libloading::Library;
 use log::{debug, error, info};
 
 use shutdown::init_signal_handler;
+use signals::{init_log_level_toggle, init_stats_dump};
 use torustiq_common::logging::init_logger;
-use xthread::PIPELINE;
 
 use crate::{
-    cli::CliArgs,
-    config::PipelineDefinition,
-    modules::module_loader::load_libraries,
-    pipeline::pipeline::Pipeline
+    cli::{CliArgs, Command, EncryptValueArgs},
+    config::{NotificationEvent, PipelineDefinition},
+    errors::{write_error_report, TorustiqError},
+    instance_lock::InstanceLock,
+    lineage::{LineageRun, RunState},
+    runner::{create_pipeline, run_pipeline, validate_pipeline_definition},
 };
 
-/// Creates a pipeline from pipeline definition file
-fn create_pipeline(args: &CliArgs) -> Result<(Pipeline, Vec<Library>), String> {
+/// Runs the pipeline from pipeline definition file
+fn run(args: &CliArgs) -> Result<(), TorustiqError> {
     debug!("Creating a pipeline from definition file: {}", &args.pipeline_file);
-    let pipeline_def: String = match fs::read_to_string(&args.pipeline_file) {
-        Ok(c) => c,
-        Err(e) => return Err(format!("Cannot open the pipeline file: '{}'. {}", args.pipeline_file, e)),
-    };
-    let pipeline_def: PipelineDefinition = match serde_yaml::from_str(pipeline_def.as_str()) {
-        Ok(c) => c,
-        Err(e) => return Err(format!("Cannot parse the pipeline: '{}'. {}", args.pipeline_file, e)),
+    let pipeline_def = PipelineDefinition::from_file(&args.pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)?;
+    network::init_network(pipeline_def.network.as_ref()).map_err(TorustiqError::Config)?;
+    // The lock is released once the pipeline is stopped, so the restarted application acquires it again
+    let _instance_lock = match &args.instance_lock {
+        Some(dir) => Some(InstanceLock::acquire(dir, &pipeline_def.get_name())?),
+        None => None,
     };
+    let mut lineage = pipeline_def.lineage.as_ref().map(|l| LineageRun::new(l, &pipeline_def.get_name()));
+    let result = create_pipeline(args, &pipeline_def).and_then(|(pipeline, _loaded_libs)| {
+        if let Some(l) = lineage.as_mut() {
+            l.set_run_id(pipeline.run_id);
+            l.set_datasets(&pipeline);
+            l.emit(RunState::Start, None);
+        }
+        run_pipeline(pipeline)
+    });
+    if let Some(l) = &lineage {
+        match &result {
+            Ok(_) => l.emit(RunState::Complete, None),
+            Err(e) => l.emit(RunState::Fail, Some(&format!("[{}] {}", e.get_code(), e))),
+        }
+    }
+    if let Err(e) = &result {
+        let message = format!("[{}] {}", e.get_code(), e);
+        let notifications = pipeline_def.notifications.as_deref().unwrap_or_default();
+        notifications::notify(notifications, NotificationEvent::PipelineFailure, &pipeline_def.get_name(), &message);
+    }
+    result
+}
 
-    let module_ids_required = pipeline_def.get_module_ids_in_use();
-    let loaded_libs: modules::module_loader::LoadedLibraries = load_libraries(&args.module_dir, module_ids_required)?;
-    info!("All modules are loaded.");
-    loaded_libs.init();
+/// Checks if the pipeline can be created from pipeline file
+fn validate(args: &CliArgs) -> Result<(), TorustiqError> {
+    let pipeline_def = PipelineDefinition::from_file(&args.pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)?;
+    validate_pipeline_definition(args, &pipeline_def)
+}
 
-    let pipeline = match Pipeline::try_from((&pipeline_def, &loaded_libs)) {
-        Ok(p) => p,
-        Err(e) => return Err(format!("Failed to create a pipeline from definition: {}", e))
+/// Encrypts the value from arguments or stdin
+fn encrypt_value(args: &CliArgs, encrypt_args: &EncryptValueArgs) -> Result<String, String> {
+    let key_file = match &args.key_file {
+        Some(k) => k,
+        None => return Err(String::from("Please set the path to key file with '--key-file' option")),
+    };
+    let value = match &encrypt_args.value {
+        Some(v) => v.clone(),
+        None => {
+            let mut value = String::new();
+            if let Err(e) = io::stdin().read_line(&mut value) {
+                return Err(format!("Cannot read the value from stdin: {}", e))
+            }
+            value.trim_end_matches(['\r', '\n']).to_string()
+        },
     };
-    info!("Constructed a pipeline which contains {} steps", pipeline.steps.len());
-    let loaded_libs = loaded_libs.libs;
-    Ok((pipeline, loaded_libs))
+    encryption::encrypt_value(key_file, &value)
 }
 
 fn main() {
     init_logger();
     info!("Starting the application...");
-    if let Err(msg) = init_signal_handler() {
-        return crash_with_message(msg)
-    };
 
     let args = CliArgs::do_parse();
-    let (pipeline, _loaded_libs) = match create_pipeline(&args) {
-        Ok(p) => p,
-        Err(msg) => return crash_with_message(format!("Failed to create a pipeline: {}", msg))
+    let validation_result = match &args.command {
+        Some(Command::Completions(_)) | Some(Command::EncryptValue(_)) => Ok(()),
+        // The module directory is created by command
+        Some(Command::FetchModules) | Some(Command::MigrateConfig(_)) => args.validate_paths(true, false),
+        Some(Command::Example(_)) | Some(Command::Eval(_)) => args.validate_paths(false, true),
+        _ => args.validate_paths(true, true),
     };
-    if pipeline.description.is_some() {
-        debug!("Description of pipeline: {}", pipeline.description.clone().unwrap());
+    if let Err(msg) = validation_result {
+        return crash_with_message(msg)
     }
-    
-    let pipeline_arc = Arc::new(Mutex::new(pipeline));
-
-    if let Err(_) = PIPELINE.set(pipeline_arc.clone()) {
-        crash_with_message(format!("Failed to register the pipeline in static context"))
+    // Forking must happen before any thread is started
+    if let (true, None) = (args.daemonize, &args.command) {
+        if let Err(msg) = daemon::daemonize(&args.pid_file, args.log_file.as_ref()) {
+            return crash_with_message(format!("Failed to start as daemon: {}", msg))
+        }
     }
-
-    {
-        let mut pipeline = pipeline_arc.lock().unwrap();
-
-        if let Err(msg) = pipeline.configure_steps() {
-            return crash_with_message(format!("Cannot configure steps: {}", msg));
-        };
-
-        if let Err(msg) = pipeline.configure_listeners() {
-            return crash_with_message(format!("Cannot configure listeners: {}", msg));
-        };
-
-        if let Err(msg) = pipeline.start_senders_receivers() {
-            return crash_with_message(format!("Cannot start the sender and receiver channels: {}", msg));
-        };
-
-        if let Err(msg) = pipeline.start_steps() {
-            return crash_with_message(format!("Cannot start steps: {}", msg))
-        };
-    }
-
-    while pipeline_arc.lock().unwrap().is_running() {
-        thread::sleep(time::Duration::from_millis(100));
+    if let Err(msg) = modules::native::register_native_modules() {
+        return crash_with_message(msg)
+    };
+    if let Err(msg) = init_signal_handler() {
+        return crash_with_message(msg)
+    };
+    if let Err(msg) = init_log_level_toggle() {
+        return crash_with_message(msg)
+    };
+    if let Err(msg) = init_stats_dump() {
+        return crash_with_message(msg)
+    };
+    if let (Some(path), false) = (&args.key_file, matches!(args.command, Some(Command::EncryptValue(_)))) {
+        if let Err(msg) = encryption::init_encryption_key(path) {
+            return crash_with_message(msg)
+        }
     }
-    debug!("Exited from main loop");
+    match &args.command {
+        Some(Command::Completions(completions_args)) => CliArgs::print_completions(completions_args.shell),
+        Some(Command::Example(example_args)) => match example::get_example(&args, example_args) {
+            Ok(example) => println!("{}", example),
+            Err(msg) => return crash_with_message(msg),
+        },
+        Some(Command::EncryptValue(encrypt_args)) => match encrypt_value(&args, encrypt_args) {
+            Ok(value) => println!("!{} {}", encryption::ENCRYPTED_TAG, value),
+            Err(msg) => return crash_with_message(format!("Failed to encrypt the value: {}", msg)),
+        },
+        Some(Command::Test(test_args)) => match testing::run_test(&args, test_args) {
+            Ok(true) => info!("Test passed."),
+            Ok(false) => {
+                error!("Test failed.");
+                exit(1);
+            },
+            Err(msg) => return crash_with_message(format!("Failed to run the test: {}", msg)),
+        },
+        Some(Command::FetchModules) => if let Err(msg) = fetch::fetch_modules(&args) {
+            return crash_with_message(msg)
+        },
+        Some(Command::Validate) => match validate(&args) {
+            Ok(_) => info!("The pipeline definition is valid."),
+            Err(e) => return crash_with_message(format!("[{}] {}", e.get_code(), e)),
+        },
+        Some(Command::Eval(eval_args)) => match eval::run_eval(&args, eval_args) {
+            Ok(output) => println!("{}", output),
+            Err(msg) => return crash_with_message(format!("Failed to evaluate the step: {}", msg)),
+        },
+        Some(Command::MigrateConfig(migrate_args)) => match migrate::run_migrate_config(&args, migrate_args) {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => info!("The upgraded pipeline is written."),
+            Err(msg) => return crash_with_message(format!("Failed to migrate the pipeline file: {}", msg)),
+        },
+        Some(Command::Simulate(simulate_args)) => match simulation::run_simulation(&args, simulate_args) {
+            Ok(report) => println!("{}", report),
+            Err(msg) => return crash_with_message(format!("Failed to run the simulation: {}", msg)),
+        },
+        None => {
+            if args.watch_config {
+                if let Err(msg) = reload::init_config_watcher(&args) {
+                    return crash_with_message(msg)
+                }
+            }
+            if args.step_mode {
+                let _ = xthread::STEP_MODE.set(pipeline::step_mode::StepMode::new());
+                info!("Step-through mode is enabled. Type 'next [N]' to advance the records, 'continue' to release them");
+            }
+            if let Some(dir) = &args.profile_dir {
+                if let Err(msg) = profiling::init_profiling(dir) {
+                    return crash_with_message(msg)
+                }
+            }
+            if args.interactive || args.step_mode || args.profile_dir.is_some() {
+                repl::init_interactive_mode();
+            }
+            if let Some(path) = &args.metrics_file {
+                metrics::init_metrics_exporter(path.clone());
+            }
+            if let Some(addr) = &args.events_addr {
+                if let Err(msg) = events::init_event_stream(addr) {
+                    return crash_with_message(msg)
+                }
+            }
+            let result = run(&args);
+            if let Some(path) = &args.metrics_file {
+                if let Err(msg) = metrics::write_metrics_file(path) {
+                    error!("Failed to write metrics: {}", msg);
+                }
+            }
+            if pipeline::watchdog::is_runtime_exceeded() {
+                daemon::remove_pid_file();
+                error!("Application terminated: the maximum runtime is exceeded.");
+                exit(pipeline::watchdog::EXIT_CODE_RUNTIME_EXCEEDED);
+            }
+            if let Err(e) = result {
+                return crash_with_error(&args, e)
+            }
+            if reload::is_restart_requested() {
+                if let Err(msg) = reload::restart_application() {
+                    return crash_with_message(msg)
+                }
+            }
+        },
+    };
 
+    daemon::remove_pid_file();
     info!("Application terminated.");
 }
 
 fn crash_with_message(msg: String) {
-    error!("An error occurred. {}", msg);
+    error!("An error occurred. {}", masking::mask_text(&msg));
+    daemon::remove_pid_file();
     exit(-1);
-}
\ No newline at end of file
+}
+
+/// Logs the error, writes the error report if requested and exits with the exit code of error
+fn crash_with_error(args: &CliArgs, e: TorustiqError) {
+    let message = masking::mask_text(&e.to_string());
+    error!("An error occurred. [{}] {}", e.get_code(), message);
+    if let Some(path) = &args.error_report {
+        if let Err(msg) = write_error_report(path, &e, &message) {
+            error!("Failed to write the error report: {}", msg);
+        }
+    }
+    daemon::remove_pid_file();
+    exit(e.get_exit_code());
+}
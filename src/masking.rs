@@ -0,0 +1,88 @@
+/// Masking of secrets in diagnostics.
+/// Arguments whose keys match the masked key patterns are considered secrets. Their values are replaced
+/// in logs, REPL output, notifications and crash messages regardless of the format of message,
+/// because the masking is applied to known secret values rather than to specific syntax like `key=value`
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::xthread::{MASKED_KEY_PATTERNS, MASKED_VALUES};
+
+/// A replacement of secret values
+pub const MASK: &str = "******";
+
+/// Keys matching these patterns are always masked. `*` matches any sequence of characters
+pub const DEFAULT_MASKED_KEY_PATTERNS: [&str; 7] = [
+    "*password*", "*passwd*", "*secret*", "*token*", "*credential*", "*api_key*", "*private_key*",
+];
+
+/// Secret values shorter than this are not masked in text, as they would garble unrelated words
+const MIN_MASKED_VALUE_LEN: usize = 3;
+
+/// Returns true if text matches the pattern. Case-insensitive; `*` matches any sequence of characters
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Adds the patterns of masked keys in addition to the default ones
+pub fn add_masked_key_patterns(patterns: &[String]) {
+    MASKED_KEY_PATTERNS.write().unwrap().extend(patterns.iter().cloned());
+}
+
+/// Returns true if values of the key must be masked
+pub fn is_masked_key(key: &str) -> bool {
+    DEFAULT_MASKED_KEY_PATTERNS.iter().any(|p| matches_pattern(p, key))
+        || MASKED_KEY_PATTERNS.read().unwrap().iter().any(|p| matches_pattern(p, key))
+}
+
+/// Remembers the values of secret arguments, so they are masked in any text passed to `mask_text`
+pub fn register_secrets(args: &HashMap<String, String>) {
+    args.iter()
+        .filter(|(k, _)| is_masked_key(k))
+        .for_each(|(_, v)| register_secret_value(v));
+}
+
+/// Remembers the secret value regardless of argument key, e.g. a decrypted value
+pub fn register_secret_value(value: &str) {
+    if value.len() >= MIN_MASKED_VALUE_LEN {
+        MASKED_VALUES.write().unwrap().insert(value.to_string());
+    }
+}
+
+/// Returns a copy of arguments with masked secret values. Keys are sorted for stable output
+pub fn mask_args(args: &HashMap<String, String>) -> BTreeMap<String, String> {
+    args.iter()
+        .map(|(k, v)| match is_masked_key(k) {
+            true => (k.clone(), String::from(MASK)),
+            false => (k.clone(), v.clone()),
+        })
+        .collect()
+}
+
+/// Replaces the known secret values in text
+pub fn mask_text(text: &str) -> String {
+    let values = MASKED_VALUES.read().unwrap();
+    if values.is_empty() {
+        return text.to_string()
+    }
+    // Longer values first, so a secret which contains another secret is masked entirely
+    let mut values: Vec<&String> = values.iter().collect();
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    values.iter().fold(text.to_string(), |text, v| text.replace(v.as_str(), MASK))
+}
@@ -0,0 +1,280 @@
+/// Metrics export in Prometheus text format.
+/// Metrics are written to file which can be read by textfile collector of node exporter.
+/// The file is replaced atomically, so collectors never read a partially written file
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::{debug, error};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::{
+    masking::mask_text,
+    pipeline::{
+        drops::DropReason,
+        graph::get_graph,
+        handle::ModuleHandle,
+        latency::LATENCY_BUCKETS_MS,
+        persistent_stats::{get_lifetime_counters, PersistentCounters},
+        stats::StepStatistics,
+    },
+    xthread::{END_TO_END_LATENCY, PIPELINE, STEP_STATS},
+};
+
+/// How often the metrics file is updated
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Labels which are set by host and cannot be overridden by labels of steps
+const RESERVED_LABELS: &[&str] = &["pipeline", "step", "module", "le", "reason"];
+
+/// A prefix of label values which copy a step argument
+const ARGS_PREFIX: &str = "args.";
+
+/// Name, description and value getter of step counter
+type StepCounter = (&'static str, &'static str, fn(&StepStatistics) -> f64);
+
+/// Name, description and value getter of lifetime counter of step
+type LifetimeCounter = (&'static str, &'static str, fn(&PersistentCounters) -> u64);
+
+/// Load durations of module libraries by module ID
+static MODULE_LOAD_TIMES: Lazy<Mutex<Vec<(String, Duration)>>> = Lazy::new(|| {
+    Mutex::new(Vec::new())
+});
+
+/// Stores the duration of library loading
+pub fn record_module_load_time(module_id: &str, duration: Duration) {
+    MODULE_LOAD_TIMES.lock().unwrap().push((module_id.to_string(), duration));
+}
+
+/// Starts a thread which updates the metrics file periodically
+pub fn init_metrics_exporter(path: String) {
+    thread::spawn(move || loop {
+        thread::sleep(EXPORT_INTERVAL);
+        if let Err(msg) = write_metrics_file(&path) {
+            error!("Failed to write metrics: {}", msg);
+        }
+    });
+}
+
+/// Writes the current metrics to file
+pub fn write_metrics_file(path: &str) -> Result<(), String> {
+    let tmp_path = format!("{}.tmp", path);
+    if let Err(e) = fs::write(&tmp_path, render_metrics()) {
+        return Err(format!("Failed to write file '{}': {}", tmp_path, e))
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        return Err(format!("Failed to rename file '{}' to '{}': {}", tmp_path, path, e))
+    }
+    debug!("Metrics are written to '{}'", path);
+    Ok(())
+}
+
+/// Returns the metrics in Prometheus text format
+fn render_metrics() -> String {
+    let mut out = String::new();
+
+    // Statistics are collected in pipeline order. Steps are identified by `step` label and labels from definition
+    let (pipeline_name, steps): (String, Vec<(ModuleHandle, String)>) = match PIPELINE.get() {
+        Some(p) => {
+            let p = p.lock().unwrap();
+            let steps = p.steps.iter()
+                .map(|s| {
+                    let s = s.lock().unwrap();
+                    (s.get_handle(), format_step_labels(&s.get_id(), &s.metrics_labels))
+                })
+                .collect();
+            (escape_label(&p.name), steps)
+        },
+        None => (String::new(), Vec::new()),
+    };
+
+    out.push_str("# HELP torustiq_module_load_seconds Time spent to load the module library\n");
+    out.push_str("# TYPE torustiq_module_load_seconds gauge\n");
+    for (module_id, duration) in MODULE_LOAD_TIMES.lock().unwrap().iter() {
+        out.push_str(&format!("torustiq_module_load_seconds{{pipeline=\"{}\",module=\"{}\"}} {}\n",
+            pipeline_name, escape_label(module_id), duration.as_secs_f64()));
+    }
+
+    let step_stats = STEP_STATS.lock().unwrap();
+    let stats: Vec<(String, Arc<StepStatistics>)> = steps.into_iter()
+        .filter_map(|(handle, step_labels)| step_stats.get(&handle).map(|s| (step_labels, s.clone())))
+        .collect();
+    drop(step_stats);
+
+    let counters: [StepCounter; 10] = [
+        ("torustiq_step_records_received_total", "Records received from the previous step",
+            |s| s.records_received.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_records_succeeded_total", "Records processed successfully",
+            |s| s.records_succeeded.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_records_failed_total", "Records failed to process",
+            |s| s.records_failed.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_process_record_calls_total", "Calls of record processing function of module",
+            |s| s.process_record_calls.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_process_record_seconds_total", "Time spent in record processing function of module",
+            |s| Duration::from_nanos(s.process_record_ns.load(Ordering::Relaxed)).as_secs_f64()),
+        ("torustiq_step_queue_overflows_total", "Times the previous step was blocked because the input queue was full",
+            |s| s.queue_overflows.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_queue_blocked_seconds_total", "Time the previous step was blocked because the input queue was full",
+            |s| Duration::from_nanos(s.queue_blocked_ns.load(Ordering::Relaxed)).as_secs_f64()),
+        ("torustiq_step_records_leaked_total", "Records left in the input queue after the step termination",
+            |s| s.records_leaked.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_records_expired_total", "Records which were not processed because their deadline is expired",
+            |s| s.records_expired.load(Ordering::Relaxed) as f64),
+        ("torustiq_step_records_shed_total", "Records dropped by load shedding",
+            |s| s.records_shed.load(Ordering::Relaxed) as f64),
+    ];
+    for (name, help, value) in counters {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n", name, help, name));
+        for (step_labels, s) in &stats {
+            out.push_str(&format!("{}{{pipeline=\"{}\",{}}} {}\n", name, pipeline_name, step_labels, value(s)));
+        }
+    }
+
+    out.push_str("# HELP torustiq_step_records_dropped_total Records dropped by host, by reason\n");
+    out.push_str("# TYPE torustiq_step_records_dropped_total counter\n");
+    for (step_labels, s) in &stats {
+        for reason in DropReason::ALL {
+            out.push_str(&format!("torustiq_step_records_dropped_total{{pipeline=\"{}\",{},reason=\"{}\"}} {}\n",
+                pipeline_name, step_labels, reason.as_str(), s.get_records_dropped(reason)));
+        }
+    }
+
+    // Lifetime counters include the counters of previous runs. Exported only if statistics are persisted
+    let lifetime_counters = get_lifetime_counters();
+    if !lifetime_counters.is_empty() {
+        let labels_by_step_id = get_metrics_labels_by_step_id();
+        let counters: [LifetimeCounter; 3] = [
+            ("torustiq_step_records_received_lifetime_total", "Records received from the previous step, including previous runs",
+                |c| c.records_received),
+            ("torustiq_step_records_succeeded_lifetime_total", "Records processed successfully, including previous runs",
+                |c| c.records_succeeded),
+            ("torustiq_step_records_failed_lifetime_total", "Records failed to process, including previous runs",
+                |c| c.records_failed),
+        ];
+        for (name, help, value) in counters {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n", name, help, name));
+            for (step_id, c) in &lifetime_counters {
+                let step_labels = format_step_labels(step_id, labels_by_step_id.get(step_id).unwrap_or(&Vec::new()));
+                out.push_str(&format!("{}{{pipeline=\"{}\",{}}} {}\n", name, pipeline_name, step_labels, value(c)));
+            }
+        }
+    }
+
+    out.push_str("# HELP torustiq_end_to_end_latency_seconds Time between the record leaves the source and is processed by the last step\n");
+    out.push_str("# TYPE torustiq_end_to_end_latency_seconds histogram\n");
+    let cumulative_counts = END_TO_END_LATENCY.get_cumulative_counts();
+    for (bucket_ms, count) in LATENCY_BUCKETS_MS.iter().zip(cumulative_counts.iter()) {
+        out.push_str(&format!("torustiq_end_to_end_latency_seconds_bucket{{pipeline=\"{}\",le=\"{}\"}} {}\n",
+            pipeline_name, Duration::from_millis(*bucket_ms).as_secs_f64(), count));
+    }
+    out.push_str(&format!("torustiq_end_to_end_latency_seconds_bucket{{pipeline=\"{}\",le=\"+Inf\"}} {}\n",
+        pipeline_name, END_TO_END_LATENCY.get_count()));
+    out.push_str(&format!("torustiq_end_to_end_latency_seconds_sum{{pipeline=\"{}\"}} {}\n",
+        pipeline_name, END_TO_END_LATENCY.get_sum().as_secs_f64()));
+    out.push_str(&format!("torustiq_end_to_end_latency_seconds_count{{pipeline=\"{}\"}} {}\n",
+        pipeline_name, END_TO_END_LATENCY.get_count()));
+
+    out.push_str("# HELP torustiq_step_shedding 1 if the step drops records because of sustained overload\n");
+    out.push_str("# TYPE torustiq_step_shedding gauge\n");
+    for (step_labels, s) in &stats {
+        out.push_str(&format!("torustiq_step_shedding{{pipeline=\"{}\",{}}} {}\n",
+            pipeline_name, step_labels, s.is_shedding.load(Ordering::Relaxed) as u8));
+    }
+
+    out.push_str("# HELP torustiq_step_queue_full_seconds Consecutive seconds the input queue is full\n");
+    out.push_str("# TYPE torustiq_step_queue_full_seconds gauge\n");
+    for (step_labels, s) in &stats {
+        out.push_str(&format!("torustiq_step_queue_full_seconds{{pipeline=\"{}\",{}}} {}\n",
+            pipeline_name, step_labels, s.queue_full_seconds.load(Ordering::Relaxed)));
+    }
+
+    out.push_str("# HELP torustiq_step_queue_depth Records waiting in the input queue\n");
+    out.push_str("# TYPE torustiq_step_queue_depth gauge\n");
+    for (step_labels, s) in &stats {
+        out.push_str(&format!("torustiq_step_queue_depth{{pipeline=\"{}\",{}}} {}\n",
+            pipeline_name, step_labels, s.queue_depth.get()));
+    }
+
+    // The topology is exported as info metrics, so dashboards can join the statistics of steps with modules and edges
+    let graph = match PIPELINE.get() {
+        Some(p) => get_graph(&p.lock().unwrap()),
+        None => Value::Null,
+    };
+    let get_label = |v: &Value, key: &str| escape_label(v[key].as_str().unwrap_or_default());
+    out.push_str("# HELP torustiq_step_info Module and role of step. Always 1\n");
+    out.push_str("# TYPE torustiq_step_info gauge\n");
+    for node in graph["nodes"].as_array().into_iter().flatten() {
+        out.push_str(&format!("torustiq_step_info{{pipeline=\"{}\",step=\"{}\",module=\"{}\",module_version=\"{}\",kind=\"{}\",role=\"{}\"}} 1\n",
+            pipeline_name, get_label(node, "id"), get_label(&node["module"], "id"), get_label(&node["module"], "version"),
+            get_label(node, "kind"), get_label(node, "role")));
+    }
+    out.push_str("# HELP torustiq_edge_info An edge between steps. Always 1\n");
+    out.push_str("# TYPE torustiq_edge_info gauge\n");
+    for edge in graph["edges"].as_array().into_iter().flatten() {
+        out.push_str(&format!("torustiq_edge_info{{pipeline=\"{}\",from=\"{}\",to=\"{}\",type=\"{}\",class=\"{}\"}} 1\n",
+            pipeline_name, get_label(edge, "from"), get_label(edge, "to"), get_label(edge, "type"), get_label(edge, "class")));
+    }
+    out
+}
+
+/// Returns the labels of steps from pipeline by step ID
+fn get_metrics_labels_by_step_id() -> HashMap<String, Vec<(String, String)>> {
+    match PIPELINE.get() {
+        Some(p) => p.lock().unwrap().steps.iter()
+            .map(|s| {
+                let s = s.lock().unwrap();
+                (s.get_id(), s.metrics_labels.clone())
+            })
+            .collect(),
+        None => HashMap::new(),
+    }
+}
+
+/// Formats the labels which identify a step: `step="<id>",<label>="<value>",...`
+fn format_step_labels(step_id: &str, labels: &[(String, String)]) -> String {
+    let mut result = format!("step=\"{}\"", escape_label(step_id));
+    for (name, value) in labels {
+        result.push_str(&format!(",{}=\"{}\"", name, escape_label(value)));
+    }
+    result
+}
+
+/// Validates the metrics labels of step and substitutes the step arguments into values.
+/// Returns the labels sorted by name. Secret values are masked
+pub fn resolve_metrics_labels(labels: &HashMap<String, String>, args: &HashMap<String, String>) -> Result<Vec<(String, String)>, String> {
+    let mut result: Vec<(String, String)> = Vec::new();
+    for (name, value) in labels {
+        let is_valid_name = name.chars().enumerate().all(|(i, c)| c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()))
+            && !name.is_empty() && !name.starts_with("__");
+        if !is_valid_name {
+            return Err(format!("invalid metrics label name: '{}'", name))
+        }
+        if RESERVED_LABELS.contains(&name.as_str()) {
+            return Err(format!("metrics label '{}' is reserved", name))
+        }
+        let arg_key = value.trim().strip_prefix("{{")
+            .and_then(|v| v.strip_suffix("}}"))
+            .and_then(|v| v.trim().strip_prefix(ARGS_PREFIX));
+        let value = match arg_key {
+            Some(key) => match args.get(key) {
+                Some(v) => mask_text(v),
+                None => return Err(format!("metrics label '{}' refers to missing argument '{}'", name, key)),
+            },
+            None => value.clone(),
+        };
+        result.push((name.clone(), value));
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// Escapes the label value according to Prometheus text format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
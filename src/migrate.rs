@@ -0,0 +1,112 @@
+/// Upgrade of legacy pipeline definitions.
+/// Early versions accepted a minimal definition: a `steps` list where steps have handlers only,
+/// e.g. `steps: [kafka_source, {handler: stdout_destination}]`. Such definitions are upgraded on load,
+/// and the `migrate-config` command writes the upgraded definition, so it can be extended with new features
+
+use std::{collections::HashSet, fs, path::Path};
+
+use log::info;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    cli::{CliArgs, MigrateConfigArgs},
+    config::sanitize_pipeline_name,
+};
+
+/// Upgrades the legacy definition of single pipeline: steps defined as handler strings are converted into mappings
+/// and the missing step names are derived from handlers. Returns true if the definition was changed
+pub fn upgrade_legacy_definition(definition: &mut Value) -> Result<bool, String> {
+    let steps = match definition.get_mut("steps") {
+        Some(Value::Sequence(s)) => s,
+        _ => return Ok(false),
+    };
+    let mut used_names: HashSet<String> = steps.iter()
+        .filter_map(|s| s.get("name").and_then(|n| n.as_str()).map(String::from))
+        .collect();
+    let mut is_changed = false;
+    for (i, step) in steps.iter_mut().enumerate() {
+        if let Value::String(handler) = step {
+            *step = Value::Mapping(Mapping::from_iter([
+                (Value::from("handler"), Value::from(handler.as_str())),
+            ]));
+            is_changed = true;
+        }
+        let step_mapping = match step {
+            Value::Mapping(m) => m,
+            _ => return Err(format!("Step #{} must be either a mapping or a handler string", i + 1)),
+        };
+        if step_mapping.contains_key("name") {
+            continue
+        }
+        let handler = match step_mapping.get("handler").and_then(|h| h.as_str()) {
+            Some(h) => h,
+            None => return Err(format!("Step #{} has neither name nor handler", i + 1)),
+        };
+        let name = get_unique_name(&get_name_from_handler(handler), &used_names);
+        used_names.insert(name.clone());
+        // The name goes first, as in the definitions written by hand
+        let mut upgraded = Mapping::from_iter([(Value::from("name"), Value::from(name))]);
+        upgraded.extend(step_mapping.clone());
+        *step_mapping = upgraded;
+        is_changed = true;
+    }
+    Ok(is_changed)
+}
+
+/// Reads the pipeline file and returns the upgraded definition in YAML format.
+/// If no output file is set, the definition is returned for printing
+pub fn run_migrate_config(args: &CliArgs, migrate_args: &MigrateConfigArgs) -> Result<Option<String>, String> {
+    let path = &args.pipeline_file;
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Cannot open the pipeline file: '{}'. {}", path, e)),
+    };
+    let mut definition: Value = match serde_yaml::from_str(contents.as_str()) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Cannot parse the pipeline: '{}'. {}", path, e)),
+    };
+    if definition.get("pipelines").is_some() {
+        return Err(String::from("The file defines multiple pipelines. Only the single pipeline definitions can be migrated"))
+    }
+    let mut is_changed = upgrade_legacy_definition(&mut definition)?;
+    // Pipeline name defaults to the name of file, so renaming of file would change the thread names and metrics
+    let has_name = definition.get("name").is_some();
+    if let (Value::Mapping(m), false) = (&mut definition, has_name) {
+        let file_stem = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let mut upgraded = Mapping::from_iter([(Value::from("name"), Value::from(file_stem))]);
+        upgraded.extend(m.clone());
+        *m = upgraded;
+        is_changed = true;
+    }
+    if !is_changed {
+        info!("Pipeline file '{}' is up to date", path);
+    }
+    let output = match serde_yaml::to_string(&definition) {
+        Ok(o) => o,
+        Err(e) => return Err(format!("Cannot serialize the pipeline: {}", e)),
+    };
+    match &migrate_args.output {
+        Some(output_path) => match fs::write(output_path, output) {
+            Ok(_) => Ok(None),
+            Err(e) => Err(format!("Cannot write the pipeline file '{}': {}", output_path, e)),
+        },
+        None => Ok(Some(output.trim_end().to_string())),
+    }
+}
+
+/// Returns a step name for module handler: `kafka_source@^1.2` -> `kafka_source`
+fn get_name_from_handler(handler: &str) -> String {
+    let module_id = handler.split_once('@').map(|(id, _)| id).unwrap_or(handler);
+    sanitize_pipeline_name(module_id)
+}
+
+/// Appends a numeric suffix to name if it's already used
+fn get_unique_name(name: &str, used_names: &HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    let mut suffix: usize = 2;
+    while used_names.contains(&candidate) {
+        candidate = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    candidate
+}
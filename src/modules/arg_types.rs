@@ -0,0 +1,169 @@
+/// Types of module arguments.
+/// Modules declare the types of their arguments: built-in modules with `BuiltinModule::get_arg_types`,
+/// libraries with `torustiq_module_get_arg_types`. The host checks the arguments of steps and listeners
+/// against the declared types before the modules are configured. Undeclared arguments are not checked.
+/// Quoted scalars are accepted if they parse as the declared type, so the pipelines which quote numbers stay valid
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::encryption::ENCRYPTED_TAG;
+
+/// A type of module argument
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgType {
+    /// Any scalar: string, number or boolean
+    String,
+    Integer,
+    Float,
+    Boolean,
+    List,
+    Map,
+}
+
+impl ArgType {
+    /// Returns true if the YAML value has this type
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            // Unset arguments fall back to defaults of module
+            (_, Value::Null) => true,
+            // Encrypted values are decrypted later, so only their tag is known here
+            (ArgType::String, Value::Tagged(t)) => t.tag == ENCRYPTED_TAG,
+            (ArgType::String, Value::Bool(_) | Value::Number(_) | Value::String(_)) => true,
+            (ArgType::Integer, Value::Number(n)) => n.is_i64() || n.is_u64(),
+            (ArgType::Integer, Value::String(s)) => s.trim().parse::<i64>().is_ok() || s.trim().parse::<u64>().is_ok(),
+            (ArgType::Float, Value::Number(_)) => true,
+            (ArgType::Float, Value::String(s)) => s.trim().parse::<f64>().is_ok(),
+            (ArgType::Boolean, Value::Bool(_)) => true,
+            (ArgType::Boolean, Value::String(s)) => s.trim().parse::<bool>().is_ok(),
+            (ArgType::List, Value::Sequence(_)) => true,
+            (ArgType::Map, Value::Mapping(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ArgType::String => "a string",
+            ArgType::Integer => "an integer",
+            ArgType::Float => "a number",
+            ArgType::Boolean => "a boolean",
+            ArgType::List => "a list",
+            ArgType::Map => "a map",
+        }
+    }
+}
+
+/// Returns a human-readable type of YAML value for error messages
+fn get_value_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(n) if n.is_f64() => "a number",
+        Value::Number(_) => "an integer",
+        Value::String(_) => "a string",
+        Value::Sequence(_) => "a list",
+        Value::Mapping(_) => "a map",
+        Value::Tagged(_) => "a tagged value",
+    }
+}
+
+/// Parses the argument types declared by library: one argument per line in `name=type` format.
+/// Unknown types are reported and ignored
+pub fn parse_arg_types(manifest: &str) -> (HashMap<String, ArgType>, Vec<String>) {
+    let mut arg_types: HashMap<String, ArgType> = HashMap::new();
+    let mut errors: Vec<String> = Vec::new();
+    for line in manifest.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let (name, arg_type) = match line.split_once('=') {
+            Some((n, t)) => (n.trim(), t.trim()),
+            None => {
+                errors.push(format!("invalid declaration '{}'", line));
+                continue
+            },
+        };
+        match serde_yaml::from_str::<ArgType>(arg_type) {
+            Ok(t) => { arg_types.insert(name.to_string(), t); },
+            Err(_) => errors.push(format!("unknown type '{}' of argument '{}'", arg_type, name)),
+        }
+    }
+    (arg_types, errors)
+}
+
+/// Checks the arguments of step or listener against the types declared by module.
+/// Component is a prefix of error which names the step or listener, e.g. `Step 'parse'`
+pub fn check_arg_types(component: &str, args: Option<&HashMap<String, Value>>, arg_types: &HashMap<String, ArgType>) -> Result<(), String> {
+    let args = match args {
+        Some(a) => a,
+        None => return Ok(()),
+    };
+    let mut errors: Vec<String> = args.iter()
+        .filter_map(|(name, value)| arg_types.get(name).map(|t| (name, t, value)))
+        .filter(|(_, arg_type, value)| !arg_type.matches(value))
+        .map(|(name, arg_type, value)| format!("argument '{}' must be {}, but {} is set",
+            name, arg_type.get_name(), get_value_type_name(value)))
+        .collect();
+    if errors.is_empty() {
+        return Ok(())
+    }
+    errors.sort();
+    Err(format!("{}: {}", component, errors.join("; ")))
+}
+
+/// Converts the argument types declared by built-in module
+pub fn to_arg_types(declared: &[(&str, ArgType)]) -> HashMap<String, ArgType> {
+    declared.iter().map(|(name, t)| (name.to_string(), *t)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_yaml::Value;
+
+    use super::{check_arg_types, parse_arg_types, to_arg_types, ArgType};
+
+    fn parse_args(yaml: &str) -> HashMap<String, Value> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn quoted_scalars_match_if_they_parse() {
+        assert!(ArgType::Integer.matches(&Value::from("42")));
+        assert!(ArgType::Boolean.matches(&Value::from("true")));
+        assert!(ArgType::Float.matches(&Value::from("0.5")));
+        assert!(!ArgType::Integer.matches(&Value::from("forty")));
+        assert!(!ArgType::Boolean.matches(&Value::from("yes")));
+    }
+
+    #[test]
+    fn error_names_component_and_arguments() {
+        let arg_types = to_arg_types(&[("max_rate", ArgType::Integer), ("metadata", ArgType::Boolean)]);
+        let args = parse_args("{max_rate: [1], metadata: true, other: [x]}");
+        assert_eq!(
+            check_arg_types("Step 'debug'", Some(&args), &arg_types),
+            Err(String::from("Step 'debug': argument 'max_rate' must be an integer, but a list is set")));
+        assert_eq!(check_arg_types("Step 'debug'", None, &arg_types), Ok(()));
+    }
+
+    #[test]
+    fn encrypted_values_match_strings_only() {
+        let args = parse_args("{password: !encrypted abc, port: !encrypted abc}");
+        let arg_types = to_arg_types(&[("password", ArgType::String), ("port", ArgType::Integer)]);
+        assert_eq!(
+            check_arg_types("Listener 'http'", Some(&args), &arg_types),
+            Err(String::from("Listener 'http': argument 'port' must be an integer, but a tagged value is set")));
+    }
+
+    #[test]
+    fn parses_declarations_of_library() {
+        let (arg_types, errors) = parse_arg_types("port=integer\n\n tags = list \nmode=enum\nbroken\n");
+        assert_eq!(arg_types, to_arg_types(&[("port", ArgType::Integer), ("tags", ArgType::List)]));
+        assert_eq!(errors, vec![
+            String::from("unknown type 'enum' of argument 'mode'"),
+            String::from("invalid declaration 'broken'"),
+        ]);
+    }
+}
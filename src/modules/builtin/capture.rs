@@ -0,0 +1,92 @@
+/// `builtin.capture`: a destination which writes records to fixture file.
+/// Arguments:
+/// - `path`: a path to output file. The file is overwritten
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    sync::Mutex,
+};
+
+use log::error;
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::on_step_terminate_cb,
+    modules::builtin::{check_kind, fixture::{write_fixture_record, FixtureRecord}, BuiltinModule},
+    policy::ModulePosition,
+};
+
+pub const MODULE_ID: &str = "builtin.capture";
+
+struct CaptureConfig {
+    module_handle: ModuleHandle,
+    writer: Mutex<BufWriter<File>>,
+}
+
+#[derive(Default)]
+pub struct CaptureModule {
+    config: OnceCell<CaptureConfig>,
+}
+
+impl BuiltinModule for CaptureModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Destination])
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Destination)?;
+        let path = match args.get("path") {
+            Some(p) => p,
+            None => return Err(format!("Module '{}' requires 'path' argument", MODULE_ID)),
+        };
+        let file = match File::create(path) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Cannot create the capture file '{}': {}", path, e)),
+        };
+        let config = CaptureConfig {
+            module_handle,
+            writer: Mutex::new(BufWriter::new(file)),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let mut writer = config.writer.lock().unwrap();
+        write_fixture_record(&mut *writer, &FixtureRecord::from_record(&record))?;
+        Ok(false)
+    }
+
+    fn commit(&self) -> Result<(), String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        match config.writer.lock().unwrap().flush() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to flush the capture file: {}", e)),
+        }
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            if let Err(e) = config.writer.lock().unwrap().flush() {
+                error!("Failed to flush the capture file: {}", e);
+            }
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+/// `builtin.csv_parse`: parses a CSV payload. Each row becomes a record with JSON object payload: `{"column": "value"}`.
+/// Arguments:
+/// - `delimiter`: a single-character field delimiter. Default: `,`
+/// - `has_header`: the first row of payload contains column names. Default: true
+/// - `columns`: comma-separated column names. Overrides the header. By default the columns are named
+///   `column_1`, `column_2`, etc. if there is no header. Column names must be unique, as well as names in header
+/// - `fields_to_metadata`: copy the fields into metadata. Default: false
+/// - `metadata_prefix`: a prefix of metadata keys for copied fields. Default: empty
+/// - `on_error`: `fail` (default) rejects the whole payload if some row is malformed, `skip` drops malformed rows
+///
+/// Quoted fields may contain delimiters, line breaks and escaped quotes (`""`). Empty rows are skipped.
+/// Each produced record gets a copy of metadata from the original record.
+
+use std::collections::HashMap;
+
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, jsonl_parse::{FieldMapping, ParseErrorPolicy}, BuiltinModule}},
+    policy::ModulePosition,
+    records::{get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.csv_parse";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("delimiter", ArgType::String),
+    ("columns", ArgType::String),
+    ("has_header", ArgType::Boolean),
+    ("fields_to_metadata", ArgType::Boolean),
+    ("metadata_prefix", ArgType::String),
+    ("on_error", ArgType::String),
+];
+
+struct CsvParseConfig {
+    module_handle: ModuleHandle,
+    delimiter: char,
+    has_header: bool,
+    columns: Option<Vec<String>>,
+    field_mapping: FieldMapping,
+    on_error: ParseErrorPolicy,
+}
+
+#[derive(Default)]
+pub struct CsvParseModule {
+    config: OnceCell<CsvParseConfig>,
+}
+
+impl BuiltinModule for CsvParseModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let delimiter = match args.get("delimiter") {
+            None => ',',
+            Some(d) => match (d.chars().next(), d.chars().count()) {
+                (Some(c), 1) if c != '"' && c != '\n' && c != '\r' => c,
+                _ => return Err(format!("Invalid delimiter: '{}'. Delimiter must be a single character except for quote and line break", d)),
+            },
+        };
+        let columns: Option<Vec<String>> = args.get("columns")
+            .map(|c| c.split(',').map(|c| c.trim().to_string()).collect());
+        if let Some(c) = &columns {
+            check_columns(c)?;
+        }
+        let config = CsvParseConfig {
+            module_handle,
+            delimiter,
+            has_header: get_arg(args, "has_header")?.unwrap_or(true),
+            columns,
+            field_mapping: FieldMapping::from_args(args)?,
+            on_error: ParseErrorPolicy::from_args(args)?,
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = match std::str::from_utf8(get_payload(&record)) {
+            Ok(p) => p,
+            Err(e) => return Err(format!("The payload is not a valid UTF-8 text: {}", e)),
+        };
+        let mut rows = parse_csv(payload, config.delimiter)?.into_iter();
+        let columns = get_columns(&mut rows, config.has_header, config.columns.as_ref())?;
+        let metadata = get_metadata(&record);
+        // The whole payload is parsed first, so a failed payload doesn't produce partial output
+        let mut records: Vec<Record> = Vec::new();
+        for (i, row) in rows.enumerate() {
+            let result = match &columns {
+                Some(c) if c.len() != row.len() => Err(format!("Row {} has {} fields, but {} columns are expected", i + 1, row.len(), c.len())),
+                Some(c) => config.field_mapping.create_record(&to_object(c.iter().cloned(), row), &metadata),
+                None => config.field_mapping.create_record(&to_object((1..).map(|n| format!("column_{}", n)), row), &metadata),
+            };
+            match (result, config.on_error) {
+                (Ok(r), _) => records.push(r),
+                (Err(e), ParseErrorPolicy::Skip) => warn!("Skipping a row of payload. {}", e),
+                (Err(e), ParseErrorPolicy::Fail) => return Err(e),
+            }
+        }
+        for r in records {
+            on_rcv_cb(config.module_handle, r);
+        }
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
+
+/// Returns the column names. The header row is taken from rows if payload has header; the `columns` argument overrides it
+fn get_columns(rows: &mut impl Iterator<Item = Vec<String>>, has_header: bool, columns: Option<&Vec<String>>) -> Result<Option<Vec<String>>, String> {
+    let header = match has_header {
+        true => rows.next(),
+        false => None,
+    };
+    match (columns, header) {
+        (Some(c), _) => Ok(Some(c.clone())),
+        (None, Some(h)) => {
+            check_columns(&h).map_err(|e| format!("Invalid header: {}", e))?;
+            Ok(Some(h))
+        },
+        (None, None) => Ok(None),
+    }
+}
+
+/// Checks if column names are unique, so fields are not overwritten in JSON object
+fn check_columns(columns: &[String]) -> Result<(), String> {
+    match columns.iter().enumerate().find(|(i, c)| columns[..*i].contains(c)) {
+        Some((_, c)) => Err(format!("Column name '{}' is not unique", c)),
+        None => Ok(()),
+    }
+}
+
+/// Creates a JSON object from column names and row fields
+fn to_object(columns: impl Iterator<Item = String>, row: Vec<String>) -> Map<String, Value> {
+    columns.zip(row).map(|(c, f)| (c, Value::String(f))).collect()
+}
+
+/// Parses the CSV text into rows of fields. Empty rows are skipped
+pub fn parse_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    // If true, the current field is quoted, so the empty row check must not skip it
+    let mut has_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (is_quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            (true, '"') => is_quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => {
+                is_quoted = true;
+                has_quotes = true;
+            },
+            (false, c) if c == delimiter => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {},
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() || has_quotes {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+                has_quotes = false;
+            },
+            (false, c) => field.push(c),
+        }
+    }
+    if is_quoted {
+        return Err(String::from("The payload ends inside a quoted field"))
+    }
+    if !field.is_empty() || !row.is_empty() || has_quotes {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_columns, get_columns, parse_csv};
+
+    fn to_rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|r| r.iter().map(|f| f.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        let rows = parse_csv("a,\"b,c\",\"say \"\"hi\"\"\"\n", ',').unwrap();
+        assert_eq!(rows, to_rows(&[&["a", "b,c", "say \"hi\""]]));
+    }
+
+    #[test]
+    fn parses_line_breaks() {
+        let rows = parse_csv("a;b\r\n\"multi\r\nline\";c\nd;e", ';').unwrap();
+        assert_eq!(rows, to_rows(&[&["a", "b"], &["multi\r\nline", "c"], &["d", "e"]]));
+    }
+
+    #[test]
+    fn skips_empty_rows_but_keeps_quoted_empty_fields() {
+        let rows = parse_csv("\na,b\n\n\r\n\"\"\n,\n", ',').unwrap();
+        assert_eq!(rows, to_rows(&[&["a", "b"], &[""], &["", ""]]));
+        assert!(parse_csv("", ',').unwrap().is_empty());
+    }
+
+    #[test]
+    fn fails_on_unterminated_quote() {
+        assert_eq!(parse_csv("a,\"b\nc", ','), Err(String::from("The payload ends inside a quoted field")));
+    }
+
+    #[test]
+    fn columns_override_header() {
+        let columns = vec![String::from("x"), String::from("y")];
+        let mut rows = to_rows(&[&["a", "b"], &["1", "2"]]).into_iter();
+        assert_eq!(get_columns(&mut rows, true, Some(&columns)), Ok(Some(columns.clone())));
+        assert_eq!(rows.collect::<Vec<_>>(), to_rows(&[&["1", "2"]]));
+
+        let mut rows = to_rows(&[&["1", "2"]]).into_iter();
+        assert_eq!(get_columns(&mut rows, false, Some(&columns)), Ok(Some(columns)));
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn header_is_used_if_columns_are_not_set() {
+        let mut rows = to_rows(&[&["a", "b"], &["1", "2"]]).into_iter();
+        assert_eq!(get_columns(&mut rows, true, None), Ok(Some(vec![String::from("a"), String::from("b")])));
+        let mut rows = to_rows(&[&["1", "2"]]).into_iter();
+        assert_eq!(get_columns(&mut rows, false, None), Ok(None));
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn rejects_duplicate_column_names() {
+        let mut rows = to_rows(&[&["id", "name", "id"]]).into_iter();
+        assert_eq!(get_columns(&mut rows, true, None), Err(String::from("Invalid header: Column name 'id' is not unique")));
+        assert_eq!(check_columns(&[String::from("a"), String::from("a")]), Err(String::from("Column name 'a' is not unique")));
+    }
+}
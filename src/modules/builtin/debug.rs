@@ -0,0 +1,139 @@
+/// `builtin.debug`: logs a preview of records and passes them to the next step unchanged.
+/// Arguments:
+/// - `max_rate`: maximum number of logged records per second. Other records are passed without logging. Default: 10
+/// - `payload_preview_bytes`: a number of payload bytes to log. Default: 64
+/// - `metadata`: if true, metadata of record is logged too. Default: true
+///
+/// Records are logged with `info` level. Non-UTF-8 bytes of payload are replaced.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    masking::mask_args,
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, BuiltinModule}},
+    policy::ModulePosition,
+    records::{get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.debug";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("max_rate", ArgType::Integer),
+    ("payload_preview_bytes", ArgType::Integer),
+    ("metadata", ArgType::Boolean),
+];
+
+const DEFAULT_MAX_RATE: u64 = 10;
+const DEFAULT_PAYLOAD_PREVIEW_BYTES: usize = 64;
+
+struct DebugConfig {
+    module_handle: ModuleHandle,
+    max_rate: u64,
+    payload_preview_bytes: usize,
+    metadata: bool,
+}
+
+/// A number of logged and skipped records in the current second
+struct RateWindow {
+    started_at: Instant,
+    logged: u64,
+    skipped: u64,
+}
+
+#[derive(Default)]
+pub struct DebugModule {
+    config: OnceCell<DebugConfig>,
+    window: Mutex<Option<RateWindow>>,
+}
+
+impl DebugModule {
+    /// Returns the number of records skipped since the last logged record, if the record can be logged now
+    fn try_acquire(&self, max_rate: u64) -> Option<u64> {
+        let mut window = self.window.lock().unwrap();
+        let w = match window.as_mut() {
+            Some(w) if w.started_at.elapsed() < Duration::from_secs(1) => w,
+            _ => {
+                let skipped = window.as_ref().map(|w| w.skipped).unwrap_or(0);
+                window.insert(RateWindow { started_at: Instant::now(), logged: 0, skipped })
+            },
+        };
+        if w.logged >= max_rate {
+            w.skipped += 1;
+            return None
+        }
+        w.logged += 1;
+        let skipped = w.skipped;
+        w.skipped = 0;
+        Some(skipped)
+    }
+}
+
+impl BuiltinModule for DebugModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let config = DebugConfig {
+            module_handle,
+            max_rate: get_arg(args, "max_rate")?.unwrap_or(DEFAULT_MAX_RATE),
+            payload_preview_bytes: get_arg(args, "payload_preview_bytes")?.unwrap_or(DEFAULT_PAYLOAD_PREVIEW_BYTES),
+            metadata: get_arg(args, "metadata")?.unwrap_or(true),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        if let Some(skipped) = self.try_acquire(config.max_rate) {
+            let payload = get_payload(&record);
+            let preview = String::from_utf8_lossy(&payload[..payload.len().min(config.payload_preview_bytes)]);
+            let truncated = match payload.len() > config.payload_preview_bytes {
+                true => format!(" (truncated, {} bytes total)", payload.len()),
+                false => String::new(),
+            };
+            let metadata = match config.metadata {
+                true => format!(", metadata: {:?}", mask_args(&get_metadata(&record))),
+                false => String::new(),
+            };
+            let skipped = match skipped {
+                0 => String::new(),
+                s => format!(" [{} record(s) not logged]", s),
+            };
+            info!("Step {}: payload: {:?}{}{}{}", config.module_handle, preview, truncated, metadata, skipped);
+        }
+        // The record is passed as is, so the next step takes it over
+        on_rcv_cb(config.module_handle, record);
+        Ok(true)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+/// `builtin.dedup_hash`: drops the records whose hash had been seen within a time window.
+/// Arguments:
+/// - `window_ms`: how long the hash is remembered. Default: 60000
+/// - `metadata_key`: a metadata key to read the hash from. Default: `hash`
+/// - `algorithm`: an algorithm to hash the payload with, if record has no hash in metadata.
+///   See `builtin.hash` for options
+///
+/// The step is usually placed after `builtin.hash`. Records without hash in metadata are hashed here.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::trace;
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, hash::{HashAlgorithm, DEFAULT_METADATA_KEY}, BuiltinModule}},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.dedup_hash";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("window_ms", ArgType::Integer),
+    ("metadata_key", ArgType::String),
+    ("algorithm", ArgType::String),
+];
+
+struct DedupHashConfig {
+    module_handle: ModuleHandle,
+    window: Duration,
+    metadata_key: String,
+    algorithm: HashAlgorithm,
+}
+
+/// Hashes seen within the window
+#[derive(Default)]
+struct SeenHashes {
+    /// Hash -> time when the hash was seen last time
+    last_seen: HashMap<String, Instant>,
+    /// Hashes in order of appearance. Used to expire the old hashes
+    queue: VecDeque<(Instant, String)>,
+}
+
+impl SeenHashes {
+    /// Forgets the hashes which are older than window
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some((seen_at, _)) = self.queue.front() {
+            if now.duration_since(*seen_at) < window {
+                break
+            }
+            let (seen_at, hash) = self.queue.pop_front().unwrap();
+            // The hash might be seen again later; remove it only if this is the latest occurrence
+            if self.last_seen.get(&hash) == Some(&seen_at) {
+                self.last_seen.remove(&hash);
+            }
+        }
+    }
+
+    /// Remembers the hash. Returns true if hash is seen for the first time within window
+    fn insert(&mut self, now: Instant, hash: String) -> bool {
+        let is_new = self.last_seen.insert(hash.clone(), now).is_none();
+        self.queue.push_back((now, hash));
+        is_new
+    }
+}
+
+#[derive(Default)]
+pub struct DedupHashModule {
+    config: OnceCell<DedupHashConfig>,
+    seen: Mutex<SeenHashes>,
+}
+
+impl BuiltinModule for DedupHashModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let config = DedupHashConfig {
+            module_handle,
+            window: Duration::from_millis(get_arg(args, "window_ms")?.unwrap_or(60000)),
+            metadata_key: args.get("metadata_key").cloned().unwrap_or(String::from(DEFAULT_METADATA_KEY)),
+            algorithm: HashAlgorithm::from_args(args)?,
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = get_payload(&record);
+        let metadata = get_metadata(&record);
+        let hash = match metadata.get(&config.metadata_key) {
+            Some(h) => h.clone(),
+            None => config.algorithm.hash(payload),
+        };
+
+        let is_new = {
+            let now = Instant::now();
+            let mut seen = self.seen.lock().unwrap();
+            seen.expire(now, config.window);
+            seen.insert(now, hash.clone())
+        };
+        if !is_new {
+            trace!("Dropped a duplicate record with hash '{}'", hash);
+            return Ok(false)
+        }
+        on_rcv_cb(config.module_handle, create_record(payload.to_vec(), metadata));
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
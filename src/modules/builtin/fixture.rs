@@ -0,0 +1,70 @@
+/// Fixture files store records in JSON lines format, one record per line:
+/// `{"payload": "...", "metadata": {"key": "value"}}`
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+};
+
+use serde::{Deserialize, Serialize};
+use torustiq_common::ffi::types::module::Record;
+
+use crate::records::{create_record, get_metadata, get_payload};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FixtureRecord {
+    pub payload: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+impl FixtureRecord {
+    /// Creates a fixture record from record. Payload is converted into UTF-8 string
+    pub fn from_record(record: &Record) -> FixtureRecord {
+        FixtureRecord {
+            payload: String::from_utf8_lossy(get_payload(record)).to_string(),
+            metadata: get_metadata(record),
+        }
+    }
+
+    /// Creates a new record which is allocated by host
+    pub fn to_record(&self) -> Record {
+        create_record(self.payload.clone().into_bytes(), self.metadata.clone())
+    }
+}
+
+/// Reads records from fixture file. Empty lines are skipped
+pub fn read_fixture_file(path: &str) -> Result<Vec<FixtureRecord>, String> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Cannot open the fixture file '{}': {}", path, e)),
+    };
+    let mut records: Vec<FixtureRecord> = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Cannot read the fixture file '{}': {}", path, e)),
+        };
+        if line.trim().is_empty() {
+            continue
+        }
+        match serde_json::from_str(line.as_str()) {
+            Ok(r) => records.push(r),
+            Err(e) => return Err(format!("Cannot parse the record at line {} of fixture file '{}': {}", i + 1, path, e)),
+        };
+    }
+    Ok(records)
+}
+
+/// Writes a record to fixture file
+pub fn write_fixture_record<W: Write>(writer: &mut W, record: &FixtureRecord) -> Result<(), String> {
+    let line = match serde_json::to_string(record) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Cannot serialize the record: {}", e)),
+    };
+    match writeln!(writer, "{}", line) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Cannot write the record: {}", e)),
+    }
+}
@@ -0,0 +1,85 @@
+/// `builtin.fixture_source`: a source which reads records from fixture file.
+/// The step is terminated once all records are sent.
+/// Arguments:
+/// - `path`: a path to fixture file
+
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread,
+};
+
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::builtin::{check_kind, fixture::{read_fixture_file, FixtureRecord}, BuiltinModule},
+    policy::ModulePosition,
+};
+
+pub const MODULE_ID: &str = "builtin.fixture_source";
+
+struct FixtureSourceConfig {
+    module_handle: ModuleHandle,
+    records: Vec<FixtureRecord>,
+}
+
+#[derive(Default)]
+pub struct FixtureSourceModule {
+    config: OnceCell<Arc<FixtureSourceConfig>>,
+    is_shut_down: Arc<AtomicBool>,
+}
+
+impl BuiltinModule for FixtureSourceModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Source])
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Source)?;
+        let path = match args.get("path") {
+            Some(p) => p,
+            None => return Err(format!("Module '{}' requires 'path' argument", MODULE_ID)),
+        };
+        let config = FixtureSourceConfig {
+            module_handle,
+            records: read_fixture_file(path)?,
+        };
+        if self.config.set(Arc::new(config)).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), String> {
+        let config = match self.config.get() {
+            Some(c) => c.clone(),
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let is_shut_down = self.is_shut_down.clone();
+        thread::spawn(move || {
+            for record in &config.records {
+                if is_shut_down.load(Ordering::SeqCst) {
+                    break
+                }
+                on_rcv_cb(config.module_handle, record.to_record());
+            }
+            on_step_terminate_cb(config.module_handle);
+        });
+        Ok(())
+    }
+
+    fn process_record(&self, _record: Record) -> Result<bool, String> {
+        Err(format!("Module '{}' is a source and doesn't accept records", MODULE_ID))
+    }
+
+    fn shutdown(&self) {
+        // The termination is reported by the sending thread
+        self.is_shut_down.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,104 @@
+/// `builtin.hash`: computes a hash of payload and stores it in metadata.
+/// Arguments:
+/// - `algorithm`: `xxhash64` (default), `xxh3` or `sha256`
+/// - `metadata_key`: a metadata key to store the hash in. Default: `hash`
+///
+/// The hash is stored as lowercase hex string.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::builtin::{check_kind, BuiltinModule},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.hash";
+
+pub const DEFAULT_METADATA_KEY: &str = "hash";
+
+/// A hash algorithm
+#[derive(Clone, Copy)]
+pub enum HashAlgorithm {
+    Xxhash64,
+    Xxh3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Reads the algorithm from module arguments
+    pub fn from_args(args: &HashMap<String, String>) -> Result<HashAlgorithm, String> {
+        match args.get("algorithm").map(|a| a.as_str()) {
+            None | Some("xxhash64") => Ok(HashAlgorithm::Xxhash64),
+            Some("xxh3") => Ok(HashAlgorithm::Xxh3),
+            Some("sha256") => Ok(HashAlgorithm::Sha256),
+            Some(a) => Err(format!("Unknown hash algorithm: '{}'", a)),
+        }
+    }
+
+    /// Returns a hash of payload as hex string
+    pub fn hash(&self, payload: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Xxhash64 => format!("{:016x}", xxhash_rust::xxh64::xxh64(payload, 0)),
+            HashAlgorithm::Xxh3 => format!("{:016x}", xxhash_rust::xxh3::xxh3_64(payload)),
+            HashAlgorithm::Sha256 => Sha256::digest(payload).iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+struct HashConfig {
+    module_handle: ModuleHandle,
+    algorithm: HashAlgorithm,
+    metadata_key: String,
+}
+
+#[derive(Default)]
+pub struct HashModule {
+    config: OnceCell<HashConfig>,
+}
+
+impl BuiltinModule for HashModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let config = HashConfig {
+            module_handle,
+            algorithm: HashAlgorithm::from_args(args)?,
+            metadata_key: args.get("metadata_key").cloned().unwrap_or(String::from(DEFAULT_METADATA_KEY)),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = get_payload(&record);
+        let mut metadata = get_metadata(&record);
+        metadata.insert(config.metadata_key.clone(), config.algorithm.hash(payload));
+        on_rcv_cb(config.module_handle, create_record(payload.to_vec(), metadata));
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/// `builtin.join`: merges multiple records into one.
+/// Arguments:
+/// - `count`: emit a merged record once this number of records is collected
+/// - `interval_ms`: emit a merged record once this time has passed since the first collected record
+/// - `delimiter`: a delimiter to insert between payloads. Default: line break
+///
+/// At least one of `count` and `interval_ms` must be set.
+/// The merged record gets metadata of the first collected record.
+/// Remaining records are flushed on shutdown.
+
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, BuiltinModule}},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.join";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("count", ArgType::Integer),
+    ("interval_ms", ArgType::Integer),
+    ("delimiter", ArgType::String),
+];
+
+/// An interval of checks for the time-based flushes
+const TIMER_TICK: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+struct JoinConfig {
+    module_handle: ModuleHandle,
+    count: Option<usize>,
+    interval: Option<Duration>,
+    delimiter: Vec<u8>,
+}
+
+/// Records collected so far
+#[derive(Default)]
+struct JoinBuffer {
+    payloads: Vec<Vec<u8>>,
+    metadata: HashMap<String, String>,
+    first_received_at: Option<Instant>,
+}
+
+impl JoinBuffer {
+    /// Sends the merged record to the next step and resets the buffer
+    fn flush(&mut self, config: &JoinConfig) {
+        if self.payloads.is_empty() {
+            return
+        }
+        let payload = self.payloads.join(config.delimiter.as_slice());
+        let metadata = std::mem::take(&mut self.metadata);
+        self.payloads.clear();
+        self.first_received_at = None;
+        on_rcv_cb(config.module_handle, create_record(payload, metadata));
+    }
+}
+
+#[derive(Default)]
+pub struct JoinModule {
+    buffer: Arc<Mutex<JoinBuffer>>,
+    config: OnceCell<JoinConfig>,
+    is_shut_down: Arc<AtomicBool>,
+}
+
+impl BuiltinModule for JoinModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let count: Option<usize> = get_arg(args, "count")?;
+        let interval: Option<u64> = get_arg(args, "interval_ms")?;
+        if count.is_none() && interval.is_none() {
+            return Err(format!("Module '{}' requires either 'count' or 'interval_ms' argument", MODULE_ID))
+        }
+        if count == Some(0) || interval == Some(0) {
+            return Err(String::from("Values of 'count' and 'interval_ms' must be positive"))
+        }
+        let config = JoinConfig {
+            module_handle,
+            count,
+            interval: interval.map(Duration::from_millis),
+            delimiter: args.get("delimiter").cloned().unwrap_or(String::from("\n")).into_bytes(),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), String> {
+        let config = match self.config.get() {
+            Some(c) => c.clone(),
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let interval = match config.interval {
+            Some(i) => i,
+            None => return Ok(()), // count-based only: no timer needed
+        };
+        let buffer = self.buffer.clone();
+        let is_shut_down = self.is_shut_down.clone();
+        thread::spawn(move || {
+            while !is_shut_down.load(Ordering::SeqCst) {
+                thread::sleep(TIMER_TICK.min(interval));
+                let mut buffer = buffer.lock().unwrap();
+                let is_expired = buffer.first_received_at
+                    .map(|t| t.elapsed() >= interval)
+                    .unwrap_or(false);
+                if is_expired {
+                    buffer.flush(&config);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.payloads.is_empty() {
+            buffer.metadata = get_metadata(&record);
+            buffer.first_received_at = Some(Instant::now());
+        }
+        buffer.payloads.push(get_payload(&record).to_vec());
+        if config.count.map(|c| buffer.payloads.len() >= c).unwrap_or(false) {
+            buffer.flush(config);
+        }
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        self.is_shut_down.store(true, Ordering::SeqCst);
+        if let Some(config) = self.config.get() {
+            self.buffer.lock().unwrap().flush(config);
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
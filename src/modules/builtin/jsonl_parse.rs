@@ -0,0 +1,161 @@
+/// `builtin.jsonl_parse`: parses a JSON-lines payload. Each line becomes a record with normalized JSON payload.
+/// Arguments:
+/// - `fields_to_metadata`: copy the top-level scalar fields of JSON objects into metadata. Default: false
+/// - `metadata_prefix`: a prefix of metadata keys for copied fields. Default: empty
+/// - `on_error`: `fail` (default) rejects the whole payload if some line is not a valid JSON, `skip` drops invalid lines
+///
+/// Empty lines are skipped. Each produced record gets a copy of metadata from the original record.
+
+use std::collections::HashMap;
+
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, split::split_by_delimiter, BuiltinModule}},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.jsonl_parse";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("fields_to_metadata", ArgType::Boolean),
+    ("metadata_prefix", ArgType::String),
+    ("on_error", ArgType::String),
+];
+
+/// What to do with the parts of payload which cannot be parsed
+#[derive(Clone, Copy, PartialEq)]
+pub enum ParseErrorPolicy {
+    Fail,
+    Skip,
+}
+
+impl ParseErrorPolicy {
+    /// Reads the policy from module arguments
+    pub fn from_args(args: &HashMap<String, String>) -> Result<ParseErrorPolicy, String> {
+        match args.get("on_error").map(|a| a.as_str()) {
+            None | Some("fail") => Ok(ParseErrorPolicy::Fail),
+            Some("skip") => Ok(ParseErrorPolicy::Skip),
+            Some(p) => Err(format!("Unknown error policy: '{}'", p)),
+        }
+    }
+}
+
+/// Where the parsed fields go in addition to payload
+pub struct FieldMapping {
+    /// If true, the top-level scalar fields are copied into metadata
+    pub fields_to_metadata: bool,
+    pub metadata_prefix: String,
+}
+
+impl FieldMapping {
+    pub fn from_args(args: &HashMap<String, String>) -> Result<FieldMapping, String> {
+        Ok(FieldMapping {
+            fields_to_metadata: get_arg(args, "fields_to_metadata")?.unwrap_or(false),
+            metadata_prefix: args.get("metadata_prefix").cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Creates a record from parsed object
+    pub fn create_record(&self, object: &Map<String, Value>, metadata: &HashMap<String, String>) -> Result<Record, String> {
+        let mut metadata = metadata.clone();
+        if self.fields_to_metadata {
+            for (key, value) in object {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    // Nested values and nulls stay in payload only
+                    _ => continue,
+                };
+                metadata.insert(format!("{}{}", self.metadata_prefix, key), value);
+            }
+        }
+        match serde_json::to_vec(object) {
+            Ok(payload) => Ok(create_record(payload, metadata)),
+            Err(e) => Err(format!("Cannot serialize the parsed record: {}", e)),
+        }
+    }
+}
+
+struct JsonlParseConfig {
+    module_handle: ModuleHandle,
+    field_mapping: FieldMapping,
+    on_error: ParseErrorPolicy,
+}
+
+#[derive(Default)]
+pub struct JsonlParseModule {
+    config: OnceCell<JsonlParseConfig>,
+}
+
+impl BuiltinModule for JsonlParseModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let config = JsonlParseConfig {
+            module_handle,
+            field_mapping: FieldMapping::from_args(args)?,
+            on_error: ParseErrorPolicy::from_args(args)?,
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let metadata = get_metadata(&record);
+        // The whole payload is parsed first, so a failed payload doesn't produce partial output
+        let mut records: Vec<Record> = Vec::new();
+        for (i, line) in split_by_delimiter(get_payload(&record), b"\n").into_iter().enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                continue
+            }
+            let result = match serde_json::from_slice::<Value>(line) {
+                Ok(Value::Object(object)) => config.field_mapping.create_record(&object, &metadata),
+                Ok(value) => match serde_json::to_vec(&value) {
+                    Ok(payload) => Ok(create_record(payload, metadata.clone())),
+                    Err(e) => Err(format!("Cannot serialize the parsed record: {}", e)),
+                },
+                Err(e) => Err(format!("Line {} is not a valid JSON: {}", i + 1, e)),
+            };
+            match (result, config.on_error) {
+                (Ok(r), _) => records.push(r),
+                (Err(e), ParseErrorPolicy::Skip) => warn!("Skipping a line of payload. {}", e),
+                (Err(e), ParseErrorPolicy::Fail) => return Err(e),
+            }
+        }
+        for r in records {
+            on_rcv_cb(config.module_handle, r);
+        }
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
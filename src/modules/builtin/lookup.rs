@@ -0,0 +1,345 @@
+/// `builtin.lookup`: enriches records with fields of lookup table, e.g. maps a customer ID to name and region.
+/// Arguments:
+/// - `path`: a lookup table file. Required
+/// - `format`: `csv` or `json`. Default: detected by file extension
+/// - `key_column`: a column of table to match the records against. Required for CSV tables and JSON arrays
+/// - `key_metadata`: a metadata key of record with lookup key
+/// - `key_field`: a top-level field of JSON payload with lookup key. Used if `key_metadata` is not set
+/// - `fields`: comma-separated columns of table to add to record. Default: all columns except the key column
+/// - `target`: `metadata` (default) adds the fields to metadata, `payload` adds them to JSON object payload
+/// - `metadata_prefix`: a prefix of metadata keys for added fields. Default: empty
+/// - `on_miss`: `pass` (default) passes the records without match as is, `fail` rejects them
+/// - `reload_interval_ms`: if set, the file is checked with this interval and the table is reloaded once the file is changed
+///
+/// CSV tables must have a header. JSON tables are either an array of objects, or an object which maps keys to rows.
+/// If the reloaded file is invalid, the previous table is kept.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, csv_parse::parse_csv, get_arg, BuiltinModule}},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.lookup";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("path", ArgType::String),
+    ("format", ArgType::String),
+    ("key_metadata", ArgType::String),
+    ("key_field", ArgType::String),
+    ("key_column", ArgType::String),
+    ("target", ArgType::String),
+    ("on_miss", ArgType::String),
+    ("reload_interval_ms", ArgType::Integer),
+    ("fields", ArgType::String),
+    ("metadata_prefix", ArgType::String),
+];
+
+/// An interval of shutdown checks of reload thread
+const TIMER_TICK: Duration = Duration::from_millis(100);
+
+/// Rows of lookup table by key
+type LookupTable = HashMap<String, Map<String, Value>>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum TableFormat {
+    Csv,
+    Json,
+}
+
+/// Where the lookup key is read from
+enum LookupKey {
+    Metadata(String),
+    PayloadField(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LookupTarget {
+    Metadata,
+    Payload,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MissPolicy {
+    Pass,
+    Fail,
+}
+
+/// A source of lookup table
+#[derive(Clone)]
+struct TableSource {
+    path: String,
+    format: TableFormat,
+    key_column: Option<String>,
+}
+
+impl TableSource {
+    fn load(&self) -> Result<LookupTable, String> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Cannot read the lookup table '{}': {}", self.path, e)),
+        };
+        let result = match self.format {
+            TableFormat::Csv => self.parse_csv_table(&contents),
+            TableFormat::Json => self.parse_json_table(&contents),
+        };
+        result.map_err(|e| format!("Invalid lookup table '{}': {}", self.path, e))
+    }
+
+    fn parse_csv_table(&self, contents: &str) -> Result<LookupTable, String> {
+        let key_column = self.key_column.as_ref().ok_or("'key_column' argument is required for CSV tables")?;
+        let mut rows = parse_csv(contents, ',')?.into_iter();
+        let header = rows.next().ok_or("the table has no header")?;
+        if !header.contains(key_column) {
+            return Err(format!("the key column '{}' is not found in header", key_column))
+        }
+        let mut table = LookupTable::new();
+        for (i, row) in rows.enumerate() {
+            if row.len() != header.len() {
+                return Err(format!("row {} has {} fields, but {} columns are expected", i + 1, row.len(), header.len()))
+            }
+            let row: Map<String, Value> = header.iter().cloned().zip(row.into_iter().map(Value::String)).collect();
+            table.insert(value_to_string(&row[key_column]), row);
+        }
+        Ok(table)
+    }
+
+    fn parse_json_table(&self, contents: &str) -> Result<LookupTable, String> {
+        let rows: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+        let mut table = LookupTable::new();
+        match (rows, &self.key_column) {
+            (Value::Array(rows), Some(key_column)) => for (i, row) in rows.into_iter().enumerate() {
+                let row = match row {
+                    Value::Object(o) => o,
+                    _ => return Err(format!("row {} is not an object", i + 1)),
+                };
+                match row.get(key_column) {
+                    Some(k) => table.insert(value_to_string(k), row),
+                    None => return Err(format!("row {} has no key column '{}'", i + 1, key_column)),
+                };
+            },
+            (Value::Array(_), None) => return Err(String::from("'key_column' argument is required for arrays of rows")),
+            (Value::Object(rows), _) => for (key, row) in rows {
+                match row {
+                    Value::Object(o) => table.insert(key, o),
+                    _ => return Err(format!("row '{}' is not an object", key)),
+                };
+            },
+            _ => return Err(String::from("the table must be an array of objects or an object of rows")),
+        }
+        Ok(table)
+    }
+
+    fn get_modified_at(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+}
+
+struct LookupConfig {
+    module_handle: ModuleHandle,
+    source: TableSource,
+    key: LookupKey,
+    fields: Option<Vec<String>>,
+    target: LookupTarget,
+    metadata_prefix: String,
+    on_miss: MissPolicy,
+    reload_interval: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct LookupModule {
+    config: OnceCell<LookupConfig>,
+    table: Arc<RwLock<LookupTable>>,
+    is_shut_down: Arc<AtomicBool>,
+}
+
+impl BuiltinModule for LookupModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let path = match args.get("path") {
+            Some(p) => p.clone(),
+            None => return Err(format!("Module '{}' requires 'path' argument", MODULE_ID)),
+        };
+        let format = match args.get("format").map(|f| f.as_str()) {
+            Some("csv") => TableFormat::Csv,
+            Some("json") => TableFormat::Json,
+            Some(f) => return Err(format!("Unknown format of lookup table: '{}'. Expected: csv, json", f)),
+            None => match Path::new(&path).extension().and_then(|e| e.to_str()) {
+                Some("csv") => TableFormat::Csv,
+                Some("json") => TableFormat::Json,
+                _ => return Err(format!("Cannot detect the format of lookup table '{}'. Please set 'format' argument", path)),
+            },
+        };
+        let key = match (args.get("key_metadata"), args.get("key_field")) {
+            (Some(k), _) => LookupKey::Metadata(k.clone()),
+            (None, Some(f)) => LookupKey::PayloadField(f.clone()),
+            (None, None) => return Err(format!("Module '{}' requires either 'key_metadata' or 'key_field' argument", MODULE_ID)),
+        };
+        let target = match args.get("target").map(|t| t.as_str()) {
+            None | Some("metadata") => LookupTarget::Metadata,
+            Some("payload") => LookupTarget::Payload,
+            Some(t) => return Err(format!("Unknown target: '{}'. Expected: metadata, payload", t)),
+        };
+        let on_miss = match args.get("on_miss").map(|p| p.as_str()) {
+            None | Some("pass") => MissPolicy::Pass,
+            Some("fail") => MissPolicy::Fail,
+            Some(p) => return Err(format!("Unknown miss policy: '{}'. Expected: pass, fail", p)),
+        };
+        let reload_interval: Option<u64> = get_arg(args, "reload_interval_ms")?;
+        if reload_interval == Some(0) {
+            return Err(String::from("Value of 'reload_interval_ms' must be positive"))
+        }
+        let source = TableSource { path, format, key_column: args.get("key_column").cloned() };
+        let table = source.load()?;
+        info!("Loaded {} rows from lookup table '{}'", table.len(), source.path);
+        *self.table.write().unwrap() = table;
+        let config = LookupConfig {
+            module_handle,
+            source,
+            key,
+            fields: args.get("fields").map(|f| f.split(',').map(|c| c.trim().to_string()).collect()),
+            target,
+            metadata_prefix: args.get("metadata_prefix").cloned().unwrap_or_default(),
+            on_miss,
+            reload_interval: reload_interval.map(Duration::from_millis),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let interval = match config.reload_interval {
+            Some(i) => i,
+            None => return Ok(()), // the table is loaded once
+        };
+        let source = config.source.clone();
+        let table = self.table.clone();
+        let is_shut_down = self.is_shut_down.clone();
+        thread::spawn(move || {
+            let mut modified_at = source.get_modified_at();
+            let mut checked_at = Instant::now();
+            while !is_shut_down.load(Ordering::SeqCst) {
+                thread::sleep(TIMER_TICK.min(interval));
+                if checked_at.elapsed() < interval {
+                    continue
+                }
+                checked_at = Instant::now();
+                let current_modified_at = source.get_modified_at();
+                if current_modified_at == modified_at {
+                    continue
+                }
+                modified_at = current_modified_at;
+                match source.load() {
+                    Ok(t) => {
+                        debug!("Reloaded {} rows from lookup table '{}'", t.len(), source.path);
+                        *table.write().unwrap() = t;
+                    },
+                    Err(msg) => warn!("{}. The previous table is kept", msg),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = get_payload(&record);
+        let mut metadata = get_metadata(&record);
+        let key = match &config.key {
+            LookupKey::Metadata(k) => metadata.get(k).cloned(),
+            LookupKey::PayloadField(f) => serde_json::from_slice::<Value>(payload).ok()
+                .and_then(|p| p.get(f).map(value_to_string)),
+        };
+        let table = self.table.read().unwrap();
+        let row = match key.as_ref().and_then(|k| table.get(k)) {
+            Some(r) => r,
+            None if config.on_miss == MissPolicy::Pass => {
+                on_rcv_cb(config.module_handle, create_record(payload.to_vec(), metadata));
+                return Ok(false)
+            },
+            None => return Err(format!("No row is found in lookup table for key '{}'", key.unwrap_or_default())),
+        };
+        let key_column = config.source.key_column.as_ref();
+        let fields: Vec<(&String, &Value)> = row.iter()
+            .filter(|(column, _)| match &config.fields {
+                Some(f) => f.contains(column),
+                None => Some(*column) != key_column,
+            })
+            .collect();
+        let payload = match config.target {
+            LookupTarget::Metadata => {
+                for (column, value) in fields {
+                    metadata.insert(format!("{}{}", config.metadata_prefix, column), value_to_string(value));
+                }
+                payload.to_vec()
+            },
+            LookupTarget::Payload => {
+                let mut object = match serde_json::from_slice::<Value>(payload) {
+                    Ok(Value::Object(o)) => o,
+                    _ => return Err(String::from("The payload is not a JSON object, so the fields cannot be added to it")),
+                };
+                for (column, value) in fields {
+                    object.insert(column.clone(), value.clone());
+                }
+                match serde_json::to_vec(&object) {
+                    Ok(p) => p,
+                    Err(e) => return Err(format!("Cannot serialize the enriched record: {}", e)),
+                }
+            },
+        };
+        on_rcv_cb(config.module_handle, create_record(payload, metadata));
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        self.is_shut_down.store(true, Ordering::SeqCst);
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
+
+/// Converts a JSON value into string. Nested values are serialized into JSON
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
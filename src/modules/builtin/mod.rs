@@ -0,0 +1,119 @@
+/// Built-in pipeline modules.
+/// These modules are implemented in host application and cover the generic, format-agnostic operations
+/// which are needed in many pipelines, so there is no need to load a dynamic library for them
+
+pub mod capture;
+pub mod csv_parse;
+pub mod debug;
+pub mod dedup_hash;
+pub mod fixture;
+pub mod fixture_source;
+pub mod hash;
+pub mod join;
+pub mod jsonl_parse;
+pub mod lookup;
+pub mod shadow_sink;
+pub mod split;
+pub mod timing_model;
+pub mod volume_splitter;
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{modules::arg_types::ArgType, policy::ModulePosition};
+
+/// Module IDs of built-in modules start with this prefix
+pub const BUILTIN_MODULE_PREFIX: &str = "builtin.";
+
+/// A pipeline step module implemented in host application
+pub trait BuiltinModule: Send + Sync {
+    /// Returns the module ID
+    fn get_id(&self) -> String;
+
+    /// Configures the module using the step arguments
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String>;
+
+    /// Returns the positions in pipeline which the module supports. None means any position
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        None
+    }
+
+    /// Returns the types of arguments. Undeclared arguments are not checked by host
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        &[]
+    }
+
+    /// Returns an example configuration of step in YAML format, if any
+    fn get_example(&self) -> Option<String> {
+        None
+    }
+
+    /// Warms the module up using the sample records. Records are owned by host.
+    /// Host callbacks must not be called while priming, see `extensions::ModulePipelinePrimeFn`.
+    /// Built-in modules have nothing to warm up by default
+    fn prime(&self, _records: &[Record]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Commits the records processed so far. Called by host at commit boundaries of destination step
+    fn commit(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Starts the module routines, if any
+    fn start(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Processes the record.
+    /// Returns true if record is consumed by module i.e. must not be deallocated by host
+    fn process_record(&self, record: Record) -> Result<bool, String>;
+
+    /// Shuts the module down. Module must notify the host about termination
+    fn shutdown(&self);
+}
+
+/// Returns true if module ID refers to built-in module
+pub fn is_builtin_module(module_id: &str) -> bool {
+    module_id.starts_with(BUILTIN_MODULE_PREFIX)
+}
+
+/// Creates a new instance of built-in module
+pub fn create_builtin_module(module_id: &str) -> Result<Arc<dyn BuiltinModule>, String> {
+    match module_id {
+        capture::MODULE_ID => Ok(Arc::new(capture::CaptureModule::default())),
+        csv_parse::MODULE_ID => Ok(Arc::new(csv_parse::CsvParseModule::default())),
+        debug::MODULE_ID => Ok(Arc::new(debug::DebugModule::default())),
+        dedup_hash::MODULE_ID => Ok(Arc::new(dedup_hash::DedupHashModule::default())),
+        fixture_source::MODULE_ID => Ok(Arc::new(fixture_source::FixtureSourceModule::default())),
+        hash::MODULE_ID => Ok(Arc::new(hash::HashModule::default())),
+        join::MODULE_ID => Ok(Arc::new(join::JoinModule::default())),
+        jsonl_parse::MODULE_ID => Ok(Arc::new(jsonl_parse::JsonlParseModule::default())),
+        lookup::MODULE_ID => Ok(Arc::new(lookup::LookupModule::default())),
+        shadow_sink::MODULE_ID => Ok(Arc::new(shadow_sink::ShadowSinkModule::default())),
+        split::MODULE_ID => Ok(Arc::new(split::SplitModule::default())),
+        timing_model::MODULE_ID => Ok(Arc::new(timing_model::TimingModelModule::default())),
+        volume_splitter::MODULE_ID => Ok(Arc::new(volume_splitter::VolumeSplitterModule::default())),
+        _ => Err(format!("Unknown built-in module: {}", module_id)),
+    }
+}
+
+/// Returns an error if built-in module is configured as anything except the expected kind
+pub fn check_kind(module_id: &str, kind: &PipelineModuleKind, expected: ModulePosition) -> Result<(), String> {
+    match ModulePosition::from(kind) == expected {
+        true => Ok(()),
+        false => Err(format!("Built-in module '{}' can be used as {:?} only", module_id, expected)),
+    }
+}
+
+/// Parses an optional argument of built-in module
+pub fn get_arg<T: FromStr>(args: &HashMap<String, String>, key: &str) -> Result<Option<T>, String> {
+    match args.get(key) {
+        Some(v) => match v.parse::<T>() {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => Err(format!("Invalid value of argument '{}': '{}'", key, v)),
+        },
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,53 @@
+/// `builtin.shadow_sink`: the last step of shadow chain. Appended to the shadow chain by host.
+/// Discards records or passes them to comparison with the output of primary chain
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::on_step_terminate_cb,
+    modules::builtin::{check_kind, BuiltinModule},
+    pipeline::shadow::ShadowSide,
+    policy::ModulePosition,
+    xthread::SHADOW,
+};
+
+pub const MODULE_ID: &str = "builtin.shadow_sink";
+
+#[derive(Default)]
+pub struct ShadowSinkModule {
+    module_handle: OnceCell<ModuleHandle>,
+}
+
+impl BuiltinModule for ShadowSinkModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Destination])
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, _args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Destination)?;
+        if self.module_handle.set(module_handle).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        if let Some(shadow) = SHADOW.get() {
+            shadow.observe(ShadowSide::Shadow, &record);
+        }
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(module_handle) = self.module_handle.get() {
+            on_step_terminate_cb(*module_handle);
+        }
+    }
+}
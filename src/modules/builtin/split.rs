@@ -0,0 +1,176 @@
+/// `builtin.split`: splits a payload into multiple records.
+/// Arguments:
+/// - `mode`: `delimiter` (default) splits the payload by delimiter, `json_array` emits each element of JSON array
+/// - `delimiter`: a delimiter for `delimiter` mode. Default: line break
+/// - `skip_empty`: skip empty chunks. Default: true
+///
+/// Each produced record gets a copy of metadata from the original record.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, BuiltinModule}},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.split";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("mode", ArgType::String),
+    ("delimiter", ArgType::String),
+    ("skip_empty", ArgType::Boolean),
+];
+
+enum SplitMode {
+    Delimiter(Vec<u8>),
+    JsonArray,
+}
+
+struct SplitConfig {
+    module_handle: ModuleHandle,
+    mode: SplitMode,
+    skip_empty: bool,
+}
+
+#[derive(Default)]
+pub struct SplitModule {
+    config: OnceCell<SplitConfig>,
+}
+
+impl BuiltinModule for SplitModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let mode = match args.get("mode").map(|m| m.as_str()) {
+            None | Some("delimiter") => {
+                let delimiter = args.get("delimiter").cloned().unwrap_or(String::from("\n"));
+                if delimiter.is_empty() {
+                    return Err(String::from("Delimiter cannot be empty"))
+                }
+                SplitMode::Delimiter(delimiter.into_bytes())
+            },
+            Some("json_array") => SplitMode::JsonArray,
+            Some(m) => return Err(format!("Unknown split mode: '{}'", m)),
+        };
+        let config = SplitConfig {
+            module_handle,
+            mode,
+            skip_empty: get_arg(args, "skip_empty")?.unwrap_or(true),
+        };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = get_payload(&record);
+        let chunks: Vec<Vec<u8>> = match &config.mode {
+            SplitMode::Delimiter(delimiter) => split_by_delimiter(payload, delimiter)
+                .into_iter()
+                .map(|c| c.to_vec())
+                .collect(),
+            SplitMode::JsonArray => split_json_array(payload)?,
+        };
+        let metadata = get_metadata(&record);
+        for chunk in chunks {
+            if config.skip_empty && chunk.is_empty() {
+                continue
+            }
+            on_rcv_cb(config.module_handle, create_record(chunk, metadata.clone()));
+        }
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
+
+/// Splits the payload into chunks by delimiter. Empty chunks are kept. Delimiter must not be empty
+pub fn split_by_delimiter<'a>(payload: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut chunks: Vec<&[u8]> = Vec::new();
+    let mut chunk_start: usize = 0;
+    let mut i: usize = 0;
+    while i + delimiter.len() <= payload.len() {
+        if &payload[i..i + delimiter.len()] == delimiter {
+            chunks.push(&payload[chunk_start..i]);
+            i += delimiter.len();
+            chunk_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    chunks.push(&payload[chunk_start..]);
+    chunks
+}
+
+/// Splits a JSON array into serialized elements
+fn split_json_array(payload: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let value: serde_json::Value = match serde_json::from_slice(payload) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Cannot parse the payload as JSON: {}", e)),
+    };
+    let items = match value {
+        serde_json::Value::Array(items) => items,
+        _ => return Err(String::from("The payload is not a JSON array")),
+    };
+    items.iter()
+        .map(|item| serde_json::to_vec(item).map_err(|e| format!("Cannot serialize an array element: {}", e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_by_delimiter, split_json_array};
+
+    #[test]
+    fn splits_by_multi_byte_delimiter() {
+        let chunks = split_by_delimiter("a||b|c||".as_bytes(), b"||");
+        assert_eq!(chunks, vec![&b"a"[..], b"b|c", b""]);
+        let chunks = split_by_delimiter("x→y".as_bytes(), "→".as_bytes());
+        assert_eq!(chunks, vec![&b"x"[..], b"y"]);
+    }
+
+    #[test]
+    fn keeps_empty_chunks() {
+        assert_eq!(split_by_delimiter(b"", b"\n"), vec![&b""[..]]);
+        assert_eq!(split_by_delimiter(b"\n\na\n", b"\n"), vec![&b""[..], b"", b"a", b""]);
+        assert_eq!(split_by_delimiter(b"a", b"long delimiter"), vec![&b"a"[..]]);
+    }
+
+    #[test]
+    fn splits_json_array() {
+        let items = split_json_array(br#"[1, "two", {"three": [3]}]"#).unwrap();
+        assert_eq!(items, vec![b"1".to_vec(), br#""two""#.to_vec(), br#"{"three":[3]}"#.to_vec()]);
+        assert!(split_json_array(b"[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn fails_on_payload_which_is_not_json_array() {
+        assert_eq!(split_json_array(br#"{"a": 1}"#), Err(String::from("The payload is not a JSON array")));
+        assert!(split_json_array(b"").unwrap_err().starts_with("Cannot parse the payload as JSON"));
+    }
+}
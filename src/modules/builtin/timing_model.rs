@@ -0,0 +1,144 @@
+/// `builtin.timing_model`: a destination which doesn't write records anywhere,
+/// but spends the time sampled from latency distribution on each record. Used for pipeline simulation.
+/// Arguments:
+/// - `latency`: a latency distribution, milliseconds. Default: `fixed:0`. Supported distributions:
+///   - `fixed:<ms>`
+///   - `uniform:<min_ms>:<max_ms>`
+///   - `normal:<mean_ms>:<stddev_ms>`. Negative samples are truncated to zero
+///   - `exponential:<mean_ms>`
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::OnceCell;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::on_step_terminate_cb,
+    modules::builtin::{check_kind, BuiltinModule},
+    policy::ModulePosition,
+};
+
+pub const MODULE_ID: &str = "builtin.timing_model";
+
+/// A distribution of latencies, milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatencyDistribution {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Normal(f64, f64),
+    Exponential(f64),
+}
+
+impl TryFrom<&str> for LatencyDistribution {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let parts: Vec<&str> = value.split(':').collect();
+        let mut params: Vec<f64> = Vec::new();
+        for p in &parts[1..] {
+            match p.parse::<f64>() {
+                Ok(p) if p >= 0.0 => params.push(p),
+                _ => return Err(format!("Invalid parameter of latency distribution '{}': '{}'", value, p)),
+            }
+        }
+        match (parts[0], params.as_slice()) {
+            ("fixed", [ms]) => Ok(LatencyDistribution::Fixed(*ms)),
+            ("uniform", [min, max]) if min <= max => Ok(LatencyDistribution::Uniform(*min, *max)),
+            ("normal", [mean, stddev]) => Ok(LatencyDistribution::Normal(*mean, *stddev)),
+            ("exponential", [mean]) => Ok(LatencyDistribution::Exponential(*mean)),
+            _ => Err(format!("Invalid latency distribution: '{}'. Expected one of: fixed:<ms>, uniform:<min_ms>:<max_ms>, \
+                normal:<mean_ms>:<stddev_ms>, exponential:<mean_ms>", value)),
+        }
+    }
+}
+
+/// A xorshift pseudo-random number generator. Good enough for timing models
+struct Random(u64);
+
+impl Random {
+    fn new() -> Random {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        // Zero state would produce zeros only
+        Random(seed | 1)
+    }
+
+    /// Returns a number in range (0, 1]
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    }
+}
+
+impl LatencyDistribution {
+    fn sample(&self, random: &mut Random) -> Duration {
+        let ms = match *self {
+            LatencyDistribution::Fixed(ms) => ms,
+            LatencyDistribution::Uniform(min, max) => min + (max - min) * random.next(),
+            // Box-Muller transform
+            LatencyDistribution::Normal(mean, stddev) => mean + stddev
+                * (-2.0 * random.next().ln()).sqrt() * (2.0 * std::f64::consts::PI * random.next()).cos(),
+            LatencyDistribution::Exponential(mean) => -mean * random.next().ln(),
+        };
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
+
+struct TimingModelConfig {
+    module_handle: ModuleHandle,
+    latency: LatencyDistribution,
+}
+
+#[derive(Default)]
+pub struct TimingModelModule {
+    config: OnceCell<TimingModelConfig>,
+    random: Mutex<Option<Random>>,
+}
+
+impl BuiltinModule for TimingModelModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Destination])
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Destination)?;
+        let latency = match args.get("latency") {
+            Some(l) => LatencyDistribution::try_from(l.as_str())?,
+            None => LatencyDistribution::Fixed(0.0),
+        };
+        if self.config.set(TimingModelConfig { module_handle, latency }).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        *self.random.lock().unwrap() = Some(Random::new());
+        Ok(())
+    }
+
+    fn process_record(&self, _record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let latency = match self.random.lock().unwrap().as_mut() {
+            Some(r) => config.latency.sample(r),
+            None => Duration::ZERO,
+        };
+        thread::sleep(latency);
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
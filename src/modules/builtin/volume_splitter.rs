@@ -0,0 +1,162 @@
+/// `builtin.volume_splitter`: routes records by size, e.g. large payloads to an object storage and small ones to a stream.
+/// Arguments:
+/// - `threshold_bytes`: records larger than this size are large. Required
+/// - `size_metadata`: a metadata key of record with declared size in bytes, e.g. a content length
+/// - `size_field`: a top-level field of JSON payload with declared size in bytes. Used if `size_metadata` is not set
+/// - `large_class`: a class of large records. Default: `large`
+/// - `small_class`: a class of small records. Default: `small`
+///
+/// If neither `size_metadata` nor `size_field` is set, the size of payload is used.
+/// The class is written to the `class` metadata key, so the step routes records with `outputs`, see `pipeline::routing`.
+/// Records of unmapped class are sent to the next step, so usually only the large class is mapped.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::{arg_types::ArgType, builtin::{check_kind, get_arg, BuiltinModule}},
+    pipeline::routing::CLASS_METADATA_KEY,
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "builtin.volume_splitter";
+
+const ARG_TYPES: &[(&str, ArgType)] = &[
+    ("threshold_bytes", ArgType::Integer),
+    ("size_metadata", ArgType::String),
+    ("size_field", ArgType::String),
+    ("large_class", ArgType::String),
+    ("small_class", ArgType::String),
+];
+
+const EXAMPLE: &str = r#"# Large records are written to object storage, small ones are sent to the next step
+- name: split_by_volume
+  handler: builtin.volume_splitter
+  args:
+    threshold_bytes: 262144
+    # size_metadata: content_length
+    # size_field: size
+    # large_class: large
+    # small_class: small
+  outputs:
+    large: object_storage
+- name: stream
+  handler: kafka_destination
+- name: object_storage
+  handler: s3_destination
+"#;
+
+/// Where the size of record is read from
+enum RecordSize {
+    Payload,
+    Metadata(String),
+    PayloadField(String),
+}
+
+struct VolumeSplitterConfig {
+    module_handle: ModuleHandle,
+    threshold_bytes: u64,
+    size: RecordSize,
+    large_class: String,
+    small_class: String,
+}
+
+#[derive(Default)]
+pub struct VolumeSplitterModule {
+    config: OnceCell<VolumeSplitterConfig>,
+}
+
+impl BuiltinModule for VolumeSplitterModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_arg_types(&self) -> &'static [(&'static str, ArgType)] {
+        ARG_TYPES
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let threshold_bytes: u64 = match get_arg(args, "threshold_bytes")? {
+            Some(t) => t,
+            None => return Err(format!("Module '{}' requires 'threshold_bytes' argument", MODULE_ID)),
+        };
+        let size = match (args.get("size_metadata"), args.get("size_field")) {
+            (Some(k), _) => RecordSize::Metadata(k.clone()),
+            (None, Some(f)) => RecordSize::PayloadField(f.clone()),
+            (None, None) => RecordSize::Payload,
+        };
+        let large_class = args.get("large_class").cloned().unwrap_or(String::from("large"));
+        let small_class = args.get("small_class").cloned().unwrap_or(String::from("small"));
+        if large_class.is_empty() || small_class.is_empty() {
+            return Err(String::from("Classes of records cannot be empty"))
+        }
+        if large_class == small_class {
+            return Err(format!("Large and small records must have different classes, got '{}' for both", large_class))
+        }
+        let config = VolumeSplitterConfig { module_handle, threshold_bytes, size, large_class, small_class };
+        if self.config.set(config).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn get_example(&self) -> Option<String> {
+        Some(String::from(EXAMPLE))
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let payload = get_payload(&record);
+        let mut metadata = get_metadata(&record);
+        let size = match &config.size {
+            RecordSize::Payload => payload.len() as u64,
+            RecordSize::Metadata(k) => match metadata.get(k) {
+                Some(v) => v.trim().parse::<u64>()
+                    .map_err(|_| format!("Invalid size in metadata key '{}': '{}'", k, v))?,
+                None => return Err(format!("No size is found in metadata key '{}'", k)),
+            },
+            RecordSize::PayloadField(f) => get_size_field(payload, f)?,
+        };
+        let class = match size > config.threshold_bytes {
+            true => &config.large_class,
+            false => &config.small_class,
+        };
+        metadata.insert(String::from(CLASS_METADATA_KEY), class.clone());
+        on_rcv_cb(config.module_handle, create_record(payload.to_vec(), metadata));
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
+
+/// Reads the declared size from a top-level field of JSON payload. Numbers and numeric strings are accepted
+fn get_size_field(payload: &[u8], field: &str) -> Result<u64, String> {
+    let value = match serde_json::from_slice::<Value>(payload) {
+        Ok(v) => v.get(field).cloned(),
+        Err(e) => return Err(format!("Cannot parse the payload as JSON: {}", e)),
+    };
+    let size = match &value {
+        Some(Value::Number(n)) => n.as_u64(),
+        Some(Value::String(s)) => s.trim().parse::<u64>().ok(),
+        Some(_) => None,
+        None => return Err(format!("No size is found in payload field '{}'", field)),
+    };
+    size.ok_or_else(|| format!("Invalid size in payload field '{}': {}", field, value.unwrap_or_default()))
+}
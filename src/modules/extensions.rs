@@ -0,0 +1,93 @@
+/// Optional FFI extensions.
+/// Libraries may export these symbols in addition to the mandatory module API.
+/// The host uses them if they are present, so existing libraries stay compatible
+
+use std::ffi::c_void;
+
+use torustiq_common::ffi::types::{module::{ModuleHandle, Record}, std_types::ConstCharPtr};
+
+/// Statistics of pipeline step
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StepStats {
+    pub records_received: u64,
+    pub records_succeeded: u64,
+    pub records_failed: u64,
+    pub queue_depth: u64,
+}
+
+/// A host function which writes statistics of step into provided structure.
+/// Returns false if there is no step with provided handle or the pointer is null.
+/// The caller must pass a pointer to a valid writable structure
+pub type HostGetStepStatsFn = unsafe extern "C" fn(ModuleHandle, *mut StepStats) -> bool;
+
+/// `torustiq_lib_listener_set_stats_fn`: passes the statistics function to listener library
+pub type LibListenerSetStatsFn = extern "C" fn(HostGetStepStatsFn);
+
+/// `torustiq_module_listener_record_rcv_verdict`: called instead of `torustiq_module_listener_record_rcv` if exported.
+/// Returns a verdict on the received record, e.g. `flag_for_audit`, which the host adds to the record metadata.
+/// A null pointer or an empty string means no verdict. The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleListenerRecordRcvVerdictFn = extern "C" fn(ModuleHandle, *const Record) -> ConstCharPtr;
+
+/// `torustiq_module_listener_record_dropped`: called once the host drops a record, with a reason code, e.g. `expired`.
+/// If not exported, the record is passed to `torustiq_module_listener_record_send_failure` with the reason in metadata
+pub type ModuleListenerRecordDroppedFn = extern "C" fn(ModuleHandle, *const Record, ConstCharPtr);
+
+/// A host function which returns true if the step should stop its current work as soon as possible,
+/// because it's paused or shut down. Modules may call it during long-running record processing
+pub type HostIsCancelledFn = extern "C" fn(ModuleHandle) -> bool;
+
+/// `torustiq_lib_pipeline_set_cancellation_fn`: passes the cancellation check function to pipeline library
+pub type LibPipelineSetCancellationFn = extern "C" fn(HostIsCancelledFn);
+
+/// A host function which parks a record for delayed retry. The record is taken over by host in the same way
+/// as in data receive callback. Delay is in milliseconds; 0 means the default delay of step.
+/// Returns false if the record is dropped instead: retries are not configured for step or the attempts are exhausted
+pub type HostRetryRecordFn = extern "C" fn(ModuleHandle, Record, u64) -> bool;
+
+/// `torustiq_lib_pipeline_set_retry_fn`: passes the retry function to pipeline library
+pub type LibPipelineSetRetryFn = extern "C" fn(HostRetryRecordFn);
+
+/// A host function which passes the produced record to dependent step, as the data receive callback does.
+/// The step is identified by the context pointer received from `torustiq_module_pipeline_set_context`
+/// instead of the module handle. The record is taken over by host
+pub type HostContextRcvFn = unsafe extern "C" fn(*const c_void, Record);
+
+/// `torustiq_module_pipeline_set_context`: passes an opaque context pointer of step and the data receive function
+/// which accepts it. Called once the step is configured. The pointer stays valid until the application exits.
+/// Modules which don't export this symbol keep using the data receive callback from init arguments
+pub type ModulePipelineSetContextFn = extern "C" fn(ModuleHandle, *const c_void, HostContextRcvFn);
+
+/// `torustiq_module_pipeline_prime`: passes sample records to step after configuration and before the real traffic,
+/// so the module can warm up its caches or models. Records are owned by host and must not be retained by module.
+/// Module must not call the host callbacks while priming: the steps are not started yet, so the produced records
+/// and termination signals are rejected. Returns false if priming failed
+pub type ModulePipelinePrimeFn = extern "C" fn(ModuleHandle, *const Record, usize) -> bool;
+
+/// `torustiq_module_pipeline_commit`: marks a commit boundary of destination step. The records passed to step
+/// since the previous commit must be made durable, e.g. a transaction is committed or a file is flushed.
+/// Called by host between record processing calls. Returns false if commit failed
+pub type ModulePipelineCommitFn = extern "C" fn(ModuleHandle) -> bool;
+
+/// `torustiq_module_get_dependencies`: returns a manifest of native dependencies linked into library.
+/// One dependency per line in `name=version` format. Null means no manifest.
+/// The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleGetDependenciesFn = extern "C" fn() -> ConstCharPtr;
+
+/// `torustiq_module_get_example`: returns an example configuration of step or listener in YAML format,
+/// preferably with comments which document the arguments. Null means no example.
+/// The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleGetExampleFn = extern "C" fn() -> ConstCharPtr;
+
+/// `torustiq_module_get_positions`: returns the positions in pipeline which the module supports, separated by comma:
+/// `source`, `transformation`, `destination`. Null means any position. The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleGetPositionsFn = extern "C" fn() -> ConstCharPtr;
+
+/// `torustiq_module_get_arg_types`: returns the types of module arguments. One argument per line in `name=type` format,
+/// where type is `string`, `integer`, `float`, `boolean`, `list` or `map`. The host rejects the steps and listeners
+/// whose arguments don't match the types. Null means no declared types. The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleGetArgTypesFn = extern "C" fn() -> ConstCharPtr;
+
+/// `torustiq_module_get_version`: returns a semantic version of module, e.g. `1.2.0`. Null means no version.
+/// The string is deallocated by `torustiq_module_common_free_char`
+pub type ModuleGetVersionFn = extern "C" fn() -> ConstCharPtr;
@@ -0,0 +1,88 @@
+/// `json.select`: keeps the selected top-level fields of JSON objects and drops the rest.
+/// Fields which are missing in the record are skipped. Records which are not JSON objects are rejected.
+/// Arguments:
+/// - `fields`: a comma-separated list of fields to keep
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use torustiq_common::ffi::types::module::{ModuleHandle, PipelineModuleKind, Record};
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    modules::builtin::{check_kind, BuiltinModule},
+    policy::ModulePosition,
+    records::{create_record, get_metadata, get_payload},
+};
+
+pub const MODULE_ID: &str = "json.select";
+
+const EXAMPLE: &str = r#"- name: select_fields
+  handler: json.select
+  args:
+    fields: id,name,updated_at
+"#;
+
+struct JsonSelectConfig {
+    module_handle: ModuleHandle,
+    fields: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct JsonSelectModule {
+    config: OnceCell<JsonSelectConfig>,
+}
+
+impl BuiltinModule for JsonSelectModule {
+    fn get_id(&self) -> String {
+        String::from(MODULE_ID)
+    }
+
+    fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        Some(vec![ModulePosition::Transformation])
+    }
+
+    fn get_example(&self) -> Option<String> {
+        Some(String::from(EXAMPLE))
+    }
+
+    fn configure(&self, module_handle: ModuleHandle, kind: &PipelineModuleKind, args: &HashMap<String, String>) -> Result<(), String> {
+        check_kind(MODULE_ID, kind, ModulePosition::Transformation)?;
+        let fields: Vec<String> = match args.get("fields") {
+            Some(f) => f.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()).collect(),
+            None => return Err(format!("Module '{}' requires 'fields' argument", MODULE_ID)),
+        };
+        if fields.is_empty() {
+            return Err(format!("Module '{}': 'fields' argument is empty", MODULE_ID))
+        }
+        if self.config.set(JsonSelectConfig { module_handle, fields }).is_err() {
+            return Err(format!("Module '{}' is already configured", MODULE_ID))
+        }
+        Ok(())
+    }
+
+    fn process_record(&self, record: Record) -> Result<bool, String> {
+        let config = match self.config.get() {
+            Some(c) => c,
+            None => return Err(format!("Module '{}' is not configured", MODULE_ID)),
+        };
+        let mut object = match serde_json::from_slice::<Value>(get_payload(&record)) {
+            Ok(Value::Object(o)) => o,
+            Ok(_) => return Err(String::from("Payload is not a JSON object")),
+            Err(e) => return Err(format!("Cannot parse the payload as JSON: {}", e)),
+        };
+        let selected: Map<String, Value> = config.fields.iter()
+            .filter_map(|f| object.remove(f).map(|v| (f.clone(), v)))
+            .collect();
+        let payload = serde_json::to_vec(&Value::Object(selected)).map_err(|e| e.to_string())?;
+        on_rcv_cb(config.module_handle, create_record(payload, get_metadata(&record)));
+        Ok(false)
+    }
+
+    fn shutdown(&self) {
+        if let Some(config) = self.config.get() {
+            on_step_terminate_cb(config.module_handle);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+/// First-party native modules.
+/// These modules are compiled into the application with `first-party-modules` feature and registered on startup
+/// as native modules, so performance-critical steps avoid FFI overhead
+
+pub mod json_select;
+
+use std::sync::Arc;
+
+use crate::modules::native::register_native_module;
+
+/// Registers all first-party modules as native modules
+pub fn register_first_party_modules() -> Result<(), String> {
+    register_native_module(json_select::MODULE_ID, || Arc::new(json_select::JsonSelectModule::default()))?;
+    Ok(())
+}
@@ -15,7 +15,8 @@ use torustiq_common::ffi::{
 
 use crate::{
     callbacks,
-    modules::{BaseModule, LibInfo}
+    modules::{extensions, BaseModule, LibInfo},
+    pipeline::handle::ModuleHandle,
 };
 
 /// An event listener module.
@@ -32,6 +33,12 @@ pub struct ListenerModule {
     pub record_send_success_ptr: RawSymbol<fn_defs::ModuleListenerRecordSendSuccessFn>,
     /// A pointer to message send handler (failure)
     pub record_send_failure_ptr: RawSymbol<fn_defs::ModuleListenerRecordSendFailureFn>,
+    /// Optional: receives a function to query the step statistics
+    pub set_stats_fn_ptr: Option<RawSymbol<extensions::LibListenerSetStatsFn>>,
+    /// Optional: a message receive handler which returns a verdict on record
+    pub record_rcv_verdict_ptr: Option<RawSymbol<extensions::ModuleListenerRecordRcvVerdictFn>>,
+    /// Optional: a handler of records dropped by host
+    pub record_dropped_ptr: Option<RawSymbol<extensions::ModuleListenerRecordDroppedFn>>,
 }
 
 impl ListenerModule {
@@ -40,7 +47,10 @@ impl ListenerModule {
             common: module_types::LibCommonInitArgs {
                 on_step_terminate_cb: callbacks::on_step_terminate_cb,
             },
-        })
+        });
+        if let Some(set_stats_fn) = &self.set_stats_fn_ptr {
+            set_stats_fn(callbacks::get_step_stats_cb);
+        }
     }
 
     pub fn get_id(&self) -> String {
@@ -62,8 +72,8 @@ impl ListenerModule {
         }
     }
 
-    pub fn start(&self, module_handle: usize) -> Result<(), String> {
-        match (self.base.start_ptr)(usize::try_into(module_handle).unwrap()) {
+    pub fn start(&self, module_handle: ModuleHandle) -> Result<(), String> {
+        match (self.base.start_ptr)(module_handle.to_ffi()) {
             module_types::StepStartFnResult::Ok => Ok(()),
             module_types::StepStartFnResult::ErrorMisc(e) => {
                 let err_string = cchar_to_string(e.clone());
@@ -73,16 +83,16 @@ impl ListenerModule {
         }
     }
 
-    pub fn set_param<S: Into<String>>(&self, handle: usize, k: S, v: S) {
+    pub fn set_param<S: Into<String>>(&self, handle: ModuleHandle, k: S, v: S) {
         let k = string_to_cchar(k);
         let v = string_to_cchar(v);
-        (self.base.set_param_ptr)(usize::try_into(handle).unwrap(), k, v);
+        (self.base.set_param_ptr)(handle.to_ffi(), k, v);
 
         cchar_const_deallocate(k);
         cchar_const_deallocate(v);
     }
 
-    pub fn shutdown(&self, module_handle: usize) {
-        (self.base.shutdown_ptr)(usize::try_into(module_handle).unwrap());
+    pub fn shutdown(&self, module_handle: ModuleHandle) {
+        (self.base.shutdown_ptr)(module_handle.to_ffi());
     }
 }
\ No newline at end of file
@@ -0,0 +1,96 @@
+/// A cache of library metadata.
+/// Opening every library in a large module directory slows down the startup. The metadata of libraries is cached
+/// in the module directory, so the libraries which are not used in pipeline are skipped without opening them.
+/// An entry is invalidated once the size or modification time of library file changes.
+
+use std::{collections::HashMap, fs, path::Path, time::UNIX_EPOCH};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::modules::{LibInfo, ModuleKind};
+
+/// A name of cache file inside the module directory
+pub const CACHE_FILE_NAME: &str = ".torustiq_cache.yaml";
+
+/// Cached metadata of library
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CachedLibInfo {
+    pub id: String,
+    pub kind: ModuleKind,
+    pub api_version: u32,
+    /// Size of library file in bytes
+    pub size: u64,
+    /// Modification time of library file, nanoseconds since UNIX epoch
+    pub modified_at: u64,
+}
+
+impl CachedLibInfo {
+    /// Creates a cache entry. Returns None if metadata of file cannot be read
+    pub fn new(path: &Path, lib_info: &LibInfo) -> Option<CachedLibInfo> {
+        let (size, modified_at) = get_file_fingerprint(path)?;
+        Some(CachedLibInfo {
+            id: lib_info.id.clone(),
+            kind: lib_info.kind.clone(),
+            api_version: lib_info.api_version,
+            size,
+            modified_at,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct MetadataCache {
+    /// Key is a path to library
+    pub libraries: HashMap<String, CachedLibInfo>,
+}
+
+impl MetadataCache {
+    /// Loads the cache from module directory. Returns an empty cache if there is no valid cache file
+    pub fn load(module_dir: &String) -> MetadataCache {
+        let path = Path::new(module_dir).join(CACHE_FILE_NAME);
+        let contents = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => return MetadataCache::default(),
+        };
+        match serde_yaml::from_str(contents.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                debug!("Ignoring the invalid metadata cache file '{}': {}", path.display(), e);
+                MetadataCache::default()
+            }
+        }
+    }
+
+    /// Saves the cache into module directory
+    pub fn save(&self, module_dir: &String) {
+        let path = Path::new(module_dir).join(CACHE_FILE_NAME);
+        let contents = match serde_yaml::to_string(self) {
+            Ok(c) => c,
+            Err(e) => return warn!("Cannot serialize the metadata cache: {}", e),
+        };
+        if let Err(e) = fs::write(&path, contents) {
+            warn!("Cannot write the metadata cache file '{}': {}", path.display(), e);
+        }
+    }
+
+    /// Returns the cached metadata of library if library file is not modified since caching
+    pub fn get(&self, path: &Path) -> Option<&CachedLibInfo> {
+        let entry = self.libraries.get(&path.to_string_lossy().to_string())?;
+        match get_file_fingerprint(path) {
+            Some((size, modified_at)) if size == entry.size && modified_at == entry.modified_at => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, path: &Path, entry: CachedLibInfo) {
+        self.libraries.insert(path.to_string_lossy().to_string(), entry);
+    }
+}
+
+/// Returns the size and modification time of file
+fn get_file_fingerprint(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified_at = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified_at.as_nanos() as u64))
+}
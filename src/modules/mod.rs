@@ -1,24 +1,50 @@
+pub mod arg_types;
+pub mod builtin;
+pub mod extensions;
+#[cfg(feature = "first-party-modules")]
+pub mod first_party;
 pub mod listener;
+pub mod metadata_cache;
 pub mod module_loader;
+pub mod native;
 pub mod pipeline;
 
+use std::collections::HashMap;
+
 #[cfg(unix)]
 use libloading::os::unix::Symbol as RawSymbol;
 #[cfg(windows)]
 use libloading::os::windows::Symbol as RawSymbol;
+use semver::Version;
+use serde::{Deserialize, Serialize};
 
 
 use torustiq_common::ffi::{
     types::{
         functions as fn_defs,
         module::{LibInfo as FfiLibInfo, ModuleKind as FfiModuleKind, StepStartFnResult},
+        std_types::ConstCharPtr,
     },
     utils::strings::{cchar_const_deallocate, cchar_to_string, string_to_cchar}
 };
 
+use crate::{modules::arg_types::ArgType, pipeline::handle::ModuleHandle, policy::ModulePosition};
+
+
+/// Converts the string returned by optional getter of module and deallocates it.
+/// Returns None if the pointer is null, i.e. module doesn't provide the value
+pub fn take_module_string(ptr: ConstCharPtr, free: impl Fn(ConstCharPtr)) -> Option<String> {
+    if ptr.is_null() {
+        return None
+    }
+    let value = cchar_to_string(ptr);
+    free(ptr);
+    Some(value)
+}
 
 /// Defines the kind of module.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
 pub enum ModuleKind {
     /// An event listener module. Reacts to application events.
     Listener,
@@ -37,6 +63,14 @@ pub struct LibInfo {
     pub kind: ModuleKind,
     /// A human-readable name. Unlike ID, it might contain multiple words
     pub name: String,
+    /// A semantic version of module. Provided by libraries which export `torustiq_module_get_version`
+    pub version: Option<Version>,
+    /// Positions in pipeline which the module supports. Provided by libraries which export `torustiq_module_get_positions`.
+    /// None means any position
+    pub positions: Option<Vec<ModulePosition>>,
+    /// Types of arguments. Provided by libraries which export `torustiq_module_get_arg_types`.
+    /// Undeclared arguments are not checked
+    pub arg_types: HashMap<String, ArgType>,
 }
 
 impl From<FfiModuleKind> for ModuleKind {
@@ -55,6 +89,9 @@ impl From<FfiLibInfo> for LibInfo {
             id: cchar_to_string(value.id),
             kind: value.kind.into(),
             name: cchar_to_string(value.name),
+            version: None,
+            positions: None,
+            arg_types: HashMap::new(),
         }
     }
 }
@@ -66,6 +103,10 @@ pub struct BaseModule {
     pub shutdown_ptr: RawSymbol<fn_defs::ModuleStepShutdownFn>,
     pub start_ptr: RawSymbol<fn_defs::StepStartFn>,
     pub free_char_ptr: RawSymbol<fn_defs::ModuleFreeCharPtrFn>,
+    /// Optional: returns a manifest of native dependencies
+    pub get_dependencies_ptr: Option<RawSymbol<extensions::ModuleGetDependenciesFn>>,
+    /// Optional: returns an example configuration
+    pub get_example_ptr: Option<RawSymbol<extensions::ModuleGetExampleFn>>,
 
     module_info: LibInfo,
 }
@@ -75,21 +116,21 @@ impl BaseModule {
         &self.module_info
     }
 
-    pub fn shutdown(&self, module_handle: usize) {
-        (self.shutdown_ptr)(usize::try_into(module_handle).unwrap());
+    pub fn shutdown(&self, module_handle: ModuleHandle) {
+        (self.shutdown_ptr)(module_handle.to_ffi());
     }
 
-    pub fn set_param<S: Into<String>>(&self, handle: usize, k: S, v: S) {
+    pub fn set_param<S: Into<String>>(&self, handle: ModuleHandle, k: S, v: S) {
         let k = string_to_cchar(k);
         let v = string_to_cchar(v);
-        (self.set_param_ptr)(usize::try_into(handle).unwrap(), k, v);
+        (self.set_param_ptr)(handle.to_ffi(), k, v);
 
         cchar_const_deallocate(k);
         cchar_const_deallocate(v);
     }
 
-    pub fn start(&self, module_handle: usize) -> Result<(), String> {
-        match (self.start_ptr)(usize::try_into(module_handle).unwrap()) {
+    pub fn start(&self, module_handle: ModuleHandle) -> Result<(), String> {
+        match (self.start_ptr)(module_handle.to_ffi()) {
             StepStartFnResult::Ok => Ok(()),
             StepStartFnResult::ErrorMisc(e) => {
                 let err_string = cchar_to_string(e.clone());
@@ -102,4 +143,22 @@ impl BaseModule {
     pub fn free_c_char(&self, c: *const i8) {
         (self.free_char_ptr)(c);
     }
+
+    /// Returns an example configuration, if library provides it
+    pub fn get_example(&self) -> Option<String> {
+        let get_example = self.get_example_ptr.as_ref()?;
+        take_module_string(get_example(), |p| self.free_c_char(p))
+    }
+
+    /// Returns native dependencies of library as (name, version) pairs.
+    /// The list is empty if library doesn't provide a dependency manifest
+    pub fn get_dependencies(&self) -> Vec<(String, String)> {
+        let manifest = self.get_dependencies_ptr.as_ref()
+            .and_then(|get_dependencies| take_module_string(get_dependencies(), |p| self.free_c_char(p)));
+        manifest.unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().split_once('='))
+            .map(|(name, version)| (name.trim().to_string(), version.trim().to_string()))
+            .collect()
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, sync::Arc};
+use std::{collections::HashMap, fs, sync::Arc, time::Instant};
 use std::error::Error;
 
 use libloading::{Library, Symbol};
@@ -8,17 +8,21 @@ use libloading::os::unix::Symbol as RawSymbol;
 use libloading::os::windows::Symbol as RawSymbol;
 
 use log::{debug, info, warn};
+use semver::Version;
 
 use torustiq_common::{
-    ffi::{
-        types::functions as fn_defs,
-        utils::strings::cchar_to_string,
-    },
+    ffi::types::functions as fn_defs,
     CURRENT_API_VERSION
 };
 
+use crate::config::ModuleReference;
+use crate::errors::TorustiqError;
+use crate::policy::ModulePosition;
+use crate::metrics::record_module_load_time;
 use crate::modules::{
-    BaseModule, LibInfo, ModuleKind,
+    BaseModule, LibInfo, ModuleKind, extensions, take_module_string,
+    arg_types::parse_arg_types,
+    metadata_cache::{CachedLibInfo, MetadataCache, CACHE_FILE_NAME},
     pipeline::PipelineModule,
     listener::ListenerModule
 };
@@ -34,54 +38,104 @@ pub struct LoadedLibraries {
 impl LoadedLibraries {
     pub fn init(&self) {
         info!("Initialization of libraries...");
+        // The same library might be referenced by multiple handlers, but it must be initialized once
+        let mut initialized: Vec<String> = Vec::new();
         for lib in self.listeners.values() {
+            if !mark_initialized(&mut initialized, lib.get_info()) {
+                continue
+            }
             debug!("Initializing event listener library '{}' (name: '{}')...", lib.get_info().id, lib.get_info().name);
             lib.init();
         }
         for lib in self.pipeline.values() {
+            if !mark_initialized(&mut initialized, lib.get_info()) {
+                continue
+            }
             debug!("Initializing step library '{}' (name: '{}')...", lib.get_info().id, lib.get_info().name);
             lib.init();
         }
     }
 }
 
-/// Returns a HashMap of modules referenced in the pipeline definition
-pub fn load_libraries(module_dir: &String, required_module_ids: Vec<String>) -> Result<LoadedLibraries, String> {
+/// Adds the library to the list of initialized ones. Returns false if library is already in list
+fn mark_initialized(initialized: &mut Vec<String>, info: &LibInfo) -> bool {
+    let key = format!("{}@{:?}", info.id, info.version);
+    if initialized.contains(&key) {
+        return false
+    }
+    initialized.push(key);
+    true
+}
+
+fn module_load_error(module_id: Option<&String>, message: String) -> TorustiqError {
+    TorustiqError::ModuleLoad { module_id: module_id.cloned(), message }
+}
+
+/// Returns a HashMap of modules referenced in the pipeline definition.
+/// Loaded modules are keyed by handlers. If multiple libraries provide the same module,
+/// the library of the highest version which satisfies the handler's version requirement is picked
+pub fn load_libraries(module_dir: &String, required_handlers: Vec<String>) -> Result<LoadedLibraries, TorustiqError> {
     let mut loaded_libs = LoadedLibraries::default();
-    let mut loaded_module_ids: Vec<String> = Vec::new();
+    let required_modules: Vec<(String, ModuleReference)> = required_handlers
+        .into_iter()
+        .map(|h| ModuleReference::parse(&h).map(|r| (h, r)))
+        .collect::<Result<_, _>>()
+        .map_err(|e| module_load_error(None, e))?;
+    let required_module_ids: Vec<String> = required_modules.iter().map(|(_, r)| r.id.clone()).collect();
+    // Versions of libraries found for each module ID. Used for error messages
+    let mut found_versions: HashMap<String, Vec<String>> = HashMap::new();
+    let cache = MetadataCache::load(module_dir);
+    let mut updated_cache = MetadataCache::default();
 
     let dir = match fs::read_dir(module_dir) {
         Ok(d) => d,
-        Err(e) => return Err(format!("Cannot open directory '{}': {}", module_dir, e))
+        Err(e) => return Err(module_load_error(None, format!("Cannot open directory '{}': {}", module_dir, e)))
     };
     for entry in dir {
         let entry = match entry {
             Ok(e) => e,
-            Err(e) => return Err(format!("Failed to load an entry: {}", e)),
+            Err(e) => return Err(module_load_error(None, format!("Failed to load an entry: {}", e))),
         };
         let path = entry.path();
+        if entry.file_name() == CACHE_FILE_NAME {
+            continue
+        }
         let path_str = match path.clone().into_os_string().into_string() {
             Ok(p) => p,
-            Err(e) => return Err(format!("Failed to convert path into string: {:?}", e)),
+            Err(e) => return Err(module_load_error(None, format!("Failed to convert path into string: {:?}", e))),
         };
 
+        // Skip the libraries which are not needed without opening them, if their metadata is cached
+        if let Some(cached) = cache.get(&path) {
+            updated_cache.insert(&path, cached.clone());
+            if cached.api_version != CURRENT_API_VERSION || !required_module_ids.contains(&cached.id) {
+                debug!("Skipped library '{}' with module '{}' according to metadata cache", path_str, cached.id);
+                continue
+            }
+        }
+
+        let load_started_at = Instant::now();
         let (module_info, lib) = unsafe {
             let lib = match Library::new(&path) {
                 Ok(l) => l,
-                Err(e) => return Err(format!("Failed to load a library at path '{}': {}", path_str, e)),
+                Err(e) => return Err(module_load_error(None, format!("Failed to load a library at path '{}': {}", path_str, e))),
             };
             let torustiq_module_get_info: Symbol<fn_defs::LibGetInfoFn> = match lib.get(b"torustiq_module_get_info") {
                 Ok(s) => s,
-                Err(e) => return Err(format!("Failed to load function 'torustiq_module_get_info' from library '{}': {}", path_str, e)),
+                Err(e) => return Err(module_load_error(None, format!("Failed to load function 'torustiq_module_get_info' from library '{}': {}", path_str, e))),
             };
             (torustiq_module_get_info(), lib)
         };
+        let module_info: LibInfo = module_info.into();
+        if let Some(cached) = CachedLibInfo::new(&path, &module_info) {
+            updated_cache.insert(&path, cached);
+        }
         if module_info.api_version != CURRENT_API_VERSION {
             warn!("Library '{}' is skipped because it has API version {} which is incompatible with current application's API version {}",
                 path_str, module_info.api_version, CURRENT_API_VERSION);
             continue
         }
-        let module_id = cchar_to_string(module_info.id);
+        let module_id = module_info.id.clone();
         debug!("Module at path {} identified: {}", path_str, module_id);
         if !required_module_ids.contains(&module_id) {
             debug!("Skipped module '{}' because it doesn't exist in the pipeline", module_id);
@@ -90,35 +144,115 @@ pub fn load_libraries(module_dir: &String, required_module_ids: Vec<String>) ->
 
         let loaded_lib = match load_library(&lib) {
             Ok(l) => l,
-            Err(e) => return Err(format!("Failed to initialize a module from library '{}': {}", path_str, e)),
+            Err(e) => return Err(module_load_error(Some(&module_id), format!("Failed to initialize a module from library '{}': {}", path_str, e))),
         };
-        match loaded_lib {
-            LoadedLibrary::Pipeline(p) => {
-                loaded_libs.pipeline.insert(module_id.clone(), Arc::from(p));
-            },
-            LoadedLibrary::Listener(l) => {
-                loaded_libs.listeners.insert(module_id.clone(), Arc::from(l));
-            },
+        let version = loaded_lib.get_info().version.clone();
+        found_versions.entry(module_id.clone()).or_default()
+            .push(version.as_ref().map(|v| v.to_string()).unwrap_or(String::from("(no version)")));
+        let (pipeline_module, listener_module) = match loaded_lib {
+            LoadedLibrary::Pipeline(p) => (Some(Arc::from(p)), None),
+            LoadedLibrary::Listener(l) => (None, Some(Arc::from(l))),
         };
+        for (handler, reference) in &required_modules {
+            if reference.id != module_id || !reference.matches(version.as_ref()) {
+                continue
+            }
+            if let Some(m) = &pipeline_module {
+                let is_better = match loaded_libs.pipeline.get(handler) {
+                    Some(current) => is_newer(version.as_ref(), current.get_info().version.as_ref()),
+                    None => true,
+                };
+                if is_better {
+                    loaded_libs.pipeline.insert(handler.clone(), m.clone());
+                }
+            }
+            if let Some(m) = &listener_module {
+                let is_better = match loaded_libs.listeners.get(handler) {
+                    Some(current) => is_newer(version.as_ref(), current.get_info().version.as_ref()),
+                    None => true,
+                };
+                if is_better {
+                    loaded_libs.listeners.insert(handler.clone(), m.clone());
+                }
+            }
+        }
         loaded_libs.libs.push(lib);
-        loaded_module_ids.push(module_id.clone());
-        debug!("Module '{}' is loaded.", module_id);
+        record_module_load_time(&module_id, load_started_at.elapsed());
+        debug!("Library of module '{}' (version: {:?}) is loaded.", module_id, version);
     }
 
-    let missing_module_ids: Vec<String> = required_module_ids
-        .into_iter()
-        .filter(|item| !loaded_module_ids.contains(item))
-        .collect();
-    if missing_module_ids.len() > 0 {
-        for m in &missing_module_ids {
-            log::error!("An unknown module is detected in pipeline: {}", m);
+    let mut missing_handlers: Vec<String> = Vec::new();
+    for (handler, reference) in &required_modules {
+        if loaded_libs.pipeline.contains_key(handler) || loaded_libs.listeners.contains_key(handler) {
+            continue
+        }
+        match found_versions.get(&reference.id) {
+            Some(versions) => log::error!("No library of module '{}' satisfies the version requirement of handler '{}'. Found versions: {}",
+                reference.id, handler, versions.join(", ")),
+            None => log::error!("An unknown module is detected in pipeline: {}", reference.id),
         }
-        return Err(format!("Failed to load modules: {}", missing_module_ids.join(", ")));
+        missing_handlers.push(handler.clone());
+    }
+    if !missing_handlers.is_empty() {
+        // The module ID is reported only if it's unambiguous
+        let module_id = match missing_handlers.as_slice() {
+            [handler] => ModuleReference::parse(handler).ok().map(|r| r.id),
+            _ => None,
+        };
+        return Err(TorustiqError::ModuleLoad {
+            module_id,
+            message: format!("Failed to load modules: {}", missing_handlers.join(", ")),
+        });
     }
 
+    if updated_cache != cache {
+        updated_cache.save(module_dir);
+    }
+    check_dependency_conflicts(&loaded_libs);
     Ok(loaded_libs)
 }
 
+/// Returns true if candidate version is newer than current one. Any version is newer than no version
+fn is_newer(candidate: Option<&Version>, current: Option<&Version>) -> bool {
+    match (candidate, current) {
+        (Some(candidate), Some(current)) => candidate > current,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Warns if loaded libraries link different versions of the same native dependency.
+/// Such libraries might crash the application as they share the same process
+fn check_dependency_conflicts(loaded_libs: &LoadedLibraries) {
+    // dependency name -> version -> module IDs
+    let mut dependencies: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    let bases = loaded_libs.listeners.values().map(|l| &l.base)
+        .chain(loaded_libs.pipeline.values().map(|p| &p.base));
+    for base in bases {
+        for (name, version) in base.get_dependencies() {
+            dependencies
+                .entry(name).or_default()
+                .entry(version).or_default()
+                .push(base.get_info().id.clone());
+        }
+    }
+    for versions in dependencies.values_mut() {
+        for module_ids in versions.values_mut() {
+            module_ids.sort();
+            module_ids.dedup();
+        }
+    }
+    for (name, versions) in dependencies {
+        if versions.len() < 2 {
+            continue
+        }
+        let usages: Vec<String> = versions.iter()
+            .map(|(version, module_ids)| format!("{} (modules: {})", version, module_ids.join(", ")))
+            .collect();
+        warn!("Conflicting versions of native dependency '{}' are linked by modules: {}", name, usages.join("; "));
+    }
+}
+
 /// Loads a module from library
 fn load_library(lib: &Library) -> Result<LoadedLibrary, Box<dyn Error>> {
     let loader = RawPointerLoader::new(lib);
@@ -133,6 +267,11 @@ fn load_library(lib: &Library) -> Result<LoadedLibrary, Box<dyn Error>> {
             configure_ptr: loader.load(b"torustiq_module_pipeline_configure")?,
             process_record_ptr: loader.load(b"torustiq_module_pipeline_process_record")?,
             free_record_ptr: loader.load(b"torustiq_module_pipeline_free_record")?,
+            set_cancellation_fn_ptr: loader.load(b"torustiq_lib_pipeline_set_cancellation_fn").ok(),
+            set_retry_fn_ptr: loader.load(b"torustiq_lib_pipeline_set_retry_fn").ok(),
+            prime_ptr: loader.load(b"torustiq_module_pipeline_prime").ok(),
+            commit_ptr: loader.load(b"torustiq_module_pipeline_commit").ok(),
+            set_context_ptr: loader.load(b"torustiq_module_pipeline_set_context").ok(),
 
             base: create_base_module(lib, module_info)?,
         }),
@@ -142,6 +281,9 @@ fn load_library(lib: &Library) -> Result<LoadedLibrary, Box<dyn Error>> {
             record_rcv_ptr: loader.load(b"torustiq_module_listener_record_rcv")?,
             record_send_failure_ptr: loader.load(b"torustiq_module_listener_record_send_failure")?,
             record_send_success_ptr: loader.load(b"torustiq_module_listener_record_send_success")?,
+            set_stats_fn_ptr: loader.load(b"torustiq_lib_listener_set_stats_fn").ok(),
+            record_rcv_verdict_ptr: loader.load(b"torustiq_module_listener_record_rcv_verdict").ok(),
+            record_dropped_ptr: loader.load(b"torustiq_module_listener_record_dropped").ok(),
 
             base: create_base_module(lib, module_info)?,
         })
@@ -177,14 +319,59 @@ pub enum LoadedLibrary {
     Pipeline(PipelineModule),
 }
 
+impl LoadedLibrary {
+    pub fn get_info(&self) -> &LibInfo {
+        match self {
+            LoadedLibrary::Listener(l) => l.get_info(),
+            LoadedLibrary::Pipeline(p) => p.get_info(),
+        }
+    }
+}
+
 /// Consumes library + module info and creates an instace of base module
 fn create_base_module(lib: &Library, module_info: LibInfo) -> Result<BaseModule, Box<dyn Error>> {
     let loader = RawPointerLoader::new(&lib);
+    let free_char_ptr: RawSymbol<fn_defs::ModuleFreeCharPtrFn> = loader.load(b"torustiq_module_common_free_char")?;
+    let mut module_info = module_info;
+    let version = loader.load::<extensions::ModuleGetVersionFn>(b"torustiq_module_get_version").ok()
+        .and_then(|get_version| take_module_string(get_version(), |p| free_char_ptr(p)));
+    if let Some(version) = version {
+        match Version::parse(version.trim()) {
+            Ok(v) => module_info.version = Some(v),
+            Err(e) => warn!("Module '{}' has an invalid version '{}': {}", module_info.id, version, e),
+        };
+    }
+    let positions = loader.load::<extensions::ModuleGetPositionsFn>(b"torustiq_module_get_positions").ok()
+        .and_then(|get_positions| take_module_string(get_positions(), |p| free_char_ptr(p)));
+    if let Some(positions) = positions {
+        module_info.positions = Some(positions.split(',')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .filter_map(|p| match serde_yaml::from_str::<ModulePosition>(p) {
+                Ok(ModulePosition::Listener) | Err(_) => {
+                    warn!("Module '{}' declares an unknown position '{}'", module_info.id, p);
+                    None
+                },
+                Ok(p) => Some(p),
+            })
+            .collect());
+    }
+    let arg_types_manifest = loader.load::<extensions::ModuleGetArgTypesFn>(b"torustiq_module_get_arg_types").ok()
+        .and_then(|get_arg_types| take_module_string(get_arg_types(), |p| free_char_ptr(p)));
+    if let Some(manifest) = arg_types_manifest {
+        let (arg_types, errors) = parse_arg_types(&manifest);
+        for e in errors {
+            warn!("Module '{}' declares the argument types incorrectly: {}", module_info.id, e);
+        }
+        module_info.arg_types = arg_types;
+    }
     let m = BaseModule {
         set_param_ptr: loader.load(b"torustiq_module_common_set_param")?,
         shutdown_ptr: loader.load(b"torustiq_module_common_shutdown")?,
         start_ptr: loader.load(b"torustiq_module_common_start")?,
-        free_char_ptr: loader.load(b"torustiq_module_common_free_char")?,
+        free_char_ptr,
+        get_dependencies_ptr: loader.load(b"torustiq_module_get_dependencies").ok(),
+        get_example_ptr: loader.load(b"torustiq_module_get_example").ok(),
 
         module_info,
     };
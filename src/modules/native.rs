@@ -0,0 +1,61 @@
+/// Native modules: pipeline step modules implemented in Rust and compiled into the application.
+/// Native modules are called directly, without FFI, and share the pipeline machinery with library modules.
+/// Built-in modules are native modules with `builtin.` prefix. Other native modules, e.g. performance-critical
+/// first-party steps, are registered on startup with `register_native_module` under any module ID.
+/// Registered modules take precedence over libraries with the same module ID
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::modules::builtin::{create_builtin_module, is_builtin_module, BUILTIN_MODULE_PREFIX};
+
+/// Native modules implement the same trait as built-in modules
+pub use crate::modules::builtin::BuiltinModule as NativeModule;
+
+/// Creates a new instance of native module. Each step gets its own instance
+pub type NativeModuleFactory = fn() -> Arc<dyn NativeModule>;
+
+static NATIVE_MODULES: Lazy<RwLock<HashMap<String, NativeModuleFactory>>> = Lazy::new(|| {
+    RwLock::new(HashMap::new())
+});
+
+/// Registers a native module. Must be called before the pipeline is created
+pub fn register_native_module(module_id: &str, factory: NativeModuleFactory) -> Result<(), String> {
+    if is_builtin_module(module_id) {
+        return Err(format!("Cannot register native module '{}': prefix '{}' is reserved for built-in modules",
+            module_id, BUILTIN_MODULE_PREFIX))
+    }
+    let mut modules = NATIVE_MODULES.write().unwrap();
+    if modules.contains_key(module_id) {
+        return Err(format!("Native module '{}' is already registered", module_id))
+    }
+    modules.insert(module_id.to_string(), factory);
+    Ok(())
+}
+
+/// Registers the native modules which are compiled into the application. Called once on startup
+pub fn register_native_modules() -> Result<(), String> {
+    #[cfg(feature = "first-party-modules")]
+    crate::modules::first_party::register_first_party_modules()?;
+    Ok(())
+}
+
+/// Returns true if handler refers to built-in or registered native module
+pub fn is_native_module(handler: &str) -> bool {
+    is_builtin_module(handler) || NATIVE_MODULES.read().unwrap().contains_key(handler)
+}
+
+/// Creates a new instance of built-in or registered native module
+pub fn create_native_module(handler: &str) -> Result<Arc<dyn NativeModule>, String> {
+    if is_builtin_module(handler) {
+        return create_builtin_module(handler)
+    }
+    match NATIVE_MODULES.read().unwrap().get(handler) {
+        Some(factory) => Ok(factory()),
+        None => Err(format!("Unknown native module: {}", handler)),
+    }
+}
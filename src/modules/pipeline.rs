@@ -5,6 +5,9 @@ use libloading::os::unix::Symbol as RawSymbol;
 #[cfg(windows)]
 use libloading::os::windows::Symbol as RawSymbol;
 
+use std::ffi::c_void;
+
+use log::warn;
 use torustiq_common::ffi::{
     types::{
         functions as fn_defs,
@@ -15,7 +18,8 @@ use torustiq_common::ffi::{
 
 use crate::{
     callbacks,
-    modules::{BaseModule, LibInfo}
+    modules::{extensions, BaseModule, LibInfo},
+    pipeline::handle::ModuleHandle,
 };
 
 /// A pipeline step module.
@@ -29,6 +33,16 @@ pub struct PipelineModule {
     pub configure_ptr: RawSymbol<fn_defs::ModulePipelineConfigureFn>,
     pub process_record_ptr: RawSymbol<fn_defs::ModulePipelineProcessRecordFn>,
     pub free_record_ptr: RawSymbol<fn_defs::ModuleFreeRecordFn>,
+    /// Optional: receives a function to check if the current work of step should be cancelled
+    pub set_cancellation_fn_ptr: Option<RawSymbol<extensions::LibPipelineSetCancellationFn>>,
+    /// Optional: receives a function to park the records for delayed retry
+    pub set_retry_fn_ptr: Option<RawSymbol<extensions::LibPipelineSetRetryFn>>,
+    /// Optional: receives the sample records to warm up the step
+    pub prime_ptr: Option<RawSymbol<extensions::ModulePipelinePrimeFn>>,
+    /// Optional: commits the records passed to destination step
+    pub commit_ptr: Option<RawSymbol<extensions::ModulePipelineCommitFn>>,
+    /// Optional: receives the context pointer of step
+    pub set_context_ptr: Option<RawSymbol<extensions::ModulePipelineSetContextFn>>,
 }
 
 impl PipelineModule {
@@ -38,7 +52,13 @@ impl PipelineModule {
                 on_step_terminate_cb: callbacks::on_step_terminate_cb,
             },
             on_data_receive_cb: callbacks::on_rcv_cb,
-        })
+        });
+        if let Some(set_cancellation_fn) = &self.set_cancellation_fn_ptr {
+            set_cancellation_fn(callbacks::is_cancelled_cb);
+        }
+        if let Some(set_retry_fn) = &self.set_retry_fn_ptr {
+            set_retry_fn(callbacks::retry_record_cb);
+        }
     }
 
     pub fn get_id(&self) -> String {
@@ -66,24 +86,62 @@ impl PipelineModule {
         }
     }
 
-    pub fn start(&self, module_handle: usize) -> Result<(), String> {
+    /// Passes the context pointer of step to module. Does nothing if module doesn't accept the context
+    pub fn set_context(&self, module_handle: ModuleHandle, context: *const c_void) {
+        if let Some(set_context) = &self.set_context_ptr {
+            set_context(module_handle.to_ffi(), context, callbacks::on_rcv_ctx_cb);
+        }
+    }
+
+    pub fn start(&self, module_handle: ModuleHandle) -> Result<(), String> {
         self.base.start(module_handle)
     }
 
-    pub fn set_param<S: Into<String>>(&self, handle: usize, k: S, v: S) {
+    pub fn set_param<S: Into<String>>(&self, handle: ModuleHandle, k: S, v: S) {
         self.base.set_param(handle, k, v);
     }
 
-    pub fn process_record(&self, module_handle: usize, input: module_types::Record) -> module_types::ModulePipelineProcessRecordFnResult {
-        let i = usize::try_into(module_handle).unwrap();
-        (self.process_record_ptr)(i, input)
+    pub fn process_record(&self, module_handle: ModuleHandle, input: module_types::Record) -> module_types::ModulePipelineProcessRecordFnResult {
+        (self.process_record_ptr)(module_handle.to_ffi(), input)
+    }
+
+    /// Passes the sample records to step. If module doesn't support priming, the records are ignored
+    pub fn prime(&self, module_handle: ModuleHandle, records: &[module_types::Record]) -> Result<(), String> {
+        let prime = match &self.prime_ptr {
+            Some(p) => p,
+            None => {
+                warn!("Module '{}' doesn't support priming. Sample records are ignored", self.get_id());
+                return Ok(())
+            },
+        };
+        match prime(module_handle.to_ffi(), records.as_ptr(), records.len()) {
+            true => Ok(()),
+            false => Err(String::from("The module failed to process the sample records")),
+        }
+    }
+
+    /// Returns true if module accepts the commit boundaries from host
+    pub fn is_commit_supported(&self) -> bool {
+        self.commit_ptr.is_some()
+    }
+
+    /// Commits the records passed to step. Does nothing if module doesn't support commits
+    pub fn commit(&self, module_handle: ModuleHandle) -> Result<(), String> {
+        let commit = match &self.commit_ptr {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        match commit(module_handle.to_ffi()) {
+            true => Ok(()),
+            false => Err(String::from("The module failed to commit the records")),
+        }
     }
 
     pub fn free_record(&self, r: module_types::Record) {
         (self.free_record_ptr)(r);
     }
 
-    pub fn shutdown(&self, module_handle: usize) {
+    pub fn shutdown(&self, module_handle: ModuleHandle) {
         self.base.shutdown(module_handle);
     }
 
@@ -0,0 +1,123 @@
+/// Network settings of requests which are sent by host: webhooks, lineage events, module downloads.
+/// Requests go through the proxy from `network` section of pipeline, except for hosts listed in `no_proxy`.
+/// Certificates of additional certificate authorities are trusted in addition to the built-in ones,
+/// e.g. for corporate networks with TLS inspection. Modules make their own requests and are not affected
+
+use std::sync::Arc;
+
+use log::debug;
+use rustls::{pki_types::{pem::PemObject, CertificateDer}, ClientConfig, RootCertStore};
+
+use crate::{config::NetworkDefinition, xthread::NETWORK};
+
+/// HTTP agents of host
+pub struct Network {
+    /// Sends the requests through proxy, if proxy is set
+    proxied: ureq::Agent,
+    /// Sends the requests to hosts which are excluded from proxy
+    direct: ureq::Agent,
+    no_proxy: Vec<String>,
+}
+
+impl Network {
+    fn get_agent(&self, url: &str) -> ureq::Agent {
+        let host = get_host(url);
+        let is_direct = self.no_proxy.iter().any(|h| match h.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(h.as_str()),
+            None => h == "*" || *h == host,
+        });
+        match is_direct {
+            true => self.direct.clone(),
+            false => self.proxied.clone(),
+        }
+    }
+}
+
+/// Creates the HTTP agents from network settings of pipeline. Must be called before any request is sent
+pub fn init_network(definition: Option<&NetworkDefinition>) -> Result<(), String> {
+    let definition = match definition {
+        Some(d) => d,
+        None => return Ok(()),
+    };
+    let tls_config = match definition.ca_bundles.is_some() || definition.use_builtin_roots == Some(false) {
+        true => Some(create_tls_config(definition)?),
+        false => None,
+    };
+    let create_builder = || match &tls_config {
+        Some(c) => ureq::AgentBuilder::new().tls_config(c.clone()),
+        None => ureq::AgentBuilder::new(),
+    };
+    let direct = create_builder().build();
+    let proxied = match &definition.proxy {
+        Some(url) => match ureq::Proxy::new(url) {
+            Ok(p) => create_builder().proxy(p).build(),
+            Err(e) => return Err(format!("Invalid proxy URL: {}", e)),
+        },
+        None => direct.clone(),
+    };
+    let no_proxy = definition.no_proxy.clone().unwrap_or_default()
+        .into_iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    if NETWORK.set(Network { proxied, direct, no_proxy }).is_err() {
+        return Err(String::from("Network settings are already initialized"))
+    }
+    debug!("Network settings are initialized");
+    Ok(())
+}
+
+/// Returns an HTTP agent for URL. Without network settings, the default agent is returned
+pub fn get_agent(url: &str) -> ureq::Agent {
+    match NETWORK.get() {
+        Some(n) => n.get_agent(url),
+        None => ureq::agent(),
+    }
+}
+
+/// Creates a TLS configuration which trusts the certificate authorities from network settings
+fn create_tls_config(definition: &NetworkDefinition) -> Result<Arc<ClientConfig>, String> {
+    let mut roots = RootCertStore::empty();
+    if definition.use_builtin_roots.unwrap_or(true) {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    for path in definition.ca_bundles.iter().flatten() {
+        let certs = match CertificateDer::pem_file_iter(path) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Cannot read the CA bundle '{}': {}", path, e)),
+        };
+        let mut count = 0;
+        for cert in certs {
+            let cert = match cert {
+                Ok(c) => c,
+                Err(e) => return Err(format!("Invalid certificate in CA bundle '{}': {}", path, e)),
+            };
+            if let Err(e) = roots.add(cert) {
+                return Err(format!("Invalid certificate in CA bundle '{}': {}", path, e))
+            }
+            count += 1;
+        }
+        debug!("Loaded {} certificate(s) from CA bundle '{}'", count, path);
+    }
+    if roots.is_empty() {
+        return Err(String::from("No certificate authorities are trusted. Please set 'ca_bundles' or enable 'use_builtin_roots'"))
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = match ClientConfig::builder_with_provider(provider).with_safe_default_protocol_versions() {
+        Ok(b) => b.with_root_certificates(roots).with_no_client_auth(),
+        Err(e) => return Err(format!("Cannot create a TLS configuration: {}", e)),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Returns the lowercase host of URL without credentials and port
+fn get_host(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map(|(_, h)| h).unwrap_or(authority);
+    let host = match host_port.strip_prefix('[') {
+        // IPv6 address
+        Some(h) => h.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    host.to_lowercase()
+}
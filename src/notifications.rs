@@ -0,0 +1,54 @@
+/// Notifications about pipeline events.
+/// The host sends a POST request with JSON body to each webhook which is subscribed to the event.
+/// The events are also published to the event stream, see `events`
+
+use std::time::Duration;
+
+use log::{debug, error};
+use serde_json::json;
+
+use crate::{
+    config::{NotificationDefinition, NotificationEvent},
+    events::publish,
+    masking::mask_text,
+    network::get_agent,
+};
+
+/// A request body if no template is set in notification definition
+const DEFAULT_TEMPLATE: &str = r#"{"event": "{{event}}", "pipeline": "{{pipeline}}", "message": "{{message}}"}"#;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends the event to webhooks and to event stream. Failures are logged, as notifications must not affect the pipeline
+pub fn notify(notifications: &[NotificationDefinition], event: NotificationEvent, pipeline_name: &str, message: &str) {
+    let event_name = match event {
+        NotificationEvent::PipelineFailure => "pipeline_failure",
+        NotificationEvent::RuntimeExceeded => "runtime_exceeded",
+        NotificationEvent::StepStalled => "step_stalled",
+    };
+    publish(event_name, json!({"message": message}));
+    for notification in notifications {
+        if !notification.events.as_ref().map(|e| e.contains(&event)).unwrap_or(true) {
+            continue
+        }
+        let body = notification.template.as_deref().unwrap_or(DEFAULT_TEMPLATE)
+            .replace("{{event}}", &escape_json(event_name))
+            .replace("{{pipeline}}", &escape_json(pipeline_name))
+            .replace("{{message}}", &escape_json(&mask_text(message)));
+        let result = get_agent(&notification.url).post(&notification.url)
+            .timeout(REQUEST_TIMEOUT)
+            .set("Content-Type", "application/json")
+            .send_string(&body);
+        match result {
+            Ok(_) => debug!("Notification '{}' is sent to '{}'", event_name, notification.url),
+            Err(e) => error!("Failed to send notification '{}' to '{}': {}", event_name, notification.url, e),
+        }
+    }
+}
+
+/// Escapes the value to insert it into JSON string
+fn escape_json(value: &str) -> String {
+    // Serialized string is always quoted
+    let quoted = serde_json::to_string(value).unwrap_or(String::from("\"\""));
+    quoted[1..quoted.len() - 1].to_string()
+}
@@ -0,0 +1,244 @@
+/// Parameters of pipeline. The values are supplied at launch with `--param key=value` option,
+/// so one pipeline definition serves as a template of multiple jobs.
+/// Step and listener arguments reference the parameters as `{{ params.key }}`. If the whole argument is a reference,
+/// the argument gets the typed value, e.g. a number; otherwise the value is inserted into the text
+
+use std::collections::HashMap;
+
+use serde_yaml::{Number, Value};
+
+use crate::config::{ParamType, PipelineDefinition};
+
+const PARAMS_PREFIX: &str = "params.";
+
+/// Resolves the values of declared parameters and substitutes them into arguments of steps and listeners
+pub fn apply_params(definition: &mut PipelineDefinition, param_values: &[String]) -> Result<(), String> {
+    let declared = definition.params.clone().unwrap_or_default();
+    let mut supplied: HashMap<&str, &str> = HashMap::new();
+    for p in param_values {
+        let (key, value) = match p.split_once('=') {
+            Some(kv) => kv,
+            None => return Err(format!("Invalid parameter: '{}'. Expected format: key=value", p)),
+        };
+        if !declared.contains_key(key) {
+            return Err(format!("Parameter '{}' is not declared in 'params' section of pipeline", key))
+        }
+        supplied.insert(key, value);
+    }
+
+    let mut values: HashMap<String, Value> = HashMap::new();
+    for (name, param) in &declared {
+        let param_type = param.param_type.unwrap_or_default();
+        let value = match (supplied.get(name.as_str()), &param.default) {
+            (Some(v), _) => parse_value(v, param_type),
+            (None, Some(d)) => check_type(d, param_type),
+            (None, None) => return Err(format!("Parameter '{}' is required. Please set it with '--param {}=<value>' option", name, name)),
+        };
+        match value {
+            Ok(v) => values.insert(name.clone(), v),
+            Err(e) => return Err(format!("Invalid value of parameter '{}': {}", name, e)),
+        };
+    }
+
+    let shadow_steps = definition.shadow.iter_mut().flat_map(|s| s.steps.iter_mut());
+    let sampling_destination = definition.sampling.iter_mut().filter_map(|s| s.destination.as_mut());
+    let listeners = definition.listeners.iter_mut().flatten();
+    for module in definition.steps.iter_mut().chain(shadow_steps).chain(sampling_destination).chain(listeners) {
+        for (key, value) in module.args.iter_mut().flatten() {
+            if let Err(e) = substitute(value, &values) {
+                return Err(format!("Cannot substitute parameters into argument '{}' of module '{}': {}", key, module.name, e))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses the value supplied at launch
+fn parse_value(text: &str, param_type: ParamType) -> Result<Value, String> {
+    let value = match param_type {
+        ParamType::String => Some(Value::String(text.to_string())),
+        ParamType::Integer => text.parse::<i64>().ok().map(|i| Value::Number(Number::from(i))),
+        ParamType::Float => text.parse::<f64>().ok().map(|f| Value::Number(Number::from(f))),
+        ParamType::Boolean => text.parse::<bool>().ok().map(Value::Bool),
+    };
+    match value {
+        Some(v) => Ok(v),
+        None => Err(format!("'{}' is not a valid {}", text, param_type.get_name())),
+    }
+}
+
+/// Checks if the default value matches the type of parameter
+fn check_type(value: &Value, param_type: ParamType) -> Result<Value, String> {
+    let is_valid = match (param_type, value) {
+        (ParamType::String, Value::String(_)) | (ParamType::Boolean, Value::Bool(_)) | (ParamType::Float, Value::Number(_)) => true,
+        (ParamType::Integer, Value::Number(n)) => n.is_i64() || n.is_u64(),
+        _ => false,
+    };
+    match is_valid {
+        true => Ok(value.clone()),
+        false => Err(format!("the default value is not a valid {}", param_type.get_name())),
+    }
+}
+
+/// Replaces the parameter references in value. Lists and maps are processed recursively
+fn substitute(value: &mut Value, values: &HashMap<String, Value>) -> Result<(), String> {
+    match value {
+        Value::String(s) => match get_whole_reference(s) {
+            Some(name) => *value = get_value(name, values)?.clone(),
+            None => *s = replace_references(s, values)?,
+        },
+        Value::Sequence(items) => {
+            for item in items {
+                substitute(item, values)?;
+            }
+        },
+        Value::Mapping(m) => {
+            for (_, v) in m.iter_mut() {
+                substitute(v, values)?;
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+/// Returns the parameter name if the whole text is a single reference
+fn get_whole_reference(text: &str) -> Option<&str> {
+    let inner = text.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    match inner.contains("{{") || inner.contains("}}") {
+        true => None,
+        false => inner.trim().strip_prefix(PARAMS_PREFIX),
+    }
+}
+
+/// Inserts the values of referenced parameters into text. Placeholders other than `{{ params.* }}` are kept
+fn replace_references(text: &str, values: &HashMap<String, Value>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(e) => start + e,
+            None => break,
+        };
+        result.push_str(&rest[..start]);
+        match rest[start + 2..end].trim().strip_prefix(PARAMS_PREFIX) {
+            Some(name) => result.push_str(&value_to_text(get_value(name, values)?)),
+            None => result.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn get_value<'a>(name: &str, values: &'a HashMap<String, Value>) -> Result<&'a Value, String> {
+    match values.get(name) {
+        Some(v) => Ok(v),
+        None => Err(format!("parameter '{}' is not declared in 'params' section of pipeline", name)),
+    }
+}
+
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Value;
+
+    use super::apply_params;
+    use crate::config::PipelineDefinition;
+
+    const PIPELINE: &str = r#"
+params:
+  topic: {}
+  batch_size:
+    type: integer
+    default: 100
+  verbose:
+    type: boolean
+    default: false
+  unused:
+    default: x
+steps:
+  - name: src
+    handler: kafka_source
+    args:
+      topic: "{{ params.topic }}"
+      batch_size: "{{params.batch_size}}"
+      group: "reader-{{ params.topic }}-{{ params.batch_size }}"
+      template: "{{ record.id }} of {{ params.topic }}"
+      options:
+        verbose: "{{ params.verbose }}"
+        tags: ["{{ params.topic }}", fixed]
+  - name: dst
+    handler: stdout
+"#;
+
+    fn apply(param_values: &[&str]) -> Result<PipelineDefinition, String> {
+        let mut definition: PipelineDefinition = serde_yaml::from_str(PIPELINE).unwrap();
+        let param_values: Vec<String> = param_values.iter().map(|p| p.to_string()).collect();
+        apply_params(&mut definition, &param_values)?;
+        Ok(definition)
+    }
+
+    fn get_arg(definition: &PipelineDefinition, key: &str) -> Value {
+        definition.steps[0].args.as_ref().unwrap().get(key).unwrap().clone()
+    }
+
+    #[test]
+    fn substitutes_typed_values_and_text() {
+        let definition = apply(&["topic=orders", "batch_size=20"]).unwrap();
+        assert_eq!(get_arg(&definition, "topic"), Value::from("orders"));
+        assert_eq!(get_arg(&definition, "batch_size"), Value::from(20));
+        assert_eq!(get_arg(&definition, "group"), Value::from("reader-orders-20"));
+    }
+
+    #[test]
+    fn substitutes_nested_values_and_defaults() {
+        let definition = apply(&["topic=orders"]).unwrap();
+        let options: Value = serde_yaml::from_str("{verbose: false, tags: [orders, fixed]}").unwrap();
+        assert_eq!(get_arg(&definition, "options"), options);
+        assert_eq!(get_arg(&definition, "batch_size"), Value::from(100));
+    }
+
+    #[test]
+    fn keeps_other_placeholders() {
+        let definition = apply(&["topic=orders"]).unwrap();
+        assert_eq!(get_arg(&definition, "template"), Value::from("{{ record.id }} of orders"));
+    }
+
+    #[test]
+    fn values_with_equal_sign_and_braces_are_inserted_as_is() {
+        let definition = apply(&["topic=a=b{{ params.batch_size }}"]).unwrap();
+        assert_eq!(get_arg(&definition, "topic"), Value::from("a=b{{ params.batch_size }}"));
+    }
+
+    #[test]
+    fn fails_on_missing_required_param() {
+        assert_eq!(apply(&[]).unwrap_err(),
+            "Parameter 'topic' is required. Please set it with '--param topic=<value>' option");
+    }
+
+    #[test]
+    fn fails_on_undeclared_or_invalid_param() {
+        assert_eq!(apply(&["topic=orders", "region=eu"]).unwrap_err(),
+            "Parameter 'region' is not declared in 'params' section of pipeline");
+        assert_eq!(apply(&["topic"]).unwrap_err(), "Invalid parameter: 'topic'. Expected format: key=value");
+        assert_eq!(apply(&["topic=orders", "batch_size=ten"]).unwrap_err(),
+            "Invalid value of parameter 'batch_size': 'ten' is not a valid integer");
+    }
+
+    #[test]
+    fn fails_on_reference_to_undeclared_param() {
+        let mut definition: PipelineDefinition = serde_yaml::from_str(
+            "steps:\n  - name: src\n    handler: kafka_source\n    args:\n      topic: \"t-{{ params.region }}\"\n").unwrap();
+        assert_eq!(apply_params(&mut definition, &[]).unwrap_err(),
+            "Cannot substitute parameters into argument 'topic' of module 'src': parameter 'region' is not declared in 'params' section of pipeline");
+    }
+}
@@ -0,0 +1,58 @@
+/// Record channels of pipeline: outputs of steps and record deallocation functions of modules.
+/// The registry is owned by pipeline. The maps are filled once the channels are started and only read afterwards,
+/// so senders of different steps don't wait for each other.
+/// Outputs of steps are also stored in step contexts, which are passed to libraries with context extension.
+/// The FFI callbacks which carry a module handle only locate the registry of running pipeline with `get_channels`
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use torustiq_common::ffi::types::functions::ModuleFreeRecordFn;
+
+use crate::{
+    pipeline::{handle::ModuleHandle, routing::StepOutputs},
+    xthread::CHANNELS,
+};
+
+#[derive(Default)]
+pub struct Channels {
+    /// Senders submit a record to dependent step: the next step or a step which is mapped to the record class
+    outputs: RwLock<HashMap<ModuleHandle, Arc<StepOutputs>>>,
+    /// Records must be deallocated by modules they are created in.
+    /// This map allows to locate a deallocation function owned by module
+    free_record_fns: RwLock<HashMap<ModuleHandle, ModuleFreeRecordFn>>,
+}
+
+impl Channels {
+    pub fn set_outputs(&self, handle: ModuleHandle, outputs: Arc<StepOutputs>) {
+        self.outputs.write().unwrap().insert(handle, outputs);
+    }
+
+    pub fn remove_outputs(&self, handle: ModuleHandle) {
+        self.outputs.write().unwrap().remove(&handle);
+    }
+
+    /// Returns the outputs of step. The lock is released before the record is sent
+    pub fn get_outputs(&self, handle: ModuleHandle) -> Option<Arc<StepOutputs>> {
+        self.outputs.read().unwrap().get(&handle).cloned()
+    }
+
+    pub fn has_outputs(&self, handle: ModuleHandle) -> bool {
+        self.outputs.read().unwrap().contains_key(&handle)
+    }
+
+    pub fn set_free_record_fn(&self, handle: ModuleHandle, free_record_fn: ModuleFreeRecordFn) {
+        self.free_record_fns.write().unwrap().insert(handle, free_record_fn);
+    }
+
+    pub fn get_free_record_fn(&self, handle: ModuleHandle) -> Option<ModuleFreeRecordFn> {
+        self.free_record_fns.read().unwrap().get(&handle).copied()
+    }
+}
+
+/// Returns the channels of running pipeline. None if the channels are not started yet
+pub fn get_channels() -> Option<&'static Arc<Channels>> {
+    CHANNELS.get()
+}
@@ -0,0 +1,57 @@
+/// Micro-batching at destination steps.
+/// The host tells the destination module when to commit the records passed to it so far:
+/// once the batch reaches the maximum size, once the commit interval elapses since the first uncommitted record,
+/// and before the step is shut down. Commits are made between record processing calls
+
+use std::time::{Duration, Instant};
+
+/// Decides when the records processed by step must be committed
+pub struct CommitSchedule {
+    interval: Option<Duration>,
+    max_records: Option<u64>,
+    uncommitted: u64,
+    first_uncommitted_at: Option<Instant>,
+}
+
+impl CommitSchedule {
+    pub fn new(interval: Option<Duration>, max_records: Option<u64>) -> CommitSchedule {
+        CommitSchedule {
+            interval,
+            max_records,
+            uncommitted: 0,
+            first_uncommitted_at: None,
+        }
+    }
+
+    /// Registers a record which is passed to module
+    pub fn on_processed(&mut self) {
+        self.uncommitted += 1;
+        self.first_uncommitted_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn has_uncommitted(&self) -> bool {
+        self.uncommitted > 0
+    }
+
+    /// Returns true if the records must be committed now
+    pub fn is_due(&self) -> bool {
+        if !self.has_uncommitted() {
+            return false
+        }
+        let is_full = self.max_records.map(|m| self.uncommitted >= m).unwrap_or(false);
+        is_full || self.get_time_until_due() == Some(Duration::ZERO)
+    }
+
+    /// Returns the time until the commit interval elapses. None if there is nothing to commit or no interval is set
+    pub fn get_time_until_due(&self) -> Option<Duration> {
+        let first_uncommitted_at = self.first_uncommitted_at?;
+        let interval = self.interval?;
+        Some(interval.saturating_sub(first_uncommitted_at.elapsed()))
+    }
+
+    /// Starts a new batch
+    pub fn reset(&mut self) {
+        self.uncommitted = 0;
+        self.first_uncommitted_at = None;
+    }
+}
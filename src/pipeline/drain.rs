@@ -0,0 +1,92 @@
+/// Handling of records which are produced by source after the shutdown of pipeline is requested.
+/// Sources might emit records from their own threads until they acknowledge the termination,
+/// so the pipeline decides explicitly which of these records are accepted
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    config::{ShutdownDrainDefinition, ShutdownDrainMode},
+    pipeline::handle::ModuleHandle,
+};
+
+/// State of shutdown drain of source
+pub struct SourceDrain {
+    pub source_handle: ModuleHandle,
+    mode: ShutdownDrainMode,
+    window: Duration,
+    /// When the shutdown was requested
+    requested_at: OnceCell<Instant>,
+    /// If true, the source has acknowledged the termination
+    is_acknowledged: AtomicBool,
+    records_accepted: AtomicU64,
+    records_rejected: AtomicU64,
+}
+
+impl SourceDrain {
+    pub fn new(source_handle: ModuleHandle, definition: Option<&ShutdownDrainDefinition>) -> SourceDrain {
+        SourceDrain {
+            source_handle,
+            mode: definition.map(|d| d.mode).unwrap_or(ShutdownDrainMode::UntilAck),
+            window: Duration::from_millis(definition.and_then(|d| d.window_ms).unwrap_or(0)),
+            requested_at: OnceCell::new(),
+            is_acknowledged: AtomicBool::new(false),
+            records_accepted: AtomicU64::new(0),
+            records_rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks the shutdown as requested. Subsequent calls have no effect
+    pub fn request(&self) {
+        if self.requested_at.set(Instant::now()).is_ok() {
+            info!("Shutdown of source is requested. Records produced by source from now on are handled in '{}' mode",
+                self.mode.get_name());
+        }
+    }
+
+    /// Returns true if the shutdown is requested
+    pub fn is_requested(&self) -> bool {
+        self.requested_at.get().is_some()
+    }
+
+    /// Marks the termination as acknowledged by source
+    pub fn acknowledge(&self) {
+        self.is_acknowledged.store(true, Ordering::SeqCst);
+    }
+
+    /// Checks if the record produced by source is passed to the next step. Updates the counters
+    pub fn accept(&self) -> bool {
+        let requested_at = match self.requested_at.get() {
+            Some(r) => r,
+            None => return true,
+        };
+        let is_accepted = match self.mode {
+            ShutdownDrainMode::Reject => false,
+            ShutdownDrainMode::UntilAck => !self.is_acknowledged.load(Ordering::SeqCst),
+            ShutdownDrainMode::Window => requested_at.elapsed() < self.window,
+        };
+        match is_accepted {
+            true => self.records_accepted.fetch_add(1, Ordering::Relaxed),
+            false => self.records_rejected.fetch_add(1, Ordering::Relaxed),
+        };
+        is_accepted
+    }
+
+    /// Reports the number of records produced after the shutdown request
+    pub fn report(&self) {
+        if self.requested_at.get().is_none() {
+            return
+        }
+        let (accepted, rejected) = (self.records_accepted.load(Ordering::Relaxed), self.records_rejected.load(Ordering::Relaxed));
+        match rejected {
+            0 => info!("Source produced {} records after the shutdown request. All records are accepted", accepted),
+            _ => warn!("Source produced {} records after the shutdown request: {} accepted, {} rejected",
+                accepted + rejected, accepted, rejected),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+/// Accounting of records dropped by host.
+/// Each record which the host drops instead of passing it on is counted by reason and passed to listeners
+/// with `dropped` event, so no record disappears without a trace. The drop is attributed to the step which
+/// was supposed to process the record (shedding, expiry, terminated step) or to send it further (routing, drain, retries).
+/// Listeners receive the reason from `torustiq_module_listener_record_dropped` if they export it.
+/// Other listeners receive the record in failure handler, and the reason is in `torustiq.drop_reason` metadata key.
+/// Copies of records in shadow chain and sampling are not counted: they are dropped by design
+
+use std::collections::HashMap;
+
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    config::ListenerEvent,
+    pipeline::{handle::ModuleHandle, listener::Listener, pipeline_step::PipelineStep},
+    xthread::{DROP_LISTENERS, STEP_STATS},
+};
+
+/// A metadata key which contains the drop reason in records passed to failure handler of listeners
+pub const DROP_REASON_METADATA_KEY: &str = "torustiq.drop_reason";
+
+/// A number of drop reasons
+pub const DROP_REASON_COUNT: usize = 8;
+
+/// Why the host dropped a record
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DropReason {
+    /// The deadline of record is expired
+    Expired,
+    /// The record is dropped by load shedding
+    Shed,
+    /// The step which should process the record is terminated
+    StepTerminated,
+    /// No output of step accepts the class of record
+    Unroutable,
+    /// The input queue of next step is closed
+    QueueClosed,
+    /// The source produced the record after the shutdown request
+    SourceDrained,
+    /// The record is parked for retry more times than allowed
+    RetriesExhausted,
+    /// The step parked the record for retry, but retries are not configured for step
+    RetryNotConfigured,
+}
+
+impl DropReason {
+    pub const ALL: [DropReason; DROP_REASON_COUNT] = [
+        DropReason::Expired, DropReason::Shed, DropReason::StepTerminated, DropReason::Unroutable,
+        DropReason::QueueClosed, DropReason::SourceDrained, DropReason::RetriesExhausted, DropReason::RetryNotConfigured,
+    ];
+
+    /// Returns a reason code which is passed to listeners and used as metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::Expired => "expired",
+            DropReason::Shed => "shed",
+            DropReason::StepTerminated => "step_terminated",
+            DropReason::Unroutable => "unroutable",
+            DropReason::QueueClosed => "queue_closed",
+            DropReason::SourceDrained => "source_drained",
+            DropReason::RetriesExhausted => "retries_exhausted",
+            DropReason::RetryNotConfigured => "retry_not_configured",
+        }
+    }
+}
+
+/// Returns the listeners which are notified about records dropped in each step
+pub fn get_drop_listeners(steps: &[PipelineStep], listeners: &[Listener]) -> HashMap<ModuleHandle, Vec<Listener>> {
+    steps.iter()
+        .map(|s| {
+            let step_listeners = match s.is_listener_event_enabled(ListenerEvent::Dropped) {
+                true => listeners.iter().filter(|l| l.accepts(ListenerEvent::Dropped)).cloned().collect(),
+                false => Vec::new(),
+            };
+            (s.get_handle(), step_listeners)
+        })
+        .collect()
+}
+
+/// Counts the dropped record and notifies the listeners. The record is still owned and deallocated by caller
+pub fn on_record_dropped(handle: ModuleHandle, record: &Record, reason: DropReason) {
+    if let Some(stats) = STEP_STATS.lock().unwrap().get(&handle) {
+        stats.on_dropped(reason);
+    }
+    for l in DROP_LISTENERS.get().and_then(|l| l.get(&handle)).into_iter().flatten() {
+        l.ffi_on_record_dropped(handle.to_ffi(), record, reason.as_str());
+    }
+}
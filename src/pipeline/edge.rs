@@ -0,0 +1,197 @@
+/// Edges connect the pipeline steps.
+/// Each edge is a channel with a counter of records waiting in queue
+
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+use torustiq_common::ffi::types::module::Record;
+
+/// A record with sequence number assigned by the sending side of edge
+struct Envelope {
+    sequence: u64,
+    record: Record,
+}
+
+/// A sending side of channel. Bounded channels block the sender once the queue is full
+#[derive(Clone)]
+enum EdgeTx {
+    Unbounded(Sender<Envelope>),
+    Bounded(SyncSender<Envelope>),
+}
+
+/// Creates a new edge. If capacity is provided, the edge holds at most the provided number of records
+pub fn edge(capacity: Option<usize>) -> (EdgeSender, EdgeReceiver) {
+    let (tx, rx) = match capacity {
+        Some(c) => {
+            let (tx, rx) = sync_channel::<Envelope>(c);
+            (EdgeTx::Bounded(tx), rx)
+        },
+        None => {
+            let (tx, rx) = channel::<Envelope>();
+            (EdgeTx::Unbounded(tx), rx)
+        },
+    };
+    let depth = QueueDepth::default();
+    let overflows = Arc::new(AtomicU64::new(0));
+    let blocked_ns = Arc::new(AtomicU64::new(0));
+    let sender = EdgeSender {
+        tx,
+        depth: depth.clone(),
+        overflows: overflows.clone(),
+        blocked_ns: blocked_ns.clone(),
+        next_sequence: Arc::new(AtomicU64::new(0)),
+    };
+    (sender, EdgeReceiver { rx, depth, overflows, blocked_ns })
+}
+
+/// A number of records waiting in queue
+#[derive(Clone, Default)]
+pub struct QueueDepth {
+    current: Arc<AtomicUsize>,
+    /// The largest number of records in queue since the edge is created
+    peak: Arc<AtomicUsize>,
+}
+
+impl QueueDepth {
+    pub fn get(&self) -> usize {
+        self.current.load(Ordering::SeqCst)
+    }
+
+    pub fn get_peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        let depth = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    fn decrement(&self) {
+        self.current.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A sending side of edge
+#[derive(Clone)]
+pub struct EdgeSender {
+    tx: EdgeTx,
+    depth: QueueDepth,
+    /// A number of times the sender was blocked because the queue was full
+    overflows: Arc<AtomicU64>,
+    /// Total time the sender was blocked because the queue was full, nanoseconds
+    blocked_ns: Arc<AtomicU64>,
+    next_sequence: Arc<AtomicU64>,
+}
+
+impl EdgeSender {
+    /// Sends the record unless the queue is full or closed. Otherwise returns the record back
+    pub fn try_send(&self, record: Record) -> Result<(), Record> {
+        self.depth.increment();
+        let sequence = self.next_sequence.fetch_add(1, Ordering::SeqCst);
+        let envelope = Envelope { sequence, record };
+        let result = match &self.tx {
+            EdgeTx::Unbounded(tx) => tx.send(envelope).map_err(|e| e.0),
+            EdgeTx::Bounded(tx) => tx.try_send(envelope).map_err(|e| match e {
+                TrySendError::Full(envelope) | TrySendError::Disconnected(envelope) => envelope,
+            }),
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(envelope) => {
+                self.depth.decrement();
+                Err(envelope.record)
+            },
+        }
+    }
+
+    /// Sends the record, waiting while the queue is full. If the queue is closed, returns the record back
+    pub fn send(&self, record: Record) -> Result<(), Record> {
+        self.depth.increment();
+        let sequence = self.next_sequence.fetch_add(1, Ordering::SeqCst);
+        let envelope = Envelope { sequence, record };
+        let result = match &self.tx {
+            EdgeTx::Unbounded(tx) => tx.send(envelope).map_err(|e| e.0),
+            EdgeTx::Bounded(tx) => match tx.try_send(envelope) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Full(envelope)) => {
+                    self.overflows.fetch_add(1, Ordering::Relaxed);
+                    let blocked_at = Instant::now();
+                    let result = tx.send(envelope).map_err(|e| e.0);
+                    self.blocked_ns.fetch_add(blocked_at.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                    result
+                },
+                Err(TrySendError::Disconnected(envelope)) => Err(envelope),
+            },
+        };
+        if let Err(envelope) = result {
+            self.depth.decrement();
+            return Err(envelope.record)
+        }
+        Ok(())
+    }
+}
+
+/// A receiving side of edge
+pub struct EdgeReceiver {
+    rx: Receiver<Envelope>,
+    depth: QueueDepth,
+    overflows: Arc<AtomicU64>,
+    blocked_ns: Arc<AtomicU64>,
+}
+
+impl EdgeReceiver {
+    /// Returns the next record and its sequence number
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<(u64, Record), RecvTimeoutError> {
+        let envelope = self.rx.recv_timeout(timeout)?;
+        self.depth.decrement();
+        Ok((envelope.sequence, envelope.record))
+    }
+
+    /// Returns a shared counter of records waiting in queue
+    pub fn get_queue_depth_counter(&self) -> QueueDepth {
+        self.depth.clone()
+    }
+
+    /// Returns a shared counter of queue overflows
+    pub fn get_queue_overflow_counter(&self) -> Arc<AtomicU64> {
+        self.overflows.clone()
+    }
+
+    /// Returns a shared counter of time the sender was blocked on full queue, nanoseconds
+    pub fn get_blocked_time_counter(&self) -> Arc<AtomicU64> {
+        self.blocked_ns.clone()
+    }
+}
+
+/// An outcome of sequence number check
+pub enum SequenceCheckResult {
+    Ok,
+    /// Some records are missing. Contains the number of missing records
+    Gap(u64),
+    /// The record arrived later than the records sent after it
+    Reordered,
+}
+
+/// Verifies that records arrive in the same order they are sent, without gaps
+#[derive(Default)]
+pub struct SequenceCheck {
+    expected: u64,
+}
+
+impl SequenceCheck {
+    pub fn check(&mut self, sequence: u64) -> SequenceCheckResult {
+        if sequence < self.expected {
+            return SequenceCheckResult::Reordered
+        }
+        let missing = sequence - self.expected;
+        self.expected = sequence + 1;
+        match missing {
+            0 => SequenceCheckResult::Ok,
+            m => SequenceCheckResult::Gap(m),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/// Logging of record processing errors in steps.
+/// Without sampling, each error is logged. With sampling, the first errors are logged, then every Mth error only.
+/// Suppressed errors are reported periodically in a summary with counts by error message
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+use serde_json::json;
+
+use crate::{config::ErrorLogSamplingDefinition, events::publish, masking::mask_text};
+
+const DEFAULT_LOG_FIRST: u64 = 10;
+const DEFAULT_LOG_EVERY: u64 = 100;
+const DEFAULT_SUMMARY_INTERVAL_MS: u64 = 60000;
+
+/// Sampling parameters
+struct Sampling {
+    first: u64,
+    every: u64,
+    summary_interval: Duration,
+}
+
+/// An error logger of a single step
+pub struct ErrorLog {
+    step_id: String,
+    /// None means no sampling: each error is logged
+    sampling: Option<Sampling>,
+    /// A total number of errors
+    total: u64,
+    /// Suppressed errors since the last summary, by error message
+    suppressed: HashMap<String, u64>,
+    last_summary_at: Instant,
+}
+
+impl ErrorLog {
+    pub fn new(step_id: String, definition: Option<&ErrorLogSamplingDefinition>) -> ErrorLog {
+        ErrorLog {
+            step_id,
+            sampling: definition.map(|d| Sampling {
+                first: d.first.unwrap_or(DEFAULT_LOG_FIRST),
+                // 0 would cause division by zero below
+                every: d.every.unwrap_or(DEFAULT_LOG_EVERY).max(1),
+                summary_interval: Duration::from_millis(d.summary_interval_ms.unwrap_or(DEFAULT_SUMMARY_INTERVAL_MS)),
+            }),
+            total: 0,
+            suppressed: HashMap::new(),
+            last_summary_at: Instant::now(),
+        }
+    }
+
+    /// Logs the error or counts it as suppressed
+    pub fn log(&mut self, err: &str) {
+        let err = &mask_text(err);
+        self.total += 1;
+        let sampling = match &self.sampling {
+            Some(s) => s,
+            None => {
+                error!("Failed to process record in step '{}': {}", self.step_id, err);
+                publish("record_error", json!({"step": self.step_id, "error": err, "error_number": self.total}));
+                return
+            },
+        };
+        if self.total <= sampling.first || (self.total - sampling.first).is_multiple_of(sampling.every) {
+            error!("Failed to process record in step '{}' (error #{}): {}", self.step_id, self.total, err);
+            publish("record_error", json!({"step": self.step_id, "error": err, "error_number": self.total}));
+        } else {
+            *self.suppressed.entry(err.to_string()).or_insert(0) += 1;
+        }
+        self.log_summary_if_due();
+    }
+
+    /// Logs the summary of suppressed errors if the summary interval is elapsed
+    pub fn log_summary_if_due(&mut self) {
+        let is_due = match &self.sampling {
+            Some(s) => self.last_summary_at.elapsed() >= s.summary_interval,
+            None => false,
+        };
+        if is_due {
+            self.log_summary();
+        }
+    }
+
+    /// Logs the summary of suppressed errors, if any
+    pub fn log_summary(&mut self) {
+        self.last_summary_at = Instant::now();
+        if self.suppressed.is_empty() {
+            return
+        }
+        let mut counts: Vec<(String, u64)> = self.suppressed.drain().collect();
+        counts.sort_by_key(|(_, count)| Reverse(*count));
+        let suppressed_total: u64 = counts.iter().map(|(_, c)| c).sum();
+        let details = counts.iter()
+            .map(|(err, count)| format!("{} x '{}'", count, err))
+            .collect::<Vec<String>>()
+            .join(", ");
+        warn!("Step '{}': {} processing error(s) suppressed in logs ({} in total): {}",
+            self.step_id, suppressed_total, self.total, details);
+    }
+}
@@ -0,0 +1,105 @@
+/// Export of the resolved pipeline topology as a JSON graph for observability tools.
+/// The graph contains the resource attributes of application according to OpenTelemetry semantic conventions,
+/// steps with module versions and current statistics, and edges between steps including the ones inserted by host.
+/// Nodes are identified by step ID, which is the `step` label of metrics, so tools can overlay the metrics on graph
+
+use serde_json::{json, Map, Value};
+use torustiq_common::ffi::types::module::PipelineModuleKind;
+
+use crate::{
+    instance_lock::get_host_name,
+    pipeline::{
+        pipeline::{is_step_draining, is_step_paused, Pipeline},
+        pipeline_step::{PipelineStep, StepModule},
+    },
+    xthread::STEP_STATS,
+};
+
+/// A name of service in resource attributes
+const SERVICE_NAME: &str = "torustiq";
+
+/// Returns the topology of pipeline as a graph
+pub fn get_graph(pipeline: &Pipeline) -> Value {
+    let steps: Vec<PipelineStep> = pipeline.steps.iter().map(|s| s.lock().unwrap().clone()).collect();
+    let nodes: Vec<Value> = steps.iter().enumerate().map(|(i, s)| get_node(pipeline, i, s)).collect();
+    let mut edges: Vec<Value> = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        let from = step.get_id();
+        if let Some(o) = pipeline.topology.get_default_output(i) {
+            edges.push(json!({"from": from, "to": steps[o].get_id(), "type": "default"}));
+        }
+        let mut class_outputs: Vec<(String, usize)> = pipeline.topology.get_class_outputs(i).into_iter().collect();
+        class_outputs.sort();
+        for (class, o) in class_outputs {
+            edges.push(json!({"from": from, "to": steps[o].get_id(), "type": "class", "class": class}));
+        }
+        for o in pipeline.topology.get_mirror_outputs(i) {
+            let edge_type = match steps[o].is_sampling {
+                true => "sample",
+                false => "copy",
+            };
+            edges.push(json!({"from": from, "to": steps[o].get_id(), "type": edge_type}));
+        }
+        if let Some(o) = pipeline.topology.get_dead_letter_output(i) {
+            edges.push(json!({"from": from, "to": steps[o].get_id(), "type": "dead_letter"}));
+        }
+    }
+    let conversions: Vec<Value> = pipeline.conversions.iter()
+        .map(|c| json!({"step": c.step_name, "before": c.receiver, "from": c.from, "to": c.to, "module": c.module_id}))
+        .collect();
+    json!({
+        "resource": {
+            "attributes": {
+                "service.name": SERVICE_NAME,
+                "service.version": env!("CARGO_PKG_VERSION"),
+                "service.instance.id": format!("{}-{}", get_host_name(), std::process::id()),
+                "host.name": get_host_name(),
+                "process.pid": std::process::id(),
+                "torustiq.pipeline.name": pipeline.name,
+            },
+        },
+        "nodes": nodes,
+        "edges": edges,
+        "conversions": conversions,
+    })
+}
+
+/// Returns a step as a node of graph
+fn get_node(pipeline: &Pipeline, index: usize, step: &PipelineStep) -> Value {
+    let handle = step.get_handle();
+    let role = if step.is_shadow { "shadow" } else if step.is_sampling { "sampling" } else { "primary" };
+    let state = if step.component.is_terminated() { "terminated" }
+        else if is_step_paused(handle) { "paused" }
+        else if is_step_draining(handle) { "draining" }
+        else { "running" };
+    let stats = STEP_STATS.lock().unwrap().get(&handle).map(|s| s.snapshot());
+    let metrics_labels: Map<String, Value> = step.metrics_labels.iter()
+        .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+        .collect();
+    json!({
+        "id": step.get_id(),
+        "handle": index,
+        "kind": match pipeline.topology.get_kind(index) {
+            PipelineModuleKind::Source => "source",
+            PipelineModuleKind::Transformation => "transformation",
+            PipelineModuleKind::Destination => "destination",
+        },
+        "role": role,
+        "state": state,
+        "module": {
+            "id": step.module.get_id(),
+            "version": step.module.get_version(),
+            "type": match step.module {
+                StepModule::Library(_) => "library",
+                StepModule::Builtin(_) => "builtin",
+            },
+        },
+        "metrics_labels": metrics_labels,
+        "stats": stats.map(|s| json!({
+            "records_received": s.records_received,
+            "records_succeeded": s.records_succeeded,
+            "records_failed": s.records_failed,
+            "queue_depth": s.queue_depth,
+        })),
+    })
+}
@@ -0,0 +1,60 @@
+/// Handles of pipeline components.
+/// A handle is passed to modules in order to identify a step or a listener. Modules pass the handle back to host
+/// in callbacks, so the handles received from modules are validated before use.
+
+use std::fmt;
+
+use log::error;
+
+use torustiq_common::ffi::types::module::ModuleHandle as FfiModuleHandle;
+
+use crate::xthread::MODULE_HANDLES;
+
+/// A handle of pipeline component. Handles are assigned in order: steps first, then listeners
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ModuleHandle(FfiModuleHandle);
+
+impl ModuleHandle {
+    /// Converts a handle received from module. Returns None and logs an error if handle is not registered in pipeline
+    pub fn from_ffi(handle: FfiModuleHandle, context: &str) -> Option<ModuleHandle> {
+        let is_known = match MODULE_HANDLES.get() {
+            Some(handles) => handles.contains(&ModuleHandle(handle)),
+            None => {
+                error!("{}: module handle '{}' is received before the steps are started, e.g. while priming. The call is rejected", context, handle);
+                return None
+            },
+        };
+        if !is_known {
+            error!("{}: unknown module handle '{}' is received from module", context, handle);
+            return None
+        }
+        Some(ModuleHandle(handle))
+    }
+
+    /// Returns the handle to be passed to modules
+    pub fn to_ffi(&self) -> FfiModuleHandle {
+        self.0
+    }
+
+    /// Returns the position of component in pipeline: an index of step, or number of steps + index of listener
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl TryFrom<usize> for ModuleHandle {
+    type Error = String;
+
+    fn try_from(index: usize) -> Result<Self, Self::Error> {
+        match FfiModuleHandle::try_from(index) {
+            Ok(h) => Ok(ModuleHandle(h)),
+            Err(_) => Err(format!("Cannot create a module handle for component #{}: too many components", index)),
+        }
+    }
+}
+
+impl fmt::Display for ModuleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
@@ -0,0 +1,94 @@
+/// Synthetic heartbeat records.
+/// If `heartbeat` is set in pipeline, the host injects a heartbeat record at the source edge periodically,
+/// as if the source produced it. Destinations and listeners can verify the end-to-end liveness of pipeline
+/// while the real traffic is idle. Heartbeat records have `torustiq.heartbeat` metadata key with sequence number.
+/// With `deliver: false`, heartbeat records pass all transformations, but are dropped before destination steps.
+/// Heartbeats are not injected while the source is paused or in standby, and stop once the shutdown is requested
+
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::debug;
+use serde_json::json;
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    callbacks::on_rcv_cb,
+    config::HeartbeatDefinition,
+    pipeline::{handle::ModuleHandle, pipeline::is_step_paused},
+    records::{create_record, get_metadata},
+    xthread::{PIPELINE, SOURCE_DRAIN, SOURCE_LEASE},
+};
+
+/// A metadata key which marks heartbeat records. The value is a sequence number of heartbeat
+pub const HEARTBEAT_METADATA_KEY: &str = "torustiq.heartbeat";
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Returns true if the record is a heartbeat
+pub fn is_heartbeat(record: &Record) -> bool {
+    get_metadata(record).contains_key(HEARTBEAT_METADATA_KEY)
+}
+
+/// Starts a thread which injects heartbeat records at the source edge.
+/// Does nothing if heartbeat is not configured
+pub fn start_heartbeat() -> Result<(), String> {
+    let pipeline_arc = match PIPELINE.get() {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let (pipeline_name, definition, source_arc) = {
+        let pipeline = pipeline_arc.lock().unwrap();
+        match (&pipeline.heartbeat, pipeline.steps.first()) {
+            (Some(h), Some(s)) => (pipeline.name.clone(), h.clone(), s.clone()),
+            _ => return Ok(()),
+        }
+    };
+    let source_handle = source_arc.lock().unwrap().get_handle();
+    let interval = Duration::from_millis(definition.interval_ms);
+
+    let thread_name = format!("{}-heartbeat", &pipeline_name);
+    let result = thread::Builder::new().name(thread_name.clone()).spawn(move || {
+        let mut sequence: u64 = 0;
+        let mut last_sent_at = Instant::now();
+        loop {
+            thread::sleep(CHECK_INTERVAL.min(interval));
+            if source_arc.lock().unwrap().component.is_terminated() || SOURCE_DRAIN.get().map(|d| d.is_requested()).unwrap_or(false) {
+                debug!("Heartbeat is stopped: source is terminated");
+                return
+            }
+            if last_sent_at.elapsed() < interval || !is_source_active(source_handle) {
+                continue
+            }
+            last_sent_at = Instant::now();
+            sequence += 1;
+            on_rcv_cb(source_handle.to_ffi(), create_heartbeat_record(&definition, &pipeline_name, sequence));
+        }
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to start thread '{}': {}", thread_name, e)),
+    }
+}
+
+/// Returns false if the source doesn't produce records: it's paused or waits for the lease
+fn is_source_active(source_handle: ModuleHandle) -> bool {
+    !is_step_paused(source_handle) && SOURCE_LEASE.get().map(|l| l.is_active()).unwrap_or(true)
+}
+
+fn create_heartbeat_record(definition: &HeartbeatDefinition, pipeline_name: &str, sequence: u64) -> Record {
+    let payload = match &definition.payload {
+        Some(p) => p.clone(),
+        None => json!({
+            "heartbeat": sequence,
+            "pipeline": pipeline_name,
+            "time": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        }).to_string(),
+    };
+    let mut metadata: HashMap<String, String> = definition.metadata.clone().unwrap_or_default();
+    metadata.insert(String::from(HEARTBEAT_METADATA_KEY), sequence.to_string());
+    create_record(payload.into_bytes(), metadata)
+}
@@ -0,0 +1,75 @@
+/// End-to-end latency of records.
+/// Records are stamped with origin timestamp once they leave the source step.
+/// The latency is measured once records are processed by the last step
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds of histogram buckets, milliseconds
+pub const LATENCY_BUCKETS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// A histogram of latencies
+#[derive(Default)]
+pub struct LatencyHistogram {
+    /// Non-cumulative counts of each bucket. The last item is a count of values above the largest bucket
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let i = LATENCY_BUCKETS_MS.iter().position(|b| ms <= *b).unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+
+    pub fn get_count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_sum(&self) -> Duration {
+        Duration::from_millis(self.sum_ms.load(Ordering::Relaxed))
+    }
+
+    /// Returns cumulative counts of buckets. The last item is the total count
+    pub fn get_cumulative_counts(&self) -> Vec<u64> {
+        let mut total = 0;
+        self.buckets.iter()
+            .map(|b| {
+                total += b.load(Ordering::Relaxed);
+                total
+            })
+            .collect()
+    }
+
+    /// Returns an upper bound of bucket which contains the provided quantile, e.g. 0.99.
+    /// None means there are no values or the quantile is above the largest bucket
+    pub fn get_quantile_upper_bound_ms(&self, quantile: f64) -> Option<u64> {
+        let count = self.get_count();
+        if count == 0 {
+            return None
+        }
+        let rank = (count as f64 * quantile).ceil() as u64;
+        let i = self.get_cumulative_counts().iter().position(|c| *c >= rank)?;
+        LATENCY_BUCKETS_MS.get(i).copied()
+    }
+
+    /// Returns a human-readable summary
+    pub fn format_summary(&self) -> String {
+        let count = self.get_count();
+        if count == 0 {
+            return String::from("no records")
+        }
+        let format_quantile = |q: f64| match self.get_quantile_upper_bound_ms(q) {
+            Some(ms) => format!("<= {} ms", ms),
+            None => format!("> {} ms", LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]),
+        };
+        format!("{} records, avg {} ms, p50 {}, p99 {}", count, self.get_sum().as_millis() as u64 / count,
+            format_quantile(0.5), format_quantile(0.99))
+    }
+}
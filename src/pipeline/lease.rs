@@ -0,0 +1,292 @@
+/// Leases of sources for high-availability pairs of pipeline instances.
+/// Instances share a lease file which names the holder and the expiration time. Only the holder starts the source;
+/// the other instances configure and start all other steps and stay in standby until the lease is free or expired.
+/// The holder renews the lease periodically and releases it once the source is terminated, so a standby instance
+/// takes over on graceful shutdown without waiting for expiration.
+/// If the holder cannot renew the lease in time, e.g. it was suspended, and another instance takes the lease over,
+/// the source is paused until the lease is acquired again. Once the pipeline termination is requested, such a source
+/// is resumed in order to shut down, and the records it still produces are dropped.
+/// Instances must have synchronized clocks. If two instances take over an expired lease at the same time,
+/// the last write wins: both instances read the file again after a short delay before starting the source
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{atomic::{AtomicBool, Ordering}, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    callbacks::on_step_terminate_cb,
+    config::LeaseDefinition,
+    errors::TorustiqError,
+    events::publish,
+    instance_lock::get_host_name,
+    pipeline::{handle::ModuleHandle, pipeline::{set_step_paused, start_step}},
+    xthread::{PIPELINE, SOURCE_LEASE},
+};
+
+/// Default time to live of lease
+pub const DEFAULT_LEASE_TTL_MS: u64 = 15000;
+
+/// A delay before the instance checks if it won the race for the lease
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// How often the lease thread checks if the source is terminated
+const TERMINATION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Contents of lease file
+#[derive(Serialize, Deserialize, Clone)]
+struct LeaseRecord {
+    holder: String,
+    /// UNIX timestamp in ms
+    expires_at_ms: u64,
+}
+
+/// A lease of source
+pub struct SourceLease {
+    path: PathBuf,
+    holder_id: String,
+    ttl: Duration,
+    renew_interval: Duration,
+    source_handle: ModuleHandle,
+    /// True while this instance holds the lease
+    is_held: AtomicBool,
+    is_source_started: AtomicBool,
+    /// Set once the pipeline termination is requested. The source is not started anymore
+    is_stopped: AtomicBool,
+    /// A failure to start the source once the lease is acquired
+    start_error: Mutex<Option<TorustiqError>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SourceLease {
+    pub fn new(definition: &LeaseDefinition, source_handle: ModuleHandle) -> SourceLease {
+        let ttl = Duration::from_millis(definition.ttl_ms.unwrap_or(DEFAULT_LEASE_TTL_MS));
+        SourceLease {
+            path: PathBuf::from(&definition.path),
+            holder_id: definition.holder_id.clone().unwrap_or_else(|| format!("{}-{}", get_host_name(), std::process::id())),
+            ttl,
+            renew_interval: definition.renew_interval_ms.map(Duration::from_millis).unwrap_or(ttl / 3),
+            source_handle,
+            is_held: AtomicBool::new(false),
+            is_source_started: AtomicBool::new(false),
+            is_stopped: AtomicBool::new(false),
+            start_error: Mutex::new(None),
+            thread: Mutex::new(None),
+        }
+    }
+
+    pub fn get_source_handle(&self) -> ModuleHandle {
+        self.source_handle
+    }
+
+    /// Returns true if the source is started and runs under the lease of this instance
+    pub fn is_active(&self) -> bool {
+        self.is_held.load(Ordering::SeqCst) && self.is_source_started.load(Ordering::SeqCst)
+    }
+
+    /// Prevents the source from starting. Returns true if the source is started already.
+    /// A source paused by the lost lease is resumed, so it can be shut down; its records are dropped, see `accepts_records`.
+    /// Must be called with the pipeline lock held, so the source is not started concurrently
+    pub fn stop(&self) -> bool {
+        self.is_stopped.store(true, Ordering::SeqCst);
+        let is_source_started = self.is_source_started.load(Ordering::SeqCst);
+        if is_source_started && !self.is_held.load(Ordering::SeqCst) {
+            set_step_paused(self.source_handle, false);
+        }
+        is_source_started
+    }
+
+    /// Returns false if the records of source must be dropped: the lease is stopped while another instance holds it
+    pub fn accepts_records(&self) -> bool {
+        !self.is_stopped.load(Ordering::SeqCst) || self.is_held.load(Ordering::SeqCst)
+    }
+
+    /// Returns the failure to start the source, if any
+    pub fn take_start_error(&self) -> Option<TorustiqError> {
+        self.start_error.lock().unwrap().take()
+    }
+
+    /// Waits until the lease thread releases the lease and exits
+    pub fn join(&self) {
+        if let Some(t) = self.thread.lock().unwrap().take() {
+            if t.join().is_err() {
+                error!("Lease thread panicked");
+            }
+        }
+    }
+
+    /// Acquires or renews the lease and starts the source while the lease is held, until the source is terminated
+    fn run(&self) {
+        let mut held_until: Option<Instant> = None;
+        let mut last_holder: Option<String> = None;
+        while !self.is_source_terminated() {
+            match self.try_acquire() {
+                Ok(None) => {
+                    held_until = Some(Instant::now() + self.ttl);
+                    if !self.is_held.swap(true, Ordering::SeqCst) {
+                        self.on_acquired();
+                    }
+                },
+                Ok(Some(record)) => {
+                    if self.is_held.swap(false, Ordering::SeqCst) {
+                        self.on_lost(&record.holder);
+                    }
+                    if last_holder.as_ref() != Some(&record.holder) {
+                        info!("Source lease '{}' is held by '{}'. Waiting in standby", self.path.display(), record.holder);
+                        last_holder = Some(record.holder);
+                    }
+                },
+                Err(msg) => {
+                    warn!("Cannot acquire or renew the source lease: {}", msg);
+                    // Another instance might take the lease over once it expires
+                    if held_until.map(|t| Instant::now() >= t).unwrap_or(false) && self.is_held.swap(false, Ordering::SeqCst) {
+                        self.on_lost("unknown");
+                    }
+                },
+            }
+            let started_at = Instant::now();
+            while started_at.elapsed() < self.renew_interval && !self.is_source_terminated() {
+                thread::sleep(TERMINATION_CHECK_INTERVAL.min(self.renew_interval));
+            }
+        }
+        if self.is_held.swap(false, Ordering::SeqCst) {
+            match self.release() {
+                Ok(_) => info!("Source lease '{}' is released", self.path.display()),
+                Err(msg) => warn!("Cannot release the source lease: {}", msg),
+            }
+        }
+    }
+
+    fn on_acquired(&self) {
+        info!("Source lease '{}' is acquired by '{}'", self.path.display(), self.holder_id);
+        publish("lease_acquired", json!({"holder": self.holder_id}));
+        if self.is_source_started.load(Ordering::SeqCst) {
+            info!("Resuming the source");
+            set_step_paused(self.source_handle, false);
+            return
+        }
+        let pipeline = match PIPELINE.get() {
+            Some(p) => p.lock().unwrap(),
+            None => return,
+        };
+        if self.is_stopped.load(Ordering::SeqCst) {
+            return
+        }
+        let source = match pipeline.steps.first() {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        info!("Starting the source");
+        let result = start_step(&source.lock().unwrap());
+        drop(pipeline);
+        match result {
+            Ok(_) => self.is_source_started.store(true, Ordering::SeqCst),
+            Err(e) => {
+                error!("{}", e);
+                *self.start_error.lock().unwrap() = Some(e);
+                // The source is not running, so it's terminated on its behalf in order to stop the pipeline
+                on_step_terminate_cb(self.source_handle.to_ffi());
+            },
+        }
+    }
+
+    fn on_lost(&self, holder: &str) {
+        warn!("Source lease '{}' is taken over by '{}'. The source is paused", self.path.display(), holder);
+        publish("lease_lost", json!({"holder": holder}));
+        // The stopped source is being shut down, so it must not wait for the lease
+        if self.is_source_started.load(Ordering::SeqCst) && !self.is_stopped.load(Ordering::SeqCst) {
+            set_step_paused(self.source_handle, true);
+        }
+    }
+
+    fn is_source_terminated(&self) -> bool {
+        let pipeline = match PIPELINE.get() {
+            Some(p) => p.lock().unwrap(),
+            None => return false,
+        };
+        let source = pipeline.steps.first().cloned();
+        drop(pipeline);
+        source.map(|s| s.lock().unwrap().component.is_terminated()).unwrap_or(true)
+    }
+
+    /// Writes the lease if it's free, expired or held by this instance.
+    /// Returns None if the lease is held by this instance, otherwise returns the lease of another instance
+    fn try_acquire(&self) -> Result<Option<LeaseRecord>, String> {
+        let now = get_timestamp_ms();
+        if let Some(record) = self.read()?.filter(|r| r.holder != self.holder_id && r.expires_at_ms > now) {
+            return Ok(Some(record))
+        }
+        self.write(&LeaseRecord { holder: self.holder_id.clone(), expires_at_ms: now + self.ttl.as_millis() as u64 })?;
+        if self.is_held.load(Ordering::SeqCst) {
+            return Ok(None)
+        }
+        // Another instance might take the lease over at the same time. The last write wins
+        thread::sleep(SETTLE_DELAY);
+        match self.read()? {
+            Some(r) if r.holder != self.holder_id => Ok(Some(r)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes the lease file if the lease is still held by this instance
+    fn release(&self) -> Result<(), String> {
+        match self.read()? {
+            Some(r) if r.holder == self.holder_id => fs::remove_file(&self.path)
+                .map_err(|e| format!("Cannot remove the lease file '{}': {}", self.path.display(), e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn read(&self) -> Result<Option<LeaseRecord>, String> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Cannot read the lease file '{}': {}", self.path.display(), e)),
+        };
+        match serde_json::from_str(&contents) {
+            Ok(r) => Ok(Some(r)),
+            // A partially written or corrupted lease is treated as free
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Writes the lease file atomically
+    fn write(&self, record: &LeaseRecord) -> Result<(), String> {
+        let tmp_path = PathBuf::from(format!("{}.{}.tmp", self.path.display(), self.holder_id));
+        let contents = serde_json::to_string(record).map_err(|e| format!("Cannot serialize the lease: {}", e))?;
+        if let Err(e) = fs::write(&tmp_path, contents) {
+            return Err(format!("Cannot write file '{}': {}", tmp_path.display(), e))
+        }
+        if let Err(e) = fs::rename(&tmp_path, &self.path) {
+            return Err(format!("Cannot rename file '{}' to '{}': {}", tmp_path.display(), self.path.display(), e))
+        }
+        Ok(())
+    }
+}
+
+/// Starts a thread which acquires the lease and starts the source
+pub fn start_lease_thread() -> Result<(), String> {
+    let lease = match SOURCE_LEASE.get() {
+        Some(l) => l,
+        None => return Ok(()),
+    };
+    info!("Waiting for the source lease '{}' as '{}'", lease.path.display(), lease.holder_id);
+    match thread::Builder::new().name(String::from("lease")).spawn(|| lease.run()) {
+        Ok(t) => {
+            *lease.thread.lock().unwrap() = Some(t);
+            Ok(())
+        },
+        Err(e) => Err(format!("Failed to start the lease thread: {}", e)),
+    }
+}
+
+fn get_timestamp_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
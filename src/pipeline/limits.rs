@@ -0,0 +1,146 @@
+/// Resource limits of control group the application runs in, e.g. limits of container.
+/// Both cgroup v2 and v1 are supported. On other platforms no limits are detected.
+/// The limits are used to size the defaults: number of concurrent startup threads and capacity of step queues
+
+use std::{fs, path::{Path, PathBuf}, thread};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// cgroup v1 reports a huge number instead of "no limit"
+const CGROUP_V1_UNLIMITED_MEMORY: u64 = 1 << 62;
+/// A share of memory limit which is used by default for records waiting in step queues
+const QUEUE_MEMORY_SHARE: f64 = 0.25;
+/// An assumed average size of record. Used to convert the memory into the number of records
+const ASSUMED_RECORD_SIZE: u64 = 4096;
+const MIN_DEFAULT_QUEUE_CAPACITY: usize = 100;
+const MAX_DEFAULT_QUEUE_CAPACITY: usize = 100_000;
+
+/// Limits of control group. None means no limit
+#[derive(Clone, Debug, Default)]
+pub struct ResourceLimits {
+    /// A number of CPUs, might be fractional
+    pub cpus: Option<f64>,
+    /// Memory limit, bytes
+    pub memory_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Reads the limits of control group of current process
+    pub fn detect() -> ResourceLimits {
+        let (v2_path, v1_paths) = read_cgroup_paths();
+        if let Some(path) = v2_path {
+            let dir = Path::new(CGROUP_ROOT).join(path.trim_start_matches('/'));
+            if dir.join("cpu.max").is_file() || dir.join("memory.max").is_file() {
+                return ResourceLimits {
+                    cpus: read_v2_cpus(&dir),
+                    memory_bytes: read_v2_memory(&dir),
+                }
+            }
+        }
+        let cpu_dir = get_v1_dir(&v1_paths, "cpu");
+        let memory_dir = get_v1_dir(&v1_paths, "memory");
+        ResourceLimits {
+            cpus: cpu_dir.and_then(|d| read_v1_cpus(&d)),
+            memory_bytes: memory_dir.and_then(|d| read_v1_memory(&d)),
+        }
+    }
+
+    /// Returns how many threads are run concurrently: CPU limit rounded up, or the number of available CPUs
+    pub fn get_max_concurrency(&self) -> usize {
+        let available = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        match self.cpus {
+            Some(c) => (c.ceil() as usize).clamp(1, available),
+            None => available,
+        }
+    }
+
+    /// Returns the default capacity of step queue: a share of memory limit split between the steps.
+    /// Without memory limit the queues are unbounded
+    pub fn get_default_queue_capacity(&self, steps_len: usize) -> Option<usize> {
+        let memory_bytes = self.memory_bytes?;
+        let records = (memory_bytes as f64 * QUEUE_MEMORY_SHARE) as u64 / ASSUMED_RECORD_SIZE / steps_len.max(1) as u64;
+        Some((records as usize).clamp(MIN_DEFAULT_QUEUE_CAPACITY, MAX_DEFAULT_QUEUE_CAPACITY))
+    }
+
+    /// Returns a human-readable description of limits
+    pub fn format(&self) -> String {
+        let cpus = match self.cpus {
+            Some(c) => format!("{:.2}", c),
+            None => String::from("unlimited"),
+        };
+        let memory = match self.memory_bytes {
+            Some(m) => format!("{} MiB", m / 1024 / 1024),
+            None => String::from("unlimited"),
+        };
+        format!("CPU: {}, memory: {}", cpus, memory)
+    }
+}
+
+/// Reads the cgroup paths of current process: v2 path and v1 paths by controller
+fn read_cgroup_paths() -> (Option<String>, Vec<(String, String)>) {
+    let contents = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let mut v2_path: Option<String> = None;
+    let mut v1_paths: Vec<(String, String)> = Vec::new();
+    // Format: `hierarchy-ID:controller-list:cgroup-path`
+    for line in contents.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (id, controllers, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(i), Some(c), Some(p)) => (i, c, p),
+            _ => continue,
+        };
+        match (id, controllers) {
+            ("0", "") => v2_path = Some(path.to_string()),
+            _ => controllers.split(',').for_each(|c| v1_paths.push((c.to_string(), path.to_string()))),
+        }
+    }
+    (v2_path, v1_paths)
+}
+
+/// Returns the directory of v1 controller. Inside containers the cgroup path is not visible,
+/// so the root of controller is used if the path doesn't exist
+fn get_v1_dir(v1_paths: &[(String, String)], controller: &str) -> Option<PathBuf> {
+    let controller_root = Path::new(CGROUP_ROOT).join(controller);
+    let path = v1_paths.iter().find(|(c, _)| c == controller).map(|(_, p)| p.trim_start_matches('/'))?;
+    let dir = controller_root.join(path);
+    match (dir.is_dir(), controller_root.is_dir()) {
+        (true, _) => Some(dir),
+        (false, true) => Some(controller_root),
+        (false, false) => None,
+    }
+}
+
+fn read_value(path: PathBuf) -> Option<String> {
+    fs::read_to_string(path).ok().map(|v| v.trim().to_string())
+}
+
+/// `cpu.max` contains quota and period: `max 100000` or `200000 100000`
+fn read_v2_cpus(dir: &Path) -> Option<f64> {
+    let value = read_value(dir.join("cpu.max"))?;
+    let (quota, period) = value.split_once(' ')?;
+    match (quota.parse::<f64>(), period.parse::<f64>()) {
+        (Ok(q), Ok(p)) if p > 0.0 => Some(q / p),
+        _ => None,
+    }
+}
+
+/// `memory.max` contains either a number of bytes or `max`
+fn read_v2_memory(dir: &Path) -> Option<u64> {
+    read_value(dir.join("memory.max"))?.parse::<u64>().ok()
+}
+
+/// A negative quota means no limit
+fn read_v1_cpus(dir: &Path) -> Option<f64> {
+    let quota = read_value(dir.join("cpu.cfs_quota_us"))?.parse::<i64>().ok()?;
+    let period = read_value(dir.join("cpu.cfs_period_us"))?.parse::<i64>().ok()?;
+    match quota > 0 && period > 0 {
+        true => Some(quota as f64 / period as f64),
+        false => None,
+    }
+}
+
+fn read_v1_memory(dir: &Path) -> Option<u64> {
+    let limit = read_value(dir.join("memory.limit_in_bytes"))?.parse::<u64>().ok()?;
+    match limit < CGROUP_V1_UNLIMITED_MEMORY {
+        true => Some(limit),
+        false => None,
+    }
+}
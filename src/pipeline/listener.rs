@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use torustiq_common::ffi::types::module as module_types;
+use torustiq_common::ffi::{
+    types::module as module_types,
+    utils::strings::{cchar_const_deallocate, cchar_to_string, string_to_cchar},
+};
 
 use crate::{
+    config::{EventSeverity, ListenerEvent, PipelineDataSection},
     modules::listener::ListenerModule,
-    pipeline::{PipelineComponent, PipelineComponentState},
+    pipeline::{drops::DROP_REASON_METADATA_KEY, handle::ModuleHandle, PipelineComponent, PipelineComponentState},
+    records::{create_record, get_metadata, get_payload},
 };
 
 /// An event listener for pipeline events
@@ -14,12 +19,20 @@ pub struct Listener {
     pub component: PipelineComponent,
     /// A reference to module library
     pub module: Arc<ListenerModule>,
+    /// If false, the pipeline can run without this listener
+    pub required: bool,
+    /// Events which are passed to this listener
+    pub events: Vec<ListenerEvent>,
+    /// Events with lower severity are not passed to this listener
+    pub min_severity: EventSeverity,
+    /// Sections of pipeline data which are passed to this listener as `pipeline.*` arguments
+    pub exposed_data: Vec<PipelineDataSection>,
 }
 
 impl Listener {
     /// Initializes a step from module (=dynamic library).
     /// Index is a step index in pipeline. Needed to format a unique step ID
-    pub fn from_module(module: Arc<ListenerModule>, handle: usize, args: Option<HashMap<String, String>>) -> Listener {
+    pub fn from_module(module: Arc<ListenerModule>, handle: ModuleHandle, args: Option<HashMap<String, String>>) -> Listener {
         Listener {
             component: PipelineComponent {
                 args: args.unwrap_or(HashMap::new()),
@@ -28,9 +41,18 @@ impl Listener {
                 state: PipelineComponentState::Created,
             },
             module,
+            required: true,
+            events: vec![ListenerEvent::Received, ListenerEvent::Success, ListenerEvent::Error, ListenerEvent::Dropped],
+            min_severity: EventSeverity::default(),
+            exposed_data: PipelineDataSection::all(),
         }
     }
 
+    /// Returns true if the event should be passed to this listener
+    pub fn accepts(&self, event: ListenerEvent) -> bool {
+        self.events.contains(&event) && event.get_severity() >= self.min_severity
+    }
+
     pub fn configure(&mut self, args: module_types::ModuleListenerConfigureArgs) -> Result<(), String> {
         let _ = self.module.configure(args)?;
         self.component.state = PipelineComponentState::Configured;
@@ -46,12 +68,29 @@ impl Listener {
         self.component.id.clone()
     }
 
-    pub fn get_handle(&self) -> usize {
+    pub fn get_handle(&self) -> ModuleHandle {
         self.component.handle
     }
 
-    pub fn ffi_on_record_received(&self, handle: module_types::ModuleHandle, record: *const module_types::Record) {
-        (self.module.record_rcv_ptr)(handle, record)
+    /// Notifies the listener about received record. Returns a verdict of listener on record, if any
+    pub fn ffi_on_record_received(&self, handle: module_types::ModuleHandle, record: *const module_types::Record) -> Option<String> {
+        let record_rcv_verdict = match &self.module.record_rcv_verdict_ptr {
+            Some(f) => f,
+            None => {
+                (self.module.record_rcv_ptr)(handle, record);
+                return None
+            },
+        };
+        let verdict_ptr = record_rcv_verdict(handle, record);
+        if verdict_ptr.is_null() {
+            return None
+        }
+        let verdict = cchar_to_string(verdict_ptr);
+        self.module.base.free_c_char(verdict_ptr);
+        match verdict.trim() {
+            "" => None,
+            v => Some(v.to_string()),
+        }
     }
 
     pub fn ffi_on_record_sent(&self, handle: module_types::ModuleHandle, record: *const module_types::Record) {
@@ -61,4 +100,20 @@ impl Listener {
     pub fn ffi_on_record_error(&self, handle: module_types::ModuleHandle, record: *const module_types::Record) {
         (self.module.record_send_failure_ptr)(handle, record)
     }
-}
\ No newline at end of file
+
+    /// Notifies the listener about record dropped by host. Listeners without drop handler receive the record
+    /// in failure handler, with the reason in metadata
+    pub fn ffi_on_record_dropped(&self, handle: module_types::ModuleHandle, record: &module_types::Record, reason: &str) {
+        if let Some(record_dropped) = &self.module.record_dropped_ptr {
+            let reason = string_to_cchar(reason);
+            record_dropped(handle, record, reason);
+            cchar_const_deallocate(reason);
+            return
+        }
+        let mut metadata = get_metadata(record);
+        metadata.insert(String::from(DROP_REASON_METADATA_KEY), reason.to_string());
+        let mut copy = create_record(get_payload(record).to_vec(), metadata);
+        (self.module.record_send_failure_ptr)(handle, &copy);
+        copy.free_contents();
+    }
+}
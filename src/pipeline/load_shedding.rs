@@ -0,0 +1,99 @@
+/// Load shedding of steps.
+/// Once the input queue of step stays above the high-water mark longer than threshold, the step drops records
+/// according to policy, so the records which do get through are processed with acceptable latency.
+/// Shedding stops as soon as the queue goes below the high-water mark
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    config::{LoadSheddingDefinition, LoadSheddingPolicy},
+    records::get_metadata,
+};
+
+const DEFAULT_HIGH_WATER_MARK: f64 = 0.8;
+const DEFAULT_THRESHOLD_MS: u64 = 5000;
+const DEFAULT_KEEP_RATIO: f64 = 0.5;
+const DEFAULT_PRIORITY_KEY: &str = "priority";
+const DEFAULT_MIN_PRIORITY: i64 = 1;
+
+/// A load shedder of a single step
+pub struct LoadShedder {
+    step_id: String,
+    high_water_mark: usize,
+    threshold: Duration,
+    policy: LoadSheddingPolicy,
+    keep_ratio: f64,
+    priority_key: String,
+    min_priority: i64,
+    /// When the queue went above the high-water mark
+    above_since: Option<Instant>,
+    is_shedding: bool,
+    /// `sampled` policy: accumulates the keep ratio; a record is kept once the credit reaches 1
+    keep_credit: f64,
+}
+
+impl LoadShedder {
+    pub fn new(step_id: String, queue_capacity: usize, definition: &LoadSheddingDefinition) -> LoadShedder {
+        let high_water_mark = definition.high_water_mark.unwrap_or(DEFAULT_HIGH_WATER_MARK).clamp(0.0, 1.0);
+        LoadShedder {
+            step_id,
+            high_water_mark: ((queue_capacity as f64 * high_water_mark).ceil() as usize).max(1),
+            threshold: Duration::from_millis(definition.threshold_ms.unwrap_or(DEFAULT_THRESHOLD_MS)),
+            policy: definition.policy.unwrap_or(LoadSheddingPolicy::Sampled),
+            keep_ratio: definition.keep_ratio.unwrap_or(DEFAULT_KEEP_RATIO).clamp(0.0, 1.0),
+            priority_key: definition.priority_key.clone().unwrap_or(String::from(DEFAULT_PRIORITY_KEY)),
+            min_priority: definition.min_priority.unwrap_or(DEFAULT_MIN_PRIORITY),
+            above_since: None,
+            is_shedding: false,
+            keep_credit: 0.0,
+        }
+    }
+
+    pub fn is_shedding(&self) -> bool {
+        self.is_shedding
+    }
+
+    /// Updates the shedding state using the current queue depth
+    pub fn update(&mut self, queue_depth: usize) {
+        if queue_depth < self.high_water_mark {
+            if self.is_shedding {
+                info!("Step '{}': load shedding is stopped", self.step_id);
+            }
+            self.above_since = None;
+            self.is_shedding = false;
+            return
+        }
+        let above_since = *self.above_since.get_or_insert_with(Instant::now);
+        if !self.is_shedding && above_since.elapsed() >= self.threshold {
+            warn!("Step '{}': the input queue is above the high-water mark ({} records) for {} ms. Load shedding is started",
+                self.step_id, self.high_water_mark, self.threshold.as_millis());
+            self.is_shedding = true;
+        }
+    }
+
+    /// Returns true if the record must be dropped
+    pub fn should_drop(&mut self, record: &Record) -> bool {
+        if !self.is_shedding {
+            return false
+        }
+        match self.policy {
+            LoadSheddingPolicy::Sampled => {
+                self.keep_credit += self.keep_ratio;
+                if self.keep_credit >= 1.0 {
+                    self.keep_credit -= 1.0;
+                    return false
+                }
+                true
+            },
+            LoadSheddingPolicy::LowestPriority => {
+                let priority = get_metadata(record).get(&self.priority_key)
+                    .and_then(|p| p.trim().parse::<i64>().ok())
+                    .unwrap_or(0);
+                priority < self.min_priority
+            },
+        }
+    }
+}
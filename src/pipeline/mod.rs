@@ -2,9 +2,41 @@ use std::collections::HashMap;
 
 use log::debug;
 
+use handle::ModuleHandle;
+
+pub mod channels;
+pub mod commit;
+pub mod drain;
+pub mod drops;
+pub mod edge;
+pub mod error_log;
+pub mod graph;
+pub mod handle;
+pub mod heartbeat;
+pub mod latency;
+pub mod lease;
+pub mod limits;
 pub mod listener;
+pub mod load_shedding;
+pub mod numa;
+pub mod persistent_stats;
 pub mod pipeline;
 pub mod pipeline_step;
+pub mod ramp_up;
+pub mod record_history;
+pub mod retry;
+pub mod routing;
+pub mod sampling;
+pub mod self_test;
+pub mod shadow;
+pub mod snapshot;
+pub mod startup;
+pub mod stall;
+pub mod stats;
+pub mod step_context;
+pub mod step_mode;
+pub mod watchdog;
+pub mod wire_format;
 
 /// State of step
 #[derive(Clone, PartialEq)]
@@ -28,7 +60,7 @@ pub struct PipelineComponent {
     /// Module-specific arguments - credentials, formatting rules, etc
     pub args: HashMap<String, String>,
     /// This handle is passed to modules in order to identify a step
-    pub handle: usize,
+    pub handle: ModuleHandle,
     /// A human-readable identifier
     pub id: String,
     /// State of step
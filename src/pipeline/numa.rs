@@ -0,0 +1,82 @@
+/// NUMA hints for pipeline steps.
+/// A step with `numa_node` set is bound to CPUs of that node: the reader thread which feeds the step
+/// and threads spawned by module during step start inherit the CPU affinity.
+/// Memory is placed by kernel on the node of CPU which touches it first, so the input queue buffers
+/// and records allocated by module stay on the same node. Supported on Linux only
+
+use std::fs;
+
+/// Returns the IDs of CPUs which belong to NUMA node
+pub fn get_node_cpus(node: usize) -> Result<Vec<usize>, String> {
+    let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+    let cpulist = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("NUMA node {} is not available: cannot read '{}': {}", node, path, e)),
+    };
+    let cpus = parse_cpu_list(&cpulist)?;
+    if cpus.is_empty() {
+        return Err(format!("NUMA node {} has no CPUs", node))
+    }
+    Ok(cpus)
+}
+
+/// Parses a CPU list in kernel format, e.g. "0-3,8-11"
+fn parse_cpu_list(cpulist: &str) -> Result<Vec<usize>, String> {
+    let mut cpus: Vec<usize> = Vec::new();
+    for range in cpulist.trim().split(',').filter(|r| !r.is_empty()) {
+        let (start, end) = match range.split_once('-') {
+            Some((s, e)) => (s, e),
+            None => (range, range),
+        };
+        match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(s), Ok(e)) if s <= e => cpus.extend(s..=e),
+            _ => return Err(format!("Invalid CPU list: '{}'", cpulist.trim())),
+        }
+    }
+    Ok(cpus)
+}
+
+/// Binds the current thread to CPUs of NUMA node
+#[cfg(target_os = "linux")]
+pub fn bind_current_thread(node: usize) -> Result<(), String> {
+    let cpus = get_node_cpus(node)?;
+    let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+    }
+    set_current_thread_affinity(&cpu_set)
+        .map_err(|e| format!("Failed to bind the thread to NUMA node {}: {}", node, e))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bind_current_thread(_node: usize) -> Result<(), String> {
+    Err(String::from("NUMA hints are supported on Linux only"))
+}
+
+/// Runs the action on CPUs of NUMA node. The previous CPU affinity of current thread is restored afterwards
+#[cfg(target_os = "linux")]
+pub fn run_on_node<T, F: FnOnce() -> Result<T, String>>(node: usize, action: F) -> Result<T, String> {
+    let mut prev_cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut prev_cpu_set) } != 0 {
+        return Err(format!("Failed to get the CPU affinity of thread: {}", std::io::Error::last_os_error()))
+    }
+    bind_current_thread(node)?;
+    let result = action();
+    if let Err(e) = set_current_thread_affinity(&prev_cpu_set) {
+        return Err(format!("Failed to restore the CPU affinity of thread: {}", e))
+    }
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn run_on_node<T, F: FnOnce() -> Result<T, String>>(_node: usize, _action: F) -> Result<T, String> {
+    Err(String::from("NUMA hints are supported on Linux only"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_thread_affinity(cpu_set: &libc::cpu_set_t) -> Result<(), std::io::Error> {
+    match unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), cpu_set) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,102 @@
+/// Persistent statistics of steps.
+/// Cumulative counters of steps are saved to file on pipeline termination and loaded on start,
+/// so the lifetime totals survive restarts. The counters in `StepStatistics` still count since the process start.
+/// Steps are identified by step ID, so the totals of steps which change their position or module start from zero
+
+use std::{collections::HashMap, fs, path::Path, sync::atomic::Ordering};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::xthread::{LIFETIME_STATS_BASE, PIPELINE, STEP_STATS};
+
+/// Cumulative counters of step
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub struct PersistentCounters {
+    pub records_received: u64,
+    pub records_succeeded: u64,
+    pub records_failed: u64,
+}
+
+/// Contents of statistics file
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+struct PersistentStats {
+    pipeline: String,
+    steps: HashMap<String, PersistentCounters>,
+}
+
+/// Loads the counters saved by previous runs. A missing file means the first run
+pub fn load_persistent_stats(path: &str, pipeline_name: &str) -> Result<(), String> {
+    let base = match Path::new(path).exists() {
+        true => {
+            let contents = match fs::read_to_string(path) {
+                Ok(c) => c,
+                Err(e) => return Err(format!("Cannot read the statistics file '{}': {}", path, e)),
+            };
+            let stats: PersistentStats = match serde_json::from_str(&contents) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Cannot parse the statistics file '{}': {}", path, e)),
+            };
+            if stats.pipeline != pipeline_name {
+                return Err(format!("The statistics file '{}' belongs to pipeline '{}', not '{}'", path, stats.pipeline, pipeline_name))
+            }
+            info!("Loaded the lifetime statistics of {} step(s) from '{}'", stats.steps.len(), path);
+            stats.steps
+        },
+        false => HashMap::new(),
+    };
+    if LIFETIME_STATS_BASE.set(base).is_err() {
+        return Err(String::from("The lifetime statistics are already loaded"))
+    }
+    Ok(())
+}
+
+/// Returns the lifetime counters of steps: counters of previous runs plus counters since the process start.
+/// Empty if the lifetime statistics are not loaded
+pub fn get_lifetime_counters() -> Vec<(String, PersistentCounters)> {
+    let base = match LIFETIME_STATS_BASE.get() {
+        Some(b) => b,
+        None => return Vec::new(),
+    };
+    let steps: Vec<_> = match PIPELINE.get() {
+        Some(p) => p.lock().unwrap().steps.iter()
+            .map(|s| {
+                let s = s.lock().unwrap();
+                (s.get_handle(), s.get_id())
+            })
+            .collect(),
+        None => return Vec::new(),
+    };
+    let step_stats = STEP_STATS.lock().unwrap();
+    steps.into_iter()
+        .map(|(handle, step_id)| {
+            let mut counters = base.get(&step_id).copied().unwrap_or_default();
+            if let Some(s) = step_stats.get(&handle) {
+                counters.records_received += s.records_received.load(Ordering::Relaxed);
+                counters.records_succeeded += s.records_succeeded.load(Ordering::Relaxed);
+                counters.records_failed += s.records_failed.load(Ordering::Relaxed);
+            }
+            (step_id, counters)
+        })
+        .collect()
+}
+
+/// Saves the lifetime counters to file. The file is replaced atomically
+pub fn save_persistent_stats(path: &str, pipeline_name: &str) -> Result<(), String> {
+    let mut steps: HashMap<String, PersistentCounters> = LIFETIME_STATS_BASE.get().cloned().unwrap_or_default();
+    steps.extend(get_lifetime_counters());
+    let stats = PersistentStats { pipeline: pipeline_name.to_string(), steps };
+    let contents = match serde_json::to_string_pretty(&stats) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Cannot serialize the statistics: {}", e)),
+    };
+    let tmp_path = format!("{}.tmp", path);
+    if let Err(e) = fs::write(&tmp_path, contents) {
+        return Err(format!("Failed to write file '{}': {}", tmp_path, e))
+    }
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        return Err(format!("Failed to rename file '{}' to '{}': {}", tmp_path, path, e))
+    }
+    debug!("Lifetime statistics are saved to '{}'", path);
+    Ok(())
+}
@@ -1,87 +1,333 @@
 use std::{
-    collections::HashMap, sync::{
+    collections::{BTreeMap, HashMap, HashSet}, sync::{
         mpsc::{channel, Receiver},
+        atomic::Ordering,
         Arc, Mutex
-    }, thread, time::Duration
+    }, thread::{self, JoinHandle}, time::{Duration, Instant}
 };
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use serde_json::json;
 
-use torustiq_common::ffi::{
-    types::{
-        module::{
-            ModuleListenerConfigureArgs, ModulePipelineConfigureArgs, ModulePipelineProcessRecordFnResult, PipelineModuleKind, Record,
-        },
-        std_types,
-    }, utils::strings::cchar_to_string
+use torustiq_common::ffi::types::module::{
+    ModuleListenerConfigureArgs, ModulePipelineConfigureArgs,
 };
+use uuid::Uuid;
 
 use crate::{
-    config::PipelineDefinition,
-    modules::module_loader::LoadedLibraries,
+    callbacks::on_step_terminate_cb,
+    config::{HeartbeatDefinition, LeaseDefinition, ListenerEvent, ModuleDefinition, NotificationDefinition, PipelineDataSection, PipelineDefinition, RampUpDefinition, SamplingDefinition, ShutdownDrainDefinition},
+    errors::TorustiqError,
+    events::publish,
+    modules::builtin::{capture, shadow_sink},
+    modules::{
+        arg_types::check_arg_types,
+        native::{create_native_module, is_native_module},
+        module_loader::LoadedLibraries,
+    },
     pipeline::{
+        channels::Channels,
+        commit::CommitSchedule,
+        drain::SourceDrain,
+        drops::{get_drop_listeners, on_record_dropped, DropReason},
+        edge::{edge, EdgeReceiver, EdgeSender, QueueDepth, SequenceCheck, SequenceCheckResult},
+        error_log::ErrorLog,
+        load_shedding::LoadShedder,
+        numa,
+        handle::ModuleHandle,
+        heartbeat::is_heartbeat,
+        lease::{start_lease_thread, SourceLease},
         listener::Listener,
-        pipeline_step::PipelineStep
+        pipeline_step::{PipelineStep, StepModule},
+        ramp_up::RampUp,
+        record_history::RecordHistory,
+        retry::{RetryQueue, RetryRoute, DEFAULT_RETRY_DELAY_MS, DEFAULT_RETRY_MAX_ATTEMPTS},
+        routing::{StepOutputs, Topology},
+        self_test::{acknowledge_termination, create_self_test_handle, is_self_test_handle},
+        sampling::{Sampling, DEFAULT_SAMPLING_QUEUE_CAPACITY, SAMPLING_STEP_NAME},
+        shadow::{Shadow, ShadowSide, DEFAULT_SHADOW_QUEUE_CAPACITY, SHADOW_SINK_STEP_NAME},
+        snapshot::{complete_commit_request, is_commit_requested},
+        startup::for_each_step_concurrently,
+        wire_format::{insert_conversion_steps, Conversion},
+        stats::StepStatistics,
+        step_context::{get_step_context, register_step_contexts, set_steps_shutting_down, StepContext},
     },
-    xthread::{SystemMessage, FREE_BUF, PIPELINE, SENDERS, SYSTEM_MESSAGES}
+    masking::{add_masked_key_patterns, register_secrets},
+    metrics::resolve_metrics_labels,
+    policy::{ModulePolicy, ModulePosition},
+    records::{append_verdicts, get_time_since_origin, update_deadline},
+    xthread::{SystemMessage, CHANNELS, DROP_LISTENERS, END_TO_END_LATENCY, IS_DEADLINE_TRACKING_ENABLED, LATENCY_SOURCE_HANDLE, MODULE_HANDLES, PIPELINE, PROVENANCE_STEP_IDS, RAMP_UP, RESOURCE_LIMITS, RETRY_QUEUE, SAMPLING, SELF_TEST_HANDLE, SHADOW, SOURCE_DRAIN, SOURCE_LEASE, STEP_MODE, STEP_STATS, SYSTEM_MESSAGES}
 };
 
 /// Starts a system command thread.
 /// System command threads change the state of pipeline. For instance, a command thread can terminate the pipeline.
-fn start_system_command_thread(m_rx: Receiver<SystemMessage>) {
-    // A system command thread
-    thread::spawn(|| {
-        let m_rx = m_rx;
-        loop {
-            let msg = match m_rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(r) => r,
-                Err(_) => continue, // timeout
+/// The thread runs until it receives the shutdown message
+fn start_system_command_thread(thread_name: String, m_rx: Receiver<SystemMessage>) -> Result<JoinHandle<()>, String> {
+    let result = thread::Builder::new().name(thread_name.clone()).spawn(move || loop {
+        let msg = match m_rx.recv() {
+            Ok(m) => m,
+            Err(_) => {
+                warn!("System command thread is stopped: the system message channel is closed");
+                return
+            },
+        };
+        match handle_system_message(msg) {
+            Ok(true) => {},
+            Ok(false) => {
+                debug!("System command thread is stopped");
+                return
+            },
+            // A failure to handle one message must not affect the handling of further messages
+            Err(msg) => error!("Failed to handle a system message: {}", msg),
+        }
+    });
+    match result {
+        Ok(h) => Ok(h),
+        Err(e) => Err(format!("Failed to start thread '{}': {}", thread_name, e)),
+    }
+}
+
+/// Handles a system message. Returns false if the system command thread must stop
+fn handle_system_message(msg: SystemMessage) -> Result<bool, String> {
+    match msg {
+        SystemMessage::TerminateStep(module_handle) if is_self_test_handle(module_handle) => {
+            acknowledge_termination();
+            Ok(true)
+        },
+        SystemMessage::TerminateStep(module_handle) => {
+            let mut pipeline = match PIPELINE.get() {
+                Some(p) => p.lock().unwrap(),
+                None => return Err(format!("Cannot process the termination callback for step {}: \
+                    pipeline is not registered in static context", module_handle)),
+            };
+            let pipeline_step_arc = match pipeline.get_step_by_handle_mut(module_handle) {
+                Some(s) => s,
+                None => return Err(format!("Cannot find a pipeline step with handle '{}' in static context", module_handle)),
             };
+            let step_id = {
+                let mut step = pipeline_step_arc.lock().unwrap();
+                step.set_state_terminated();
+                step.get_id()
+            };
+            publish("step_terminated", json!({"step": step_id}));
+            pipeline.handle_step_termination(module_handle);
+            Ok(true)
+        },
+        SystemMessage::Shutdown => Ok(false),
+    }
+}
 
-            match msg {
-                SystemMessage::TerminateStep(module_handle) => {
-                    let pipeline = match PIPELINE.get() {
-                        Some(p) => p.lock().unwrap(),
-                        None => {
-                            error!("Cannot process the termination callback for step {}: \
-                                pipeline is not registered in static context", module_handle);
-                            return;
-                        }
-                    };
-                    let pipeline_step_arc = match pipeline.get_step_by_handle_mut(usize::try_from(module_handle).unwrap()) {
-                        Some(s) => s,
-                        None => {
-                            error!("Cannot find a pipeline step with handle '{}' in static context", module_handle);
-                            return;
-                        }
-                    };
-                    let mut pipeline_step = pipeline_step_arc.lock().unwrap();
-                    pipeline_step.component.set_state_terminated();
-                }
-            }
+/// Stops the system command thread and waits until it exits.
+/// Must be called without the pipeline lock held, as the thread might wait for the lock to handle the last messages
+pub fn stop_system_command_thread(handle: JoinHandle<()>) {
+    if handle.is_finished() {
+        error!("System command thread has stopped before the pipeline termination");
+    } else if let Some(tx) = SYSTEM_MESSAGES.get() {
+        if let Err(e) = tx.send(SystemMessage::Shutdown) {
+            error!("Failed to send a shutdown message to system command thread: {}", e);
+            return
         }
-    });
+    }
+    if handle.join().is_err() {
+        error!("System command thread panicked");
+    }
+}
+
+/// Returns true if the step is paused
+pub fn is_step_paused(handle: ModuleHandle) -> bool {
+    get_step_context(handle).map(|c| c.is_paused()).unwrap_or(false)
+}
+
+/// Returns true if the step is being drained
+pub fn is_step_draining(handle: ModuleHandle) -> bool {
+    get_step_context(handle).map(|c| c.is_draining()).unwrap_or(false)
+}
+
+/// Starts or stops draining of the step
+pub fn set_step_draining(handle: ModuleHandle, is_draining: bool) {
+    match get_step_context(handle) {
+        Some(c) => c.set_draining(is_draining),
+        None => warn!("Cannot drain step '{}': steps are not configured yet", handle),
+    }
+}
+
+/// Pauses or resumes the step
+pub fn set_step_paused(handle: ModuleHandle, is_paused: bool) {
+    match get_step_context(handle) {
+        Some(c) if is_paused && c.is_shutting_down() => warn!("Step '{}' is not paused: the pipeline is terminating", handle),
+        Some(c) => c.set_paused(is_paused),
+        None => warn!("Cannot pause step '{}': steps are not configured yet", handle),
+    }
+}
+
+/// Starts the step on its NUMA node, if any
+pub fn start_step(step: &PipelineStep) -> Result<(), TorustiqError> {
+    // Threads spawned by module inherit the CPU affinity of the current thread
+    let result = match step.numa_node {
+        Some(node) => numa::run_on_node(node, || step.start()),
+        None => step.start(),
+    };
+    match result {
+        Ok(_) => {
+            debug!("Started pipeline step '{}'", step.component.id);
+            Ok(())
+        },
+        Err(msg) => Err(TorustiqError::StepStart {
+            step: step.component.id.clone(),
+            module_id: step.module.get_id(),
+            message: msg,
+        }),
+    }
 }
 
 /// Starts a reader thread.
 /// Reader threads listen input from the previous (sender) steps and forward records to further (receiver) steps
-fn start_reader_thread(step_sender_arc: Arc<Mutex<PipelineStep>>, step_receiver_arc: Arc<Mutex<PipelineStep>>, rx: Receiver<Record>, listeners: Vec<Listener>) {
+fn start_reader_thread(thread_name: String, step_sender_arcs: Vec<Arc<Mutex<PipelineStep>>>, step_receiver_arc: Arc<Mutex<PipelineStep>>,
+    rx: EdgeReceiver, listeners: Vec<Listener>, stats: Arc<StepStatistics>, measure_latency: bool) -> Result<(), String> {
     let step_rcv = step_receiver_arc.lock().unwrap().clone();
-    thread::spawn(move || {
-        let i_receiver_ffi = u32::try_from(step_rcv.get_handle()).unwrap();
+    // The state of steps is read through their contexts, so the loop doesn't lock the steps
+    let sender_contexts: Vec<Arc<StepContext>> = step_sender_arcs.iter().map(|s| s.lock().unwrap().context.clone()).collect();
+    let result = thread::Builder::new().name(thread_name.clone()).spawn(move || {
+        if let Some(node) = step_rcv.numa_node {
+            match numa::bind_current_thread(node) {
+                Ok(_) => debug!("Reader thread of step '{}' is bound to NUMA node {}", step_rcv.get_id(), node),
+                Err(msg) => warn!("{}", msg),
+            }
+        }
+        let i_receiver_ffi = step_rcv.get_handle().to_ffi();
+        let mut is_receiver_termination_reported = false;
+        // Listeners are filtered once here in order to avoid the checks for each record
+        let filter_listeners = |event: ListenerEvent| -> Vec<Listener> {
+            match step_rcv.is_listener_event_enabled(event) {
+                true => listeners.iter().filter(|l| l.accepts(event)).cloned().collect(),
+                false => Vec::new(),
+            }
+        };
+        let listeners_received = filter_listeners(ListenerEvent::Received);
+        let listeners_success = filter_listeners(ListenerEvent::Success);
+        let listeners_error = filter_listeners(ListenerEvent::Error);
+        let mut sequence_check = match step_rcv.check_sequence {
+            true => Some(SequenceCheck::default()),
+            false => None,
+        };
+        let mut error_log = ErrorLog::new(step_rcv.get_id(), step_rcv.error_log_sampling.as_ref());
+        // Unbounded queues are never full, so there is no overload to detect
+        let mut load_shedder = match (step_rcv.queue_capacity, &step_rcv.load_shedding) {
+            (Some(capacity), Some(definition)) => Some(LoadShedder::new(step_rcv.get_id(), capacity, definition)),
+            _ => None,
+        };
+        let mut commit_schedule = match step_rcv.is_commit_enabled() {
+            true => Some(CommitSchedule::new(step_rcv.commit_interval, step_rcv.commit_max_records)),
+            false => None,
+        };
         loop {
-            let mut record = match rx.recv_timeout(Duration::from_millis(100)) {
+            // Records are kept in queue while the step is paused or drained
+            let handle = step_rcv.get_handle();
+            let context = &step_rcv.context;
+            if (context.is_paused() || context.is_draining()) && !context.is_terminated() {
+                stats.is_input_stopped.store(true, Ordering::SeqCst);
+                // Commits out of schedule are made between record processing calls, so the step must be stopped
+                if is_commit_requested(handle) {
+                    match commit_schedule.as_mut() {
+                        Some(schedule) => commit_step(&step_rcv, schedule),
+                        None => if let Err(msg) = step_rcv.commit() {
+                            error!("Failed to commit the records of step '{}': {}", step_rcv.get_id(), msg);
+                        },
+                    }
+                    complete_commit_request(handle);
+                }
+                thread::sleep(step_rcv.poll_interval);
+                continue;
+            }
+            stats.is_input_stopped.store(false, Ordering::SeqCst);
+            // Idle step wakes up in time to commit the records by interval
+            let timeout = match commit_schedule.as_ref().and_then(|c| c.get_time_until_due()) {
+                Some(t) => t.min(step_rcv.poll_interval),
+                None => step_rcv.poll_interval,
+            };
+            let (sequence, mut record) = match rx.recv_timeout(timeout) {
                 Ok(r) => r,
                 Err(_) => { // timeout
-                    if step_sender_arc.lock().unwrap().component.is_terminated() { // no messages because the source is shut down
+                    error_log.log_summary_if_due();
+                    if let Some(schedule) = commit_schedule.as_mut().filter(|c| c.is_due()) {
+                        commit_step(&step_rcv, schedule);
+                    }
+                    // no messages because all upstream steps are shut down and no records are parked for retry in this step
+                    if sender_contexts.iter().all(|c| c.is_terminated())
+                        && !RETRY_QUEUE.get().map(|q| q.has_pending(handle)).unwrap_or(false) {
                         break;
                     } else {
                         continue; // no messages, but source is online
                     }
                 }
             };
-            for l in &listeners {
-                l.ffi_on_record_received(i_receiver_ffi, &record);
+            if step_rcv.drops_heartbeats && is_heartbeat(&record) {
+                record.free_contents();
+                continue;
+            }
+            stats.on_received();
+            if let Some(history) = &step_rcv.record_history {
+                history.add(sequence, &record);
+            }
+            if let (Some(step_mode), false) = (STEP_MODE.get(), step_rcv.is_shadow || step_rcv.is_sampling) {
+                step_mode.hold(handle, &step_rcv.get_id(), sequence, &record);
+            }
+            if let Some(check) = sequence_check.as_mut() {
+                match check.check(sequence) {
+                    SequenceCheckResult::Ok => {},
+                    SequenceCheckResult::Gap(missing) => {
+                        warn!("Step '{}': {} record(s) missing before record #{}", step_rcv.get_id(), missing, sequence);
+                        stats.sequence_gaps.fetch_add(missing, Ordering::Relaxed);
+                    },
+                    SequenceCheckResult::Reordered => {
+                        warn!("Step '{}': record #{} arrived out of order", step_rcv.get_id(), sequence);
+                        stats.sequence_reorderings.fetch_add(1, Ordering::Relaxed);
+                    },
+                }
+            }
+            // Verdicts of listeners are added to metadata, so the receiving step and downstream steps can act on them
+            let verdicts: Vec<(String, String)> = listeners_received.iter()
+                .filter_map(|l| l.ffi_on_record_received(i_receiver_ffi, &record).map(|v| (l.get_id(), v)))
+                .collect();
+            if !verdicts.is_empty() {
+                record = append_verdicts(record, &verdicts);
+            }
+
+            if let Some(shedder) = load_shedder.as_mut() {
+                shedder.update(rx.get_queue_depth_counter().get());
+                stats.is_shedding.store(shedder.is_shedding(), Ordering::Relaxed);
+                if shedder.should_drop(&record) {
+                    stats.records_shed.fetch_add(1, Ordering::Relaxed);
+                    on_record_dropped(handle, &record, DropReason::Shed);
+                    record.free_contents();
+                    continue;
+                }
+            }
+
+            // The receiver is terminated before upstream: nowhere to send the record
+            if step_rcv.context.is_terminated() {
+                if !is_receiver_termination_reported {
+                    warn!("Step '{}' is terminated. Records from upstream are dropped", step_rcv.get_id());
+                    is_receiver_termination_reported = true;
+                }
+                stats.on_processed(false);
+                on_record_dropped(handle, &record, DropReason::StepTerminated);
+                record.free_contents();
+                continue;
+            }
+
+            // Records with expired deadline are not processed anymore
+            if IS_DEADLINE_TRACKING_ENABLED.load(Ordering::Relaxed) {
+                let (updated_record, is_expired) = update_deadline(record);
+                record = updated_record;
+                if is_expired {
+                    error_log.log("the record deadline is expired");
+                    stats.records_expired.fetch_add(1, Ordering::Relaxed);
+                    stats.on_processed(false);
+                    on_record_dropped(handle, &record, DropReason::Expired);
+                    record.free_contents();
+                    continue;
+                }
             }
 
             // NO deep copy here for performance purposes.
@@ -89,43 +335,195 @@ fn start_reader_thread(step_sender_arc: Arc<Mutex<PipelineStep>>, step_receiver_
             // - it's used only partially (e.g. metadata only)
             // - it's processed instantly and therefore not stored inside module.
             // let record_copy = record.shallow_copy();
-            let (success, is_consumed) = match step_rcv.ffi_process_record(record, i_receiver_ffi) {
-                ModulePipelineProcessRecordFnResult::Ok(is_consumed) => (true, is_consumed),
-                ModulePipelineProcessRecordFnResult::ErrWrongModuleHandle(handle, is_consumed) => {
-                    error!("Wrong module handle '{}'", handle);
-                    (false, is_consumed)
-                },
-                ModulePipelineProcessRecordFnResult::ErrMisc(cerr, is_consumed) => {
-                    let err: String = cchar_to_string(cerr);
-                    error!("Failed to process record in step '{}': {}", step_rcv.get_id(), err);
-                    step_rcv.ffi_free_char(cerr);
-                    (false, is_consumed)
+            // Read before processing, as the module might take over the record
+            let time_since_origin = match measure_latency {
+                true => get_time_since_origin(&record),
+                false => None,
+            };
+            if step_rcv.is_shadow_compared {
+                if let Some(shadow) = SHADOW.get() {
+                    shadow.observe(ShadowSide::Primary, &record);
+                }
+            }
+            let started_at = Instant::now();
+            let result = step_rcv.process_record(record);
+            let processing_time = started_at.elapsed();
+            stats.on_process_record_call(processing_time);
+            if let (Some(t), None) = (time_since_origin, &result.error) {
+                END_TO_END_LATENCY.observe(t + processing_time);
+            }
+            let success = match &result.error {
+                None => true,
+                Some(err) => {
+                    error_log.log(err);
+                    if let Some(history) = &step_rcv.record_history {
+                        match history.on_failure(sequence, err) {
+                            Ok(Some(path)) => warn!("History of records of step '{}' is written to '{}'", step_rcv.get_id(), path),
+                            Ok(None) => {},
+                            Err(msg) => error!("{}", msg),
+                        }
+                    }
+                    false
                 }
             };
+            stats.on_processed(success);
             if success {
-                for l in &listeners {
+                for l in &listeners_success {
                     l.ffi_on_record_sent(i_receiver_ffi, &record);
                 }
             } else {
-                for l in &listeners {
+                for l in &listeners_error {
                     l.ffi_on_record_error(i_receiver_ffi, &record);
                 }
             }
-            if !is_consumed {
-                record.free_contents();
+            if !result.is_consumed {
+                // Failed records might be retried or sent to the dead letter step, depending on the error
+                let record = match (&result.error, RETRY_QUEUE.get()) {
+                    (Some(err), Some(retry_queue)) => retry_queue.on_error(handle, record, err).err(),
+                    _ => Some(record),
+                };
+                if let Some(mut record) = record {
+                    record.free_contents();
+                }
+            }
+            if let Some(schedule) = commit_schedule.as_mut() {
+                schedule.on_processed();
+                if schedule.is_due() {
+                    commit_step(&step_rcv, schedule);
+                }
             }
         }
 
+        error_log.log_summary();
+        let records_left = rx.get_queue_depth_counter().get();
+        if records_left > 0 {
+            warn!("Step '{}': {} record(s) are left in the input queue and will not be deallocated", step_rcv.get_id(), records_left);
+            stats.records_leaked.fetch_add(records_left as u64, Ordering::Relaxed);
+        }
+
         // Processed all the data from upstream. Terminating the current step
-        step_rcv.ffi_shutdown(i_receiver_ffi);
+        if !step_rcv.context.is_terminated() {
+            if let Some(schedule) = commit_schedule.as_mut().filter(|c| c.has_uncommitted()) {
+                commit_step(&step_rcv, schedule);
+            }
+            step_rcv.shutdown();
+        }
     });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to start thread '{}': {}", thread_name, e)),
+    }
 }
 
+/// Returns a definition of step which receives the samples of records
+fn create_sampling_destination_definition(sampling: &SamplingDefinition) -> Result<ModuleDefinition, String> {
+    if let Some(d) = &sampling.destination {
+        return Ok(d.clone())
+    }
+    let path = sampling.path.clone().unwrap_or_default();
+    let definition = serde_yaml::Mapping::from_iter([
+        (serde_yaml::Value::from("name"), serde_yaml::Value::from(SAMPLING_STEP_NAME)),
+        (serde_yaml::Value::from("handler"), serde_yaml::Value::from(capture::MODULE_ID)),
+        (serde_yaml::Value::from("args"), serde_yaml::Value::Mapping(serde_yaml::Mapping::from_iter([
+            (serde_yaml::Value::from("path"), serde_yaml::Value::from(path)),
+        ]))),
+    ]);
+    match serde_yaml::from_value(serde_yaml::Value::Mapping(definition)) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Cannot create a definition of sampling destination: {}", e)),
+    }
+}
+
+/// Returns a definition of step which ends the shadow chain
+fn create_shadow_sink_definition() -> Result<ModuleDefinition, String> {
+    let definition = serde_yaml::Mapping::from_iter([
+        (serde_yaml::Value::from("name"), serde_yaml::Value::from(SHADOW_SINK_STEP_NAME)),
+        (serde_yaml::Value::from("handler"), serde_yaml::Value::from(shadow_sink::MODULE_ID)),
+    ]);
+    match serde_yaml::from_value(serde_yaml::Value::Mapping(definition)) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Cannot create a definition of shadow sink: {}", e)),
+    }
+}
+
+/// Commits the records passed to step and starts a new batch
+fn commit_step(step: &PipelineStep, schedule: &mut CommitSchedule) {
+    match step.commit() {
+        Ok(_) => debug!("Records of step '{}' are committed", step.get_id()),
+        Err(msg) => error!("Failed to commit the records of step '{}': {}", step.get_id(), msg),
+    }
+    schedule.reset();
+}
+
+/// State of pipeline
+#[derive(Clone, Default, PartialEq)]
+pub enum PipelineState {
+    /// Pipeline is running or terminated normally
+    #[default]
+    Running,
+    /// A step is terminated while the upstream steps were running. Contains the step ID
+    DownstreamTerminated(String),
+}
+
+/// A prefix of step arguments which contain the feature flags of pipeline
+pub const FEATURE_ARG_PREFIX: &str = "feature.";
+
+/// Default period of inactivity of step with full input queue after which the step is reported as stalled
+pub const DEFAULT_STALL_TIMEOUT_MS: u64 = 60000;
+
+/// Default period between graceful and forced shutdown if the pipeline exceeds the maximum runtime
+pub const DEFAULT_SHUTDOWN_GRACE_MS: u64 = 30000;
+
 #[derive(Default)]
 pub struct Pipeline {
+    /// A name of pipeline. Used in thread names, logs and metrics
+    pub name: String,
     pub description: Option<String>,
     pub listeners: Vec<Arc<Mutex<Listener>>>,
+    /// If set, restricts the positions of modules in pipeline
+    pub policy: Option<ModulePolicy>,
+    /// If true, the steps append provenance entries to records
+    pub provenance: bool,
+    /// If true, the processing budget of records is tracked
+    pub deadlines: bool,
+    /// If true, the end-to-end latency of records is measured
+    pub latency_tracking: bool,
+    /// The pipeline is shut down once it runs longer than this
+    pub max_runtime: Option<Duration>,
+    /// A period between graceful and forced shutdown if the pipeline exceeds the maximum runtime
+    pub shutdown_grace_period: Duration,
+    /// Webhooks which are called on pipeline events
+    pub notifications: Vec<NotificationDefinition>,
+    /// A step is reported as stalled if it doesn't consume records for this period while its input queue is full
+    pub stall_timeout: Duration,
+    /// If set, the rate of source grows gradually on startup
+    pub ramp_up: Option<RampUpDefinition>,
     pub steps: Vec<Arc<Mutex<PipelineStep>>>,
+    /// Edges between steps
+    pub topology: Topology,
+    pub state: PipelineState,
+    /// A file to persist the cumulative statistics of steps across restarts
+    pub stats_file: Option<String>,
+    /// Feature flags which are passed to every step
+    pub features: BTreeMap<String, bool>,
+    /// Handling of records which are produced by source after the shutdown request
+    pub shutdown_drain: Option<ShutdownDrainDefinition>,
+    /// If set, the source runs only while this instance holds the lease
+    pub lease: Option<LeaseDefinition>,
+    /// If set, heartbeat records are injected at the source periodically
+    pub heartbeat: Option<HeartbeatDefinition>,
+    /// Set if pipeline has a shadow chain. True if the shadow output is compared with the primary output
+    pub shadow_compare: Option<bool>,
+    /// A share of records which are copied to sampling destination, in percent. Set if sampling is configured
+    pub sampling_percent: Option<f64>,
+    /// A thread which handles the system messages. Set once the channels are started
+    pub system_thread: Option<JoinHandle<()>>,
+    /// Conversion steps which are inserted by host between steps of different wire formats
+    pub conversions: Vec<Conversion>,
+    /// Outputs of steps and record deallocation functions. Filled once the channels are started
+    pub channels: Arc<Channels>,
+    /// A unique ID of pipeline run. Passed to listeners and lineage events
+    pub run_id: Uuid,
 }
 
 impl Pipeline {
@@ -141,125 +539,425 @@ impl Pipeline {
         if steps_len < 2 {
             return Err(format!("Pipeline must have at least two steps. The actual number of steps: {}", steps_len))
         }
+        if let Some(ramp_up) = &self.ramp_up {
+            ramp_up.validate()?;
+        }
+        if let Some(shutdown_drain) = &self.shutdown_drain {
+            shutdown_drain.validate()?;
+        }
+        if let Some(lease) = &self.lease {
+            lease.validate()?;
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.validate()?;
+        }
+        self.validate_schemas()?;
+        self.validate_formats()?;
+        self.validate_positions()?;
+        self.validate_numa_nodes()?;
+        self.validate_commit_settings()?;
+        Ok(())
+    }
+
+    /// Checks if commit boundaries are set for destination steps only
+    fn validate_commit_settings(&self) -> Result<(), String> {
+        for (step_index, step) in self.steps.iter().enumerate() {
+            let step = step.lock().unwrap();
+            if !step.is_commit_enabled() {
+                continue
+            }
+            if !self.topology.is_destination(step_index) || 0 == step_index {
+                return Err(format!("Step '{}' is not a destination. Commit settings are applicable to destination steps only", step.get_id()))
+            }
+            if step.commit_max_records == Some(0) {
+                return Err(format!("Step '{}': the maximum number of records in commit must be positive", step.get_id()))
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if NUMA nodes of steps are available on this machine
+    fn validate_numa_nodes(&self) -> Result<(), String> {
+        for step in &self.steps {
+            let step = step.lock().unwrap();
+            if let Some(node) = step.numa_node {
+                if let Err(msg) = numa::get_node_cpus(node) {
+                    return Err(format!("Invalid NUMA node of step '{}': {}", step.get_id(), msg))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if each module supports its position in pipeline: source modules must be the first step,
+    /// destination modules must end a branch, transformation modules must be in between.
+    /// Modules which don't declare the supported positions are not checked
+    fn validate_positions(&self) -> Result<(), String> {
+        for (step_index, step) in self.steps.iter().enumerate() {
+            let step = step.lock().unwrap();
+            let supported = match step.module.get_positions() {
+                Some(p) => p,
+                None => continue,
+            };
+            let position: ModulePosition = (&self.topology.get_kind(step_index)).into();
+            if supported.contains(&position) {
+                continue
+            }
+            let expected: Vec<String> = supported.iter()
+                .map(|p| match p {
+                    ModulePosition::Source => String::from("source (the first step)"),
+                    ModulePosition::Transformation => String::from("transformation (between the first and the last steps)"),
+                    ModulePosition::Destination => String::from("destination (the last step of branch)"),
+                    ModulePosition::Listener => String::from("listener"),
+                })
+                .collect();
+            return Err(format!("Module '{}' of step '{}' cannot be used as {:?} at position #{}. Supported positions: {}",
+                step.module.get_id(), step.get_id(), position, step_index, expected.join(", ")))
+        }
+        Ok(())
+    }
+
+    /// Checks if the output schema of each step matches the input schema of the steps it sends records to.
+    /// Edges where either of steps doesn't declare a schema are not checked
+    fn validate_schemas(&self) -> Result<(), String> {
+        for i_receiver in 1..self.steps.len() {
+            let receiver = self.steps[i_receiver].lock().unwrap();
+            for i_sender in self.topology.get_inputs(i_receiver) {
+                let sender = self.steps[i_sender].lock().unwrap();
+                if let (Some(output_schema), Some(input_schema)) = (&sender.output_schema, &receiver.input_schema) {
+                    if output_schema != input_schema {
+                        return Err(format!("Schema mismatch on edge between steps '{}' and '{}': \
+                            step '{}' produces records of schema '{}', but step '{}' expects schema '{}'",
+                            sender.get_id(), receiver.get_id(),
+                            sender.get_id(), output_schema, receiver.get_id(), input_schema))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if the output format of each step matches the input format of the steps it sends records to.
+    /// The known conversions are inserted before, so a mismatch means there is no conversion, e.g. in shadow chain
+    fn validate_formats(&self) -> Result<(), String> {
+        for i_receiver in 1..self.steps.len() {
+            let receiver = self.steps[i_receiver].lock().unwrap();
+            for i_sender in self.topology.get_inputs(i_receiver) {
+                let sender = self.steps[i_sender].lock().unwrap();
+                if let (Some(output_format), Some(input_format)) = (&sender.output_format, &receiver.input_format) {
+                    if output_format != input_format {
+                        return Err(format!("Wire format mismatch on edge between steps '{}' and '{}': \
+                            step '{}' produces format '{}', but step '{}' expects format '{}'",
+                            sender.get_id(), receiver.get_id(),
+                            sender.get_id(), output_format, receiver.get_id(), input_format))
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
     /// Pass configuration to each step
-    pub fn configure_steps(&mut self) -> Result<(), String> {
+    pub fn configure_steps(&mut self) -> Result<(), TorustiqError> {
         info!("Configuring steps...");
-        let last_step_index = self.steps.len() - 1;
-        for (step_index, step_mtx) in self.steps.iter_mut().enumerate() {
+        let contexts = self.steps.iter()
+            .map(|s| {
+                let s = s.lock().unwrap();
+                (s.get_handle(), s.context.clone())
+            })
+            .collect();
+        register_step_contexts(contexts).map_err(TorustiqError::Config)?;
+        let get_kind = |step_index: usize| self.topology.get_kind(step_index);
+        if let Some(policy) = &self.policy {
+            for (step_index, step_mtx) in self.steps.iter().enumerate() {
+                let step = step_mtx.lock().unwrap();
+                if step.is_host_inserted {
+                    continue
+                }
+                policy.check_position(&step.module.get_id(), (&get_kind(step_index)).into())
+                    .map_err(TorustiqError::Policy)?;
+            }
+        }
+        for_each_step_concurrently(&self.steps, |step_index, step_mtx| {
             let mut step = step_mtx.lock().unwrap();
             let module_handle = step.component.handle;
-            for (k, v) in &step.component.args { // set arguments for step
-                step.module.set_param(module_handle, k, v);
+            let configure_error = |step: &PipelineStep, message: String| TorustiqError::StepConfigure {
+                step: step.get_id(),
+                module_id: step.module.get_id(),
+                message,
+            };
+            if let Err(msg) = step.configure(ModulePipelineConfigureArgs{
+                kind: get_kind(step_index),
+                module_handle: module_handle.to_ffi(),
+            }) {
+                return Err(configure_error(&step, msg))
             }
-            let kind = if 0 == step_index { PipelineModuleKind::Source }
-                else if last_step_index == step_index { PipelineModuleKind::Destination }
-                else { PipelineModuleKind::Transformation };
-            step.configure(ModulePipelineConfigureArgs{
-                kind,
-                module_handle: std_types::Uint::try_from(module_handle).unwrap(),
-            })?;
-        }
-        Ok(())
+            if step.is_commit_enabled() && !step.is_commit_supported() {
+                warn!("Module '{}' doesn't support commits. Commit settings of step '{}' are ignored", step.module.get_id(), step.get_id());
+                step.commit_interval = None;
+                step.commit_max_records = None;
+            }
+            if let Some(fixture_path) = step.prime.clone() {
+                info!("Priming step '{}' with sample records from '{}'", step.get_id(), fixture_path);
+                if let Err(msg) = step.prime(&fixture_path) {
+                    return Err(configure_error(&step, format!("Failed to prime the step: {}", msg)))
+                }
+            }
+            Ok(())
+        })
     }
 
     pub fn configure_listeners(&mut self) -> Result<(), String> {
         info!("Configuring steps...");
 
-        // Format the pipeline info in order to pass it to listeners
-        let mut pipeline_data: HashMap<String, String> = HashMap::new();
+        // Format the pipeline info in order to pass it to listeners. Each listener receives the sections it exposes
+        let mut pipeline_data: Vec<(PipelineDataSection, String, String)> = vec![
+            (PipelineDataSection::Pipeline, String::from("name"), self.name.clone()),
+            (PipelineDataSection::Pipeline, String::from("run_id"), self.run_id.to_string()),
+        ];
+        if let Some(description) = &self.description {
+            pipeline_data.push((PipelineDataSection::Pipeline, String::from("description"), description.clone()));
+        }
         self.listeners.iter().for_each(|l| {
             let l = l.lock().unwrap();
             let handle = l.get_handle().to_string();
-            pipeline_data.insert(format!("listeners.{}.handle", &handle), handle.clone());
-            pipeline_data.insert(format!("listeners.{}.id", &handle), l.get_id());
+            pipeline_data.push((PipelineDataSection::Handles, format!("listeners.{}.handle", &handle), handle.clone()));
+            pipeline_data.push((PipelineDataSection::Listeners, format!("listeners.{}.id", &handle), l.get_id()));
+            pipeline_data.push((PipelineDataSection::Modules, format!("listeners.{}.module", &handle), l.module.get_id()));
+            if let Some(version) = &l.module.get_info().version {
+                pipeline_data.push((PipelineDataSection::Modules, format!("listeners.{}.module_version", &handle), version.to_string()));
+            }
         });
         self.steps.iter().for_each(|step| {
             let step = step.lock().unwrap();
             let handle = step.get_handle().to_string();
-            pipeline_data.insert(format!("steps.{}.handle", &handle), handle.clone());
-            pipeline_data.insert(format!("steps.{}.id", &handle), step.get_id());
+            pipeline_data.push((PipelineDataSection::Handles, format!("steps.{}.handle", &handle), handle.clone()));
+            pipeline_data.push((PipelineDataSection::Steps, format!("steps.{}.id", &handle), step.get_id()));
+            pipeline_data.push((PipelineDataSection::Modules, format!("steps.{}.module", &handle), step.module.get_id()));
+            if let Some(version) = step.module.get_version() {
+                pipeline_data.push((PipelineDataSection::Modules, format!("steps.{}.module_version", &handle), version));
+            }
         });
 
+        let mut failed_listener_handles: Vec<ModuleHandle> = Vec::new();
         for listener_mtx in self.listeners.iter_mut() {
             let mut listener = listener_mtx.lock().unwrap();
             let module_handle = listener.component.handle;
+            if let Some(policy) = &self.policy {
+                policy.check_position(&listener.module.get_id(), ModulePosition::Listener)?;
+            }
             listener.component.args.iter()
                 .for_each(|(k,v) | listener.module.set_param(module_handle, k, v));
-            pipeline_data.iter().for_each(|(k, v)| {
-                listener.module.set_param(module_handle, format!("pipeline.{}", k), v.clone())
+            pipeline_data.iter()
+                .filter(|(section, _, _)| listener.exposed_data.contains(section))
+                .for_each(|(_, k, v)| listener.module.set_param(module_handle, format!("pipeline.{}", k), v.clone()));
+            let result = listener.configure(ModuleListenerConfigureArgs{
+                module_handle: module_handle.to_ffi(),
             });
-            listener.configure(ModuleListenerConfigureArgs{
-                module_handle: std_types::Uint::try_from(module_handle).unwrap(),
-            })?;
+            if let Err(msg) = result {
+                if listener.required {
+                    return Err(msg)
+                }
+                warn!("Optional event listener '{}' is disabled because it failed to configure: {}", listener.get_id(), msg);
+                failed_listener_handles.push(module_handle);
+            }
         }
+        self.listeners.retain(|l| !failed_listener_handles.contains(&l.lock().unwrap().get_handle()));
         Ok(())
     }
 
     /// Start senders and receivers
-    pub fn start_senders_receivers(&self) -> Result<(), String> {
-        let mut senders = SENDERS.lock().unwrap();
+    pub fn start_senders_receivers(&mut self) -> Result<(), String> {
+        let mut handles: HashSet<ModuleHandle> = self.steps.iter()
+            .map(|s| s.lock().unwrap().get_handle())
+            .chain(self.listeners.iter().map(|l| l.lock().unwrap().get_handle()))
+            .collect();
+        let self_test_handle = create_self_test_handle(&handles)?;
+        if SELF_TEST_HANDLE.set(self_test_handle).is_err() {
+            return Err(String::from("Failed to register the self-test handle in static context"))
+        }
+        handles.insert(self_test_handle);
+        if MODULE_HANDLES.set(handles).is_err() {
+            return Err(String::from("Failed to register the module handles in static context"))
+        }
+        if self.provenance {
+            let step_ids = self.steps.iter()
+                .map(|s| {
+                    let s = s.lock().unwrap();
+                    (s.get_handle(), s.get_id())
+                })
+                .collect();
+            if PROVENANCE_STEP_IDS.set(step_ids).is_err() {
+                return Err(String::from("Failed to register the step IDs for provenance tracking"))
+            }
+        }
+
+        let source_handle = self.steps[0].lock().unwrap().get_handle();
+        if SOURCE_DRAIN.set(SourceDrain::new(source_handle, self.shutdown_drain.as_ref())).is_err() {
+            return Err(String::from("Failed to register the shutdown drain in static context"))
+        }
+
+        if let Some(compare) = self.shadow_compare {
+            if SHADOW.set(Shadow::new(compare)).is_err() {
+                return Err(String::from("Failed to register the shadow chain in static context"))
+            }
+        }
+        if let Some(percent) = self.sampling_percent {
+            if SAMPLING.set(Sampling::new(percent)).is_err() {
+                return Err(String::from("Failed to register the sampling in static context"))
+            }
+        }
+
+        IS_DEADLINE_TRACKING_ENABLED.store(self.deadlines, Ordering::SeqCst);
+        if self.latency_tracking {
+            let source_handle = self.steps.first().unwrap().lock().unwrap().get_handle();
+            if LATENCY_SOURCE_HANDLE.set(source_handle).is_err() {
+                return Err(String::from("Failed to register the source step for latency tracking"))
+            }
+        }
+
+        if CHANNELS.set(self.channels.clone()).is_err() {
+            return Err(String::from("Failed to register the record channels in static context"))
+        }
 
         let (m_tx, m_rx) = channel::<SystemMessage>();
         if let Err(_) = SYSTEM_MESSAGES.set(m_tx) {
             return Err(String::from("Failed to initialize a system message channel"))
         };
 
-        start_system_command_thread(m_rx);
+        self.system_thread = Some(start_system_command_thread(format!("{}-system", self.name), m_rx)?);
 
         let listeners: Vec<Listener> = self.listeners
             .iter()
             .map(|l| l.lock().unwrap().clone())
             .collect();
+        let steps: Vec<PipelineStep> = self.steps.iter().map(|s| s.lock().unwrap().clone()).collect();
+        if DROP_LISTENERS.set(get_drop_listeners(&steps, &listeners)).is_err() {
+            return Err(String::from("Failed to register the listeners of dropped records in static context"))
+        }
+
+        let mut step_stats = STEP_STATS.lock().unwrap();
+        // Source has no input queue
+        let source_handle = self.steps.first().unwrap().lock().unwrap().get_handle();
+        step_stats.insert(source_handle, Arc::new(StepStatistics::new(QueueDepth::default(), Arc::default(), Arc::default())));
+        // Record channels. Each step except the source has a single input queue which is shared by upstream steps
+        let mut edge_senders: HashMap<usize, EdgeSender> = HashMap::new();
+        for i_receiver in 1..self.steps.len() {
+            let step_receiver_arc = self.steps[i_receiver].clone();
+            // Steps which send dead letters to the receiver are its inputs too
+            let step_sender_arcs: Vec<Arc<Mutex<PipelineStep>>> = self.topology.get_inputs(i_receiver)
+                .into_iter()
+                .chain(self.topology.get_dead_letter_inputs(i_receiver))
+                .map(|i| self.steps[i].clone())
+                .collect();
+            let receiver_handle = step_receiver_arc.lock().unwrap().get_handle();
 
-        for i in 0..self.steps.len() - 1 {
-            let i_sender = i;
-            let i_receiver = i_sender + 1;
-            let step_sender_arc = self.steps
-                .get(i_sender).unwrap().clone();
-            let step_receiver_arc = self.steps
-                .get(i_receiver).unwrap().clone();
+            let (tx, rx) = edge(step_receiver_arc.lock().unwrap().queue_capacity);
+            edge_senders.insert(i_receiver, tx);
 
-            let i_sender_ffi = u32::try_from(i_sender).unwrap();
+            let stats = Arc::new(StepStatistics::new(rx.get_queue_depth_counter(),
+                rx.get_queue_overflow_counter(), rx.get_blocked_time_counter()));
+            step_stats.insert(receiver_handle, stats.clone());
 
+            let thread_name = format!("{}-reader-{}", self.name, receiver_handle);
+            // Samples are copies, so their latency is not the latency of pipeline
+            let measure_latency = self.latency_tracking && self.topology.is_destination(i_receiver)
+                && !step_receiver_arc.lock().unwrap().is_sampling;
+            start_reader_thread(thread_name, step_sender_arcs, step_receiver_arc, rx, listeners.clone(), stats, measure_latency)?;
+        }
+        for i_sender in 0..self.steps.len() {
+            if self.topology.is_destination(i_sender) {
+                continue
+            }
+            let (sample_outputs, mirror_outputs): (Vec<usize>, Vec<usize>) = self.topology.get_mirror_outputs(i_sender)
+                .into_iter()
+                .partition(|i| self.steps[*i].lock().unwrap().is_sampling);
+            let step_sender = self.steps[i_sender].lock().unwrap();
             // Store a pointer to Free Record function in static context
-            FREE_BUF.lock().unwrap().insert(i_sender_ffi, *step_sender_arc.lock().unwrap().module.free_record_ptr.clone());
-            // Record channels
-            let (tx, rx) = channel::<Record>();
-            senders.insert(i_sender_ffi, tx);
+            if let StepModule::Library(m) = &step_sender.module {
+                self.channels.set_free_record_fn(step_sender.get_handle(), *m.free_record_ptr.clone());
+            }
+            let outputs = Arc::new(StepOutputs {
+                default: self.topology.get_default_output(i_sender).map(|i| edge_senders[&i].clone()),
+                by_class: self.topology.get_class_outputs(i_sender)
+                    .into_iter()
+                    .map(|(class, i)| (class, edge_senders[&i].clone()))
+                    .collect(),
+                mirrors: mirror_outputs.into_iter()
+                    .map(|i| edge_senders[&i].clone())
+                    .collect(),
+                sample: sample_outputs.first().map(|i| edge_senders[i].clone()),
+            });
+            step_sender.context.set_outputs(outputs.clone())?;
+            self.channels.set_outputs(step_sender.get_handle(), outputs);
+        }
 
-            start_reader_thread(step_sender_arc, step_receiver_arc, rx, listeners.clone());
+        let mut retry_routes: HashMap<ModuleHandle, RetryRoute> = HashMap::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let step = step.lock().unwrap();
+            if let (Some(retry), Some(target)) = (&step.retry, step.retry_target) {
+                retry_routes.insert(step.get_handle(), RetryRoute {
+                    // Handles are assigned by step indexes, and the target might be the step itself, which is locked here
+                    target_handle: ModuleHandle::try_from(target)?,
+                    target: edge_senders[&target].clone(),
+                    default_delay: Duration::from_millis(retry.delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS)),
+                    max_attempts: retry.max_attempts.unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS),
+                    dead_letter: match self.topology.get_dead_letter_output(i) {
+                        Some(t) => Some((ModuleHandle::try_from(t)?, edge_senders[&t].clone())),
+                        None => None,
+                    },
+                    error_rules: retry.errors.clone().unwrap_or_default(),
+                });
+            }
+        }
+        if !retry_routes.is_empty() {
+            let retry_queue = RetryQueue::start(format!("{}-retry", self.name), retry_routes)?;
+            if RETRY_QUEUE.set(retry_queue).is_err() {
+                return Err(String::from("Failed to register the retry queue in static context"))
+            }
         }
 
         Ok(())
     }
 
     /// Starts the data processing routines inside each step
-    pub fn start_steps(&self) -> Result<(), String> {
+    pub fn start_steps(&self) -> Result<(), TorustiqError> {
         info!("Starting steps...");
+        if let Some(ramp_up) = &self.ramp_up {
+            let source_handle = self.steps.first().unwrap().lock().unwrap().get_handle();
+            let ramp_up = RampUp::new(source_handle, ramp_up.clone());
+            if RAMP_UP.set(ramp_up).is_err() {
+                return Err(TorustiqError::Pipeline(String::from("Failed to initialize the source ramp-up")))
+            }
+        }
         for step_mtx in &self.listeners {
             let step = step_mtx.lock().unwrap();
             let module_handle = step.component.handle;
-            match step.module.start(module_handle.into()) {
+            match step.module.start(module_handle) {
                 Ok(_) => debug!("Started event listener '{}'", step.component.id),
                 Err(msg) => {
-                    return Err(format!("Failed to start event listener '{}': {}", step.component.id, msg));
+                    return Err(TorustiqError::StepStart {
+                        step: step.component.id.clone(),
+                        module_id: step.module.get_id(),
+                        message: msg,
+                    });
                 }
             }
         }
-        for step_mtx in &self.steps {
-            let step = step_mtx.lock().unwrap();
-            let module_handle = step.component.handle;
-            match step.module.start(module_handle.into()) {
-                Ok(_) => debug!("Started pipeline step '{}'", step.component.id),
-                Err(msg) => {
-                    return Err(format!("Failed to start pipeline step '{}': {}", step.component.id, msg));
-                }
+        if let Some(lease) = &self.lease {
+            let source_handle = self.steps.first().unwrap().lock().unwrap().get_handle();
+            if SOURCE_LEASE.set(Arc::new(SourceLease::new(lease, source_handle))).is_err() {
+                return Err(TorustiqError::Pipeline(String::from("Failed to initialize the source lease")))
             }
         }
-        Ok(())
+        for_each_step_concurrently(&self.steps, |i, step_mtx| {
+            // The source is started by the lease thread once the lease is acquired
+            if i == 0 && SOURCE_LEASE.get().is_some() {
+                return Ok(())
+            }
+            start_step(&step_mtx.lock().unwrap())
+        })?;
+        start_lease_thread().map_err(TorustiqError::Pipeline)
     }
 
     /// Returns true if the pipeline is running
@@ -273,7 +971,7 @@ impl Pipeline {
         steps_terminated < steps_total
     }
 
-    pub fn get_step_by_handle_mut(&self, handle: usize) -> Option<Arc<Mutex<PipelineStep>>> {
+    pub fn get_step_by_handle_mut(&self, handle: ModuleHandle) -> Option<Arc<Mutex<PipelineStep>>> {
         for h in &self.steps {
             if h.lock().unwrap().component.handle == handle {
                 return Some(h.clone())
@@ -282,7 +980,78 @@ impl Pipeline {
         None
     }
 
+    /// Handles the termination of step.
+    /// If step is terminated while upstream steps are still running, the upstream steps are stopped,
+    /// because they have no destination for their records anymore
+    pub fn handle_step_termination(&mut self, handle: ModuleHandle) {
+        let position = match self.steps.iter().position(|s| s.lock().unwrap().get_handle() == handle) {
+            Some(p) => p,
+            None => return,
+        };
+        // The side steps must not affect the primary chain
+        if self.steps[position].lock().unwrap().is_sampling {
+            if self.topology.get_upstream(position).iter().any(|i| !self.steps[*i].lock().unwrap().component.is_terminated()) {
+                warn!("Sampling destination '{}' is terminated before the sampled step", self.steps[position].lock().unwrap().get_id());
+            }
+            return
+        }
+        if self.steps[position].lock().unwrap().is_shadow {
+            if self.topology.get_upstream(position).iter().any(|i| !self.steps[*i].lock().unwrap().component.is_terminated()) {
+                warn!("Shadow step '{}' is terminated before the upstream steps", self.steps[position].lock().unwrap().get_id());
+            }
+            return
+        }
+        // The first running step upstream. Once it's shut down, the termination is propagated downstream by reader threads
+        let upstream_step = self.topology.get_upstream(position).into_iter()
+            .map(|i| &self.steps[i])
+            .find(|s| !s.lock().unwrap().component.is_terminated());
+        let upstream_step = match upstream_step {
+            Some(s) if self.state == PipelineState::Running => s.clone(),
+            _ => return,
+        };
+        let step_id = self.steps[position].lock().unwrap().get_id();
+        error!("Step '{}' terminated while upstream steps are still running. Stopping the upstream steps...", step_id);
+        self.state = PipelineState::DownstreamTerminated(step_id);
+        upstream_step.lock().unwrap().shutdown();
+    }
+
+    /// Toggles the feature flag in all running steps.
+    /// Built-in modules don't accept parameters at runtime, so they get the new value on the next start only
+    pub fn set_feature(&mut self, name: &str, is_enabled: bool) -> Result<(), String> {
+        match self.features.get_mut(name) {
+            Some(f) => *f = is_enabled,
+            None => return Err(format!("Unknown feature: '{}'. Declared features: {}", name,
+                self.features.keys().cloned().collect::<Vec<String>>().join(", "))),
+        };
+        let key = format!("{}{}", FEATURE_ARG_PREFIX, name);
+        for step in &self.steps {
+            let mut step = step.lock().unwrap();
+            step.component.args.insert(key.clone(), is_enabled.to_string());
+            if let (StepModule::Library(m), false) = (&step.module, step.component.is_terminated()) {
+                m.set_param(step.get_handle(), key.clone(), is_enabled.to_string());
+            }
+        }
+        info!("Feature '{}' is {}", name, match is_enabled { true => "enabled", false => "disabled" });
+        Ok(())
+    }
+
     pub fn trigger_termination(&self) {
+        // Paused steps would never finish, e.g. a paused source doesn't return from the record callback
+        set_steps_shutting_down();
+        if let Some(drain) = SOURCE_DRAIN.get() {
+            drain.request();
+        }
+        // Held records must reach the destinations, otherwise the pipeline never terminates
+        if let Some(step_mode) = STEP_MODE.get() {
+            step_mode.release();
+        }
+        // A source of standby instance is not started, so it's terminated on its behalf
+        if let Some(lease) = SOURCE_LEASE.get() {
+            if !lease.stop() {
+                on_step_terminate_cb(lease.get_source_handle().to_ffi());
+                return
+            }
+        }
         let first_step = self.steps
             .first().unwrap()
             .lock().unwrap();
@@ -295,41 +1064,221 @@ impl TryFrom<(&PipelineDefinition, &LoadedLibraries)> for Pipeline {
         let (definition, loaded_libs) = value;
         // Validate references to modules
         let mut pipeline = Pipeline::new();
+        pipeline.name = definition.get_name();
         pipeline.description = definition.description.clone();
+        pipeline.run_id = Uuid::new_v4();
+        pipeline.ramp_up = definition.ramp_up.clone();
+        pipeline.provenance = definition.provenance.unwrap_or(false);
+        pipeline.deadlines = definition.deadlines.unwrap_or(false);
+        pipeline.latency_tracking = definition.latency_tracking.unwrap_or(false);
+        pipeline.notifications = definition.notifications.clone().unwrap_or_default();
+        pipeline.stall_timeout = Duration::from_millis(definition.stall_timeout_ms.unwrap_or(DEFAULT_STALL_TIMEOUT_MS));
+        pipeline.stats_file = definition.stats_file.clone();
+        pipeline.shutdown_drain = definition.shutdown_drain.clone();
+        pipeline.lease = definition.lease.clone();
+        pipeline.heartbeat = definition.heartbeat.clone();
+        pipeline.features = definition.features.clone().unwrap_or_default().into_iter().collect();
+        add_masked_key_patterns(definition.masked_keys.as_ref().unwrap_or(&Vec::new()));
+        pipeline.max_runtime = definition.max_runtime_ms.map(Duration::from_millis);
+        pipeline.shutdown_grace_period = Duration::from_millis(definition.shutdown_grace_ms.unwrap_or(DEFAULT_SHUTDOWN_GRACE_MS));
 
-        for step_def in &definition.steps {
-            if loaded_libs.pipeline.get(&step_def.handler).is_none() {
-                return Err(format!("Module not found: {}", &step_def.handler));
-            }
-        }
+        // Shadow steps follow the primary steps and end with a sink which receives the shadow output.
+        // The sampling destination is the last step
+        let (primary_defs, conversions) = insert_conversion_steps(&definition.steps)?;
+        pipeline.conversions = conversions;
+        let primary_len = primary_defs.len();
+        let shadow_sink_def: Option<ModuleDefinition> = match &definition.shadow {
+            Some(_) => Some(create_shadow_sink_definition()?),
+            None => None,
+        };
+        let sampling_def: Option<ModuleDefinition> = match &definition.sampling {
+            Some(s) => {
+                let step_names: Vec<&str> = primary_defs.iter().chain(definition.get_shadow_steps()).map(|d| d.name.as_str()).collect();
+                s.validate(&step_names)?;
+                Some(create_sampling_destination_definition(s)?)
+            },
+            None => None,
+        };
+        let step_defs: Vec<&ModuleDefinition> = primary_defs.iter()
+            .chain(definition.get_shadow_steps())
+            .chain(shadow_sink_def.iter())
+            .chain(sampling_def.iter())
+            .collect();
+        let shadow_end = primary_len + definition.get_shadow_steps().len() + shadow_sink_def.iter().count();
+        let sampling_index = sampling_def.as_ref().map(|_| shadow_end);
 
         let mut step_index: usize = 0;
-        pipeline.steps = definition
-            .steps
-            .iter()
-            // .enumerate()
-            .map(|step_def| {
-                let s = PipelineStep::from_module(
-                    loaded_libs.pipeline.get(&step_def.handler).unwrap().clone(),
-                    step_index, step_def.args.clone());
-                step_index += 1;
-                Arc::new(Mutex::new(s))
-            })
-            .collect();
-        pipeline.listeners = definition
-            .listeners
-            .as_ref()
-            .unwrap_or(&Vec::new())
-            .iter()
-            // .enumerate()
-            .map(|step_def| {
-                let s = Listener::from_module(
-                    loaded_libs.listeners.get(&step_def.handler).unwrap().clone(),
-                    step_index, step_def.args.clone());
-                step_index += 1;
-                Arc::new(Mutex::new(s))
-            })
-            .collect();
+        let mut dead_letter_outputs: Vec<Option<usize>> = vec![None; step_defs.len()];
+        for step_def in &step_defs {
+            let module = if is_native_module(&step_def.handler) {
+                StepModule::Builtin(create_native_module(&step_def.handler)?)
+            } else {
+                match loaded_libs.pipeline.get(&step_def.handler) {
+                    Some(m) => StepModule::Library(m.clone()),
+                    None => return Err(format!("Module not found: {}", &step_def.handler)),
+                }
+            };
+            check_arg_types(&format!("Step '{}'", step_def.name), step_def.args.as_ref(), &module.get_arg_types())?;
+            let mut args = step_def.get_args()?;
+            register_secrets(&args);
+            for (name, is_enabled) in &pipeline.features {
+                args.insert(format!("{}{}", FEATURE_ARG_PREFIX, name), is_enabled.to_string());
+            }
+            let metrics_labels = match &step_def.metrics_labels {
+                Some(labels) => resolve_metrics_labels(labels, &args).map_err(|e| format!("Step '{}': {}", step_def.name, e))?,
+                None => Vec::new(),
+            };
+            let mut s = PipelineStep::from_module(module, ModuleHandle::try_from(step_index)?, Some(args));
+            s.metrics_labels = metrics_labels;
+            s.check_sequence = step_def.check_sequence.unwrap_or(false);
+            if let Some(events) = &step_def.events {
+                s.listener_events = events.clone();
+            }
+            s.input_schema = step_def.input_schema.clone();
+            s.output_schema = step_def.output_schema.clone();
+            s.input_format = step_def.input_format.clone();
+            s.output_format = step_def.output_format.clone();
+            s.queue_capacity = step_def.queue_capacity
+                .or(definition.profile.and_then(|p| p.get_queue_capacity()))
+                .or(RESOURCE_LIMITS.get_default_queue_capacity(step_defs.len()));
+            if let Some(ms) = step_def.poll_interval_ms.or(definition.profile.map(|p| p.get_poll_interval_ms())) {
+                s.poll_interval = Duration::from_millis(ms);
+            }
+            s.max_runtime = step_def.max_runtime_ms.map(Duration::from_millis);
+            s.error_log_sampling = step_def.error_log_sampling.clone();
+            s.prime = step_def.prime.clone();
+            s.load_shedding = step_def.load_shedding.clone().or(definition.load_shedding.clone());
+            s.numa_node = step_def.numa_node;
+            s.commit_interval = step_def.commit_interval_ms.map(Duration::from_millis);
+            s.commit_max_records = step_def.commit_max_records;
+            s.is_shadow = (primary_len..shadow_end).contains(&step_index);
+            s.is_sampling = sampling_index == Some(step_index);
+            // The sampling destination is declared in pipeline if set explicitly, otherwise it's inserted by host
+            let is_default_sampling_destination = s.is_sampling
+                && definition.sampling.as_ref().map(|d| d.destination.is_none()).unwrap_or(false);
+            let is_conversion = pipeline.conversions.iter().any(|c| c.step_name == step_def.name);
+            s.is_host_inserted = (shadow_sink_def.is_some() && step_index + 1 == shadow_end) || is_default_sampling_destination || is_conversion;
+            // Source has no input queue
+            if let (Some(history), true) = (step_def.record_history.as_ref().or(definition.record_history.as_ref()), step_index > 0) {
+                s.record_history = Some(Arc::new(RecordHistory::new(s.get_id(), history)));
+            }
+            if let (Some(shadow), true) = (&definition.shadow, step_index == primary_len) {
+                // Copies are dropped once the queue is full, so the queue must be bounded
+                s.queue_capacity = shadow.queue_capacity.or(s.queue_capacity).or(Some(DEFAULT_SHADOW_QUEUE_CAPACITY));
+            }
+            if let (Some(sampling), true) = (&definition.sampling, s.is_sampling) {
+                // Samples are dropped once the queue is full, so the queue must be bounded
+                s.queue_capacity = sampling.queue_capacity.or(s.queue_capacity).or(Some(DEFAULT_SAMPLING_QUEUE_CAPACITY));
+            }
+            if let Some(retry) = &step_def.retry {
+                let target_name = retry.target.as_ref().unwrap_or(&step_def.name);
+                match step_defs.iter().position(|d| &d.name == target_name) {
+                    // Source has no input queue
+                    Some(0) => return Err(format!("Step '{}' cannot retry records in source step '{}'", step_def.name, target_name)),
+                    Some(t) => s.retry_target = Some(t),
+                    None => return Err(format!("Step '{}' retries records in unknown step '{}'", step_def.name, target_name)),
+                }
+                if let Some(dead_letter) = &retry.dead_letter {
+                    match step_defs.iter().position(|d| &d.name == dead_letter) {
+                        Some(0) => return Err(format!("Step '{}' cannot send dead letters to source step '{}'", step_def.name, dead_letter)),
+                        Some(t) if t == step_index => return Err(format!("Step '{}' cannot send dead letters to itself", step_def.name)),
+                        Some(t) => dead_letter_outputs[step_index] = Some(t),
+                        None => return Err(format!("Step '{}' sends dead letters to unknown step '{}'", step_def.name, dead_letter)),
+                    }
+                }
+                for rule in retry.errors.as_ref().unwrap_or(&Vec::new()) {
+                    rule.validate().map_err(|e| format!("Invalid retry settings of step '{}': {}", step_def.name, e))?;
+                }
+                s.retry = Some(retry.clone());
+            }
+            for dependency in step_def.depends_on.as_ref().unwrap_or(&Vec::new()) {
+                match step_defs.iter().position(|d| &d.name == dependency) {
+                    Some(i) => s.depends_on.push(ModuleHandle::try_from(i)?),
+                    None => return Err(format!("Step '{}' depends on unknown step '{}'", step_def.name, dependency)),
+                }
+            }
+            step_index += 1;
+            pipeline.steps.push(Arc::new(Mutex::new(s)));
+        }
+        let mut class_outputs: Vec<HashMap<String, usize>> = Vec::new();
+        for (i, step_def) in step_defs.iter().enumerate() {
+            let mut outputs: HashMap<String, usize> = HashMap::new();
+            for (class, target) in step_def.outputs.as_ref().unwrap_or(&HashMap::new()) {
+                match step_defs.iter().position(|d| &d.name == target) {
+                    Some(t) if sampling_index == Some(t) => return Err(format!(
+                        "Step '{}' routes class '{}' to sampling destination '{}'", step_def.name, class, target)),
+                    Some(t) if i < primary_len && t >= primary_len => return Err(format!(
+                        "Step '{}' routes class '{}' to step '{}' of shadow chain", step_def.name, class, target)),
+                    Some(t) if t > i => outputs.insert(class.clone(), t),
+                    Some(_) => return Err(format!("Step '{}' routes class '{}' to step '{}' which is not located after it",
+                        step_def.name, class, target)),
+                    None => return Err(format!("Step '{}' routes class '{}' to unknown step '{}'", step_def.name, class, target)),
+                };
+            }
+            class_outputs.push(outputs);
+        }
+        let mut mirror_outputs: Vec<Vec<usize>> = vec![Vec::new(); step_defs.len()];
+        if let Some(shadow) = &definition.shadow {
+            mirror_outputs[0].push(primary_len);
+            pipeline.shadow_compare = Some(shadow.compare.unwrap_or(false));
+        }
+        let mut sampled_index: Option<usize> = None;
+        if let (Some(sampling), Some(sampling_index)) = (&definition.sampling, sampling_index) {
+            let after = match step_defs.iter().position(|d| d.name == sampling.after) {
+                Some(i) => i,
+                None => return Err(format!("Sampling refers to unknown step '{}'", sampling.after)),
+            };
+            mirror_outputs[after].push(sampling_index);
+            sampled_index = Some(after);
+            pipeline.sampling_percent = Some(sampling.percent);
+        }
+        pipeline.topology = Topology::new(class_outputs, mirror_outputs, dead_letter_outputs);
+        // Only records which are sent further are sampled, so the sampled step must have outputs
+        if let Some(i) = sampled_index {
+            if pipeline.topology.get_default_output(i).is_none() && pipeline.topology.get_class_outputs(i).is_empty() {
+                return Err(format!("Sampled step '{}' is a destination and doesn't send records further", step_defs[i].name))
+            }
+        }
+        // The dead letter step stops once the steps sending dead letters to it are terminated, so it must not feed them
+        for i in 0..pipeline.steps.len() {
+            if let Some(t) = pipeline.topology.get_dead_letter_output(i) {
+                if pipeline.topology.get_upstream(i).contains(&t) {
+                    return Err(format!("Step '{}' sends dead letters to upstream step '{}'", step_defs[i].name, step_defs[t].name))
+                }
+            }
+        }
+        // Records which reach the primary destinations are compared with the shadow output
+        if pipeline.shadow_compare == Some(true) {
+            for i in (0..primary_len).filter(|i| pipeline.topology.is_destination(*i)) {
+                pipeline.steps[i].lock().unwrap().is_shadow_compared = true;
+            }
+        }
+        if definition.heartbeat.as_ref().and_then(|h| h.deliver) == Some(false) {
+            for i in (0..pipeline.steps.len()).filter(|i| pipeline.topology.is_destination(*i)) {
+                pipeline.steps[i].lock().unwrap().drops_heartbeats = true;
+            }
+        }
+        for listener_def in definition.listeners.as_ref().unwrap_or(&Vec::new()) {
+            // Built-in modules and pipeline libraries cannot be listeners
+            let module = match loaded_libs.listeners.get(&listener_def.handler) {
+                Some(m) => m.clone(),
+                None => return Err(format!("Listener '{}': handler '{}' is not a listener library", listener_def.name, listener_def.handler)),
+            };
+            check_arg_types(&format!("Listener '{}'", listener_def.name), listener_def.args.as_ref(), &module.get_info().arg_types)?;
+            let args = listener_def.get_args()?;
+            register_secrets(&args);
+            let mut l = Listener::from_module(module, ModuleHandle::try_from(step_index)?, Some(args));
+            l.required = listener_def.required.unwrap_or(true);
+            if let Some(events) = &listener_def.events {
+                l.events = events.clone();
+            }
+            l.min_severity = listener_def.min_severity.unwrap_or_default();
+            if let Some(expose) = &listener_def.expose {
+                l.exposed_data = expose.clone();
+            }
+            step_index += 1;
+            pipeline.listeners.push(Arc::new(Mutex::new(l)));
+        }
         pipeline.validate()?;
 
         Ok(pipeline)
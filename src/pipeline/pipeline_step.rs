@@ -1,69 +1,278 @@
 use std::{
-    collections::HashMap, sync::Arc,
+    collections::HashMap, sync::Arc, time::Duration,
 };
 
-use torustiq_common::ffi::types::{
-    module as module_types,
-    std_types,
+use torustiq_common::ffi::{
+    types::module as module_types,
+    utils::strings::cchar_to_string,
 };
 
 use crate::{
-    modules::pipeline::PipelineModule,
-    pipeline::{PipelineComponent, PipelineComponentState},
+    config::{ErrorLogSamplingDefinition, ListenerEvent, LoadSheddingDefinition, RetryDefinition},
+    modules::{arg_types::{to_arg_types, ArgType}, builtin::{fixture::read_fixture_file, BuiltinModule}, pipeline::PipelineModule},
+    pipeline::{handle::ModuleHandle, record_history::RecordHistory, step_context::StepContext, PipelineComponent, PipelineComponentState},
+    policy::ModulePosition,
+    xthread::CANCELLED_STEPS,
 };
 
+/// A module which implements the pipeline step
+#[derive(Clone)]
+pub enum StepModule {
+    /// A module loaded from dynamic library
+    Library(Arc<PipelineModule>),
+    /// A module implemented in host application
+    Builtin(Arc<dyn BuiltinModule>),
+}
+
+impl StepModule {
+    pub fn get_id(&self) -> String {
+        match self {
+            StepModule::Library(m) => m.get_id(),
+            StepModule::Builtin(m) => m.get_id(),
+        }
+    }
+
+    /// Returns the version of module, if known. Built-in modules have the version of application
+    pub fn get_version(&self) -> Option<String> {
+        match self {
+            StepModule::Library(m) => m.get_info().version.as_ref().map(|v| v.to_string()),
+            StepModule::Builtin(_) => Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+
+    /// Returns the positions in pipeline which the module supports. None means any position
+    pub fn get_positions(&self) -> Option<Vec<ModulePosition>> {
+        match self {
+            StepModule::Library(m) => m.get_info().positions.clone(),
+            StepModule::Builtin(m) => m.get_positions(),
+        }
+    }
+
+    /// Returns the types of arguments declared by module
+    pub fn get_arg_types(&self) -> HashMap<String, ArgType> {
+        match self {
+            StepModule::Library(m) => m.get_info().arg_types.clone(),
+            StepModule::Builtin(m) => to_arg_types(m.get_arg_types()),
+        }
+    }
+}
+
+/// A result of record processing in step
+pub struct ProcessRecordResult {
+    /// An error message if processing failed
+    pub error: Option<String>,
+    /// If true, the record is taken over by module and must not be deallocated by host
+    pub is_consumed: bool,
+}
+
+/// Default interval of upstream termination check if no profile is set
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 100;
+
 /// A single step in pipeline
 #[derive(Clone)]
 pub struct PipelineStep {
     /// Base pipeline component attributes
     pub component: PipelineComponent,
-    /// A reference to module library
-    pub module: Arc<PipelineModule>,
+    /// State of step which is read on the record path. Shared by all copies of step
+    pub context: Arc<StepContext>,
+    /// A reference to module
+    pub module: StepModule,
+    /// If true, the order of incoming records is verified
+    pub check_sequence: bool,
+    /// Listener events which are fired for records arriving to this step
+    pub listener_events: Vec<ListenerEvent>,
+    /// An ID of schema of accepted records, if declared
+    pub input_schema: Option<String>,
+    /// An ID of schema of produced records, if declared
+    pub output_schema: Option<String>,
+    /// A wire format of accepted payload, if declared
+    pub input_format: Option<String>,
+    /// A wire format of produced payload, if declared
+    pub output_format: Option<String>,
+    /// Maximum number of records in the input queue. None means unbounded queue
+    pub queue_capacity: Option<usize>,
+    /// How often an idle step checks if upstream is terminated
+    pub poll_interval: Duration,
+    /// The step is shut down once it runs longer than this
+    pub max_runtime: Option<Duration>,
+    /// If set, only a sample of processing errors is logged
+    pub error_log_sampling: Option<ErrorLogSamplingDefinition>,
+    /// Steps which must be configured and started before this step
+    pub depends_on: Vec<ModuleHandle>,
+    /// A path to fixture file with sample records which warm up the step before real traffic
+    pub prime: Option<String>,
+    /// If set, records are dropped under sustained overload
+    pub load_shedding: Option<LoadSheddingDefinition>,
+    /// If set, threads of step are bound to CPUs of this NUMA node
+    pub numa_node: Option<usize>,
+    /// Destination steps only. Maximum time between the first uncommitted record and commit
+    pub commit_interval: Option<Duration>,
+    /// Destination steps only. Maximum number of records in commit
+    pub commit_max_records: Option<u64>,
+    /// If set, the step can park records for delayed retry
+    pub retry: Option<RetryDefinition>,
+    /// An index of step which receives the records parked by this step. Set if retries are configured
+    pub retry_target: Option<usize>,
+    /// If true, the step belongs to shadow chain
+    pub is_shadow: bool,
+    /// If true, the records arriving to this step are compared with the output of shadow chain
+    pub is_shadow_compared: bool,
+    /// If true, the step receives samples of records. See `pipeline::sampling`
+    pub is_sampling: bool,
+    /// If true, the step is inserted by host rather than declared in pipeline, e.g. the sink of shadow chain.
+    /// Such steps are not checked against the module policy
+    pub is_host_inserted: bool,
+    /// If true, heartbeat records are dropped before this step. See `pipeline::heartbeat`
+    pub drops_heartbeats: bool,
+    /// If set, the last records arriving to this step are kept for debugging
+    pub record_history: Option<Arc<RecordHistory>>,
+    /// Labels which are added to metrics of step, sorted by name
+    pub metrics_labels: Vec<(String, String)>,
 }
 
 impl PipelineStep {
-    /// Initializes a step from module (=dynamic library).
+    /// Initializes a step from module (=dynamic library or built-in module).
     /// Index is a step index in pipeline. Needed to format a unique step ID
-    pub fn from_module(module: Arc<PipelineModule>, handle: usize, args: Option<HashMap<String, String>>) -> PipelineStep {
+    pub fn from_module(module: StepModule, handle: ModuleHandle, args: Option<HashMap<String, String>>) -> PipelineStep {
         PipelineStep {
             component: PipelineComponent {
                 args: args.unwrap_or(HashMap::new()),
                 handle,
-                id: format!("step_{}_{}", handle, module.get_info().id),
+                id: format!("step_{}_{}", handle, module.get_id()),
                 state: PipelineComponentState::Created,
             },
+            context: Arc::new(StepContext::new(handle)),
             module,
+            check_sequence: false,
+            listener_events: vec![ListenerEvent::Received, ListenerEvent::Success, ListenerEvent::Error, ListenerEvent::Dropped],
+            input_schema: None,
+            output_schema: None,
+            input_format: None,
+            output_format: None,
+            queue_capacity: None,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            max_runtime: None,
+            error_log_sampling: None,
+            depends_on: Vec::new(),
+            prime: None,
+            load_shedding: None,
+            numa_node: None,
+            commit_interval: None,
+            commit_max_records: None,
+            retry: None,
+            retry_target: None,
+            is_shadow: false,
+            is_shadow_compared: false,
+            is_sampling: false,
+            is_host_inserted: false,
+            drops_heartbeats: false,
+            record_history: None,
+            metrics_labels: Vec::new(),
         }
     }
 
+    /// Marks the step as terminated
+    pub fn set_state_terminated(&mut self) {
+        self.component.set_state_terminated();
+        self.context.set_terminated();
+    }
+
+    /// Returns true if listeners should be notified about the event for records arriving to this step
+    pub fn is_listener_event_enabled(&self, event: ListenerEvent) -> bool {
+        self.listener_events.contains(&event)
+    }
+
+    /// Passes the arguments to module and configures it
     pub fn configure(&mut self, args: module_types::ModulePipelineConfigureArgs) -> Result<(), String> {
-        let _ = self.module.configure(args)?;
+        match &self.module {
+            StepModule::Library(m) => {
+                for (k, v) in &self.component.args {
+                    m.set_param(self.component.handle, k, v);
+                }
+                m.configure(args)?;
+                m.set_context(self.component.handle, self.context.as_ffi_ptr());
+            },
+            StepModule::Builtin(m) => m.configure(args.module_handle, &args.kind, &self.component.args)?,
+        };
         self.component.state = PipelineComponentState::Configured;
         Ok(())
     }
 
-    pub fn shutdown(&self) {
-        self.module.shutdown(self.component.handle);
+    /// Passes the sample records from fixture file to module
+    pub fn prime(&self, fixture_path: &str) -> Result<(), String> {
+        let records: Vec<module_types::Record> = read_fixture_file(fixture_path)?.iter()
+            .map(|r| r.to_record())
+            .collect();
+        let result = match &self.module {
+            StepModule::Library(m) => m.prime(self.component.handle, &records),
+            StepModule::Builtin(m) => m.prime(&records),
+        };
+        records.into_iter().for_each(|mut r| r.free_contents());
+        result
     }
 
-    pub fn get_id(&self) -> String {
-        self.component.id.clone()
+    /// Returns true if commit boundaries are set for step
+    pub fn is_commit_enabled(&self) -> bool {
+        self.commit_interval.is_some() || self.commit_max_records.is_some()
     }
 
-    pub fn get_handle(&self) -> usize {
-        self.component.handle
+    /// Returns true if module accepts the commit boundaries
+    pub fn is_commit_supported(&self) -> bool {
+        match &self.module {
+            StepModule::Library(m) => m.is_commit_supported(),
+            StepModule::Builtin(_) => true,
+        }
     }
 
-    pub fn ffi_free_char(&self, c: std_types::ConstCharPtr) {
-        (self.module.base.free_char_ptr)(c);
+    /// Commits the records passed to step
+    pub fn commit(&self) -> Result<(), String> {
+        match &self.module {
+            StepModule::Library(m) => m.commit(self.component.handle),
+            StepModule::Builtin(m) => m.commit(),
+        }
     }
 
-    pub fn ffi_process_record(&self, record: module_types::Record, handle: module_types::ModuleHandle) -> module_types::ModulePipelineProcessRecordFnResult {
-        (self.module.process_record_ptr)(handle, record)
+    pub fn start(&self) -> Result<(), String> {
+        match &self.module {
+            StepModule::Library(m) => m.start(self.component.handle),
+            StepModule::Builtin(m) => m.start(),
+        }
+    }
 
+    pub fn shutdown(&self) {
+        CANCELLED_STEPS.lock().unwrap().insert(self.component.handle);
+        match &self.module {
+            StepModule::Library(m) => m.shutdown(self.component.handle),
+            StepModule::Builtin(m) => m.shutdown(),
+        }
     }
 
-    pub fn ffi_shutdown(&self, handle: module_types::ModuleHandle) {
-        (self.module.base.shutdown_ptr)(handle);
+    pub fn get_id(&self) -> String {
+        self.component.id.clone()
+    }
+
+    pub fn get_handle(&self) -> ModuleHandle {
+        self.component.handle
     }
-}
\ No newline at end of file
+
+    pub fn process_record(&self, record: module_types::Record) -> ProcessRecordResult {
+        let m = match &self.module {
+            StepModule::Library(m) => m,
+            StepModule::Builtin(m) => return match m.process_record(record) {
+                Ok(is_consumed) => ProcessRecordResult { error: None, is_consumed },
+                Err(e) => ProcessRecordResult { error: Some(e), is_consumed: false },
+            },
+        };
+        match m.process_record(self.component.handle, record) {
+            module_types::ModulePipelineProcessRecordFnResult::Ok(is_consumed) =>
+                ProcessRecordResult { error: None, is_consumed },
+            module_types::ModulePipelineProcessRecordFnResult::ErrWrongModuleHandle(handle, is_consumed) =>
+                ProcessRecordResult { error: Some(format!("Wrong module handle '{}'", handle)), is_consumed },
+            module_types::ModulePipelineProcessRecordFnResult::ErrMisc(cerr, is_consumed) => {
+                let err = cchar_to_string(cerr);
+                m.free_c_char(cerr);
+                ProcessRecordResult { error: Some(err), is_consumed }
+            },
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/// Ramp-up of source.
+/// The source starts at low rate which grows gradually to the target rate. If the downstream queues are too long,
+/// the growth of rate is paused until the queues are drained
+
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{config::RampUpDefinition, pipeline::handle::ModuleHandle, xthread::STEP_STATS};
+
+struct RampUpState {
+    /// Time spent on ramp-up excluding pauses caused by long queues
+    progress: Duration,
+    last_update: Instant,
+    /// Time when the next record is allowed to be sent
+    next_slot: Instant,
+}
+
+pub struct RampUp {
+    /// A handle of source step which is throttled
+    pub source_handle: ModuleHandle,
+    definition: RampUpDefinition,
+    state: Mutex<RampUpState>,
+}
+
+impl RampUp {
+    pub fn new(source_handle: ModuleHandle, definition: RampUpDefinition) -> RampUp {
+        let now = Instant::now();
+        RampUp {
+            source_handle,
+            definition,
+            state: Mutex::new(RampUpState {
+                progress: Duration::ZERO,
+                last_update: now,
+                next_slot: now,
+            }),
+        }
+    }
+
+    /// Blocks the caller until the next record is allowed to be sent according to current rate
+    pub fn wait(&self) {
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            if !self.is_congested() {
+                let elapsed = now - state.last_update;
+                state.progress += elapsed;
+            }
+            state.last_update = now;
+
+            let interval = Duration::from_secs_f64(1.0 / self.get_rate(state.progress));
+            let slot = state.next_slot.max(now);
+            state.next_slot = slot + interval;
+            slot - now
+        };
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Returns the current rate (records per second)
+    fn get_rate(&self, progress: Duration) -> f64 {
+        let duration = Duration::from_millis(self.definition.duration_ms);
+        let ratio = match duration.is_zero() {
+            true => 1.0,
+            false => (progress.as_secs_f64() / duration.as_secs_f64()).min(1.0),
+        };
+        self.definition.initial_rate + (self.definition.target_rate - self.definition.initial_rate) * ratio
+    }
+
+    /// Returns true if downstream queues contain more records than allowed
+    fn is_congested(&self) -> bool {
+        let max_queue_depth = match self.definition.max_queue_depth {
+            Some(d) => d,
+            None => return false,
+        };
+        let queue_depth: usize = STEP_STATS.lock().unwrap()
+            .values()
+            .map(|s| s.queue_depth.get())
+            .sum();
+        queue_depth > max_queue_depth
+    }
+}
@@ -0,0 +1,160 @@
+/// A history of the last records arriving to step, for debugging of failures which are hard to reproduce.
+/// The history is a ring buffer: once it's full, the oldest record is dropped. Payloads are truncated.
+/// Once the step fails to process a record, the history up to the failing record is written to a debug file.
+/// The history is also available in interactive mode, see `repl`
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    config::RecordHistoryDefinition,
+    masking::mask_text,
+    records::{get_metadata, get_payload},
+};
+
+const DEFAULT_SIZE: usize = 100;
+const DEFAULT_MAX_PAYLOAD_BYTES: usize = 4096;
+const DEFAULT_DUMP_INTERVAL_MS: u64 = 60000;
+
+/// A copy of record in history
+struct HistoryEntry {
+    /// A sequence number of record in the input queue of step
+    sequence: u64,
+    received_at: SystemTime,
+    /// A truncated payload
+    payload: Vec<u8>,
+    /// The original size of payload
+    payload_size: usize,
+    metadata: HashMap<String, String>,
+    /// An error message if the step failed to process the record
+    error: Option<String>,
+}
+
+impl HistoryEntry {
+    fn to_json(&self) -> Value {
+        let received_at = self.received_at.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        let mut entry = json!({
+            "sequence": self.sequence,
+            "received_at_ms": received_at,
+            "payload_size": self.payload_size,
+            "is_payload_truncated": self.payload.len() < self.payload_size,
+            "metadata": self.metadata,
+            "error": self.error,
+        });
+        // Binary payloads are encoded, as JSON doesn't support them.
+        // Truncation might split the last character of text, so incomplete characters at the end are ignored
+        let text = match std::str::from_utf8(&self.payload) {
+            Ok(t) => Some(t),
+            Err(e) if e.error_len().is_none() => std::str::from_utf8(&self.payload[..e.valid_up_to()]).ok(),
+            Err(_) => None,
+        };
+        match text {
+            Some(t) => entry["payload"] = Value::String(t.to_string()),
+            None => entry["payload_base64"] = Value::String(BASE64.encode(&self.payload)),
+        }
+        entry
+    }
+}
+
+/// A history of records of a single step. Shared by the reader thread of step and the control commands
+pub struct RecordHistory {
+    step_id: String,
+    size: usize,
+    max_payload_bytes: usize,
+    dump_dir: Option<String>,
+    dump_interval: Duration,
+    entries: Mutex<VecDeque<HistoryEntry>>,
+    last_dump_at: Mutex<Option<Instant>>,
+}
+
+impl RecordHistory {
+    pub fn new(step_id: String, definition: &RecordHistoryDefinition) -> RecordHistory {
+        // A history without records is useless, so at least one record is kept
+        let size = definition.size.unwrap_or(DEFAULT_SIZE).max(1);
+        RecordHistory {
+            step_id,
+            size,
+            max_payload_bytes: definition.max_payload_bytes.unwrap_or(DEFAULT_MAX_PAYLOAD_BYTES),
+            dump_dir: definition.dump_dir.clone(),
+            dump_interval: Duration::from_millis(definition.dump_interval_ms.unwrap_or(DEFAULT_DUMP_INTERVAL_MS)),
+            entries: Mutex::new(VecDeque::with_capacity(size)),
+            last_dump_at: Mutex::new(None),
+        }
+    }
+
+    /// Adds a copy of record to history. Must be called before the record is passed to step
+    pub fn add(&self, sequence: u64, record: &Record) {
+        let payload = get_payload(record);
+        let entry = HistoryEntry {
+            sequence,
+            received_at: SystemTime::now(),
+            payload: payload[..payload.len().min(self.max_payload_bytes)].to_vec(),
+            payload_size: payload.len(),
+            metadata: get_metadata(record),
+            error: None,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.size {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Marks the record as failed and writes the history to dump directory, unless the history was dumped recently.
+    /// Returns the path to dump file
+    pub fn on_failure(&self, sequence: u64, error: &str) -> Result<Option<String>, String> {
+        if let Some(entry) = self.entries.lock().unwrap().iter_mut().rev().find(|e| e.sequence == sequence) {
+            entry.error = Some(error.to_string());
+        }
+        let dump_dir = match &self.dump_dir {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        {
+            let mut last_dump_at = self.last_dump_at.lock().unwrap();
+            if last_dump_at.is_some_and(|t| t.elapsed() < self.dump_interval) {
+                return Ok(None)
+            }
+            *last_dump_at = Some(Instant::now());
+        }
+        if let Err(e) = fs::create_dir_all(dump_dir) {
+            return Err(format!("Cannot create the directory of record history '{}': {}", dump_dir, e))
+        }
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let path = Path::new(dump_dir).join(format!("{}-{}.json", self.step_id, created_at));
+        let path = path.to_string_lossy().to_string();
+        self.dump(&path, Some(sequence))?;
+        Ok(Some(path))
+    }
+
+    /// Returns the history as JSON
+    pub fn to_json(&self, failed_sequence: Option<u64>) -> Value {
+        let entries: Vec<Value> = self.entries.lock().unwrap().iter().map(|e| e.to_json()).collect();
+        json!({
+            "step": self.step_id,
+            "failed_sequence": failed_sequence,
+            "records": entries,
+        })
+    }
+
+    /// Writes the history to file. Secret values are masked, see `masking::mask_text`
+    pub fn dump(&self, path: &str, failed_sequence: Option<u64>) -> Result<(), String> {
+        let contents = match serde_json::to_string_pretty(&self.to_json(failed_sequence)) {
+            Ok(c) => mask_text(&c),
+            Err(e) => return Err(format!("Cannot serialize the record history: {}", e)),
+        };
+        match fs::write(path, contents) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Cannot write the record history to '{}': {}", path, e)),
+        }
+    }
+}
@@ -0,0 +1,352 @@
+/// Delayed retries of records.
+/// Steps park the records which failed temporarily, e.g. a destination responded with 429 Too Many Requests.
+/// Once the delay elapses, the record is re-injected into the input queue of target step.
+/// The number of attempts is stored in record metadata, so records are not retried forever.
+/// The host also retries the records which failed in step, if the error is classified as transient by rules of step.
+/// Records with permanent errors skip the retries and go straight to the dead letter step, if the step has one.
+/// Records which are retried too many times are sent to the dead letter step too, otherwise they are dropped.
+/// Dead letters have the error message in `torustiq.error` metadata key
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    config::{ErrorClassificationRule, ProcessingErrorKind},
+    masking::{mask_text, matches_pattern},
+    pipeline::{drops::{on_record_dropped, DropReason}, edge::EdgeSender, handle::ModuleHandle},
+    records::{get_metadata, set_metadata_value},
+};
+
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 3;
+
+/// A metadata key with the number of retry attempts of record
+pub const RETRY_ATTEMPT_METADATA_KEY: &str = "torustiq.retry_attempt";
+
+/// A metadata key with the error message of record sent to the dead letter step
+pub const ERROR_METADATA_KEY: &str = "torustiq.error";
+
+/// Where the parked records of step are re-injected
+pub struct RetryRoute {
+    /// A handle of step whose input queue receives the records
+    pub target_handle: ModuleHandle,
+    pub target: EdgeSender,
+    /// A delay which is used if step doesn't provide its own one
+    pub default_delay: Duration,
+    pub max_attempts: u32,
+    /// A handle and input queue of step which receives the dead letters
+    pub dead_letter: Option<(ModuleHandle, EdgeSender)>,
+    /// Rules which classify the processing errors of step
+    pub error_rules: Vec<ErrorClassificationRule>,
+}
+
+/// A parked record
+struct ParkedRecord {
+    due_at: Instant,
+    /// Keeps the order of records with the same due time
+    sequence: u64,
+    source_handle: ModuleHandle,
+    record: Record,
+}
+
+impl PartialEq for ParkedRecord {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due_at, self.sequence) == (other.due_at, other.sequence)
+    }
+}
+
+impl Eq for ParkedRecord {}
+
+impl PartialOrd for ParkedRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ParkedRecord {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.due_at, self.sequence).cmp(&(other.due_at, other.sequence))
+    }
+}
+
+#[derive(Default)]
+struct RetryState {
+    parked: BinaryHeap<Reverse<ParkedRecord>>,
+    next_sequence: u64,
+    /// A number of parked records by target step
+    pending: HashMap<ModuleHandle, usize>,
+}
+
+/// A delay queue of records. Records are re-injected by a dedicated thread
+pub struct RetryQueue {
+    routes: HashMap<ModuleHandle, RetryRoute>,
+    state: Arc<(Mutex<RetryState>, Condvar)>,
+}
+
+impl RetryQueue {
+    /// Creates a queue and starts the re-injection thread
+    pub fn start(thread_name: String, routes: HashMap<ModuleHandle, RetryRoute>) -> Result<RetryQueue, String> {
+        let queue = RetryQueue {
+            routes,
+            state: Arc::new((Mutex::new(RetryState::default()), Condvar::new())),
+        };
+        let state = queue.state.clone();
+        let targets: HashMap<ModuleHandle, (ModuleHandle, EdgeSender)> = queue.routes.iter()
+            .map(|(h, r)| (*h, (r.target_handle, r.target.clone())))
+            .collect();
+        let result = thread::Builder::new().name(thread_name).spawn(move || loop {
+            let (lock, condvar) = &*state;
+            let mut st = lock.lock().unwrap();
+            let wait_time = st.parked.peek().map(|p| p.0.due_at.saturating_duration_since(Instant::now()));
+            match wait_time {
+                None => drop(condvar.wait(st).unwrap()),
+                Some(t) if !t.is_zero() => drop(condvar.wait_timeout(st, t).unwrap()),
+                Some(_) => {
+                    let parked = st.parked.pop().unwrap().0;
+                    let (target_handle, target) = &targets[&parked.source_handle];
+                    // The record is counted as pending until it's in the target queue, so the target doesn't stop earlier
+                    drop(st);
+                    if let Err(mut record) = target.send(parked.record) {
+                        error!("Failed to re-inject a record into step '{}': the input queue is closed", target_handle);
+                        on_record_dropped(parked.source_handle, &record, DropReason::QueueClosed);
+                        record.free_contents();
+                    }
+                    let mut st = lock.lock().unwrap();
+                    if let Some(p) = st.pending.get_mut(target_handle) {
+                        *p -= 1;
+                    }
+                },
+            }
+        });
+        match result {
+            Ok(_) => Ok(queue),
+            Err(e) => Err(format!("Cannot start the retry thread: {}", e)),
+        }
+    }
+
+    /// Parks the record produced by step. If delay is not set, the default delay of step is used.
+    /// Returns false if the record is not parked: the step has no retry route or the record has no attempts left.
+    /// Records without attempts left are sent to the dead letter step, if any
+    pub fn park(&self, source_handle: ModuleHandle, record: Record, delay: Option<Duration>) -> bool {
+        self.park_failed(source_handle, record, delay, None)
+    }
+
+    /// Handles the record which the step failed to process, according to error classification rules of step.
+    /// Returns the record back if no rule matches, or the error is permanent and the step has no dead letter step
+    pub fn on_error(&self, source_handle: ModuleHandle, record: Record, error: &str) -> Result<(), Record> {
+        let route = match self.routes.get(&source_handle) {
+            Some(r) => r,
+            None => return Err(record),
+        };
+        match (classify_error(&route.error_rules, error), &route.dead_letter) {
+            (Some(ProcessingErrorKind::Transient), _) => {
+                self.park_failed(source_handle, record, None, Some(error));
+                Ok(())
+            },
+            (Some(ProcessingErrorKind::Permanent), Some(dead_letter)) => {
+                send_to_dead_letter(source_handle, dead_letter, record, error);
+                Ok(())
+            },
+            _ => Err(record),
+        }
+    }
+
+    /// Parks the record. The error is passed to the dead letter step once the record has no attempts left
+    fn park_failed(&self, source_handle: ModuleHandle, record: Record, delay: Option<Duration>, error: Option<&str>) -> bool {
+        let mut record = record;
+        let route = match self.routes.get(&source_handle) {
+            Some(r) => r,
+            None => {
+                error!("Step '{}' requested a retry, but retries are not configured for this step", source_handle);
+                on_record_dropped(source_handle, &record, DropReason::RetryNotConfigured);
+                record.free_contents();
+                return false
+            },
+        };
+        let attempt: u32 = get_metadata(&record).get(RETRY_ATTEMPT_METADATA_KEY)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(0) + 1;
+        if attempt > route.max_attempts {
+            if let Some(dead_letter) = &route.dead_letter {
+                let message = format!("{} retry attempts are exhausted", route.max_attempts);
+                send_to_dead_letter(source_handle, dead_letter, record, error.unwrap_or(&message));
+                return false
+            }
+            warn!("A record from step '{}' is dropped after {} retry attempts", source_handle, route.max_attempts);
+            on_record_dropped(source_handle, &record, DropReason::RetriesExhausted);
+            record.free_contents();
+            return false
+        }
+        let record = set_metadata_value(record, RETRY_ATTEMPT_METADATA_KEY, &attempt.to_string());
+        let (lock, condvar) = &*self.state;
+        let mut st = lock.lock().unwrap();
+        let sequence = st.next_sequence;
+        st.next_sequence += 1;
+        *st.pending.entry(route.target_handle).or_default() += 1;
+        st.parked.push(Reverse(ParkedRecord {
+            due_at: Instant::now() + delay.unwrap_or(route.default_delay),
+            sequence,
+            source_handle,
+            record,
+        }));
+        condvar.notify_one();
+        true
+    }
+
+    /// Returns true if some parked records are waiting for re-injection into the step
+    pub fn has_pending(&self, target_handle: ModuleHandle) -> bool {
+        self.state.0.lock().unwrap().pending.get(&target_handle).map(|p| *p > 0).unwrap_or(false)
+    }
+
+    /// Returns the total number of parked records
+    pub fn get_parked_count(&self) -> usize {
+        self.state.0.lock().unwrap().parked.len()
+    }
+}
+
+/// Returns the kind of error according to the first matching rule
+fn classify_error(rules: &[ErrorClassificationRule], error: &str) -> Option<ProcessingErrorKind> {
+    let code = get_error_code(error);
+    rules.iter()
+        .find(|r| r.pattern.as_ref().map(|p| matches_pattern(p, error)).unwrap_or(true)
+            && r.code.as_ref().map(|c| code.map(|code| c.eq_ignore_ascii_case(code)).unwrap_or(false)).unwrap_or(true))
+        .map(|r| r.kind)
+}
+
+/// Returns the error code from the start of error message, e.g. `E_PARSE` from `[E_PARSE] unexpected token`
+fn get_error_code(error: &str) -> Option<&str> {
+    let rest = error.trim_start().strip_prefix('[')?;
+    rest.find(']').map(|i| rest[..i].trim()).filter(|c| !c.is_empty())
+}
+
+/// Sends the record with error message to the dead letter step
+fn send_to_dead_letter(source_handle: ModuleHandle, dead_letter: &(ModuleHandle, EdgeSender), record: Record, error: &str) {
+    let (target_handle, target) = dead_letter;
+    let record = set_metadata_value(record, ERROR_METADATA_KEY, &mask_text(error));
+    if let Err(mut record) = target.send(record) {
+        error!("Failed to send a dead letter from step '{}' to step '{}': the input queue is closed", source_handle, target_handle);
+        on_record_dropped(source_handle, &record, DropReason::QueueClosed);
+        record.free_contents();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use crate::{
+        config::{ErrorClassificationRule, ProcessingErrorKind},
+        pipeline::{edge::{edge, EdgeReceiver}, handle::ModuleHandle},
+        records::{create_record, get_metadata},
+    };
+
+    use super::{classify_error, get_error_code, RetryQueue, RetryRoute, ERROR_METADATA_KEY, RETRY_ATTEMPT_METADATA_KEY};
+
+    fn rule(pattern: Option<&str>, code: Option<&str>, kind: ProcessingErrorKind) -> ErrorClassificationRule {
+        ErrorClassificationRule { pattern: pattern.map(String::from), code: code.map(String::from), kind }
+    }
+
+    fn handle(index: usize) -> ModuleHandle {
+        ModuleHandle::try_from(index).unwrap()
+    }
+
+    /// Creates a queue with route of step #1. Returns the queue, the retry target and the dead letter receivers
+    fn start_queue(rules: Vec<ErrorClassificationRule>, has_dead_letter: bool) -> (RetryQueue, EdgeReceiver, Option<EdgeReceiver>) {
+        let (target, target_rcv) = edge(None);
+        let (dead_letter, dead_letter_rcv) = match has_dead_letter {
+            true => {
+                let (s, r) = edge(None);
+                (Some((handle(3), s)), Some(r))
+            },
+            false => (None, None),
+        };
+        let route = RetryRoute {
+            target_handle: handle(1),
+            target,
+            default_delay: Duration::from_millis(10),
+            max_attempts: 1,
+            dead_letter,
+            error_rules: rules,
+        };
+        let queue = RetryQueue::start(String::from("retry-test"), HashMap::from([(handle(1), route)])).unwrap();
+        (queue, target_rcv, dead_letter_rcv)
+    }
+
+    #[test]
+    fn parses_error_code() {
+        assert_eq!(get_error_code("[E_PARSE] unexpected token"), Some("E_PARSE"));
+        assert_eq!(get_error_code("  [ 429 ]too many requests"), Some("429"));
+        assert_eq!(get_error_code("[] empty"), None);
+        assert_eq!(get_error_code("[E_PARSE unclosed"), None);
+        assert_eq!(get_error_code("failed: [E_PARSE]"), None);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = vec![
+            rule(Some("*timeout*"), None, ProcessingErrorKind::Transient),
+            rule(None, Some("e_parse"), ProcessingErrorKind::Permanent),
+            rule(Some("*quota*"), Some("429"), ProcessingErrorKind::Transient),
+        ];
+        assert_eq!(classify_error(&rules, "[E_PARSE] Read TIMEOUT"), Some(ProcessingErrorKind::Transient));
+        assert_eq!(classify_error(&rules, "[E_PARSE] unexpected token"), Some(ProcessingErrorKind::Permanent));
+        assert_eq!(classify_error(&rules, "[429] quota exceeded"), Some(ProcessingErrorKind::Transient));
+    }
+
+    #[test]
+    fn pattern_and_code_must_both_match() {
+        let rules = vec![rule(Some("*quota*"), Some("429"), ProcessingErrorKind::Transient)];
+        assert_eq!(classify_error(&rules, "[429] too many requests"), None);
+        assert_eq!(classify_error(&rules, "[500] quota service is down"), None);
+        assert_eq!(classify_error(&rules, "quota exceeded"), None);
+        assert_eq!(classify_error(&[], "[429] quota exceeded"), None);
+    }
+
+    #[test]
+    fn rule_without_pattern_and_code_matches_any_error() {
+        let rules = vec![rule(None, None, ProcessingErrorKind::Permanent)];
+        assert_eq!(classify_error(&rules, "anything"), Some(ProcessingErrorKind::Permanent));
+    }
+
+    #[test]
+    fn returns_record_with_permanent_error_without_dead_letter_step() {
+        let (queue, _target_rcv, _) = start_queue(vec![rule(None, None, ProcessingErrorKind::Permanent)], false);
+        let mut record = queue.on_error(handle(1), create_record(b"a".to_vec(), HashMap::new()), "bad record").unwrap_err();
+        assert_eq!(queue.get_parked_count(), 0);
+        record.free_contents();
+    }
+
+    #[test]
+    fn returns_unclassified_errors_and_errors_of_unknown_steps() {
+        let (queue, _target_rcv, _dead_letter_rcv) = start_queue(vec![rule(Some("timeout"), None, ProcessingErrorKind::Transient)], true);
+        let mut record = queue.on_error(handle(1), create_record(b"a".to_vec(), HashMap::new()), "bad record").unwrap_err();
+        record = queue.on_error(handle(2), record, "timeout").unwrap_err();
+        record.free_contents();
+    }
+
+    #[test]
+    fn sends_permanent_errors_to_dead_letter_step() {
+        let (queue, _target_rcv, dead_letter_rcv) = start_queue(vec![rule(None, Some("E_PARSE"), ProcessingErrorKind::Permanent)], true);
+        assert!(queue.on_error(handle(1), create_record(b"a".to_vec(), HashMap::new()), "[E_PARSE] unexpected token").is_ok());
+        let (_, mut record) = dead_letter_rcv.unwrap().recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(get_metadata(&record).get(ERROR_METADATA_KEY), Some(&String::from("[E_PARSE] unexpected token")));
+        record.free_contents();
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let (queue, target_rcv, _) = start_queue(vec![rule(Some("*timeout*"), None, ProcessingErrorKind::Transient)], false);
+        assert!(queue.on_error(handle(1), create_record(b"a".to_vec(), HashMap::new()), "read timeout").is_ok());
+        let (_, mut record) = target_rcv.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(get_metadata(&record).get(RETRY_ATTEMPT_METADATA_KEY), Some(&String::from("1")));
+        record.free_contents();
+    }
+}
@@ -0,0 +1,143 @@
+/// Routing of records between steps.
+/// By default each step sends records to the next step. A step with declared outputs sends records
+/// to the step which is mapped to the `class` metadata value of record; records of other classes are sent to the next step.
+/// A step which receives records of some class starts a branch: it is not fed by the previous step in the list,
+/// so the previous step ends its own branch, unless it's the step which routes records there.
+/// Mirror outputs receive a copy of each record, e.g. the shadow chain. The sampling destination is a mirror output too,
+/// but it receives a copy of some records only
+
+use std::collections::{HashMap, HashSet};
+
+use torustiq_common::ffi::types::module::{PipelineModuleKind, Record};
+
+use crate::{pipeline::edge::EdgeSender, records::get_metadata};
+
+/// A metadata key which contains the class of record. Set by transformation modules to route records
+pub const CLASS_METADATA_KEY: &str = "class";
+
+/// Edges between steps. Steps are referenced by index in pipeline
+#[derive(Clone, Default)]
+pub struct Topology {
+    /// A step which receives records of unmapped classes. None if the step ends a branch
+    default_outputs: Vec<Option<usize>>,
+    /// Steps which receive records of mapped classes, by class
+    class_outputs: Vec<HashMap<String, usize>>,
+    /// Steps which receive a copy of each record
+    mirror_outputs: Vec<Vec<usize>>,
+    /// A step which receives the records failed in step, see `pipeline::retry`. Not a part of record flow
+    dead_letter_outputs: Vec<Option<usize>>,
+}
+
+impl Topology {
+    /// Creates a topology from mappings of classes to step indexes, from mirror outputs and from dead letter outputs.
+    /// All of them are provided for each step
+    pub fn new(class_outputs: Vec<HashMap<String, usize>>, mirror_outputs: Vec<Vec<usize>>, dead_letter_outputs: Vec<Option<usize>>) -> Topology {
+        let branch_starts: HashSet<usize> = class_outputs.iter()
+            .flat_map(|o| o.values().copied())
+            .chain(mirror_outputs.iter().flatten().copied())
+            .chain(dead_letter_outputs.iter().flatten().copied())
+            .collect();
+        let default_outputs = (0..class_outputs.len())
+            .map(|i| {
+                let next = i + 1;
+                let is_fed = !branch_starts.contains(&next) || class_outputs[i].values().any(|t| *t == next);
+                match next < class_outputs.len() && is_fed {
+                    true => Some(next),
+                    false => None,
+                }
+            })
+            .collect();
+        Topology { default_outputs, class_outputs, mirror_outputs, dead_letter_outputs }
+    }
+
+    pub fn get_default_output(&self, step_index: usize) -> Option<usize> {
+        self.default_outputs.get(step_index).copied().flatten()
+    }
+
+    pub fn get_class_outputs(&self, step_index: usize) -> HashMap<String, usize> {
+        self.class_outputs.get(step_index).cloned().unwrap_or_default()
+    }
+
+    pub fn get_mirror_outputs(&self, step_index: usize) -> Vec<usize> {
+        self.mirror_outputs.get(step_index).cloned().unwrap_or_default()
+    }
+
+    /// Returns the indexes of steps which send records to the step
+    pub fn get_inputs(&self, step_index: usize) -> Vec<usize> {
+        (0..self.default_outputs.len())
+            .filter(|i| self.get_default_output(*i) == Some(step_index)
+                || self.class_outputs[*i].values().any(|t| *t == step_index)
+                || self.mirror_outputs[*i].contains(&step_index))
+            .collect()
+    }
+
+    pub fn get_dead_letter_output(&self, step_index: usize) -> Option<usize> {
+        self.dead_letter_outputs.get(step_index).copied().flatten()
+    }
+
+    /// Returns the indexes of steps which send dead letters to the step
+    pub fn get_dead_letter_inputs(&self, step_index: usize) -> Vec<usize> {
+        (0..self.dead_letter_outputs.len())
+            .filter(|i| self.dead_letter_outputs[*i] == Some(step_index))
+            .collect()
+    }
+
+    /// Returns the indexes of steps which send records to the step directly or through other steps, in pipeline order
+    pub fn get_upstream(&self, step_index: usize) -> Vec<usize> {
+        let mut upstream: HashSet<usize> = HashSet::new();
+        let mut pending: Vec<usize> = vec![step_index];
+        while let Some(i) = pending.pop() {
+            for input in self.get_inputs(i) {
+                if upstream.insert(input) {
+                    pending.push(input);
+                }
+            }
+        }
+        let mut upstream: Vec<usize> = upstream.into_iter().collect();
+        upstream.sort();
+        upstream
+    }
+
+    /// Returns true if step doesn't send records to any other step
+    pub fn is_destination(&self, step_index: usize) -> bool {
+        self.get_default_output(step_index).is_none() && self.get_class_outputs(step_index).is_empty()
+            && self.get_mirror_outputs(step_index).is_empty()
+    }
+
+    /// Returns the kind of step according to its edges
+    pub fn get_kind(&self, step_index: usize) -> PipelineModuleKind {
+        if 0 == step_index { PipelineModuleKind::Source }
+        else if self.is_destination(step_index) { PipelineModuleKind::Destination }
+        else { PipelineModuleKind::Transformation }
+    }
+}
+
+/// Sending sides of edges which start at step
+#[derive(Clone, Default)]
+pub struct StepOutputs {
+    /// An edge to the next step. None if the step ends a branch
+    pub default: Option<EdgeSender>,
+    /// Edges to steps which receive records of mapped classes
+    pub by_class: HashMap<String, EdgeSender>,
+    /// Edges to steps which receive a copy of each record
+    pub mirrors: Vec<EdgeSender>,
+    /// An edge to the sampling destination which receives a random share of records
+    pub sample: Option<EdgeSender>,
+}
+
+impl StepOutputs {
+    /// Selects an edge for record according to its class
+    pub fn select(&self, record: &Record) -> Result<&EdgeSender, String> {
+        if self.by_class.is_empty() {
+            return self.default.as_ref().ok_or_else(|| String::from("the step has no outputs"))
+        }
+        let class = get_metadata(record).remove(CLASS_METADATA_KEY);
+        if let Some(sender) = class.as_ref().and_then(|c| self.by_class.get(c)) {
+            return Ok(sender)
+        }
+        match &self.default {
+            Some(sender) => Ok(sender),
+            None => Err(format!("no output is mapped to record class '{}'", class.unwrap_or_default())),
+        }
+    }
+}
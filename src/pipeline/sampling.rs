@@ -0,0 +1,74 @@
+/// Continuous sampling of records for data quality monitoring.
+/// A random share of records produced by the sampled step is copied to a side destination which runs next to the pipeline.
+/// Like the shadow chain, the destination never affects the pipeline: copies are dropped once its input queue is full,
+/// and its termination doesn't stop the pipeline
+
+use std::{
+    sync::{atomic::{AtomicU64, Ordering}, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    pipeline::edge::EdgeSender,
+    records::{create_record, get_metadata, get_payload},
+};
+
+/// Default capacity of the input queue of sampling destination
+pub const DEFAULT_SAMPLING_QUEUE_CAPACITY: usize = 10000;
+
+/// A name of step which writes the samples to file, if no destination step is defined
+pub const SAMPLING_STEP_NAME: &str = "torustiq.sampling";
+
+/// State of sampling
+pub struct Sampling {
+    /// A share of records to copy, from 0 to 1
+    ratio: f64,
+    /// A state of xorshift pseudo-random number generator
+    random: Mutex<u64>,
+    records_sampled: AtomicU64,
+    records_dropped: AtomicU64,
+}
+
+impl Sampling {
+    pub fn new(percent: f64) -> Sampling {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        Sampling {
+            ratio: (percent / 100.0).clamp(0.0, 1.0),
+            // Zero state would produce zeros only
+            random: Mutex::new(seed | 1),
+            records_sampled: AtomicU64::new(0),
+            records_dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Copies the record to sampling destination with the configured probability
+    pub fn sample(&self, record: &Record, destination: &EdgeSender) {
+        if self.next_random() >= self.ratio {
+            return
+        }
+        let copy = create_record(get_payload(record).to_vec(), get_metadata(record));
+        match destination.try_send(copy) {
+            Ok(_) => self.records_sampled.fetch_add(1, Ordering::Relaxed),
+            Err(mut copy) => {
+                copy.free_contents();
+                self.records_dropped.fetch_add(1, Ordering::Relaxed)
+            },
+        };
+    }
+
+    pub fn format_summary(&self) -> String {
+        format!("{} records sampled, {} dropped because the sampling queue is full",
+            self.records_sampled.load(Ordering::Relaxed), self.records_dropped.load(Ordering::Relaxed))
+    }
+
+    /// Returns a number in range [0, 1)
+    fn next_random(&self) -> f64 {
+        let mut state = self.random.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,142 @@
+/// Self-test of module callbacks.
+/// Before steps are started, the callbacks are called with a reserved handle which doesn't belong to any component:
+/// a synthetic record is passed through the data receive callback to a dedicated channel,
+/// and the termination callback must be acknowledged by system command thread.
+/// The static context of steps is verified too, so wiring errors are reported before any data is processed
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::{
+    callbacks::{on_rcv_cb, on_step_terminate_cb},
+    pipeline::{
+        channels::Channels,
+        edge::edge,
+        handle::ModuleHandle,
+        pipeline::Pipeline,
+        pipeline_step::StepModule,
+        routing::StepOutputs,
+    },
+    records::{create_record, get_payload},
+    xthread::SELF_TEST_HANDLE,
+};
+
+/// How long to wait for the callback effects
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+const RESPONSE_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+const SELF_TEST_PAYLOAD: &[u8] = b"torustiq self-test";
+
+/// Set by system command thread once the termination message of self-test is handled
+static IS_TERMINATION_ACKNOWLEDGED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the handle is reserved for self-test
+pub fn is_self_test_handle(handle: ModuleHandle) -> bool {
+    SELF_TEST_HANDLE.get() == Some(&handle)
+}
+
+/// Returns a handle which follows the handles of components. Disabled listeners keep their handles,
+/// so the handle is not derived from the number of components
+pub fn create_self_test_handle(handles: &HashSet<ModuleHandle>) -> Result<ModuleHandle, String> {
+    ModuleHandle::try_from(handles.iter().map(|h| h.index() + 1).max().unwrap_or(0))
+}
+
+/// Called from system command thread instead of the step termination
+pub fn acknowledge_termination() {
+    IS_TERMINATION_ACKNOWLEDGED.store(true, Ordering::SeqCst);
+}
+
+/// Runs the self-test. Must be called once the channels are started, but before steps are started
+pub fn run_self_test(pipeline: &Pipeline) -> Result<(), String> {
+    debug!("Running the self-test of callbacks...");
+    let handle = match SELF_TEST_HANDLE.get() {
+        Some(h) => *h,
+        None => return Err(String::from("the self-test handle is not registered")),
+    };
+    check_static_context(pipeline)?;
+    check_data_receive_callback(&pipeline.channels, handle)?;
+    check_termination_callback(handle)?;
+    debug!("Self-test of callbacks is passed");
+    Ok(())
+}
+
+/// Checks if handles, output channels and deallocation functions of components are registered
+fn check_static_context(pipeline: &Pipeline) -> Result<(), String> {
+    for (step_index, step) in pipeline.steps.iter().enumerate() {
+        let step = step.lock().unwrap();
+        let handle = step.get_handle();
+        if ModuleHandle::from_ffi(handle.to_ffi(), "Self-test").is_none() {
+            return Err(format!("the handle of step '{}' is not registered", step.get_id()))
+        }
+        if pipeline.topology.is_destination(step_index) {
+            continue
+        }
+        if !pipeline.channels.has_outputs(handle) {
+            return Err(format!("step '{}' has no output channel", step.get_id()))
+        }
+        if matches!(step.module, StepModule::Library(_)) && pipeline.channels.get_free_record_fn(handle).is_none() {
+            return Err(format!("no record deallocation function is registered for step '{}'", step.get_id()))
+        }
+    }
+    for listener in &pipeline.listeners {
+        let listener = listener.lock().unwrap();
+        if ModuleHandle::from_ffi(listener.get_handle().to_ffi(), "Self-test").is_none() {
+            return Err(format!("the handle of event listener '{}' is not registered", listener.get_id()))
+        }
+    }
+    Ok(())
+}
+
+/// Passes a synthetic record through the data receive callback to a dedicated channel
+fn check_data_receive_callback(channels: &Channels, handle: ModuleHandle) -> Result<(), String> {
+    let (tx, rx) = edge(None);
+    channels.set_outputs(handle, Arc::new(StepOutputs { default: Some(tx), by_class: HashMap::new(), mirrors: Vec::new(), sample: None }));
+    on_rcv_cb(handle.to_ffi(), create_record(SELF_TEST_PAYLOAD.to_vec(), HashMap::new()));
+    channels.remove_outputs(handle);
+
+    let mut record = match rx.recv_timeout(RESPONSE_TIMEOUT) {
+        Ok((_, r)) => r,
+        Err(_) => return Err(String::from("the data receive callback didn't deliver a record to channel")),
+    };
+    let is_intact = get_payload(&record) == SELF_TEST_PAYLOAD;
+    record.free_contents();
+    match is_intact {
+        true => Ok(()),
+        false => Err(String::from("the data receive callback delivered a corrupted record")),
+    }
+}
+
+/// Sends a termination message and waits until system command thread handles it
+fn check_termination_callback(handle: ModuleHandle) -> Result<(), String> {
+    IS_TERMINATION_ACKNOWLEDGED.store(false, Ordering::SeqCst);
+    on_step_terminate_cb(handle.to_ffi());
+    let started_at = Instant::now();
+    while !IS_TERMINATION_ACKNOWLEDGED.load(Ordering::SeqCst) {
+        if started_at.elapsed() >= RESPONSE_TIMEOUT {
+            return Err(format!("the system command thread didn't handle the termination message within {} s",
+                RESPONSE_TIMEOUT.as_secs()))
+        }
+        thread::sleep(RESPONSE_CHECK_INTERVAL);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::create_self_test_handle;
+    use crate::pipeline::handle::ModuleHandle;
+
+    #[test]
+    fn self_test_handle_follows_the_handles_of_disabled_listeners() {
+        // Steps 0 and 1, listeners 2 and 4. Listener 3 is disabled
+        let handles: HashSet<ModuleHandle> = [0, 1, 2, 4].into_iter().map(|i| ModuleHandle::try_from(i).unwrap()).collect();
+        assert_eq!(create_self_test_handle(&handles), ModuleHandle::try_from(5));
+    }
+}
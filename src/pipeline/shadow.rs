@@ -0,0 +1,137 @@
+/// Shadow chain of steps.
+/// The records produced by source are copied into the shadow chain which runs next to the primary chain
+/// without affecting it: copies are dropped once the shadow input queue is full,
+/// and termination of shadow steps doesn't stop the pipeline.
+/// If comparison is enabled, each source record gets an ID in metadata, and records which reach the primary destination
+/// are compared with the output of shadow chain by this ID
+
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicU64, Ordering}, Mutex},
+};
+
+use log::warn;
+use torustiq_common::ffi::types::module::Record;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    pipeline::edge::EdgeSender,
+    records::{create_record, get_metadata, get_payload, set_metadata_value},
+};
+
+/// A metadata key which contains the ID of source record. Set by host if the shadow output is compared
+pub const SHADOW_ID_METADATA_KEY: &str = "torustiq.shadow_id";
+
+/// Default capacity of the input queue of shadow chain
+pub const DEFAULT_SHADOW_QUEUE_CAPACITY: usize = 10000;
+
+/// A name of step which is appended to the shadow chain to receive its output
+pub const SHADOW_SINK_STEP_NAME: &str = "torustiq.shadow_sink";
+
+/// Only the first mismatches are logged, the rest are counted
+const MAX_LOGGED_MISMATCHES: u64 = 10;
+
+/// The end of chain where the record is observed
+pub enum ShadowSide {
+    Primary,
+    Shadow,
+}
+
+/// Hashes of payloads produced from the same source record
+#[derive(Default)]
+struct PendingComparison {
+    primary: Option<u64>,
+    shadow: Option<u64>,
+}
+
+/// State of shadow chain
+pub struct Shadow {
+    is_compare_enabled: bool,
+    next_id: AtomicU64,
+    records_copied: AtomicU64,
+    records_dropped: AtomicU64,
+    records_matched: AtomicU64,
+    records_mismatched: AtomicU64,
+    /// Records which are observed at one end of chain only so far
+    pending: Mutex<HashMap<u64, PendingComparison>>,
+}
+
+impl Shadow {
+    pub fn new(is_compare_enabled: bool) -> Shadow {
+        Shadow {
+            is_compare_enabled,
+            next_id: AtomicU64::new(0),
+            records_copied: AtomicU64::new(0),
+            records_dropped: AtomicU64::new(0),
+            records_matched: AtomicU64::new(0),
+            records_mismatched: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Copies the record into shadow chain. Returns the original record, which gets an ID if comparison is enabled
+    pub fn mirror(&self, record: Record, mirrors: &[EdgeSender]) -> Record {
+        let record = match self.is_compare_enabled {
+            true => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                set_metadata_value(record, SHADOW_ID_METADATA_KEY, &id.to_string())
+            },
+            false => record,
+        };
+        for mirror in mirrors {
+            let copy = create_record(get_payload(&record).to_vec(), get_metadata(&record));
+            match mirror.try_send(copy) {
+                Ok(_) => self.records_copied.fetch_add(1, Ordering::Relaxed),
+                Err(mut copy) => {
+                    copy.free_contents();
+                    self.records_dropped.fetch_add(1, Ordering::Relaxed)
+                },
+            };
+        }
+        record
+    }
+
+    /// Registers a record which reached the end of chain
+    pub fn observe(&self, side: ShadowSide, record: &Record) {
+        if !self.is_compare_enabled {
+            return
+        }
+        let id: u64 = match get_metadata(record).get(SHADOW_ID_METADATA_KEY).and_then(|id| id.parse().ok()) {
+            Some(id) => id,
+            None => return,
+        };
+        let hash = xxh3_64(get_payload(record));
+        let mut pending = self.pending.lock().unwrap();
+        let comparison = pending.entry(id).or_default();
+        match side {
+            ShadowSide::Primary => comparison.primary = Some(hash),
+            ShadowSide::Shadow => comparison.shadow = Some(hash),
+        };
+        let (primary, shadow) = match (comparison.primary, comparison.shadow) {
+            (Some(p), Some(s)) => (p, s),
+            _ => return,
+        };
+        pending.remove(&id);
+        if primary == shadow {
+            self.records_matched.fetch_add(1, Ordering::Relaxed);
+            return
+        }
+        let mismatches = self.records_mismatched.fetch_add(1, Ordering::Relaxed) + 1;
+        if mismatches <= MAX_LOGGED_MISMATCHES {
+            warn!("Shadow chain produced a different record from source record #{}", id);
+        }
+    }
+
+    pub fn format_summary(&self) -> String {
+        let mut summary = format!("{} records copied, {} dropped because the shadow queue is full",
+            self.records_copied.load(Ordering::Relaxed), self.records_dropped.load(Ordering::Relaxed));
+        if self.is_compare_enabled {
+            let pending = self.pending.lock().unwrap();
+            summary.push_str(&format!(". Comparison: {} matched, {} mismatched, {} missing in shadow output, {} missing in primary output",
+                self.records_matched.load(Ordering::Relaxed), self.records_mismatched.load(Ordering::Relaxed),
+                pending.values().filter(|p| p.shadow.is_none()).count(),
+                pending.values().filter(|p| p.primary.is_none()).count()));
+        }
+        summary
+    }
+}
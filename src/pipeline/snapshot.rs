@@ -0,0 +1,160 @@
+/// Quiescent snapshots of running pipeline.
+/// The source is paused, then the steps are drained one by one in the order of pipeline, so each step
+/// processes everything produced by upstream steps. Once the pipeline is quiet, the steps commit the processed records
+/// and a marker file is written. Then the pipeline is resumed. Between the marker and the resume, the external
+/// state of pipeline is consistent, e.g. for backups or maintenance of infrastructure
+
+use std::{
+    fs,
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{
+    errors::TorustiqError,
+    pipeline::{
+        handle::ModuleHandle,
+        pipeline::{is_step_draining, is_step_paused, set_step_draining, set_step_paused, Pipeline},
+        stats::StepStatistics,
+    },
+    xthread::{COMMIT_REQUESTS, RETRY_QUEUE, STEP_STATS},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A step which takes part in snapshot
+struct SnapshotStep {
+    handle: ModuleHandle,
+    id: String,
+    is_commit_supported: bool,
+    stats: Option<Arc<StepStatistics>>,
+}
+
+/// Returns true if the step is requested to commit the processed records
+pub fn is_commit_requested(handle: ModuleHandle) -> bool {
+    COMMIT_REQUESTS.lock().unwrap().contains(&handle)
+}
+
+/// Marks the commit request of step as done
+pub fn complete_commit_request(handle: ModuleHandle) {
+    COMMIT_REQUESTS.lock().unwrap().remove(&handle);
+}
+
+/// Takes a quiescent snapshot and writes a marker file. The pipeline is resumed even if snapshot fails.
+/// Returns a summary of snapshot
+pub fn take_snapshot(pipeline: &Arc<Mutex<Pipeline>>, marker_path: &str, timeout: Duration) -> Result<String, TorustiqError> {
+    let started_at = Instant::now();
+    let (pipeline_name, source, steps) = get_snapshot_steps(pipeline)?;
+    info!("Taking a snapshot of pipeline '{}'...", pipeline_name);
+    set_step_paused(source, true);
+    let result = quiesce(&steps, started_at + timeout)
+        .and_then(|_| write_marker(marker_path, &pipeline_name, &steps));
+    for step in &steps {
+        set_step_draining(step.handle, false);
+        complete_commit_request(step.handle);
+    }
+    set_step_paused(source, false);
+    result?;
+    info!("Snapshot of pipeline '{}' is taken. Marker: {}", pipeline_name, marker_path);
+    Ok(format!("Snapshot is taken in {} ms. Marker: {}. The pipeline is resumed", started_at.elapsed().as_millis(), marker_path))
+}
+
+/// Returns the name of pipeline, the handle of source and the running steps after source
+fn get_snapshot_steps(pipeline: &Arc<Mutex<Pipeline>>) -> Result<(String, ModuleHandle, Vec<SnapshotStep>), TorustiqError> {
+    let pipeline = pipeline.lock().unwrap();
+    let step_stats = STEP_STATS.lock().unwrap();
+    let mut source: Option<ModuleHandle> = None;
+    let mut steps: Vec<SnapshotStep> = Vec::new();
+    for step in &pipeline.steps {
+        let step = step.lock().unwrap();
+        let handle = step.get_handle();
+        if is_step_paused(handle) || is_step_draining(handle) {
+            return Err(TorustiqError::Unsupported(format!("Step '{}' is paused or drained. Please resume it before taking a snapshot", step.get_id())))
+        }
+        if source.is_none() {
+            source = Some(handle);
+            continue
+        }
+        if step.component.is_terminated() {
+            continue
+        }
+        steps.push(SnapshotStep {
+            handle,
+            id: step.get_id(),
+            is_commit_supported: step.is_commit_supported(),
+            stats: step_stats.get(&handle).cloned(),
+        });
+    }
+    match source {
+        Some(s) => Ok((pipeline.name.clone(), s, steps)),
+        None => Err(TorustiqError::NotRunning),
+    }
+}
+
+/// Drains the steps in the order of pipeline and commits the processed records
+fn quiesce(steps: &[SnapshotStep], deadline: Instant) -> Result<(), TorustiqError> {
+    for step in steps {
+        let stats = match &step.stats {
+            Some(s) => s,
+            None => continue,
+        };
+        wait_until(deadline, &step.id, "input queue is empty", || stats.queue_depth.get() == 0)?;
+        set_step_draining(step.handle, true);
+        wait_until(deadline, &step.id, "current record is processed", || stats.is_input_stopped.load(Ordering::SeqCst))?;
+    }
+    // Drained steps handle the commit requests, see `start_reader_thread`
+    let committed: Vec<&SnapshotStep> = steps.iter().filter(|s| s.is_commit_supported && s.stats.is_some()).collect();
+    COMMIT_REQUESTS.lock().unwrap().extend(committed.iter().map(|s| s.handle));
+    for step in committed {
+        wait_until(deadline, &step.id, "processed records are committed", || !is_commit_requested(step.handle))?;
+    }
+    Ok(())
+}
+
+/// Waits until the condition is true
+fn wait_until<F: Fn() -> bool>(deadline: Instant, step_id: &str, condition_name: &str, condition: F) -> Result<(), TorustiqError> {
+    while !condition() {
+        if Instant::now() >= deadline {
+            return Err(TorustiqError::Timeout(format!("Snapshot is cancelled: step '{}' is not quiet within timeout ({})",
+                step_id, condition_name)))
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Writes a marker file with statistics of steps at the moment of snapshot
+fn write_marker(path: &str, pipeline_name: &str, steps: &[SnapshotStep]) -> Result<(), TorustiqError> {
+    let parked = RETRY_QUEUE.get().map(|q| q.get_parked_count()).unwrap_or(0);
+    if parked > 0 {
+        warn!("{} records are parked for retry. They are re-injected after the snapshot", parked);
+    }
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let steps_json: Vec<serde_json::Value> = steps.iter()
+        .filter_map(|s| s.stats.as_ref().map(|st| (s, st.snapshot())))
+        .map(|(s, st)| json!({
+            "step": s.id,
+            "records_received": st.records_received,
+            "records_succeeded": st.records_succeeded,
+            "records_failed": st.records_failed,
+        }))
+        .collect();
+    let marker = json!({
+        "pipeline": pipeline_name,
+        "created_at_ms": created_at as u64,
+        "records_parked_for_retry": parked,
+        "steps": steps_json,
+    });
+    let contents = match serde_json::to_string_pretty(&marker) {
+        Ok(c) => c,
+        Err(e) => return Err(TorustiqError::Pipeline(format!("Cannot serialize the snapshot marker: {}", e))),
+    };
+    match fs::write(path, contents) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(TorustiqError::Pipeline(format!("Cannot write the snapshot marker to '{}': {}", path, e))),
+    }
+}
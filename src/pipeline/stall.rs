@@ -0,0 +1,100 @@
+/// Detection of stalled steps.
+/// A step is stalled if its input queue is full and the step doesn't take any records from it.
+/// The detector counts the consecutive seconds of full queue for each step and warns once a stall is detected
+
+use std::{
+    sync::atomic::Ordering,
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    config::NotificationEvent,
+    notifications::notify,
+    xthread::{PIPELINE, STEP_STATS},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A state of step which has bounded input queue
+struct MonitoredStep {
+    step_id: String,
+    capacity: usize,
+    last_received: u64,
+    /// Seconds since the step took a record from the full queue
+    stalled_seconds: u64,
+    is_stall_reported: bool,
+}
+
+/// Starts a thread which detects the stalled steps.
+/// Does nothing if no step has bounded input queue, as unbounded queues are never full
+pub fn start_stall_detector(stall_timeout: Duration) -> Result<(), String> {
+    let pipeline_arc = match PIPELINE.get() {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let (pipeline_name, notifications, mut steps) = {
+        let pipeline = pipeline_arc.lock().unwrap();
+        let steps: Vec<(_, MonitoredStep)> = pipeline.steps.iter()
+            .filter_map(|s| {
+                let s = s.lock().unwrap();
+                s.queue_capacity.map(|capacity| (s.get_handle(), MonitoredStep {
+                    step_id: s.get_id(),
+                    capacity,
+                    last_received: 0,
+                    stalled_seconds: 0,
+                    is_stall_reported: false,
+                }))
+            })
+            .collect();
+        (pipeline.name.clone(), pipeline.notifications.clone(), steps)
+    };
+    if steps.is_empty() {
+        return Ok(())
+    }
+
+    let thread_name = format!("{}-stall-detector", &pipeline_name);
+    let result = thread::Builder::new().name(thread_name.clone()).spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        if !pipeline_arc.lock().unwrap().is_running() {
+            debug!("Stall detector is stopped: pipeline is terminated");
+            return
+        }
+        for (handle, step) in steps.iter_mut() {
+            let stats = match STEP_STATS.lock().unwrap().get(handle) {
+                Some(s) => s.clone(),
+                None => continue,
+            };
+            let received = stats.records_received.load(Ordering::Relaxed);
+            let is_full = stats.queue_depth.get() >= step.capacity;
+            match is_full {
+                true => stats.queue_full_seconds.fetch_add(1, Ordering::Relaxed),
+                false => stats.queue_full_seconds.swap(0, Ordering::Relaxed),
+            };
+
+            if received != step.last_received || !is_full {
+                if step.is_stall_reported {
+                    info!("Step '{}' is not stalled anymore", step.step_id);
+                }
+                step.last_received = received;
+                step.stalled_seconds = 0;
+                step.is_stall_reported = false;
+                continue
+            }
+            step.stalled_seconds += 1;
+            if step.stalled_seconds >= stall_timeout.as_secs() && !step.is_stall_reported {
+                let message = format!("Step '{}' is stalled: no records are consumed for {} s while the input queue is full",
+                    step.step_id, step.stalled_seconds);
+                warn!("{}", message);
+                notify(&notifications, NotificationEvent::StepStalled, &pipeline_name, &message);
+                step.is_stall_reported = true;
+            }
+        }
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to start thread '{}': {}", thread_name, e)),
+    }
+}
@@ -0,0 +1,65 @@
+/// Concurrent startup of steps.
+/// Steps are processed in waves: each wave contains the steps whose dependencies are processed in previous waves.
+/// Steps inside a wave are processed concurrently, except for steps of the same module,
+/// as modules are not required to handle concurrent calls from host.
+/// The number of concurrent threads is limited by CPU limit of control group
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{
+    errors::TorustiqError,
+    pipeline::{handle::ModuleHandle, pipeline_step::PipelineStep},
+    xthread::RESOURCE_LIMITS,
+};
+
+/// Applies the action to each step, respecting the dependencies between steps.
+/// Errors of all steps in a wave are aggregated. If some wave fails, the next waves are not processed
+pub fn for_each_step_concurrently<F>(steps: &[Arc<Mutex<PipelineStep>>], action: F) -> Result<(), TorustiqError>
+where
+    F: Fn(usize, &Arc<Mutex<PipelineStep>>) -> Result<(), TorustiqError> + Sync
+{
+    let dependencies: Vec<(ModuleHandle, Vec<ModuleHandle>, String)> = steps.iter()
+        .map(|s| {
+            let s = s.lock().unwrap();
+            (s.get_handle(), s.depends_on.clone(), s.module.get_id())
+        })
+        .collect();
+    let mut done: HashSet<ModuleHandle> = HashSet::new();
+    while done.len() < steps.len() {
+        // Steps of the same module are processed sequentially in one thread
+        let mut wave: HashMap<&String, Vec<usize>> = HashMap::new();
+        for (i, (handle, depends_on, module_id)) in dependencies.iter().enumerate() {
+            if !done.contains(handle) && depends_on.iter().all(|d| done.contains(d)) {
+                wave.entry(module_id).or_default().push(i);
+            }
+        }
+        if wave.is_empty() {
+            return Err(TorustiqError::Pipeline(String::from("Steps have circular dependencies")))
+        }
+
+        let groups: Vec<&Vec<usize>> = wave.values().collect();
+        let errors: Vec<TorustiqError> = groups.chunks(RESOURCE_LIMITS.get_max_concurrency())
+            .flat_map(|chunk| thread::scope(|scope| {
+                let threads: Vec<_> = chunk.iter()
+                    .map(|indexes| scope.spawn(|| indexes.iter()
+                        .filter_map(|i| action(*i, &steps[*i]).err())
+                        .collect::<Vec<TorustiqError>>()))
+                    .collect();
+                threads.into_iter()
+                    .flat_map(|t| t.join().unwrap_or_else(|_| vec![TorustiqError::Pipeline(String::from("Startup thread panicked"))]))
+                    .collect::<Vec<TorustiqError>>()
+            }))
+            .collect();
+        if !errors.is_empty() {
+            return Err(TorustiqError::from_many(errors))
+        }
+        wave.values().flatten().for_each(|i| {
+            done.insert(dependencies[*i].0);
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+/// Per-step statistics.
+/// Counters are updated by reader threads and can be read by listener modules on demand
+
+use std::{
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc},
+    time::Duration,
+};
+
+use crate::{
+    modules::extensions::StepStats,
+    pipeline::{drops::{DropReason, DROP_REASON_COUNT}, edge::QueueDepth},
+};
+
+#[derive(Default)]
+pub struct StepStatistics {
+    /// Records received from the previous step
+    pub records_received: AtomicU64,
+    /// Records processed successfully
+    pub records_succeeded: AtomicU64,
+    /// Records failed to process
+    pub records_failed: AtomicU64,
+    /// Records waiting in the input queue of step
+    pub queue_depth: QueueDepth,
+    /// Records missing in the input sequence. Counted if sequence check is enabled
+    pub sequence_gaps: AtomicU64,
+    /// Records which arrived out of order. Counted if sequence check is enabled
+    pub sequence_reorderings: AtomicU64,
+    /// A number of record processing calls
+    pub process_record_calls: AtomicU64,
+    /// Total duration of record processing calls, nanoseconds
+    pub process_record_ns: AtomicU64,
+    /// A number of times the previous step was blocked because the input queue was full
+    pub queue_overflows: Arc<AtomicU64>,
+    /// Total time the previous step was blocked because the input queue was full, nanoseconds
+    pub queue_blocked_ns: Arc<AtomicU64>,
+    /// A number of consecutive seconds the input queue is full. Updated by stall detector
+    pub queue_full_seconds: AtomicU64,
+    /// Records left in the input queue after the step is terminated. These records are never deallocated
+    pub records_leaked: AtomicU64,
+    /// Records which were not processed because their deadline is expired
+    pub records_expired: AtomicU64,
+    /// Records dropped by load shedding
+    pub records_shed: AtomicU64,
+    /// Records dropped by host, by reason. See `pipeline::drops`
+    pub records_dropped: [AtomicU64; DROP_REASON_COUNT],
+    /// True if the step drops records because of sustained overload
+    pub is_shedding: AtomicBool,
+    /// True if the step doesn't take records from the input queue, because it's paused or drained
+    pub is_input_stopped: AtomicBool,
+}
+
+impl StepStatistics {
+    pub fn new(queue_depth: QueueDepth, queue_overflows: Arc<AtomicU64>, queue_blocked_ns: Arc<AtomicU64>) -> StepStatistics {
+        StepStatistics {
+            queue_depth,
+            queue_overflows,
+            queue_blocked_ns,
+            ..Default::default()
+        }
+    }
+
+    pub fn on_received(&self) {
+        self.records_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_processed(&self, success: bool) {
+        match success {
+            true => self.records_succeeded.fetch_add(1, Ordering::Relaxed),
+            false => self.records_failed.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn on_process_record_call(&self, duration: Duration) {
+        self.process_record_calls.fetch_add(1, Ordering::Relaxed);
+        self.process_record_ns.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn on_dropped(&self, reason: DropReason) {
+        self.records_dropped[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_records_dropped(&self, reason: DropReason) -> u64 {
+        self.records_dropped[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns a snapshot of statistics in FFI-compatible format
+    pub fn snapshot(&self) -> StepStats {
+        StepStats {
+            records_received: self.records_received.load(Ordering::Relaxed),
+            records_succeeded: self.records_succeeded.load(Ordering::Relaxed),
+            records_failed: self.records_failed.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.get() as u64,
+        }
+    }
+}
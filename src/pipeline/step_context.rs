@@ -0,0 +1,133 @@
+/// Per-step state which is read on the record path.
+/// A context is created for each step and registered in static context once the steps are configured.
+/// The flags are atomics, so reader threads and callbacks check them for each record without taking
+/// a process-wide lock, and steps of different edges don't wait for each other.
+/// Libraries which export `torustiq_module_pipeline_set_context` receive a pointer to context of their step
+/// and pass it back with produced records, so the host doesn't look the step up. See `modules::extensions`.
+/// Once the pipeline termination is requested, steps are not paused or drained anymore, so they can finish
+
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+
+use once_cell::sync::OnceCell;
+
+use crate::{pipeline::{handle::ModuleHandle, routing::StepOutputs}, xthread::STEP_CONTEXTS};
+
+/// State of step which is shared between the pipeline and the threads which process the records of step
+pub struct StepContext {
+    pub handle: ModuleHandle,
+    /// Paused steps neither process nor emit records
+    is_paused: AtomicBool,
+    /// Drained steps don't take new records from the input queue,
+    /// but finish processing of the current record and emit the results
+    is_draining: AtomicBool,
+    /// Mirrors the terminated state of step component, which is guarded by the step mutex
+    is_terminated: AtomicBool,
+    /// Set once the pipeline termination is requested. Overrides the paused and drained state
+    is_shutting_down: AtomicBool,
+    /// Outputs of step. Set once the channels are started. Destination steps have no outputs
+    outputs: OnceCell<Arc<StepOutputs>>,
+}
+
+impl StepContext {
+    pub fn new(handle: ModuleHandle) -> StepContext {
+        StepContext {
+            handle,
+            is_paused: AtomicBool::new(false),
+            is_draining: AtomicBool::new(false),
+            is_terminated: AtomicBool::new(false),
+            is_shutting_down: AtomicBool::new(false),
+            outputs: OnceCell::new(),
+        }
+    }
+
+    /// Returns a pointer which is passed to modules. The context lives until the application exits
+    pub fn as_ffi_ptr(self: &Arc<Self>) -> *const c_void {
+        Arc::as_ptr(self) as *const c_void
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::SeqCst) && !self.is_shutting_down()
+    }
+
+    pub fn set_paused(&self, is_paused: bool) {
+        self.is_paused.store(is_paused, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.is_draining.load(Ordering::SeqCst) && !self.is_shutting_down()
+    }
+
+    pub fn set_draining(&self, is_draining: bool) {
+        self.is_draining.store(is_draining, Ordering::SeqCst);
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.is_terminated.load(Ordering::SeqCst)
+    }
+
+    pub fn set_terminated(&self) {
+        self.is_terminated.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.is_shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn set_shutting_down(&self) {
+        self.is_shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn get_outputs(&self) -> Option<&Arc<StepOutputs>> {
+        self.outputs.get()
+    }
+
+    pub fn set_outputs(&self, outputs: Arc<StepOutputs>) -> Result<(), String> {
+        match self.outputs.set(outputs) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("Outputs of step '{}' are already set", self.handle)),
+        }
+    }
+}
+
+/// Registers the contexts of steps in static context
+pub fn register_step_contexts(contexts: HashMap<ModuleHandle, Arc<StepContext>>) -> Result<(), String> {
+    match STEP_CONTEXTS.set(contexts) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Failed to register the step contexts in static context")),
+    }
+}
+
+/// Marks all steps as shutting down, so paused and drained steps finish. Does nothing if steps are not configured yet
+pub fn set_steps_shutting_down() {
+    for context in STEP_CONTEXTS.get().iter().flat_map(|c| c.values()) {
+        context.set_shutting_down();
+    }
+}
+
+/// Returns the context of step. None if the handle doesn't belong to a step or steps are not configured yet
+pub fn get_step_context(handle: ModuleHandle) -> Option<&'static Arc<StepContext>> {
+    STEP_CONTEXTS.get().and_then(|c| c.get(&handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StepContext;
+    use crate::pipeline::handle::ModuleHandle;
+
+    #[test]
+    fn shutdown_overrides_paused_and_drained_state() {
+        let context = StepContext::new(ModuleHandle::try_from(0).unwrap());
+        context.set_paused(true);
+        context.set_draining(true);
+        assert!(context.is_paused() && context.is_draining());
+        context.set_shutting_down();
+        assert!(!context.is_paused() && !context.is_draining());
+        // Steps cannot be paused again during termination
+        context.set_paused(true);
+        assert!(!context.is_paused());
+    }
+}
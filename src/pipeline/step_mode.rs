@@ -0,0 +1,101 @@
+/// Step-through mode for debugging of pipeline logic on small inputs.
+/// Each record arriving to a step is held at the input of step until the operator advances it in interactive console,
+/// see `repl`. A preview of each held record is printed, so the operator can follow the records from step to step.
+/// Records of side steps (shadow chain, sampling) are not held. All records are released once the pipeline is shut down
+
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use torustiq_common::ffi::types::module::Record;
+
+use crate::{
+    masking::{mask_args, mask_text},
+    pipeline::handle::ModuleHandle,
+    records::{get_metadata, get_payload},
+};
+
+/// A number of payload bytes in preview of record
+const PAYLOAD_PREVIEW_BYTES: usize = 256;
+
+/// Held records wake up periodically in case the notification is missed
+const WAIT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct StepModeState {
+    /// If false, records are not held
+    is_released: bool,
+    /// A number of records which are allowed to pass
+    credits: u64,
+    /// Steps which hold a record, and sequence numbers of held records
+    held: BTreeMap<ModuleHandle, u64>,
+}
+
+/// State of step-through mode
+#[derive(Default)]
+pub struct StepMode {
+    state: Mutex<StepModeState>,
+    advanced: Condvar,
+}
+
+impl StepMode {
+    pub fn new() -> StepMode {
+        StepMode::default()
+    }
+
+    /// Prints a preview of record and blocks until the record is advanced by operator
+    pub fn hold(&self, handle: ModuleHandle, step_id: &str, sequence: u64, record: &Record) {
+        let mut state = self.state.lock().unwrap();
+        if state.is_released {
+            return
+        }
+        println!("{}", mask_text(&format!("[step-mode] {} <- record #{}: {}", step_id, sequence, format_preview(record))));
+        state.held.insert(handle, sequence);
+        while !state.is_released && state.credits == 0 {
+            state = self.advanced.wait_timeout(state, WAIT_INTERVAL).unwrap().0;
+        }
+        if !state.is_released {
+            state.credits -= 1;
+        }
+        state.held.remove(&handle);
+    }
+
+    /// Lets the next records pass. Returns the number of records which are held now
+    pub fn advance(&self, count: u64) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.credits += count;
+        self.advanced.notify_all();
+        state.held.len()
+    }
+
+    /// Lets all records pass without holding
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.is_released = true;
+        state.credits = 0;
+        self.advanced.notify_all();
+    }
+
+    /// Starts holding the records again
+    pub fn resume_holding(&self) {
+        self.state.lock().unwrap().is_released = false;
+    }
+
+    /// Returns the steps which hold a record, and sequence numbers of held records
+    pub fn get_held(&self) -> BTreeMap<ModuleHandle, u64> {
+        self.state.lock().unwrap().held.clone()
+    }
+}
+
+/// Returns a preview of payload and metadata of record. Secret metadata values are masked
+fn format_preview(record: &Record) -> String {
+    let payload = get_payload(record);
+    let preview = String::from_utf8_lossy(&payload[..payload.len().min(PAYLOAD_PREVIEW_BYTES)]);
+    let truncated = match payload.len() > PAYLOAD_PREVIEW_BYTES {
+        true => format!(" (truncated, {} bytes total)", payload.len()),
+        false => String::new(),
+    };
+    format!("payload: {:?}{}, metadata: {:?}", preview, truncated, mask_args(&get_metadata(record)))
+}
@@ -0,0 +1,111 @@
+/// Execution budget of pipeline and steps.
+/// Once the pipeline exceeds its maximum runtime, it's shut down gracefully. If the pipeline is still running
+/// after the grace period, the application is terminated forcefully. Steps which exceed their maximum runtime
+/// are shut down individually. In both cases the application exits with a dedicated exit code
+
+use std::{
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, warn};
+
+use crate::{config::NotificationEvent, notifications::notify, xthread::PIPELINE};
+
+/// Exit code if runtime budget is exceeded and the pipeline is shut down gracefully
+pub const EXIT_CODE_RUNTIME_EXCEEDED: i32 = 3;
+/// Exit code if the pipeline is not terminated within the grace period after the runtime budget is exceeded
+pub const EXIT_CODE_RUNTIME_EXCEEDED_FORCED: i32 = 4;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Step index and maximum runtime of step
+type StepBudget = (usize, Duration);
+
+static IS_RUNTIME_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the pipeline or any step exceeded the maximum runtime
+pub fn is_runtime_exceeded() -> bool {
+    IS_RUNTIME_EXCEEDED.load(Ordering::SeqCst)
+}
+
+/// Starts a thread which watches the runtime of pipeline and steps.
+/// Does nothing if neither pipeline nor steps have maximum runtime
+pub fn start_watchdog() -> Result<(), String> {
+    let pipeline_arc = match PIPELINE.get() {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let (pipeline_name, notifications, max_runtime, grace_period, step_budgets) = {
+        let pipeline = pipeline_arc.lock().unwrap();
+        let step_budgets: Vec<StepBudget> = pipeline.steps.iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.lock().unwrap().max_runtime.map(|r| (i, r)))
+            .collect();
+        (pipeline.name.clone(), pipeline.notifications.clone(), pipeline.max_runtime, pipeline.shutdown_grace_period, step_budgets)
+    };
+    if max_runtime.is_none() && step_budgets.is_empty() {
+        return Ok(())
+    }
+
+    let thread_name = format!("{}-watchdog", &pipeline_name);
+    let result = thread::Builder::new().name(thread_name.clone()).spawn(move || {
+        let started_at = Instant::now();
+        let mut pending_step_budgets = step_budgets;
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+            let elapsed = started_at.elapsed();
+            if !pipeline_arc.lock().unwrap().is_running() {
+                debug!("Watchdog is stopped: pipeline is terminated");
+                return
+            }
+
+            // Steps
+            let (exceeded, pending): (Vec<StepBudget>, Vec<StepBudget>) = pending_step_budgets
+                .into_iter()
+                .partition(|(_, budget)| elapsed >= *budget);
+            pending_step_budgets = pending;
+            for (step_index, budget) in exceeded {
+                let step_arc = pipeline_arc.lock().unwrap().steps[step_index].clone();
+                let step = step_arc.lock().unwrap();
+                if step.component.is_terminated() {
+                    continue
+                }
+                let message = format!("Step '{}' exceeded the maximum runtime of {} ms and is shut down",
+                    step.get_id(), budget.as_millis());
+                warn!("{}", message);
+                IS_RUNTIME_EXCEEDED.store(true, Ordering::SeqCst);
+                step.shutdown();
+                drop(step);
+                notify(&notifications, NotificationEvent::RuntimeExceeded, &pipeline_name, &message);
+            }
+
+            // Pipeline
+            let max_runtime = match max_runtime {
+                Some(r) => r,
+                None => continue,
+            };
+            if elapsed < max_runtime {
+                continue
+            }
+            warn!("Pipeline exceeded the maximum runtime of {} ms. Shutting down gracefully...", max_runtime.as_millis());
+            IS_RUNTIME_EXCEEDED.store(true, Ordering::SeqCst);
+            pipeline_arc.lock().unwrap().trigger_termination();
+            notify(&notifications, NotificationEvent::RuntimeExceeded, &pipeline_name,
+                &format!("Pipeline exceeded the maximum runtime of {} ms", max_runtime.as_millis()));
+            thread::sleep(grace_period);
+            if pipeline_arc.lock().unwrap().is_running() {
+                error!("Pipeline is not terminated within the grace period of {} ms. Shutting down forcefully",
+                    grace_period.as_millis());
+                exit(EXIT_CODE_RUNTIME_EXCEEDED_FORCED);
+            }
+            return
+        }
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to start thread '{}': {}", thread_name, e)),
+    }
+}
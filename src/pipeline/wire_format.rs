@@ -0,0 +1,126 @@
+/// Wire formats of payload on edges between steps.
+/// Steps declare the formats of accepted and produced payload. If the step receives a format which differs
+/// from the declared one, the host inserts a built-in conversion step before it, e.g. `builtin.jsonl_parse`
+/// to convert JSON lines into JSON records. If no conversion is known, the pipeline is not created
+
+use std::collections::HashMap;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    config::ModuleDefinition,
+    modules::builtin::{csv_parse, jsonl_parse},
+    pipeline::routing::Topology,
+};
+
+/// Known conversions: source format, target format, module ID of conversion step
+const CONVERSIONS: &[(&str, &str, &str)] = &[
+    ("jsonl", "json", jsonl_parse::MODULE_ID),
+    ("csv", "json", csv_parse::MODULE_ID),
+];
+
+/// A prefix of names of inserted conversion steps. The name of receiving step follows the prefix
+const CONVERSION_STEP_PREFIX: &str = "torustiq.convert.";
+
+/// A conversion step which is inserted by host
+#[derive(Clone, Debug)]
+pub struct Conversion {
+    pub step_name: String,
+    /// A name of step which receives the converted records
+    pub receiver: String,
+    pub from: String,
+    pub to: String,
+    pub module_id: String,
+}
+
+impl Conversion {
+    pub fn format(&self) -> String {
+        format!("'{}': {} -> {} by {}, before step '{}'", self.step_name, self.from, self.to, self.module_id, self.receiver)
+    }
+}
+
+/// Returns the steps with inserted conversion steps.
+/// The class routes and dead-letter targets which name receiving steps are redirected to conversion steps.
+/// Retry targets are not redirected, as retried records are already converted
+pub fn insert_conversion_steps(steps: &[ModuleDefinition]) -> Result<(Vec<ModuleDefinition>, Vec<Conversion>), String> {
+    let topology = get_topology(steps);
+    let mut result: Vec<ModuleDefinition> = Vec::new();
+    let mut conversions: Vec<Conversion> = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        let input_format = match (&step.input_format, i) {
+            (Some(f), i) if i > 0 => f,
+            _ => {
+                result.push(step.clone());
+                continue
+            },
+        };
+        let senders: Vec<&ModuleDefinition> = topology.get_inputs(i).into_iter().map(|s| &steps[s]).collect();
+        let from = match senders.iter().filter_map(|s| s.output_format.as_ref()).find(|f| *f != input_format) {
+            Some(f) => f.clone(),
+            None => {
+                result.push(step.clone());
+                continue
+            },
+        };
+        // A conversion step converts all records of receiver, so all senders must produce the same format
+        if let Some(s) = senders.iter().find(|s| s.output_format.as_ref() != Some(&from)) {
+            return Err(format!("Step '{}' receives records of format '{}' and records of format '{}' from step '{}'. \
+                Conversion step cannot be inserted", step.name, from, s.output_format.as_deref().unwrap_or("(not declared)"), s.name))
+        }
+        let module_id = match CONVERSIONS.iter().find(|(f, t, _)| *f == from && t == input_format) {
+            Some((_, _, m)) => m.to_string(),
+            None => return Err(format!("Wire format mismatch: step '{}' expects format '{}', but receives format '{}'. \
+                No conversion is known", step.name, input_format, from)),
+        };
+        let conversion = Conversion {
+            step_name: format!("{}{}", CONVERSION_STEP_PREFIX, step.name),
+            receiver: step.name.clone(),
+            from: from.clone(),
+            to: input_format.clone(),
+            module_id,
+        };
+        result.push(create_conversion_step(&conversion)?);
+        result.push(step.clone());
+        conversions.push(conversion);
+    }
+    for conversion in &conversions {
+        for step in result.iter_mut() {
+            let dead_letter = step.retry.as_mut().and_then(|r| r.dead_letter.as_mut());
+            for target in step.outputs.iter_mut().flat_map(|o| o.values_mut()).chain(dead_letter) {
+                if *target == conversion.receiver {
+                    target.clone_from(&conversion.step_name);
+                }
+            }
+        }
+    }
+    Ok((result, conversions))
+}
+
+/// Returns the topology of steps. Unknown steps in routes are ignored here, they are reported once the pipeline is created
+fn get_topology(steps: &[ModuleDefinition]) -> Topology {
+    let class_outputs: Vec<HashMap<String, usize>> = steps.iter()
+        .map(|s| s.outputs.iter()
+            .flatten()
+            .filter_map(|(class, target)| steps.iter().position(|d| &d.name == target).map(|t| (class.clone(), t)))
+            .collect())
+        .collect();
+    let dead_letter_outputs: Vec<Option<usize>> = steps.iter()
+        .map(|s| s.retry.as_ref()
+            .and_then(|r| r.dead_letter.as_ref())
+            .and_then(|target| steps.iter().position(|d| &d.name == target)))
+        .collect();
+    Topology::new(class_outputs, vec![Vec::new(); steps.len()], dead_letter_outputs)
+}
+
+fn create_conversion_step(conversion: &Conversion) -> Result<ModuleDefinition, String> {
+    let definition = Mapping::from_iter([
+        (Value::from("name"), Value::from(conversion.step_name.as_str())),
+        (Value::from("handler"), Value::from(conversion.module_id.as_str())),
+        (Value::from("input_format"), Value::from(conversion.from.as_str())),
+        (Value::from("output_format"), Value::from(conversion.to.as_str())),
+    ]);
+    match serde_yaml::from_value(Value::Mapping(definition)) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Cannot create a definition of conversion step: {}", e)),
+    }
+}
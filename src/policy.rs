@@ -0,0 +1,84 @@
+/// Module policy restricts which modules may be loaded and which positions they may occupy in pipeline.
+/// Organizations which vet the modules centrally can distribute a policy file along with the approved libraries.
+
+use std::{collections::HashMap, fs};
+
+use serde::{Deserialize, Serialize};
+
+use torustiq_common::ffi::types::module::PipelineModuleKind;
+
+/// A position of module in pipeline
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ModulePosition {
+    Source,
+    Transformation,
+    Destination,
+    Listener,
+}
+
+impl From<&PipelineModuleKind> for ModulePosition {
+    fn from(value: &PipelineModuleKind) -> ModulePosition {
+        match value {
+            PipelineModuleKind::Source => ModulePosition::Source,
+            PipelineModuleKind::Transformation => ModulePosition::Transformation,
+            PipelineModuleKind::Destination => ModulePosition::Destination,
+        }
+    }
+}
+
+/// Restrictions for a single module
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ModuleRule {
+    /// Positions the module may occupy. If not set, any position is allowed
+    pub positions: Option<Vec<ModulePosition>>,
+}
+
+/// A module allowlist. Modules which are not listed here cannot be used in pipeline
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ModulePolicy {
+    /// Allowed modules. The key is a module ID
+    pub modules: HashMap<String, ModuleRule>,
+}
+
+impl ModulePolicy {
+    /// Reads the policy from YAML file
+    pub fn from_file(path: &String) -> Result<ModulePolicy, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Cannot open the policy file: '{}'. {}", path, e)),
+        };
+        match serde_yaml::from_str(contents.as_str()) {
+            Ok(p) => Ok(p),
+            Err(e) => Err(format!("Cannot parse the policy file: '{}'. {}", path, e)),
+        }
+    }
+
+    /// Checks if all provided modules are allowed to be loaded
+    pub fn check_module_ids(&self, module_ids: &[String]) -> Result<(), String> {
+        let mut denied_ids: Vec<String> = module_ids
+            .iter()
+            .filter(|id| !self.modules.contains_key(*id))
+            .cloned()
+            .collect();
+        if denied_ids.is_empty() {
+            return Ok(())
+        }
+        denied_ids.sort();
+        Err(format!("Modules are not allowed by policy: {}", denied_ids.join(", ")))
+    }
+
+    /// Checks if module is allowed to occupy the provided position in pipeline
+    pub fn check_position(&self, module_id: &String, position: ModulePosition) -> Result<(), String> {
+        let rule = match self.modules.get(module_id) {
+            Some(r) => r,
+            None => return Err(format!("Module '{}' is not allowed by policy", module_id)),
+        };
+        match &rule.positions {
+            Some(positions) if !positions.contains(&position) =>
+                Err(format!("Module '{}' is not allowed by policy to be used as {:?}. Allowed positions: {:?}",
+                    module_id, position, positions)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+/// Environment profiles of pipeline, so one pipeline file serves development, staging and production.
+/// Profiles are declared in `profiles` section and override the arguments of steps and listeners by module name.
+/// The profile is selected at launch with `--profile` option. Its arguments are merged over the base arguments
+/// before the parameters are substituted, so overrides might reference parameters as well.
+/// The effective overrides are logged as a diff against the base arguments. Values of secret arguments are masked
+
+use std::collections::{BTreeMap, HashMap};
+
+use log::info;
+use serde_yaml::Value;
+
+use crate::{
+    config::{ModuleDefinition, PipelineDefinition},
+    masking::{is_masked_key, matches_pattern, MASK},
+};
+
+/// Merges the arguments of selected profile over the base arguments of steps and listeners.
+/// Does nothing if no profile is selected
+pub fn apply_profile(definition: &mut PipelineDefinition, profile: Option<&String>) -> Result<(), String> {
+    let profile_name = match profile {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    let profile = match definition.profiles.as_ref().and_then(|p| p.get(profile_name)) {
+        Some(p) => p.clone(),
+        None => {
+            let mut declared: Vec<&String> = definition.profiles.iter().flat_map(|p| p.keys()).collect();
+            declared.sort();
+            return Err(match declared.is_empty() {
+                true => format!("Profile '{}' is selected, but the pipeline declares no profiles", profile_name),
+                false => format!("Profile '{}' is not declared. Declared profiles: {}", profile_name,
+                    declared.into_iter().cloned().collect::<Vec<String>>().join(", ")),
+            })
+        },
+    };
+    let masked_key_patterns = definition.masked_keys.clone().unwrap_or_default();
+    let is_secret = |key: &str| is_masked_key(key) || masked_key_patterns.iter().any(|p| matches_pattern(p, key));
+
+    let mut modules: HashMap<String, &mut ModuleDefinition> = HashMap::new();
+    let shadow_steps = definition.shadow.iter_mut().flat_map(|s| s.steps.iter_mut());
+    let sampling_destination = definition.sampling.iter_mut().filter_map(|s| s.destination.as_mut());
+    let listeners = definition.listeners.iter_mut().flatten();
+    for module in definition.steps.iter_mut().chain(shadow_steps).chain(sampling_destination).chain(listeners) {
+        modules.insert(module.name.clone(), module);
+    }
+
+    let mut diff: Vec<String> = Vec::new();
+    let overrides: BTreeMap<&String, &HashMap<String, Value>> = profile.args.iter().collect();
+    for (module_name, args) in overrides {
+        let module = match modules.get_mut(module_name) {
+            Some(m) => m,
+            None => return Err(format!("Profile '{}' overrides arguments of unknown step or listener '{}'", profile_name, module_name)),
+        };
+        let base_args = module.args.get_or_insert(HashMap::new());
+        let args: BTreeMap<&String, &Value> = args.iter().collect();
+        for (key, value) in args {
+            let old_value = match value {
+                Value::Null => base_args.remove(key),
+                v => base_args.insert(key.clone(), v.clone()),
+            };
+            if old_value.as_ref() == Some(value) || (old_value.is_none() && value.is_null()) {
+                continue
+            }
+            let format = |v: Option<&Value>| match (v, is_secret(key)) {
+                (None, _) | (Some(Value::Null), _) => String::from("(not set)"),
+                (Some(_), true) => String::from(MASK),
+                (Some(v), false) => format_value(v),
+            };
+            diff.push(format!("  {}.{}: {} -> {}", module_name, key, format(old_value.as_ref()), format(Some(value))));
+        }
+    }
+    match diff.is_empty() {
+        true => info!("Profile '{}' is applied. No arguments are changed", profile_name),
+        false => info!("Profile '{}' is applied. Changed arguments:\n{}", profile_name, diff.join("\n")),
+    }
+    Ok(())
+}
+
+/// Formats the argument value for diff. Encrypted values are not decrypted
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s),
+        Value::Tagged(_) => String::from(MASK),
+        v => serde_json::to_string(v).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Value;
+
+    use super::apply_profile;
+    use crate::{config::PipelineDefinition, params::apply_params};
+
+    const PIPELINE: &str = r#"
+params:
+  prod_topic:
+    default: orders
+profiles:
+  prod:
+    args:
+      src:
+        topic: "{{ params.prod_topic }}"
+        debug: ~
+        options:
+          retries: 5
+      audit:
+        url: https://audit.example.com
+  empty:
+    args: {}
+steps:
+  - name: src
+    handler: kafka_source
+    args:
+      topic: test
+      debug: true
+      group: reader
+      options:
+        retries: 1
+        timeout_ms: 100
+  - name: dst
+    handler: stdout
+listeners:
+  - name: audit
+    handler: http_listener
+"#;
+
+    fn apply(profile: Option<&str>) -> Result<PipelineDefinition, String> {
+        let mut definition: PipelineDefinition = serde_yaml::from_str(PIPELINE).unwrap();
+        apply_profile(&mut definition, profile.map(String::from).as_ref())?;
+        Ok(definition)
+    }
+
+    fn get_arg(definition: &PipelineDefinition, key: &str) -> Option<Value> {
+        definition.steps[0].args.as_ref().unwrap().get(key).cloned()
+    }
+
+    #[test]
+    fn keeps_arguments_without_profile() {
+        let definition = apply(None).unwrap();
+        assert_eq!(get_arg(&definition, "topic"), Some(Value::from("test")));
+        assert_eq!(get_arg(&definition, "debug"), Some(Value::from(true)));
+        assert_eq!(apply(Some("empty")).unwrap().steps, definition.steps);
+    }
+
+    #[test]
+    fn overrides_and_removes_arguments() {
+        let definition = apply(Some("prod")).unwrap();
+        assert_eq!(get_arg(&definition, "debug"), None);
+        assert_eq!(get_arg(&definition, "group"), Some(Value::from("reader")));
+        let listener = &definition.listeners.as_ref().unwrap()[0];
+        assert_eq!(listener.args.as_ref().unwrap().get("url"), Some(&Value::from("https://audit.example.com")));
+    }
+
+    #[test]
+    fn replaces_nested_maps_as_a_whole() {
+        let definition = apply(Some("prod")).unwrap();
+        let options: Value = serde_yaml::from_str("{retries: 5}").unwrap();
+        assert_eq!(get_arg(&definition, "options"), Some(options));
+    }
+
+    #[test]
+    fn parameters_are_substituted_into_overrides() {
+        let mut definition = apply(Some("prod")).unwrap();
+        apply_params(&mut definition, &[String::from("prod_topic=payments")]).unwrap();
+        assert_eq!(get_arg(&definition, "topic"), Some(Value::from("payments")));
+    }
+
+    #[test]
+    fn fails_on_unknown_profile() {
+        assert_eq!(apply(Some("staging")).unwrap_err(), "Profile 'staging' is not declared. Declared profiles: empty, prod");
+        let mut definition: PipelineDefinition = serde_yaml::from_str("steps: []").unwrap();
+        assert_eq!(apply_profile(&mut definition, Some(&String::from("prod"))).unwrap_err(),
+            "Profile 'prod' is selected, but the pipeline declares no profiles");
+    }
+
+    #[test]
+    fn fails_on_override_of_unknown_step() {
+        let mut definition: PipelineDefinition = serde_yaml::from_str(PIPELINE).unwrap();
+        definition.profiles.as_mut().unwrap().get_mut("empty").unwrap().args.insert(String::from("sink"), Default::default());
+        assert_eq!(apply_profile(&mut definition, Some(&String::from("empty"))).unwrap_err(),
+            "Profile 'empty' overrides arguments of unknown step or listener 'sink'");
+    }
+}
@@ -0,0 +1,322 @@
+/// Profiling of host threads, so users can attach the profiles to performance issues.
+/// If `--profile-dir` is set, `profile [seconds]` command of interactive console captures the profiles
+/// over the time window and writes them to the directory:
+/// - `<pipeline>-<timestamp>.cpu.pb.gz`: CPU time of host threads in pprof format, e.g. for `go tool pprof`.
+///   The profile has thread granularity: each thread is a single frame named after the thread. Linux only
+/// - `<pipeline>-<timestamp>.alloc.json`: allocations made by host threads within the window
+///
+/// Allocations are counted only while the profile is captured. Allocations inside module libraries
+/// are not counted, as each library has its own allocator
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use serde_json::json;
+
+use crate::{config::sanitize_pipeline_name, events::publish, xthread::PIPELINE};
+
+/// A time window of profile if not set in command
+pub const DEFAULT_PROFILE_DURATION: Duration = Duration::from_secs(30);
+
+/// Maximum time window of profile
+pub const MAX_PROFILE_DURATION: Duration = Duration::from_secs(600);
+
+/// How often the CPU time of threads is read. Threads which exit between reads lose the time since the last read
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A directory of profiles. Set only if profiling is enabled
+static PROFILE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// True while a profile is captured
+static IS_CAPTURING: AtomicBool = AtomicBool::new(false);
+
+static IS_COUNTING_ALLOCATIONS: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static REALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static FREED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator which counts the allocations while a profile is captured
+struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() && IS_COUNTING_ALLOCATIONS.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() && IS_COUNTING_ALLOCATIONS.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        if IS_COUNTING_ALLOCATIONS.load(Ordering::Relaxed) {
+            DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            FREED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() && IS_COUNTING_ALLOCATIONS.load(Ordering::Relaxed) {
+            REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+            FREED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Enables profiling. Profiles are written to the directory, which is created if missing
+pub fn init_profiling(dir: &str) -> Result<(), String> {
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(format!("Cannot create the profile directory '{}': {}", dir, e))
+    }
+    let _ = PROFILE_DIR.set(PathBuf::from(dir));
+    info!("Profiling is enabled. Type 'profile [seconds]' to capture the CPU and allocation profiles into '{}'", dir);
+    Ok(())
+}
+
+pub fn is_profiling_enabled() -> bool {
+    PROFILE_DIR.get().is_some()
+}
+
+/// Starts capturing the profiles in background. Returns the path prefix of profile files
+pub fn start_profile(duration: Duration) -> Result<String, String> {
+    let dir = match PROFILE_DIR.get() {
+        Some(d) => d.clone(),
+        None => return Err(String::from("Profiling is not enabled. Please start the application with '--profile-dir'")),
+    };
+    if IS_CAPTURING.swap(true, Ordering::SeqCst) {
+        return Err(String::from("A profile is already being captured"))
+    }
+    let pipeline_name = PIPELINE.get().map(|p| p.lock().unwrap().name.clone()).unwrap_or_default();
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let prefix = dir.join(format!("{}-{}", sanitize_pipeline_name(&pipeline_name), started_at.as_secs()));
+    let prefix_str = prefix.to_string_lossy().to_string();
+    let result = thread::Builder::new().name(String::from("profiler")).spawn(move || {
+        match capture(&prefix, &pipeline_name, started_at, duration) {
+            Ok(files) => {
+                info!("Profiles are written: {}", files.join(", "));
+                publish("profile_completed", json!({"files": files}));
+            },
+            Err(msg) => error!("Failed to capture the profiles: {}", msg),
+        }
+        IS_CAPTURING.store(false, Ordering::SeqCst);
+    });
+    if let Err(e) = result {
+        IS_CAPTURING.store(false, Ordering::SeqCst);
+        return Err(format!("Failed to start the profiler thread: {}", e))
+    }
+    Ok(prefix_str)
+}
+
+/// Captures the profiles and writes them to files. Returns the paths of written files
+fn capture(prefix: &Path, pipeline_name: &str, started_at: Duration, duration: Duration) -> Result<Vec<String>, String> {
+    let cpu_baseline = read_thread_cpu_ticks();
+    for counter in [&ALLOCATIONS, &DEALLOCATIONS, &REALLOCATIONS, &ALLOCATED_BYTES, &FREED_BYTES] {
+        counter.store(0, Ordering::Relaxed);
+    }
+    IS_COUNTING_ALLOCATIONS.store(true, Ordering::Relaxed);
+    let window_started_at = Instant::now();
+    // The last known CPU time of each thread, including the threads started within the window
+    let mut cpu_last: HashMap<u64, (String, u64)> = cpu_baseline.clone().unwrap_or_default();
+    while window_started_at.elapsed() < duration {
+        thread::sleep(CPU_SAMPLE_INTERVAL.min(duration.saturating_sub(window_started_at.elapsed())));
+        if let Ok(ticks) = read_thread_cpu_ticks() {
+            cpu_last.extend(ticks);
+        }
+    }
+    IS_COUNTING_ALLOCATIONS.store(false, Ordering::Relaxed);
+    let elapsed = window_started_at.elapsed();
+
+    let mut files: Vec<String> = Vec::new();
+    match cpu_baseline {
+        Ok(baseline) => {
+            let threads: Vec<(u64, String, u64)> = cpu_last.into_iter()
+                .map(|(tid, (name, ticks))| (tid, name, ticks.saturating_sub(baseline.get(&tid).map(|b| b.1).unwrap_or(0))))
+                .filter(|(_, _, ticks)| *ticks > 0)
+                .collect();
+            let path = with_suffix(prefix, "cpu.pb.gz");
+            write_gzip(&path, &encode_cpu_profile(&threads, started_at, elapsed))?;
+            files.push(path.to_string_lossy().to_string());
+        },
+        Err(msg) => error!("CPU profile is skipped: {}", msg),
+    }
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let freed_bytes = FREED_BYTES.load(Ordering::Relaxed);
+    let seconds = elapsed.as_secs_f64();
+    let stats = json!({
+        "pipeline": pipeline_name,
+        "started_at": humantime::format_rfc3339_millis(UNIX_EPOCH + started_at).to_string(),
+        "duration_seconds": seconds,
+        "allocations": allocations,
+        "deallocations": DEALLOCATIONS.load(Ordering::Relaxed),
+        "reallocations": REALLOCATIONS.load(Ordering::Relaxed),
+        "allocated_bytes": allocated_bytes,
+        "freed_bytes": freed_bytes,
+        "net_allocated_bytes": allocated_bytes as i64 - freed_bytes as i64,
+        "allocations_per_second": allocations as f64 / seconds,
+        "allocated_bytes_per_second": allocated_bytes as f64 / seconds,
+    });
+    let path = with_suffix(prefix, "alloc.json");
+    let contents = serde_json::to_string_pretty(&stats).map_err(|e| format!("Cannot serialize the allocation statistics: {}", e))?;
+    fs::write(&path, contents).map_err(|e| format!("Cannot write file '{}': {}", path.display(), e))?;
+    files.push(path.to_string_lossy().to_string());
+    Ok(files)
+}
+
+fn with_suffix(prefix: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", prefix.display(), suffix))
+}
+
+fn write_gzip(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let file = fs::File::create(path).map_err(|e| format!("Cannot create file '{}': {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(contents)
+        .and_then(|_| encoder.finish().map(|_| ()))
+        .map_err(|e| format!("Cannot write file '{}': {}", path.display(), e))
+}
+
+/// Returns the names and consumed CPU time of threads of current process, by thread ID. CPU time is in clock ticks
+#[cfg(target_os = "linux")]
+fn read_thread_cpu_ticks() -> Result<HashMap<u64, (String, u64)>, String> {
+    let entries = fs::read_dir("/proc/self/task").map_err(|e| format!("Cannot read the threads of process: {}", e))?;
+    let mut result: HashMap<u64, (String, u64)> = HashMap::new();
+    for entry in entries.flatten() {
+        let tid: u64 = match entry.file_name().to_string_lossy().parse() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        // The thread might exit at any moment
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        // Format: `tid (name) state ...`. The name might contain spaces and parentheses
+        let (name, fields) = match (stat.find('('), stat.rfind(')')) {
+            (Some(start), Some(end)) if start < end => (&stat[start + 1..end], &stat[end + 1..]),
+            _ => continue,
+        };
+        // `utime` and `stime` are fields 14 and 15 of stat, while the fields after name start from field 3
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let ticks = match (fields.get(11).and_then(|f| f.parse::<u64>().ok()), fields.get(12).and_then(|f| f.parse::<u64>().ok())) {
+            (Some(utime), Some(stime)) => utime + stime,
+            _ => continue,
+        };
+        result.insert(tid, (name.to_string(), ticks));
+    }
+    Ok(result)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_thread_cpu_ticks() -> Result<HashMap<u64, (String, u64)>, String> {
+    Err(String::from("CPU profiling is supported on Linux only"))
+}
+
+/// Returns the duration of clock tick in nanoseconds
+fn get_tick_ns() -> i64 {
+    #[cfg(target_os = "linux")]
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    #[cfg(not(target_os = "linux"))]
+    let ticks_per_second = 100;
+    match ticks_per_second > 0 {
+        true => 1_000_000_000 / ticks_per_second as i64,
+        false => 10_000_000,
+    }
+}
+
+/// Encodes the CPU time of threads as pprof profile, see `profile.proto` of pprof project.
+/// Each thread is a sample with a single location, whose function is named after the thread
+fn encode_cpu_profile(threads: &[(u64, String, u64)], started_at: Duration, elapsed: Duration) -> Vec<u8> {
+    let tick_ns = get_tick_ns();
+    let mut strings: Vec<String> = vec![String::new(), String::from("cpu"), String::from("nanoseconds"), String::from("thread_id")];
+    let mut profile = ProtoWriter::default();
+    let value_type = ProtoWriter::default().int(1, 1).int(2, 2);
+    profile = profile.message(1, &value_type);
+    for (i, (tid, name, ticks)) in threads.iter().enumerate() {
+        let id = i as u64 + 1;
+        let label = ProtoWriter::default().int(1, 3).int(3, *tid as i64);
+        let sample = ProtoWriter::default()
+            .packed(1, &[id])
+            .packed(2, &[(*ticks as i64 * tick_ns) as u64])
+            .message(3, &label);
+        profile = profile.message(2, &sample);
+        strings.push(name.clone());
+        let name_index = strings.len() as i64 - 1;
+        let line = ProtoWriter::default().int(1, id as i64);
+        let location = ProtoWriter::default().int(1, id as i64).message(4, &line);
+        let function = ProtoWriter::default().int(1, id as i64).int(2, name_index).int(3, name_index);
+        profile = profile.message(4, &location).message(5, &function);
+    }
+    for s in &strings {
+        profile = profile.bytes(6, s.as_bytes());
+    }
+    profile
+        .int(9, started_at.as_nanos() as i64)
+        .int(10, elapsed.as_nanos() as i64)
+        .message(11, &value_type)
+        .int(12, tick_ns)
+        .0
+}
+
+/// A minimal writer of protocol buffers
+#[derive(Default)]
+struct ProtoWriter(Vec<u8>);
+
+impl ProtoWriter {
+    fn varint(mut self, mut value: u64) -> ProtoWriter {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+        self
+    }
+
+    fn int(self, field: u64, value: i64) -> ProtoWriter {
+        self.varint(field << 3).varint(value as u64)
+    }
+
+    fn bytes(self, field: u64, value: &[u8]) -> ProtoWriter {
+        let mut writer = self.varint(field << 3 | 2).varint(value.len() as u64);
+        writer.0.extend_from_slice(value);
+        writer
+    }
+
+    fn message(self, field: u64, message: &ProtoWriter) -> ProtoWriter {
+        self.bytes(field, &message.0)
+    }
+
+    fn packed(self, field: u64, values: &[u64]) -> ProtoWriter {
+        let payload = values.iter().fold(ProtoWriter::default(), |w, v| w.varint(*v));
+        self.message(field, &payload)
+    }
+}
@@ -0,0 +1,140 @@
+/// Host-side utilities for records.
+/// The host application creates records in built-in modules and reads their contents for diagnostics
+
+use std::{
+    collections::HashMap, slice, time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use torustiq_common::ffi::types::module::Record;
+
+/// A metadata key which contains the provenance chain of record.
+/// The chain is a list of entries separated by `;`. Each entry is `<step ID>,<UNIX timestamp in ms>,<outcome>`
+pub const PROVENANCE_METADATA_KEY: &str = "torustiq.provenance";
+
+/// A metadata key which contains the remaining processing budget of record in milliseconds.
+/// Set by sources; the host updates the value before each step, so modules can bound their own I/O timeouts
+pub const DEADLINE_METADATA_KEY: &str = "deadline_ms";
+
+/// A metadata key which contains the deadline of record as UNIX timestamp in ms.
+/// Set by host once the record with processing budget is produced
+pub const DEADLINE_AT_METADATA_KEY: &str = "torustiq.deadline_at_ms";
+
+/// A metadata key which contains the time when record left the source step, UNIX timestamp in ms.
+/// Set by host if latency tracking is enabled in pipeline
+pub const ORIGIN_TIMESTAMP_METADATA_KEY: &str = "torustiq.origin_ts_ms";
+
+/// A metadata key which contains the verdicts of listeners on record.
+/// Verdicts are separated by `;`. Each verdict is `<listener ID>=<verdict>`
+pub const VERDICTS_METADATA_KEY: &str = "torustiq.verdicts";
+
+/// Returns the record payload
+pub fn get_payload(record: &Record) -> &[u8] {
+    if record.content.len == 0 || record.content.bytes.is_null() {
+        return &[]
+    }
+    unsafe { slice::from_raw_parts(record.content.bytes, record.content.len) }
+}
+
+/// Returns a copy of record metadata
+pub fn get_metadata(record: &Record) -> HashMap<String, String> {
+    record.get_metadata_as_hashmap()
+}
+
+/// Creates a new record.
+/// The record is allocated by host, so it must be released with `Record::free_contents`
+pub fn create_record(payload: Vec<u8>, metadata: HashMap<String, String>) -> Record {
+    Record::from_std(payload, metadata)
+}
+
+/// Returns the current UNIX timestamp in ms
+fn now_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+/// Replaces the record with a new one which has provided metadata.
+/// As records are immutable, a new record is created and the original one is released
+fn replace_metadata(mut record: Record, metadata: HashMap<String, String>) -> Record {
+    let new_record = create_record(get_payload(&record).to_vec(), metadata);
+    record.free_contents();
+    new_record
+}
+
+/// Sets the metadata value of record.
+/// As records are immutable, a new record is created and the original one is released
+pub fn set_metadata_value(record: Record, key: &str, value: &str) -> Record {
+    let mut metadata = get_metadata(&record);
+    metadata.insert(key.to_string(), value.to_string());
+    replace_metadata(record, metadata)
+}
+
+/// Sets the deadline of record if the record has a processing budget, but no deadline yet
+pub fn stamp_deadline(record: Record) -> Record {
+    let mut metadata = get_metadata(&record);
+    if metadata.contains_key(DEADLINE_AT_METADATA_KEY) {
+        return record
+    }
+    let budget: u128 = match metadata.get(DEADLINE_METADATA_KEY).and_then(|b| b.parse().ok()) {
+        Some(b) => b,
+        None => return record,
+    };
+    metadata.insert(String::from(DEADLINE_AT_METADATA_KEY), (now_ms() + budget).to_string());
+    replace_metadata(record, metadata)
+}
+
+/// Updates the remaining processing budget of record.
+/// Returns the record and a flag which is true if the deadline is expired
+pub fn update_deadline(record: Record) -> (Record, bool) {
+    let mut metadata = get_metadata(&record);
+    let deadline_at: u128 = match metadata.get(DEADLINE_AT_METADATA_KEY).and_then(|d| d.parse().ok()) {
+        Some(d) => d,
+        None => return (record, false),
+    };
+    let now = now_ms();
+    if now >= deadline_at {
+        return (record, true)
+    }
+    metadata.insert(String::from(DEADLINE_METADATA_KEY), (deadline_at - now).to_string());
+    (replace_metadata(record, metadata), false)
+}
+
+/// Sets the origin timestamp of record if the record doesn't have it yet
+pub fn stamp_origin_timestamp(record: Record) -> Record {
+    let mut metadata = get_metadata(&record);
+    if metadata.contains_key(ORIGIN_TIMESTAMP_METADATA_KEY) {
+        return record
+    }
+    metadata.insert(String::from(ORIGIN_TIMESTAMP_METADATA_KEY), now_ms().to_string());
+    replace_metadata(record, metadata)
+}
+
+/// Returns the time elapsed since the record left the source step
+pub fn get_time_since_origin(record: &Record) -> Option<Duration> {
+    let origin: u128 = get_metadata(record).get(ORIGIN_TIMESTAMP_METADATA_KEY).and_then(|t| t.parse().ok())?;
+    Some(Duration::from_millis(now_ms().saturating_sub(origin) as u64))
+}
+
+/// Appends a provenance entry to the record metadata.
+/// As records are immutable, a new record is created and the original one is released
+pub fn append_provenance(record: Record, step_id: &str, outcome: &str) -> Record {
+    let entry = format!("{},{},{}", step_id, now_ms(), outcome);
+    let mut metadata = get_metadata(&record);
+    let chain = match metadata.remove(PROVENANCE_METADATA_KEY) {
+        Some(c) if !c.is_empty() => format!("{};{}", c, entry),
+        _ => entry,
+    };
+    metadata.insert(String::from(PROVENANCE_METADATA_KEY), chain);
+    replace_metadata(record, metadata)
+}
+
+/// Appends the verdicts of listeners to the record metadata.
+/// As records are immutable, a new record is created and the original one is released
+pub fn append_verdicts(record: Record, verdicts: &[(String, String)]) -> Record {
+    let mut metadata = get_metadata(&record);
+    let mut entries: Vec<String> = match metadata.remove(VERDICTS_METADATA_KEY) {
+        Some(v) if !v.is_empty() => vec![v],
+        _ => Vec::new(),
+    };
+    entries.extend(verdicts.iter().map(|(listener_id, verdict)| format!("{}={}", listener_id, verdict)));
+    metadata.insert(String::from(VERDICTS_METADATA_KEY), entries.join(";"));
+    replace_metadata(record, metadata)
+}
@@ -0,0 +1,143 @@
+/// Restart of pipeline on changes of pipeline file.
+/// The directory of pipeline file is watched rather than the file itself, because the mounted Kubernetes ConfigMaps
+/// are updated by replacing a symlink. Once the file content is changed and the new definition is valid,
+/// the pipeline is shut down gracefully and the application is restarted with the same arguments.
+/// The definition is parsed and validated in the running process. The full check, which loads the modules
+/// and constructs the pipeline, runs in a child process with `validate` command, as it changes the static context.
+
+use std::{
+    fs, path::Path, process::Command, sync::{atomic::{AtomicBool, Ordering}, mpsc}, thread, time::Duration
+};
+
+use log::{debug, error, info, warn};
+use notify::{RecursiveMode, Watcher};
+
+use crate::{
+    cli::CliArgs,
+    config::PipelineDefinition,
+    xthread::PIPELINE,
+};
+
+/// Changes of pipeline file are handled once no more changes are detected within this interval
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(1000);
+
+static IS_RESTART_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the pipeline had been terminated in order to restart it with new definition
+pub fn is_restart_requested() -> bool {
+    IS_RESTART_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Starts a thread which watches the pipeline file
+pub fn init_config_watcher(args: &CliArgs) -> Result<(), String> {
+    let pipeline_file = args.pipeline_file.clone();
+    let watched_dir = match Path::new(&pipeline_file).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    let mut contents = match fs::read_to_string(&pipeline_file) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Cannot read the pipeline file '{}': {}", pipeline_file, e)),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(w) => w,
+        Err(e) => return Err(format!("Failed to create a watcher of pipeline file: {}", e)),
+    };
+    if let Err(e) = watcher.watch(&watched_dir, RecursiveMode::NonRecursive) {
+        return Err(format!("Failed to watch the directory '{}': {}", watched_dir.display(), e))
+    }
+
+    info!("Watching the pipeline file '{}' for changes", pipeline_file);
+    let args = args.clone();
+    thread::spawn(move || {
+        // The watcher stops once dropped
+        let _watcher = watcher;
+        while rx.recv().is_ok() {
+            // Debounce: wait until the file system is quiet
+            while rx.recv_timeout(DEBOUNCE_INTERVAL).is_ok() {}
+
+            let new_contents = match fs::read_to_string(&pipeline_file) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("Cannot read the updated pipeline file '{}': {}", pipeline_file, e);
+                    continue
+                },
+            };
+            if new_contents == contents {
+                continue
+            }
+            contents = new_contents;
+            info!("The pipeline file is changed. Validating the new pipeline definition...");
+            let validation_result = PipelineDefinition::from_file(&pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)
+                .and_then(|def| def.validate());
+            if let Err(e) = validation_result {
+                error!("The new pipeline definition is invalid and therefore ignored: {}", e);
+                continue
+            }
+            if let Err(msg) = validate_in_child_process() {
+                error!("The new pipeline definition is invalid and therefore ignored: {}", msg);
+                continue
+            }
+
+            info!("Restarting the pipeline with new definition...");
+            IS_RESTART_REQUESTED.store(true, Ordering::SeqCst);
+            match PIPELINE.get() {
+                Some(p) => p.lock().unwrap().trigger_termination(),
+                None => error!("Cannot receive a pipeline singleton"),
+            };
+            return
+        }
+        debug!("Watcher of pipeline file is stopped");
+    });
+    Ok(())
+}
+
+/// Runs `validate` command with the same arguments in a child process.
+/// Returns the error which is reported by child process if the pipeline cannot be created
+fn validate_in_child_process() -> Result<(), String> {
+    let exe = match std::env::current_exe() {
+        Ok(e) => e,
+        Err(e) => return Err(format!("Cannot detect the path to executable: {}", e)),
+    };
+    let output = match Command::new(exe).args(std::env::args_os().skip(1)).arg("validate").output() {
+        Ok(o) => o,
+        Err(e) => return Err(format!("Failed to start the validation process: {}", e)),
+    };
+    if output.status.success() {
+        return Ok(())
+    }
+    // The error is the last line logged by child process
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().find(|l| !l.trim().is_empty()) {
+        Some(line) => Err(line.split_once("An error occurred. ").map(|(_, msg)| msg).unwrap_or(line).to_string()),
+        None => Err(format!("The validation process exited with {}", output.status)),
+    }
+}
+
+/// Replaces the current process with a new instance of application with the same arguments
+#[cfg(unix)]
+pub fn restart_application() -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+
+    let exe = match std::env::current_exe() {
+        Ok(e) => e,
+        Err(e) => return Err(format!("Cannot detect the path to executable: {}", e)),
+    };
+    let e = std::process::Command::new(exe).args(std::env::args_os().skip(1)).exec();
+    Err(format!("Failed to restart the application: {}", e))
+}
+
+/// Starts a new instance of application with the same arguments. The current process should exit afterwards
+#[cfg(not(unix))]
+pub fn restart_application() -> Result<(), String> {
+    let exe = match std::env::current_exe() {
+        Ok(e) => e,
+        Err(e) => return Err(format!("Cannot detect the path to executable: {}", e)),
+    };
+    match std::process::Command::new(exe).args(std::env::args_os().skip(1)).spawn() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to restart the application: {}", e)),
+    }
+}
@@ -0,0 +1,359 @@
+/// An interactive console to control the running pipeline from terminal.
+/// Commands are read from stdin line by line:
+/// - `status`: states of steps
+/// - `stats`: statistics of steps
+/// - `pause <step>`, `resume <step>`: pauses and resumes the processing in step
+/// - `drain-step <step> [timeout_s]`: stops feeding the step, waits until the current record is processed,
+///   then pauses the step. Records from upstream are kept in the input queue.
+///   Paused and drained steps are resumed once the pipeline termination is requested, so they process the queued records
+/// - `config`: arguments of steps. Secret values are masked
+/// - `topology`: edges between steps, including the conversion steps which are inserted by host
+/// - `topology json [file]`: the topology as a graph with module versions and statistics of steps.
+///   Prints the graph or writes it to file. See `pipeline::graph`
+/// - `set-param <step> <key> <value>`: passes a parameter to module of running step
+/// - `features`: feature flags of pipeline
+/// - `set-feature <name> <on|off>`: toggles the feature flag in all steps
+/// - `snapshot <marker_file> [timeout_s]`: pauses the source, drains and commits all steps, writes a marker file
+///   and resumes the pipeline. See `pipeline::snapshot`
+/// - `history <step> [file]`: the last records arriving to step, if record history is enabled.
+///   Prints the records or writes them to file. See `pipeline::record_history`
+/// - `next [N]`, `continue`, `break`: step-through mode only. Advances the next N held records (default: 1),
+///   releases all records, or starts holding them again. See `pipeline::step_mode`
+/// - `profile [seconds]`: captures the CPU and allocation profiles of host threads in background (default: 30 s),
+///   if profiling is enabled with `--profile-dir`. See `profiling`
+/// - `shutdown`: shuts the pipeline down gracefully
+/// - `help`: list of commands
+///
+/// Steps are referenced by handle or ID. Secret values are masked in the output of all commands.
+/// Errors are printed with error code, e.g. `Error [step_not_found]: ...`, see `errors::TorustiqError`
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead},
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info};
+
+use crate::{
+    errors::TorustiqError,
+    masking::{mask_args, mask_text, register_secrets},
+    pipeline::{
+        graph::get_graph,
+        handle::ModuleHandle,
+        pipeline::{is_step_draining, is_step_paused, set_step_draining, set_step_paused, Pipeline},
+        pipeline_step::{PipelineStep, StepModule},
+        record_history::RecordHistory,
+        snapshot::take_snapshot,
+        step_mode::StepMode,
+    },
+    profiling::{self, is_profiling_enabled, DEFAULT_PROFILE_DURATION, MAX_PROFILE_DURATION},
+    xthread::{END_TO_END_LATENCY, PIPELINE, STEP_MODE, STEP_STATS},
+};
+
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+const HELP: &str = "Commands:
+  status                          states of steps
+  stats                           statistics of steps
+  pause <step>                    pauses the processing in step
+  resume <step>                   resumes the processing in step
+  drain-step <step> [timeout_s]   finishes the current record in step, then pauses the step
+  config                          arguments of steps
+  topology                        edges between steps and inserted conversions
+  topology json [file]            the topology as a JSON graph; written to file if set
+  set-param <step> <key> <value>  passes a parameter to module of step
+  features                        feature flags of pipeline
+  set-feature <name> <on|off>     toggles the feature flag in all steps
+  snapshot <file> [timeout_s]     quiesces and commits all steps, writes a marker file, then resumes
+  history <step> [file]           the last records arriving to step; written to file if set
+  next [N]                        step-through mode: advances the next N held records (default: 1)
+  continue                        step-through mode: releases all records
+  break                           step-through mode: holds the records again
+  profile [seconds]               captures CPU and allocation profiles in background (default: 30 s)
+  shutdown                        shuts the pipeline down gracefully
+  help                            this message
+Steps are referenced by handle or ID.";
+
+/// Starts a thread which reads commands from stdin
+pub fn init_interactive_mode() {
+    thread::spawn(|| {
+        info!("Interactive mode is enabled. Type 'help' to see the list of commands");
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue
+            }
+            match execute_command(&words) {
+                Ok(output) => println!("{}", mask_text(&output)),
+                Err(e) => println!("Error [{}]: {}", e.get_code(), mask_text(&e.to_string())),
+            }
+        }
+        debug!("Interactive mode is stopped: stdin is closed");
+    });
+}
+
+/// Executes a command. Returns the output of command
+fn execute_command(words: &[&str]) -> Result<String, TorustiqError> {
+    if words[0] == "help" {
+        return Ok(String::from(HELP))
+    }
+    let pipeline = match PIPELINE.get() {
+        Some(p) => p.clone(),
+        None => return Err(TorustiqError::NotRunning),
+    };
+    match words {
+        ["status"] => Ok(format_status(&pipeline.lock().unwrap())),
+        ["stats"] => Ok(format_stats(&pipeline.lock().unwrap())),
+        ["config"] => Ok(format_config(&pipeline.lock().unwrap())),
+        ["topology"] => Ok(format_topology(&pipeline.lock().unwrap())),
+        ["topology", "json"] => serde_json::to_string_pretty(&get_graph(&pipeline.lock().unwrap()))
+            .map_err(|e| TorustiqError::Pipeline(format!("Cannot serialize the topology: {}", e))),
+        ["topology", "json", path] => {
+            let graph = serde_json::to_string_pretty(&get_graph(&pipeline.lock().unwrap()))
+                .map_err(|e| TorustiqError::Pipeline(format!("Cannot serialize the topology: {}", e)))?;
+            fs::write(path, mask_text(&graph))
+                .map_err(|e| TorustiqError::Pipeline(format!("Cannot write the topology to '{}': {}", path, e)))?;
+            Ok(format!("Topology is written to '{}'", path))
+        },
+        ["pause", step] => {
+            let handle = find_step(&pipeline, step)?.lock().unwrap().get_handle();
+            set_step_paused(handle, true);
+            Ok(format!("Step {} is paused", handle))
+        },
+        ["resume", step] => {
+            let handle = find_step(&pipeline, step)?.lock().unwrap().get_handle();
+            set_step_paused(handle, false);
+            Ok(format!("Step {} is resumed", handle))
+        },
+        ["set-param", step, key, value @ ..] if !value.is_empty() => {
+            register_secrets(&HashMap::from([(key.to_string(), value.join(" "))]));
+            let step_arc = find_step(&pipeline, step)?;
+            let step = step_arc.lock().unwrap();
+            match &step.module {
+                StepModule::Library(m) => m.set_param(step.get_handle(), key.to_string(), value.join(" ")),
+                StepModule::Builtin(_) => return Err(TorustiqError::Unsupported(String::from("Built-in modules don't accept parameters at runtime"))),
+            };
+            Ok(format!("Parameter '{}' is passed to step '{}'. It depends on module whether it's applied at runtime", key, step.get_id()))
+        },
+        ["features"] => Ok(format_features(&pipeline.lock().unwrap())),
+        ["set-feature", name, state] => {
+            let is_enabled = match *state {
+                "on" => true,
+                "off" => false,
+                _ => return Err(TorustiqError::InvalidCommand(format!("Invalid feature state: '{}'. Expected: on, off", state))),
+            };
+            pipeline.lock().unwrap().set_feature(name, is_enabled).map_err(TorustiqError::InvalidCommand)?;
+            Ok(format!("Feature '{}' is turned {} in all steps", name, state))
+        },
+        ["drain-step", step] => drain_step(&pipeline, step, DEFAULT_DRAIN_TIMEOUT),
+        ["drain-step", step, timeout] => match timeout.parse::<u64>() {
+            Ok(t) => drain_step(&pipeline, step, Duration::from_secs(t)),
+            Err(_) => Err(TorustiqError::InvalidCommand(format!("Invalid timeout: '{}'", timeout))),
+        },
+        ["snapshot", marker_path] => take_snapshot(&pipeline, marker_path, DEFAULT_DRAIN_TIMEOUT),
+        ["snapshot", marker_path, timeout] => match timeout.parse::<u64>() {
+            Ok(t) => take_snapshot(&pipeline, marker_path, Duration::from_secs(t)),
+            Err(_) => Err(TorustiqError::InvalidCommand(format!("Invalid timeout: '{}'", timeout))),
+        },
+        ["history", step] => {
+            let history = get_record_history(&pipeline, step)?;
+            serde_json::to_string_pretty(&history.to_json(None))
+                .map_err(|e| TorustiqError::Pipeline(format!("Cannot serialize the record history: {}", e)))
+        },
+        ["history", step, path] => {
+            get_record_history(&pipeline, step)?.dump(path, None).map_err(TorustiqError::Pipeline)?;
+            Ok(format!("History of records is written to '{}'", path))
+        },
+        ["next"] => advance_records(1),
+        ["next", count] => match count.parse::<u64>() {
+            Ok(c) if c > 0 => advance_records(c),
+            _ => Err(TorustiqError::InvalidCommand(format!("Invalid number of records: '{}'", count))),
+        },
+        ["continue"] => {
+            get_step_mode()?.release();
+            Ok(String::from("All records are released. Type 'break' to hold the records again"))
+        },
+        ["break"] => {
+            get_step_mode()?.resume_holding();
+            Ok(String::from("Records are held at the inputs of steps. Type 'next [N]' to advance them"))
+        },
+        ["profile"] => start_profile(DEFAULT_PROFILE_DURATION),
+        ["profile", seconds] => match seconds.parse::<u64>().map(Duration::from_secs) {
+            Ok(d) if !d.is_zero() && d <= MAX_PROFILE_DURATION => start_profile(d),
+            _ => Err(TorustiqError::InvalidCommand(format!("Invalid duration: '{}'. Expected: 1 to {} seconds",
+                seconds, MAX_PROFILE_DURATION.as_secs()))),
+        },
+        ["shutdown"] => {
+            pipeline.lock().unwrap().trigger_termination();
+            Ok(String::from("Shutting down..."))
+        },
+        _ => Err(TorustiqError::InvalidCommand(format!("Unknown command or wrong arguments: '{}'. Type 'help' to see the list of commands", words.join(" ")))),
+    }
+}
+
+/// Drains the step: stops feeding it with new records, waits until the current record is processed
+/// and pauses the step. If step is not drained within timeout, draining is cancelled
+fn drain_step(pipeline: &Arc<Mutex<Pipeline>>, step: &str, timeout: Duration) -> Result<String, TorustiqError> {
+    let handle = find_step(pipeline, step)?.lock().unwrap().get_handle();
+    let is_source = pipeline.lock().unwrap().steps.first().map(|s| s.lock().unwrap().get_handle()) == Some(handle);
+    let stats = STEP_STATS.lock().unwrap().get(&handle).cloned();
+    set_step_draining(handle, true);
+    let started_at = Instant::now();
+    // Source has no input queue, so it's paused immediately
+    while !is_source && !stats.as_ref().map(|s| s.is_input_stopped.load(Ordering::SeqCst)).unwrap_or(true) {
+        if started_at.elapsed() >= timeout {
+            set_step_draining(handle, false);
+            return Err(TorustiqError::Timeout(format!("Step {} is not drained within {} s. Draining is cancelled", handle, timeout.as_secs())))
+        }
+        thread::sleep(DRAIN_CHECK_INTERVAL);
+    }
+    set_step_paused(handle, true);
+    set_step_draining(handle, false);
+    let queue_depth = stats.map(|s| s.queue_depth.get()).unwrap_or(0);
+    Ok(format!("Step {} is drained and paused. Records waiting in the input queue: {}. Type 'resume {}' to resume the step. \
+        Termination of pipeline resumes the step as well", handle, queue_depth, handle))
+}
+
+/// Starts capturing the profiles in background
+fn start_profile(duration: Duration) -> Result<String, TorustiqError> {
+    if !is_profiling_enabled() {
+        return Err(TorustiqError::Unsupported(String::from("Profiling is not enabled. Please start the application with '--profile-dir'")))
+    }
+    let prefix = profiling::start_profile(duration).map_err(TorustiqError::Pipeline)?;
+    Ok(format!("Capturing the profiles for {} s. Files will be written to '{}.*'", duration.as_secs(), prefix))
+}
+
+/// Lets the next held records pass
+fn advance_records(count: u64) -> Result<String, TorustiqError> {
+    let held = get_step_mode()?.advance(count);
+    Ok(format!("Advancing {} record(s). Records held before advancing: {}", count, held))
+}
+
+fn get_step_mode() -> Result<&'static StepMode, TorustiqError> {
+    match STEP_MODE.get() {
+        Some(m) => Ok(m),
+        None => Err(TorustiqError::Unsupported(String::from("Step-through mode is not enabled. Please start the application with '--step-mode'"))),
+    }
+}
+
+/// Returns the record history of step
+fn get_record_history(pipeline: &Arc<Mutex<Pipeline>>, step: &str) -> Result<Arc<RecordHistory>, TorustiqError> {
+    let step_arc = find_step(pipeline, step)?;
+    let step = step_arc.lock().unwrap();
+    match &step.record_history {
+        Some(h) => Ok(h.clone()),
+        None => Err(TorustiqError::Unsupported(format!("Record history is not enabled in step '{}'", step.get_id()))),
+    }
+}
+
+/// Finds a step by handle or ID
+fn find_step(pipeline: &Arc<Mutex<Pipeline>>, step: &str) -> Result<Arc<Mutex<PipelineStep>>, TorustiqError> {
+    let pipeline = pipeline.lock().unwrap();
+    let handle = step.parse::<usize>().ok().and_then(|i| ModuleHandle::try_from(i).ok());
+    for s in &pipeline.steps {
+        let is_match = {
+            let s = s.lock().unwrap();
+            Some(s.get_handle()) == handle || s.get_id() == step
+        };
+        if is_match {
+            return Ok(s.clone())
+        }
+    }
+    Err(TorustiqError::StepNotFound(step.to_string()))
+}
+
+fn format_status(pipeline: &Pipeline) -> String {
+    let mut lines: Vec<String> = vec![format!("Pipeline: {}", match pipeline.is_running() {
+        true => "running",
+        false => "terminated",
+    })];
+    let held = STEP_MODE.get().map(|m| m.get_held()).unwrap_or_default();
+    for step in &pipeline.steps {
+        let step = step.lock().unwrap();
+        let state = if step.component.is_terminated() { String::from("terminated") }
+            else if is_step_paused(step.get_handle()) { String::from("paused") }
+            else if is_step_draining(step.get_handle()) { String::from("draining") }
+            else if let Some(sequence) = held.get(&step.get_handle()) { format!("holding record #{}", sequence) }
+            else { String::from("running") };
+        lines.push(format!("  {:>3}  {:<40} {}", step.get_handle(), step.get_id(), state));
+    }
+    lines.join("\n")
+}
+
+/// Returns the arguments of steps with masked secrets
+fn format_config(pipeline: &Pipeline) -> String {
+    let mut lines: Vec<String> = vec![format!("Pipeline: {}", pipeline.name)];
+    for step in &pipeline.steps {
+        let step = step.lock().unwrap();
+        lines.push(format!("  {:>3}  {}", step.get_handle(), step.get_id()));
+        for (k, v) in mask_args(&step.component.args) {
+            lines.push(format!("         {}: {}", k, v));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Returns the outputs of each step and the inserted conversion steps
+fn format_topology(pipeline: &Pipeline) -> String {
+    let mut lines: Vec<String> = vec![format!("Pipeline: {}", pipeline.name)];
+    let get_id = |i: usize| pipeline.steps[i].lock().unwrap().get_id();
+    for i in 0..pipeline.steps.len() {
+        let mut outputs: Vec<String> = Vec::new();
+        if let Some(o) = pipeline.topology.get_default_output(i) {
+            outputs.push(get_id(o));
+        }
+        let mut class_outputs: Vec<(String, usize)> = pipeline.topology.get_class_outputs(i).into_iter().collect();
+        class_outputs.sort();
+        outputs.extend(class_outputs.into_iter().map(|(class, o)| format!("{} (class '{}')", get_id(o), class)));
+        outputs.extend(pipeline.topology.get_mirror_outputs(i).into_iter().map(|o| format!("{} (copy)", get_id(o))));
+        outputs.extend(pipeline.topology.get_dead_letter_output(i).map(|o| format!("{} (dead letters)", get_id(o))));
+        lines.push(format!("  {:>3}  {} -> {}", i, get_id(i), match outputs.is_empty() {
+            true => String::from("(destination)"),
+            false => outputs.join(", "),
+        }));
+    }
+    if !pipeline.conversions.is_empty() {
+        lines.push(String::from("Inserted conversion steps:"));
+        lines.extend(pipeline.conversions.iter().map(|c| format!("  {}", c.format())));
+    }
+    lines.join("\n")
+}
+
+fn format_features(pipeline: &Pipeline) -> String {
+    if pipeline.features.is_empty() {
+        return String::from("No features are declared in pipeline")
+    }
+    pipeline.features.iter()
+        .map(|(name, is_enabled)| format!("  {:<40} {}", name, match is_enabled { true => "on", false => "off" }))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Returns the statistics of steps and end-to-end latency as a table
+pub fn format_stats(pipeline: &Pipeline) -> String {
+    let step_stats = STEP_STATS.lock().unwrap();
+    let mut lines: Vec<String> = vec![format!("  {:>3}  {:<40} {:>12} {:>12} {:>12} {:>8}",
+        "#", "step", "received", "succeeded", "failed", "queue")];
+    for step in &pipeline.steps {
+        let step = step.lock().unwrap();
+        let stats = match step_stats.get(&step.get_handle()) {
+            Some(s) => s.snapshot(),
+            None => continue,
+        };
+        lines.push(format!("  {:>3}  {:<40} {:>12} {:>12} {:>12} {:>8}", step.get_handle(), step.get_id(),
+            stats.records_received, stats.records_succeeded, stats.records_failed, stats.queue_depth));
+    }
+    if pipeline.latency_tracking {
+        lines.push(format!("End-to-end latency: {}", END_TO_END_LATENCY.format_summary()));
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,163 @@
+/// Pipeline runner: creates a pipeline from definition, configures and runs it
+
+use std::{
+    sync::{Arc, Mutex}, thread, time
+};
+
+use libloading::Library;
+use log::{debug, error, info, warn};
+use serde_json::json;
+
+use crate::{
+    cli::CliArgs,
+    config::PipelineDefinition,
+    errors::TorustiqError,
+    events::{publish, set_pipeline_name},
+    modules::{module_loader::{load_libraries, LoadedLibraries}, native::{create_native_module, is_native_module}},
+    pipeline::{persistent_stats::{load_persistent_stats, save_persistent_stats}, pipeline::{stop_system_command_thread, Pipeline, PipelineState}, heartbeat::start_heartbeat, self_test::run_self_test, stall::start_stall_detector, watchdog::start_watchdog},
+    policy::ModulePolicy,
+    xthread::{PIPELINE, RESOURCE_LIMITS, RETRY_QUEUE, SAMPLING, SHADOW, SOURCE_DRAIN, SOURCE_LEASE},
+};
+
+/// Creates a pipeline from pipeline definition
+pub fn create_pipeline(args: &CliArgs, pipeline_def: &PipelineDefinition) -> Result<(Pipeline, Vec<Library>), TorustiqError> {
+    let policy = match &args.policy_file {
+        Some(path) => Some(ModulePolicy::from_file(path).map_err(TorustiqError::Config)?),
+        None => None,
+    };
+
+    if let Some(policy) = &policy {
+        policy.check_module_ids(&pipeline_def.get_module_ids_in_use()).map_err(TorustiqError::Policy)?;
+    }
+    let library_handlers: Vec<String> = pipeline_def.get_handlers_in_use()
+        .into_iter()
+        .filter(|h| !is_native_module(h))
+        .collect();
+    let loaded_libs: LoadedLibraries = load_libraries(&args.module_dir, library_handlers)?;
+    info!("All modules are loaded.");
+    loaded_libs.init();
+
+    let mut pipeline = match Pipeline::try_from((pipeline_def, &loaded_libs)) {
+        Ok(p) => p,
+        Err(e) => return Err(TorustiqError::Config(format!("Failed to create a pipeline from definition: {}", e)))
+    };
+    pipeline.policy = policy;
+    info!("Constructed a pipeline which contains {} steps", pipeline.steps.len());
+    for conversion in &pipeline.conversions {
+        info!("Inserted a conversion step {}", conversion.format());
+    }
+    let loaded_libs = loaded_libs.libs;
+    Ok((pipeline, loaded_libs))
+}
+
+/// Checks if a pipeline can be created from definition: validates the definition and policy,
+/// checks if all modules exist and support their positions. Libraries are not initialized
+pub fn validate_pipeline_definition(args: &CliArgs, pipeline_def: &PipelineDefinition) -> Result<(), TorustiqError> {
+    pipeline_def.validate()?;
+    if let Some(path) = &args.policy_file {
+        ModulePolicy::from_file(path).map_err(TorustiqError::Config)?
+            .check_module_ids(&pipeline_def.get_module_ids_in_use()).map_err(TorustiqError::Policy)?;
+    }
+    let mut library_handlers: Vec<String> = Vec::new();
+    for handler in pipeline_def.get_handlers_in_use() {
+        match is_native_module(&handler) {
+            true => {
+                create_native_module(&handler).map_err(|e| TorustiqError::ModuleLoad { module_id: Some(handler.clone()), message: e })?;
+            },
+            false => library_handlers.push(handler),
+        }
+    }
+    let loaded_libs = load_libraries(&args.module_dir, library_handlers)?;
+    Pipeline::try_from((pipeline_def, &loaded_libs)).map_err(TorustiqError::Config)?;
+    Ok(())
+}
+
+/// Configures and starts the pipeline. Returns once all steps are terminated
+pub fn run_pipeline(pipeline: Pipeline) -> Result<(), TorustiqError> {
+    info!("Starting pipeline '{}'...", pipeline.name);
+    if let Some(description) = &pipeline.description {
+        debug!("Description of pipeline: {}", description);
+    }
+    info!("Resource limits of control group: {}", RESOURCE_LIMITS.format());
+
+    set_pipeline_name(&pipeline.name);
+    let pipeline_arc = Arc::new(Mutex::new(pipeline));
+
+    if PIPELINE.set(pipeline_arc.clone()).is_err() {
+        return Err(TorustiqError::Pipeline(String::from("Failed to register the pipeline in static context")))
+    }
+
+    {
+        let mut pipeline = pipeline_arc.lock().unwrap();
+
+        if let Some(path) = &pipeline.stats_file {
+            load_persistent_stats(path, &pipeline.name).map_err(TorustiqError::Pipeline)?;
+        }
+
+        pipeline.configure_steps()?;
+
+        if let Err(msg) = pipeline.configure_listeners() {
+            return Err(TorustiqError::Pipeline(format!("Cannot configure listeners: {}", msg)));
+        };
+
+        if let Err(msg) = pipeline.start_senders_receivers() {
+            return Err(TorustiqError::Pipeline(format!("Cannot start the sender and receiver channels: {}", msg)));
+        };
+
+        run_self_test(&pipeline).map_err(TorustiqError::SelfTest)?;
+
+        pipeline.start_steps()?;
+        publish("pipeline_started", json!({"run_id": pipeline.run_id.to_string()}));
+    }
+    start_watchdog().map_err(TorustiqError::Pipeline)?;
+    let stall_timeout = pipeline_arc.lock().unwrap().stall_timeout;
+    start_stall_detector(stall_timeout).map_err(TorustiqError::Pipeline)?;
+    start_heartbeat().map_err(TorustiqError::Pipeline)?;
+
+    while pipeline_arc.lock().unwrap().is_running() {
+        // Without system command thread, the step terminations are not handled and the pipeline never stops
+        if pipeline_arc.lock().unwrap().system_thread.as_ref().map(|t| t.is_finished()).unwrap_or(false) {
+            return Err(TorustiqError::Pipeline(format!("System command thread of pipeline '{}' died prematurely", pipeline_arc.lock().unwrap().name)))
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+    debug!("Exited from main loop");
+    if let Some(lease) = SOURCE_LEASE.get() {
+        lease.join();
+    }
+    let system_thread = pipeline_arc.lock().unwrap().system_thread.take();
+    if let Some(t) = system_thread {
+        stop_system_command_thread(t);
+    }
+    let (stats_file, pipeline_name) = {
+        let pipeline = pipeline_arc.lock().unwrap();
+        (pipeline.stats_file.clone(), pipeline.name.clone())
+    };
+    if let Some(path) = stats_file {
+        if let Err(msg) = save_persistent_stats(&path, &pipeline_name) {
+            error!("Failed to save the statistics: {}", msg);
+        }
+    }
+    if let Some(parked) = RETRY_QUEUE.get().map(|q| q.get_parked_count()).filter(|c| *c > 0) {
+        warn!("{} records parked for retry are lost, as the pipeline is terminated", parked);
+    }
+    if let Some(drain) = SOURCE_DRAIN.get() {
+        drain.report();
+    }
+    if let Some(shadow) = SHADOW.get() {
+        info!("Shadow chain: {}", shadow.format_summary());
+    }
+    if let Some(sampling) = SAMPLING.get() {
+        info!("Sampling: {}", sampling.format_summary());
+    }
+    if let Some(e) = SOURCE_LEASE.get().and_then(|l| l.take_start_error()) {
+        return Err(e)
+    }
+    let pipeline = pipeline_arc.lock().unwrap();
+    if let PipelineState::DownstreamTerminated(step_id) = &pipeline.state {
+        return Err(TorustiqError::DownstreamTerminated { pipeline: pipeline.name.clone(), step: step_id.clone() })
+    }
+    info!("Pipeline '{}' is terminated", pipeline.name);
+    publish("pipeline_terminated", json!({"run_id": pipeline.run_id.to_string()}));
+    Ok(())
+}
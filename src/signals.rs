@@ -0,0 +1,78 @@
+/// Handlers of system signals which don't terminate the application
+
+use log::LevelFilter;
+
+use crate::{repl::format_stats, xthread::PIPELINE};
+
+/// Returns the next log level in cycle: info -> debug -> trace -> info
+fn next_log_level(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::Info => LevelFilter::Debug,
+        LevelFilter::Debug => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    }
+}
+
+/// Switches the log level to the next one in cycle.
+/// Messages are emitted only if they pass the logger filter as well, i.e. `RUST_LOG` must allow the target level
+pub fn toggle_log_level() {
+    let level = next_log_level(log::max_level());
+    log::set_max_level(level);
+    // Logged with 'warn' level to make the message visible on any level
+    log::warn!("Log level is switched to {}", level);
+}
+
+/// Initializes a handler of SIGUSR2 which toggles the log level
+#[cfg(unix)]
+pub fn init_log_level_toggle() -> Result<(), String> {
+    use signal_hook::{consts::SIGUSR2, iterator::Signals};
+
+    let mut signals = match Signals::new([SIGUSR2]) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Failed to init a SIGUSR2 handler: {}", e)),
+    };
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            toggle_log_level();
+        }
+    });
+    log::info!("Send SIGUSR2 to the process to switch between info, debug and trace log levels");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn init_log_level_toggle() -> Result<(), String> {
+    Ok(())
+}
+
+/// Writes the statistics of pipeline to log
+pub fn dump_stats() {
+    match PIPELINE.get() {
+        // Logged with 'warn' level to make the message visible on any level
+        Some(p) => log::warn!("Statistics of pipeline:\n{}", format_stats(&p.lock().unwrap())),
+        None => log::warn!("Cannot dump the statistics: pipeline is not running yet"),
+    }
+}
+
+/// Initializes a handler of SIGUSR1 which writes the statistics of pipeline to log
+#[cfg(unix)]
+pub fn init_stats_dump() -> Result<(), String> {
+    use signal_hook::{consts::SIGUSR1, iterator::Signals};
+
+    let mut signals = match Signals::new([SIGUSR1]) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Failed to init a SIGUSR1 handler: {}", e)),
+    };
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            dump_stats();
+        }
+    });
+    log::info!("Send SIGUSR1 to the process to write the statistics of pipeline to log");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn init_stats_dump() -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,83 @@
+/// Pipeline simulation.
+/// Replays the captured records through the pipeline with the destination replaced by a timing model,
+/// then reports the throughput and queue behavior of steps. Useful for capacity planning:
+/// transformations run as usual, but no data is written to destination systems
+
+use std::{
+    collections::HashMap,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use serde_yaml::Value;
+
+use crate::{
+    cli::{CliArgs, SimulateArgs},
+    config::PipelineDefinition,
+    modules::builtin::{fixture::read_fixture_file, fixture_source, timing_model::{self, LatencyDistribution}},
+    runner::{create_pipeline, run_pipeline},
+    xthread::{PIPELINE, STEP_STATS},
+};
+
+/// Runs the simulation. Returns a report
+pub fn run_simulation(args: &CliArgs, simulate_args: &SimulateArgs) -> Result<String, String> {
+    // Fail early on invalid arguments rather than once the pipeline is configured
+    LatencyDistribution::try_from(simulate_args.latency.as_str())?;
+    let input_records = read_fixture_file(&simulate_args.input)?.len();
+
+    let mut pipeline_def = PipelineDefinition::from_file(&args.pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)?;
+    if pipeline_def.steps.len() < 2 {
+        return Err(String::from("Pipeline must have at least two steps"))
+    }
+    // Simulation must not alert anyone
+    pipeline_def.notifications = None;
+    let source = pipeline_def.steps.first_mut().unwrap();
+    source.handler = String::from(fixture_source::MODULE_ID);
+    source.args = Some(HashMap::from([(String::from("path"), Value::String(simulate_args.input.clone()))]));
+    let destination = pipeline_def.steps.last_mut().unwrap();
+    destination.handler = String::from(timing_model::MODULE_ID);
+    destination.args = Some(HashMap::from([(String::from("latency"), Value::String(simulate_args.latency.clone()))]));
+
+    let (pipeline, _loaded_libs) = create_pipeline(args, &pipeline_def)?;
+    let started_at = Instant::now();
+    run_pipeline(pipeline)?;
+    Ok(format_report(input_records, started_at.elapsed()))
+}
+
+/// Returns the throughput of pipeline and queue behavior of steps
+fn format_report(input_records: usize, elapsed: Duration) -> String {
+    let pipeline = match PIPELINE.get() {
+        Some(p) => p.lock().unwrap(),
+        None => return String::from("No statistics: the pipeline is not started"),
+    };
+    let step_stats = STEP_STATS.lock().unwrap();
+    let mut lines: Vec<String> = vec![
+        format!("Simulation of pipeline '{}'", pipeline.name),
+        format!("  Records replayed: {}", input_records),
+        format!("  Duration: {:.3} s", elapsed.as_secs_f64()),
+    ];
+    let delivered = pipeline.steps.last()
+        .and_then(|s| step_stats.get(&s.lock().unwrap().get_handle()).cloned())
+        .map(|s| s.records_succeeded.load(Ordering::Relaxed))
+        .unwrap_or(0);
+    lines.push(format!("  Records delivered: {}", delivered));
+    lines.push(format!("  Predicted throughput: {:.1} records/s", delivered as f64 / elapsed.as_secs_f64().max(f64::EPSILON)));
+    lines.push(format!("  {:>3}  {:<40} {:>12} {:>14} {:>10} {:>12}",
+        "#", "step", "processed", "avg time, ms", "peak queue", "blocked, s"));
+    for step in &pipeline.steps {
+        let step = step.lock().unwrap();
+        let stats = match step_stats.get(&step.get_handle()) {
+            Some(s) => s,
+            None => continue,
+        };
+        let calls = stats.process_record_calls.load(Ordering::Relaxed);
+        let avg_ms = match calls {
+            0 => 0.0,
+            c => Duration::from_nanos(stats.process_record_ns.load(Ordering::Relaxed) / c).as_secs_f64() * 1000.0,
+        };
+        lines.push(format!("  {:>3}  {:<40} {:>12} {:>14.3} {:>10} {:>12.3}", step.get_handle(), step.get_id(),
+            calls, avg_ms, stats.queue_depth.get_peak(),
+            Duration::from_nanos(stats.queue_blocked_ns.load(Ordering::Relaxed)).as_secs_f64()));
+    }
+    lines.join("\n")
+}
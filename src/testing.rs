@@ -0,0 +1,131 @@
+/// Pipeline test harness.
+/// Runs the pipeline with the source replaced by fixture records and the destination replaced by capture file,
+/// then compares the captured records with the expected ones
+
+use std::{
+    collections::HashMap, env, fs, path::Path, process
+};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::{
+    cli::{CliArgs, TestArgs},
+    config::PipelineDefinition,
+    modules::builtin::{capture, fixture::{read_fixture_file, FixtureRecord}, fixture_source},
+    runner::{create_pipeline, run_pipeline},
+};
+
+/// A test case definition
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct TestCase {
+    /// A fixture file with input records. Relative paths are resolved against the test case directory
+    pub input: String,
+    /// A fixture file with expected output records. Relative paths are resolved against the test case directory
+    pub expected: String,
+    /// Normalization rules. Applied to both actual and expected records before comparison
+    pub normalize: Option<Vec<NormalizationRule>>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalizationRule {
+    /// Trims whitespace around payload
+    Trim,
+    /// Parses payload as JSON and serializes it back, so formatting and key order are ignored
+    Json,
+    /// Ignores the order of records
+    Sort,
+    /// Ignores metadata
+    IgnoreMetadata,
+}
+
+/// Runs the test case. Returns true if the output matches the expected records
+pub fn run_test(args: &CliArgs, test_args: &TestArgs) -> Result<bool, String> {
+    let test_case: TestCase = match fs::read_to_string(&test_args.test_file) {
+        Ok(c) => match serde_yaml::from_str(c.as_str()) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Cannot parse the test case '{}': {}", test_args.test_file, e)),
+        },
+        Err(e) => return Err(format!("Cannot open the test case '{}': {}", test_args.test_file, e)),
+    };
+    let test_dir = Path::new(&test_args.test_file).parent().unwrap_or(Path::new(""));
+    let input_path = test_dir.join(&test_case.input).to_string_lossy().to_string();
+    let expected_path = test_dir.join(&test_case.expected).to_string_lossy().to_string();
+    let capture_path = env::temp_dir()
+        .join(format!("torustiq_test_{}.jsonl", process::id()))
+        .to_string_lossy().to_string();
+
+    let mut pipeline_def = PipelineDefinition::from_file(&args.pipeline_file, args.pipeline.as_ref(), args.profile.as_ref(), &args.param)?;
+    if pipeline_def.steps.len() < 2 {
+        return Err(String::from("Pipeline must have at least two steps"))
+    }
+    let source = pipeline_def.steps.first_mut().unwrap();
+    source.handler = String::from(fixture_source::MODULE_ID);
+    source.args = Some(HashMap::from([(String::from("path"), Value::String(input_path))]));
+    let destination = pipeline_def.steps.last_mut().unwrap();
+    destination.handler = String::from(capture::MODULE_ID);
+    destination.args = Some(HashMap::from([(String::from("path"), Value::String(capture_path.clone()))]));
+
+    let (pipeline, _loaded_libs) = create_pipeline(args, &pipeline_def)?;
+    run_pipeline(pipeline)?;
+
+    let actual = read_fixture_file(&capture_path);
+    let _ = fs::remove_file(&capture_path);
+    let rules = test_case.normalize.unwrap_or_default();
+    let actual = normalize(actual?, &rules)?;
+    let expected = normalize(read_fixture_file(&expected_path)?, &rules)?;
+    Ok(compare(&expected, &actual))
+}
+
+/// Applies normalization rules to records
+fn normalize(mut records: Vec<FixtureRecord>, rules: &[NormalizationRule]) -> Result<Vec<FixtureRecord>, String> {
+    for r in records.iter_mut() {
+        if rules.contains(&NormalizationRule::Trim) {
+            r.payload = r.payload.trim().to_string();
+        }
+        if rules.contains(&NormalizationRule::Json) {
+            r.payload = match serde_json::from_str::<serde_json::Value>(&r.payload) {
+                Ok(v) => v.to_string(),
+                Err(e) => return Err(format!("Cannot normalize the payload as JSON: {}. Payload: {}", e, r.payload)),
+            };
+        }
+        if rules.contains(&NormalizationRule::IgnoreMetadata) {
+            r.metadata.clear();
+        }
+    }
+    if rules.contains(&NormalizationRule::Sort) {
+        records.sort_by(|a, b| a.payload.cmp(&b.payload));
+    }
+    Ok(records)
+}
+
+/// Compares the records and prints the differences.
+/// Metadata keys which are not listed in expected record are ignored
+fn compare(expected: &[FixtureRecord], actual: &[FixtureRecord]) -> bool {
+    let mut is_match = true;
+    if expected.len() != actual.len() {
+        println!("Expected {} records, got {}", expected.len(), actual.len());
+        is_match = false;
+    }
+    for (i, (e, a)) in expected.iter().zip(actual.iter()).enumerate() {
+        if e.payload != a.payload {
+            println!("Record #{}: payload mismatch.\n  expected: {}\n  actual:   {}", i, e.payload, a.payload);
+            is_match = false;
+        }
+        for (k, v) in &e.metadata {
+            if a.metadata.get(k) != Some(v) {
+                println!("Record #{}: metadata mismatch in key '{}'.\n  expected: {}\n  actual:   {}",
+                    i, k, v, a.metadata.get(k).map(|v| v.as_str()).unwrap_or("<missing>"));
+                is_match = false;
+            }
+        }
+    }
+    for (i, a) in actual.iter().enumerate().skip(expected.len()) {
+        println!("Record #{}: unexpected record: {}", i, a.payload);
+    }
+    for (i, e) in expected.iter().enumerate().skip(actual.len()) {
+        println!("Record #{}: missing record: {}", i, e.payload);
+    }
+    is_match
+}
@@ -1,40 +1,110 @@
 /// Cross-thread communication
 
 use std::{
-    collections::HashMap,
-    sync::{mpsc::Sender, Arc, Mutex},
+    collections::{HashMap, HashSet},
+    sync::{atomic::AtomicBool, mpsc::Sender, Arc, Mutex, RwLock},
 };
 
 use once_cell::sync::{Lazy, OnceCell};
 
-use torustiq_common::ffi::types::{
-    functions::ModuleFreeRecordFn,
-    module::{ModuleHandle, Record},
-};
-
-use crate::pipeline::pipeline::Pipeline;
+use crate::{network::Network, pipeline::{
+    channels::Channels, handle::ModuleHandle, drain::SourceDrain, latency::LatencyHistogram, listener::Listener, limits::ResourceLimits, lease::SourceLease, persistent_stats::PersistentCounters, pipeline::Pipeline, ramp_up::RampUp, retry::RetryQueue, sampling::Sampling, shadow::Shadow, stats::StepStatistics, step_context::StepContext, step_mode::StepMode
+}};
 
 /// System messages are sent from modules to control the pipeline
 pub enum SystemMessage {
     /// Terminates a step with provided handle
     TerminateStep(ModuleHandle),
+    /// Stops the system command thread. Sent once the pipeline is terminated
+    Shutdown,
 }
 
-/// A hashmap of sender channels for each step
-/// Senders submit a record to dependent step. currently it's just the next step,
-/// but it might change in the future (e.g. multiple steps)
-pub static SENDERS: Lazy<Mutex<HashMap<ModuleHandle, Sender<Record>>>> = Lazy::new(|| {
-    Mutex::new(HashMap::new())
-});
+/// Record channels of running pipeline. The registry is owned by pipeline, see `pipeline::channels`
+pub static CHANNELS: OnceCell<Arc<Channels>> = OnceCell::new();
 
 /// Module callbacks send system messages here
 pub static SYSTEM_MESSAGES: OnceCell<Sender<SystemMessage>> = OnceCell::new();
 
-/// A hashmap of pointers to torustiq_module_pipeline_free_record functions.
-/// As records must be deallocated by modules they are created in, this map allows to
-/// locate a deallocation function owned by module
-pub static FREE_BUF: Lazy<Mutex<HashMap<ModuleHandle, ModuleFreeRecordFn>>> = Lazy::new(|| {
+/// Statistics of each step
+pub static STEP_STATS: Lazy<Mutex<HashMap<ModuleHandle, Arc<StepStatistics>>>> = Lazy::new(|| {
     Mutex::new(HashMap::new())
 });
 
-pub static PIPELINE: OnceCell<Arc<Mutex<Pipeline>>> = OnceCell::new();
\ No newline at end of file
+/// Contexts of steps: paused, drained and terminated state. Set once the steps are configured, see `pipeline::step_context`
+pub static STEP_CONTEXTS: OnceCell<HashMap<ModuleHandle, Arc<StepContext>>> = OnceCell::new();
+
+/// Steps which are requested to shut down. Modules can check it during long-running record processing
+pub static CANCELLED_STEPS: Lazy<Mutex<HashSet<ModuleHandle>>> = Lazy::new(|| {
+    Mutex::new(HashSet::new())
+});
+
+/// Steps which are requested to commit the processed records out of schedule. See `pipeline::snapshot`
+pub static COMMIT_REQUESTS: Lazy<Mutex<HashSet<ModuleHandle>>> = Lazy::new(|| {
+    Mutex::new(HashSet::new())
+});
+
+/// Handles of all steps and listeners in pipeline. Handles received from modules are validated against this set
+pub static MODULE_HANDLES: OnceCell<HashSet<ModuleHandle>> = OnceCell::new();
+
+/// State of shadow chain. Set only if pipeline has a shadow chain
+pub static SHADOW: OnceCell<Shadow> = OnceCell::new();
+
+/// State of sampling. Set only if sampling is configured in pipeline
+pub static SAMPLING: OnceCell<Sampling> = OnceCell::new();
+
+/// State of step-through mode. Set only if the application is started in step-through mode
+pub static STEP_MODE: OnceCell<StepMode> = OnceCell::new();
+
+/// Listeners which are notified about records dropped by host, by step. See `pipeline::drops`
+pub static DROP_LISTENERS: OnceCell<HashMap<ModuleHandle, Vec<Listener>>> = OnceCell::new();
+
+/// Handling of records produced by source after the shutdown request
+pub static SOURCE_DRAIN: OnceCell<SourceDrain> = OnceCell::new();
+
+/// A handle which doesn't belong to any component. Used to call the callbacks in startup self-test
+pub static SELF_TEST_HANDLE: OnceCell<ModuleHandle> = OnceCell::new();
+
+/// IDs of steps by handles. Set only if provenance tracking is enabled in pipeline
+pub static PROVENANCE_STEP_IDS: OnceCell<HashMap<ModuleHandle, String>> = OnceCell::new();
+
+/// If true, the processing budget of records is tracked. See `records::DEADLINE_METADATA_KEY`
+pub static IS_DEADLINE_TRACKING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A handle of source step. Set only if latency tracking is enabled in pipeline
+pub static LATENCY_SOURCE_HANDLE: OnceCell<ModuleHandle> = OnceCell::new();
+
+/// Resource limits of control group. Detected once on first access
+pub static RESOURCE_LIMITS: Lazy<ResourceLimits> = Lazy::new(ResourceLimits::detect);
+
+/// End-to-end latency of records. Measured only if latency tracking is enabled in pipeline
+pub static END_TO_END_LATENCY: Lazy<LatencyHistogram> = Lazy::new(LatencyHistogram::default);
+
+/// Patterns of argument keys whose values are masked in diagnostics, in addition to the default ones
+pub static MASKED_KEY_PATTERNS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| {
+    RwLock::new(Vec::new())
+});
+
+/// Values of secret arguments. See `masking::mask_text`
+pub static MASKED_VALUES: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| {
+    RwLock::new(HashSet::new())
+});
+
+/// Counters of steps saved by previous runs, by step ID. Loaded only if statistics file is set in pipeline
+pub static LIFETIME_STATS_BASE: OnceCell<HashMap<String, PersistentCounters>> = OnceCell::new();
+
+/// A key to decrypt the encrypted values in pipeline files. See `encryption` module
+pub static ENCRYPTION_KEY: OnceCell<Vec<u8>> = OnceCell::new();
+
+pub static PIPELINE: OnceCell<Arc<Mutex<Pipeline>>> = OnceCell::new();
+
+/// A delay queue of records parked for retry. Initialized only if some step has retries configured
+pub static RETRY_QUEUE: OnceCell<RetryQueue> = OnceCell::new();
+
+/// HTTP agents of host. Set only if network settings are defined in pipeline, see `network` module
+pub static NETWORK: OnceCell<Network> = OnceCell::new();
+
+/// Source ramp-up. Initialized when steps are started, if ramp-up is enabled in pipeline
+pub static RAMP_UP: OnceCell<RampUp> = OnceCell::new();
+
+/// Source lease. Initialized when steps are started, if the lease is configured in pipeline
+pub static SOURCE_LEASE: OnceCell<Arc<SourceLease>> = OnceCell::new();
\ No newline at end of file